@@ -1,6 +1,9 @@
 #[derive(Debug, Clone, serde::Serialize)]
 pub enum CommandError {
     Aborted,
+    GameAlreadyRunning {
+        game: String,
+    },
     Error {
         messages: Vec<String>,
         backtrace: String,
@@ -27,6 +30,7 @@ impl From<Error> for CommandError {
     fn from(value: Error) -> Self {
         match value {
             Error::Aborted => Self::Aborted,
+            Error::GameAlreadyRunning { game } => Self::GameAlreadyRunning { game },
             Error::Error(e) => Self::from(e),
         }
     }
@@ -36,6 +40,9 @@ impl From<Error> for CommandError {
 pub enum Error {
     #[error("Aborted by the user")]
     Aborted,
+    /// The game was about to be launched while another instance of it is running.
+    #[error("{game} is already running")]
+    GameAlreadyRunning { game: String },
     #[error(transparent)]
     Error(#[from] anyhow::Error),
 }
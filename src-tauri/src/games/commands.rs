@@ -2,18 +2,83 @@ use anyhow::Context;
 use tauri::ipc::InvokeResponseBody;
 
 use crate::{
-    games::Game,
+    games::{Game, GameSupport, PackageLoader, StorePlatform, StorePlatformMetadata},
+    stores::gog::GogInstall,
+    stores::heroic::HeroicInstall,
     util::search::{self, Score, SortOption},
     CommandError,
 };
 
-use super::{games, GAMES_MOD_DOWNLOADS, GAMES_REVIEWS};
+use super::{games, games_by_id, GAMES_MOD_DOWNLOADS, GAMES_REVIEWS};
 
 #[tauri::command]
 pub async fn get_games() -> Result<&'static [Game<'static>], CommandError> {
     Ok(games()?)
 }
 
+/// Returns the games whose mods are loaded by `loader`.
+#[tauri::command]
+pub async fn get_games_by_loader(
+    loader: PackageLoader,
+) -> Result<Vec<&'static Game<'static>>, CommandError> {
+    Ok(games()?
+        .iter()
+        .filter(|g| g.package_loader == loader)
+        .collect())
+}
+
+/// Returns the support status of each game, in the same order as [`get_games`].
+#[tauri::command]
+pub async fn get_games_support() -> Result<Vec<GameSupport>, CommandError> {
+    Ok(games()?.iter().map(Game::support).collect())
+}
+
+#[tauri::command]
+pub async fn get_game_store_platforms(
+    game: &str,
+) -> Result<Vec<StorePlatform<'static>>, CommandError> {
+    Ok(games_by_id()?
+        .get(game)
+        .context("No such game")?
+        .store_platforms())
+}
+
+/// Looks for an Epic Games Store copy of the game installed through Heroic or Legendary.
+#[tauri::command]
+pub async fn find_heroic_installation(game: &str) -> Result<Option<HeroicInstall>, CommandError> {
+    let log = slog_scope::logger();
+
+    let game = games_by_id()?.get(game).context("No such game")?;
+    for metadata in &game.store_platform_metadata {
+        if let StorePlatformMetadata::Epic { store_identifier } = metadata {
+            if let Some(install) =
+                crate::stores::heroic::find_epic_game(&log, store_identifier).await?
+            {
+                return Ok(Some(install));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Looks for a GOG copy of the game.
+#[tauri::command]
+pub async fn find_gog_installation(game: &str) -> Result<Option<GogInstall>, CommandError> {
+    let log = slog_scope::logger();
+
+    let game = games_by_id()?.get(game).context("No such game")?;
+    for gog in game
+        .store_platform_metadata
+        .iter()
+        .filter_map(StorePlatformMetadata::gog)
+    {
+        if let Some(install) = crate::stores::gog::find_gog_game(&log, gog.id).await? {
+            return Ok(Some(install));
+        }
+    }
+    Ok(None)
+}
+
 #[derive(Debug, Clone, Copy, serde::Deserialize)]
 pub enum SortColumn {
     Relevance,
@@ -22,9 +87,12 @@ pub enum SortColumn {
     Popularity,
 }
 
+/// Returns the indices of the games matching `query`, and `loader` if given, in the order of
+/// `sort`.
 #[tauri::command]
 pub async fn search_games(
     query: String,
+    loader: Option<PackageLoader>,
     sort: Vec<SortOption<SortColumn>>,
 ) -> Result<Vec<usize>, CommandError> {
     let games_mod_downloads = GAMES_MOD_DOWNLOADS
@@ -40,11 +108,12 @@ pub async fn search_games(
         let mut buf = games
             .iter()
             .enumerate()
+            .filter(|(_, g)| loader.is_none_or(|loader| g.package_loader == loader))
             .filter_map(|(i, g)| {
                 if query.is_empty() {
                     Some((i, Score::MAX))
                 } else {
-                    let score = search::score(&query, &g.name)?;
+                    let score = g.search_score(&query)?;
                     // can be helpful when tweaking the search scoring
                     // slog::trace!(logger, "search_games [{i}] {:?}: {score:?}", g.name);
                     Some((i, score))
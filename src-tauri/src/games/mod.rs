@@ -1,17 +1,71 @@
 pub mod commands;
 
-use std::{borrow::Cow, collections::HashMap, marker::PhantomData, sync::LazyLock};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 
 use anyhow::{Context, Result};
+use slog::{error, info};
+
+use crate::paths::local_data_dir;
+use crate::util::search;
+use crate::util::IoErrorKindExt as _;
 
 #[derive(Debug, Clone, thiserror::Error)]
 #[error("{0}")]
 pub struct StringError(String);
 
+/// The name of the file in the local data directory that holds games added by the user, in the
+/// same format as the built-in `games.json`. They replace built-in games with the same id.
+pub const USER_GAMES_FILE_NAME: &str = "games.json";
+
 static GAMES: LazyLock<Result<Vec<Game>, StringError>> = LazyLock::new(|| {
-    serde_json::from_str(include_str!("games.json")).map_err(|e| StringError(e.to_string()))
+    let mut games =
+        serde_json::from_str(include_str!("games.json")).map_err(|e| StringError(e.to_string()))?;
+    if let Some(user_games) = load_user_games() {
+        merge_user_games(&mut games, user_games);
+    }
+    Ok(games)
 });
 
+/// Loads the user's games, logging rather than failing if they can't be, so that a bad file
+/// doesn't take the built-in games down with it.
+fn load_user_games() -> Option<Vec<Game<'static>>> {
+    let log = slog_scope::logger();
+    let path = local_data_dir().join(USER_GAMES_FILE_NAME);
+    let json = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return None,
+        Err(e) => {
+            error!(log, "Failed to read user games from {path:?}: {e}");
+            return None;
+        }
+    };
+    // like the built-in games, these borrow from the JSON for the rest of the process' life
+    match serde_json::from_str::<Vec<Game>>(json.leak()) {
+        Ok(games) => {
+            info!(log, "Loaded {} user games from {path:?}", games.len());
+            Some(games)
+        }
+        Err(e) => {
+            error!(log, "Invalid user games in {path:?}: {e}");
+            None
+        }
+    }
+}
+
+fn merge_user_games<'a>(games: &mut Vec<Game<'a>>, user_games: Vec<Game<'a>>) {
+    for mut game in user_games {
+        game.user_defined = true;
+        match games.iter_mut().find(|g| g.id == game.id) {
+            Some(g) => *g = game,
+            None => games.push(game),
+        }
+    }
+}
+
 struct IndexedGameData<T>(Vec<T>);
 
 impl<'de, T: Clone + Default + serde::Deserialize<'de>> serde::Deserialize<'de>
@@ -55,7 +109,12 @@ impl<'de, T: Clone + Default + serde::Deserialize<'de>> serde::Deserialize<'de>
                 Ok(IndexedGameData(
                     buf.into_iter()
                         .enumerate()
-                        .map(|(i, o)| o.ok_or_else(|| A::Error::missing_field(games[i].id)))
+                        .map(|(i, o)| match o {
+                            Some(value) => Ok(value),
+                            // the user can't be expected to provide these
+                            None if games[i].user_defined => Ok(T::default()),
+                            None => Err(A::Error::missing_field(games[i].id)),
+                        })
                         .collect::<Result<Vec<_>, _>>()?,
                 ))
             }
@@ -110,6 +169,9 @@ pub struct Game<'a> {
     /// Display name of the game.
     #[serde(borrow)]
     pub name: Cow<'a, str>,
+    /// Other names the game is known by, like abbreviations, which it can be searched by.
+    #[serde(default, borrow, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<Cow<'a, str>>,
     /// Thunderstore community id for the game.
     #[serde(rename = "thunderstoreId", borrow)]
     pub thunderstore_id: &'a str,
@@ -124,6 +186,151 @@ pub struct Game<'a> {
     pub instance_type: InstanceType,
     #[serde(rename = "packageLoader")]
     pub package_loader: PackageLoader,
+    /// Whether the game comes from the user's [`USER_GAMES_FILE_NAME`].
+    #[serde(rename = "userDefined", skip_deserializing)]
+    pub user_defined: bool,
+}
+
+/// An operating system that game executables are built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Windows,
+    Linux,
+    MacOS,
+}
+
+impl Platform {
+    pub const fn host() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else if cfg!(target_os = "macos") {
+            Self::MacOS
+        } else {
+            Self::Linux
+        }
+    }
+
+    /// Guesses the platform the executable `name` is built for from its extension. Returns `None`
+    /// for names without a telling extension.
+    pub fn of_executable(name: &str) -> Option<Self> {
+        let extension = Path::new(name).extension()?.to_str()?;
+        if extension.eq_ignore_ascii_case("exe") {
+            Some(Self::Windows)
+        } else if extension.eq_ignore_ascii_case("app") {
+            Some(Self::MacOS)
+        } else if ["x86_64", "x86", "arm64", "aarch64", "sh"]
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(e))
+        {
+            Some(Self::Linux)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether Manderrow is able to install mods for and launch a game.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GameSupport {
+    pub supported: bool,
+    /// Why the game is not supported, if it isn't.
+    pub reason: Option<String>,
+}
+
+impl GameSupport {
+    fn unsupported(reason: String) -> Self {
+        Self {
+            supported: false,
+            reason: Some(reason),
+        }
+    }
+}
+
+impl Game<'_> {
+    /// Returns the best score of `query` against the game's name and aliases.
+    pub fn search_score(&self, query: &str) -> Option<search::Score> {
+        std::iter::once(&self.name)
+            .chain(&self.aliases)
+            .filter_map(|name| search::score(query, name))
+            .max()
+    }
+
+    /// Determines whether the game is supported, based on which mod loaders and stores have
+    /// complete install and launch integrations.
+    pub fn support(&self) -> GameSupport {
+        if !matches!(
+            self.package_loader,
+            PackageLoader::BepInEx | PackageLoader::NorthStar
+        ) {
+            return GameSupport::unsupported(format!(
+                "The {} mod loader is not yet supported",
+                self.package_loader.as_str()
+            ));
+        }
+        match self.store_platform_metadata.first() {
+            Some(
+                StorePlatformMetadata::Steam { .. } | StorePlatformMetadata::SteamDirect { .. },
+            ) => {}
+            Some(store) => {
+                return GameSupport::unsupported(format!(
+                    "Launching games from {} is not yet supported",
+                    store.store_name()
+                ))
+            }
+            None => {
+                return GameSupport::unsupported("The game is not available on any store".into())
+            }
+        }
+        GameSupport {
+            supported: true,
+            reason: None,
+        }
+    }
+
+    /// Returns the metadata of the game on Steam, if it is available there.
+    pub fn steam_metadata(&self) -> Option<SteamMetadata> {
+        self.store_platform_metadata
+            .iter()
+            .find_map(StorePlatformMetadata::steam_or_direct)
+    }
+
+    /// Locates the directory the game is installed in by Steam, searching every Steam library.
+    /// Returns `None` if the game isn't on Steam, or isn't installed.
+    pub async fn find_steam_install_directory(&self) -> Result<Option<PathBuf>> {
+        match self.steam_metadata() {
+            Some(steam) => crate::stores::steam::paths::find_steam_app_install_directory(steam.id)
+                .await
+                .with_context(|| format!("Failed to locate {} in Steam libraries", self.name)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the stores the game is available on, in the order listed by Thunderstore.
+    pub fn store_platforms(&self) -> Vec<StorePlatform> {
+        self.store_platform_metadata
+            .iter()
+            .map(StorePlatform::from)
+            .collect()
+    }
+}
+
+/// A frontend-friendly summary of a store a game is available on.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorePlatform<'a> {
+    pub platform: &'static str,
+    pub store_identifier: Option<&'a str>,
+    pub store_url: Option<String>,
+}
+
+impl<'a> From<&'a StorePlatformMetadata<'_>> for StorePlatform<'a> {
+    fn from(value: &'a StorePlatformMetadata<'_>) -> Self {
+        Self {
+            platform: value.platform_name(),
+            store_identifier: value.store_identifier(),
+            store_url: value.store_url(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -146,6 +353,12 @@ pub enum StorePlatformMetadata<'a> {
         #[serde(rename = "storeIdentifier", borrow)]
         store_identifier: Cow<'a, str>,
     },
+    /// The identifier is GOG's numeric product id.
+    #[serde(alias = "GOG", alias = "GogGalaxy", alias = "GOGGalaxy")]
+    Gog {
+        #[serde(rename = "storeIdentifier", borrow)]
+        store_identifier: Cow<'a, str>,
+    },
     Oculus,
     Origin,
     Other,
@@ -156,7 +369,69 @@ pub struct SteamMetadata<'a> {
     pub id: &'a str,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct GogMetadata<'a> {
+    pub id: &'a str,
+}
+
 impl<'a> StorePlatformMetadata<'a> {
+    pub const fn store_name(&self) -> &'static str {
+        match self {
+            StorePlatformMetadata::Steam { .. } | StorePlatformMetadata::SteamDirect { .. } => {
+                "Steam"
+            }
+            StorePlatformMetadata::Epic { .. } => "the Epic Games Store",
+            StorePlatformMetadata::Xbox { .. } => "the Xbox app",
+            StorePlatformMetadata::Gog { .. } => "GOG",
+            StorePlatformMetadata::Oculus => "the Oculus store",
+            StorePlatformMetadata::Origin => "Origin",
+            StorePlatformMetadata::Other => "other stores",
+        }
+    }
+
+    /// Returns a short, user-facing name of the platform, without an article.
+    pub const fn platform_name(&self) -> &'static str {
+        match self {
+            StorePlatformMetadata::Steam { .. } | StorePlatformMetadata::SteamDirect { .. } => {
+                "Steam"
+            }
+            StorePlatformMetadata::Epic { .. } => "Epic Games Store",
+            StorePlatformMetadata::Xbox { .. } => "Xbox",
+            StorePlatformMetadata::Gog { .. } => "GOG",
+            StorePlatformMetadata::Oculus => "Oculus",
+            StorePlatformMetadata::Origin => "Origin",
+            StorePlatformMetadata::Other => "Other",
+        }
+    }
+
+    pub fn store_identifier(&self) -> Option<&str> {
+        match self {
+            StorePlatformMetadata::Steam { store_identifier }
+            | StorePlatformMetadata::SteamDirect { store_identifier }
+            | StorePlatformMetadata::Epic { store_identifier }
+            | StorePlatformMetadata::Xbox { store_identifier }
+            | StorePlatformMetadata::Gog { store_identifier } => Some(store_identifier.as_ref()),
+            StorePlatformMetadata::Oculus
+            | StorePlatformMetadata::Origin
+            | StorePlatformMetadata::Other => None,
+        }
+    }
+
+    /// Returns the URL of the game's store page, if one can be built from the store identifier.
+    ///
+    /// Epic and Xbox identifiers are a catalog namespace and a partial package family name
+    /// respectively, and GOG store pages are addressed by a slug rather than the product id, so
+    /// none of them is enough to address a store page.
+    pub fn store_url(&self) -> Option<String> {
+        match self {
+            StorePlatformMetadata::Steam { store_identifier }
+            | StorePlatformMetadata::SteamDirect { store_identifier } => Some(format!(
+                "https://store.steampowered.com/app/{store_identifier}/"
+            )),
+            _ => None,
+        }
+    }
+
     pub fn steam_or_direct(&self) -> Option<SteamMetadata> {
         match self {
             StorePlatformMetadata::Steam { store_identifier }
@@ -166,9 +441,28 @@ impl<'a> StorePlatformMetadata<'a> {
             _ => None,
         }
     }
+
+    pub fn gog(&self) -> Option<GogMetadata> {
+        match self {
+            StorePlatformMetadata::Gog { store_identifier } => Some(GogMetadata {
+                id: &store_identifier,
+            }),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, strum::EnumString)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+    strum::EnumString,
+    strum::VariantArray,
+)]
 pub enum PackageLoader {
     BepInEx,
     MelonLoader,
@@ -202,6 +496,74 @@ impl PackageLoader {
             GDWeave
         )
     }
+
+    /// Returns a top-level entry that only the loader's own package ships, by which the loader
+    /// can be told apart from the mods installed in a profile.
+    pub const fn package_marker(self) -> Option<&'static str> {
+        match self {
+            Self::BepInEx => Some("BepInExPack"),
+            Self::MelonLoader => Some("MelonLoader"),
+            Self::GDWeave => Some("GDWeave"),
+            Self::ReturnOfModding => Some("ReturnOfModdingPack"),
+            Self::NorthStar => Some("Northstar"),
+            Self::GodotML | Self::AncientDungeonVR | Self::ShimLoader | Self::Lovely => None,
+        }
+    }
+
+    /// Returns whether the loader can be switched off as a whole at launch, leaving the game
+    /// vanilla without touching any installed files.
+    pub const fn has_master_switch(self) -> bool {
+        // doorstop, which BepInEx is injected with, honours `DOORSTOP_ENABLED`, and the others
+        // take an argument to launch without mods
+        matches!(
+            self,
+            Self::BepInEx | Self::MelonLoader | Self::Lovely | Self::ReturnOfModding
+        )
+    }
+
+    /// Returns whether the loader loads mods in order of their file names rather than resolving
+    /// the order from their dependencies, so that the order can only be influenced by naming.
+    pub const fn loads_in_file_name_order(self) -> bool {
+        matches!(self, Self::MelonLoader)
+    }
+
+    /// Returns the directories, relative to the root of a package, in which the loader expects
+    /// packages to ship their configuration files.
+    pub const fn config_dirs(self) -> &'static [&'static str] {
+        match self {
+            Self::BepInEx => &["config", "BepInEx/config"],
+            Self::MelonLoader => &["UserData"],
+            Self::GDWeave => &["configs", "GDWeave/configs"],
+            Self::ReturnOfModding => &["config", "ReturnOfModding/config"],
+            Self::NorthStar
+            | Self::GodotML
+            | Self::AncientDungeonVR
+            | Self::ShimLoader
+            | Self::Lovely => &["config"],
+        }
+    }
+
+    /// Returns the top-level entries of a package archive that the loader needs in order to
+    /// work, and which therefore must never be excluded from an install.
+    pub const fn required_package_entries(self) -> &'static [&'static str] {
+        match self {
+            Self::BepInEx => &[
+                "manifest.json",
+                "BepInExPack",
+                "BepInEx",
+                "winhttp.dll",
+                "doorstop_config.ini",
+            ],
+            Self::MelonLoader => &["manifest.json", "MelonLoader", "version.dll"],
+            Self::GDWeave => &["manifest.json", "GDWeave", "winmm.dll"],
+            Self::ReturnOfModding => &["manifest.json", "ReturnOfModdingPack", "version.dll"],
+            Self::NorthStar
+            | Self::GodotML
+            | Self::AncientDungeonVR
+            | Self::ShimLoader
+            | Self::Lovely => &["manifest.json"],
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
@@ -209,3 +571,79 @@ pub enum InstanceType {
     Game,
     Server,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_user_games, Game, Platform};
+
+    #[test]
+    fn test_merge_user_games() {
+        let game = |id: &'static str, name: &'static str| {
+            serde_json::json!({
+                "id": id,
+                "name": name,
+                "exeNames": [],
+                "thunderstoreId": id,
+                "thunderstoreUrl": "",
+                "storePlatformMetadata": [],
+                "instanceType": "Game",
+                "packageLoader": "BepInEx",
+            })
+        };
+        // games borrow from their JSON
+        let parse = |value: serde_json::Value| {
+            serde_json::from_str::<Game>(value.to_string().leak()).unwrap()
+        };
+
+        let mut games = vec![parse(game("a", "A")), parse(game("b", "B"))];
+        merge_user_games(
+            &mut games,
+            vec![parse(game("b", "Custom B")), parse(game("c", "C"))],
+        );
+        let summary = games
+            .iter()
+            .map(|g| (g.id, &*g.name, g.user_defined))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [("a", "A", false), ("b", "Custom B", true), ("c", "C", true)]
+        );
+
+        let mut unknown = game("d", "D");
+        unknown["steamFolderName"] = "D".into();
+        assert!(serde_json::from_str::<Game>(&unknown.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_platform_of_executable() {
+        assert_eq!(Platform::of_executable("Game.exe"), Some(Platform::Windows));
+        assert_eq!(Platform::of_executable("Game.EXE"), Some(Platform::Windows));
+        assert_eq!(
+            Platform::of_executable("Game.x86_64"),
+            Some(Platform::Linux)
+        );
+        assert_eq!(Platform::of_executable("Game.app"), Some(Platform::MacOS));
+        assert_eq!(Platform::of_executable("Game"), None);
+    }
+
+    #[test]
+    fn test_search_score() {
+        let game = serde_json::from_str::<Game>(
+            r#"{
+                "id": "lethal-company",
+                "name": "Lethal Company",
+                "aliases": ["LC"],
+                "exeNames": [],
+                "thunderstoreId": "lethal-company",
+                "thunderstoreUrl": "",
+                "storePlatformMetadata": [],
+                "instanceType": "Game",
+                "packageLoader": "BepInEx"
+            }"#,
+        )
+        .unwrap();
+        assert!(game.search_score("lethal").is_some());
+        assert!(game.search_score("lc").is_some());
+        assert!(game.search_score("ror2").is_none());
+    }
+}
@@ -0,0 +1,334 @@
+//! A global limit on how much network and CPU-bound work runs at once.
+//!
+//! Downloads, hashing, extraction, and searching are started independently by many subsystems,
+//! so without a shared limit, importing a large modpack while searching could start hundreds of
+//! downloads and keep every core busy at once. Each of them instead acquires a permit from here
+//! for the duration of the work.
+//!
+//! The defaults are derived from the machine:
+//!
+//! | Resource | Default                                                                   |
+//! |----------|---------------------------------------------------------------------------|
+//! | Network  | 8 concurrent requests, or 3 with less than 4 GiB of memory                |
+//! | CPU      | one task per available core, leaving one core free, and at least one task |
+//!
+//! Memory is only detected on Linux. Both limits can be overridden in the settings, and changes
+//! take effect immediately, although lowering a limit waits for work in progress to finish.
+//!
+//! Bulk operations that make many requests to Thunderstore, like fetching the changelogs of every
+//! mod being updated, additionally go through a [`Throttle`], which lowers their concurrency when
+//! Thunderstore starts rejecting requests with `429 Too Many Requests`.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use slog::{info, warn};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::util::http::error_status;
+
+const DEFAULT_NETWORK_LIMIT: usize = 8;
+const LOW_MEMORY_NETWORK_LIMIT: usize = 3;
+const LOW_MEMORY_THRESHOLD: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Returns the total memory of the machine in bytes, if it can be determined.
+fn total_memory() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kib = line
+        .trim_start_matches("MemTotal:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+fn default_network_limit() -> usize {
+    if total_memory().is_some_and(|total| total < LOW_MEMORY_THRESHOLD) {
+        LOW_MEMORY_NETWORK_LIMIT
+    } else {
+        DEFAULT_NETWORK_LIMIT
+    }
+}
+
+fn default_cpu_limit() -> usize {
+    std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .saturating_sub(1)
+        .max(1)
+}
+
+struct Limit {
+    semaphore: Semaphore,
+    state: Mutex<LimitState>,
+    /// Permits that must be forgotten as they are released to reach a lowered limit.
+    debt: AtomicUsize,
+}
+
+struct LimitState {
+    limit: usize,
+    default: usize,
+}
+
+impl Limit {
+    fn new(default: usize) -> Self {
+        Self {
+            semaphore: Semaphore::new(default),
+            state: Mutex::new(LimitState {
+                limit: default,
+                default,
+            }),
+            debt: AtomicUsize::new(0),
+        }
+    }
+
+    fn set(&self, limit: Option<u32>) {
+        let mut state = self.state.lock();
+        let limit = limit
+            .and_then(|n| usize::try_from(n).ok())
+            .filter(|&n| n != 0)
+            .unwrap_or(state.default);
+        if limit > state.limit {
+            let mut added = limit - state.limit;
+            // pay off outstanding debt before adding permits
+            let paid = self
+                .debt
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |debt| {
+                    Some(debt.saturating_sub(added))
+                })
+                .unwrap();
+            added -= paid.min(added);
+            self.semaphore.add_permits(added);
+        } else {
+            let removed = state.limit - limit;
+            let forgotten = self.semaphore.forget_permits(removed);
+            self.debt.fetch_add(removed - forgotten, Ordering::AcqRel);
+        }
+        state.limit = limit;
+    }
+
+    async fn acquire(&self) -> Permit<'_> {
+        Permit {
+            permit: Some(
+                self.semaphore
+                    .acquire()
+                    .await
+                    .expect("governor semaphores are never closed"),
+            ),
+            limit: self,
+        }
+    }
+}
+
+#[must_use]
+pub struct Permit<'a> {
+    permit: Option<SemaphorePermit<'a>>,
+    limit: &'a Limit,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+        let in_debt = self
+            .limit
+            .debt
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |debt| {
+                debt.checked_sub(1)
+            })
+            .is_ok();
+        if in_debt {
+            permit.forget();
+        }
+    }
+}
+
+static NETWORK: LazyLock<Limit> = LazyLock::new(|| Limit::new(default_network_limit()));
+static CPU: LazyLock<Limit> = LazyLock::new(|| Limit::new(default_cpu_limit()));
+
+/// Waits for a permit to make a network request. Hold it until the response has been read.
+pub async fn network() -> Permit<'static> {
+    NETWORK.acquire().await
+}
+
+/// Waits for a permit to do CPU-bound work, like hashing, extracting, or searching.
+pub async fn cpu() -> Permit<'static> {
+    CPU.acquire().await
+}
+
+/// Applies the limits from the settings. `None` or zero restores the default.
+pub fn configure(network: Option<u32>, cpu: Option<u32>) {
+    NETWORK.set(network);
+    CPU.set(cpu);
+}
+
+/// How long a [`Throttle`] must go without being rate limited before its limit is raised again.
+pub const DEFAULT_THROTTLE_COOLDOWN: Duration = Duration::from_secs(30);
+/// How many times a [`Throttle`] makes a request that keeps being rate limited.
+pub const DEFAULT_THROTTLE_ATTEMPTS: u32 = 5;
+/// The delay before the first retry of a rate limited request. It doubles with every attempt.
+const THROTTLE_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Rate limiting reported within this long of the last reduction is assumed to be caused by the
+/// same burst of requests, and doesn't lower the limit again.
+const THROTTLE_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Concurrency limit of a single bulk operation that adapts to rate limiting.
+///
+/// Whenever a request is rejected with `429 Too Many Requests`, the limit is halved and the
+/// request is retried after an exponentially increasing delay. Once no request has been rate
+/// limited for the cooldown, the limit is raised by one again for every successful request, up
+/// to the initial limit.
+pub struct Throttle {
+    name: String,
+    limit: Limit,
+    state: Mutex<ThrottleState>,
+    cooldown: Duration,
+    max_attempts: u32,
+}
+
+struct ThrottleState {
+    /// When the limit was last lowered.
+    throttled_at: Option<Instant>,
+    /// When the limit was last changed in either direction.
+    changed_at: Instant,
+}
+
+impl Throttle {
+    /// Creates a throttle for the operation described by `name`, starting out with the current
+    /// network limit.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            limit: Limit::new(NETWORK.state.lock().limit),
+            state: Mutex::new(ThrottleState {
+                throttled_at: None,
+                changed_at: Instant::now(),
+            }),
+            cooldown: DEFAULT_THROTTLE_COOLDOWN,
+            max_attempts: DEFAULT_THROTTLE_ATTEMPTS,
+        }
+    }
+
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    fn current_limit(&self) -> usize {
+        self.limit.state.lock().limit
+    }
+
+    fn on_rate_limited(&self, log: &slog::Logger) {
+        let mut state = self.state.lock();
+        let now = Instant::now();
+        if state
+            .throttled_at
+            .is_some_and(|t| now.duration_since(t) < THROTTLE_SETTLE_TIME)
+        {
+            return;
+        }
+        let limit = (self.current_limit() / 2).max(1);
+        self.limit.set(u32::try_from(limit).ok());
+        state.throttled_at = Some(now);
+        state.changed_at = now;
+        warn!(
+            log,
+            "Rate limited by Thunderstore, lowering concurrency of {} to {limit}", self.name
+        );
+    }
+
+    fn on_success(&self, log: &slog::Logger) {
+        let mut state = self.state.lock();
+        let Some(throttled_at) = state.throttled_at else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(throttled_at) < self.cooldown
+            || now.duration_since(state.changed_at) < self.cooldown
+        {
+            return;
+        }
+        let limit = self.current_limit() + 1;
+        self.limit.set(u32::try_from(limit).ok());
+        state.changed_at = now;
+        if self.current_limit() >= self.limit.state.lock().default {
+            state.throttled_at = None;
+            info!(log, "Concurrency of {} is back to {limit}", self.name);
+        } else {
+            info!(log, "Raising concurrency of {} to {limit}", self.name);
+        }
+    }
+
+    /// Runs `f` once a permit is available, retrying it while it fails with `429 Too Many
+    /// Requests`, up to the maximum number of attempts.
+    pub async fn run<T, F, Fut>(&self, log: &slog::Logger, mut f: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.limit.acquire().await;
+                f().await
+            };
+            attempt += 1;
+            match result {
+                Err(e)
+                    if attempt < self.max_attempts
+                        && error_status(&e) == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) =>
+                {
+                    self.on_rate_limited(log);
+                    let delay = THROTTLE_BASE_DELAY * 2u32.pow(attempt - 1);
+                    // spread out retries of requests that were rejected together
+                    let jitter = delay.mul_f64(fastrand::f64() * 0.5);
+                    tokio::time::sleep(delay + jitter).await;
+                }
+                Err(e) => return Err(e),
+                Ok(value) => {
+                    self.on_success(log);
+                    return Ok(value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Throttle;
+
+    #[test]
+    fn test_throttle() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let throttle = Throttle::new("test").cooldown(Duration::ZERO);
+        let initial = throttle.current_limit();
+
+        throttle.on_rate_limited(&log);
+        assert_eq!(throttle.current_limit(), (initial / 2).max(1));
+        // part of the same burst
+        throttle.on_rate_limited(&log);
+        assert_eq!(throttle.current_limit(), (initial / 2).max(1));
+
+        for _ in 0..initial {
+            throttle.on_success(&log);
+        }
+        assert_eq!(throttle.current_limit(), initial);
+    }
+}
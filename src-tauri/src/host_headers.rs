@@ -0,0 +1,149 @@
+//! Extra HTTP headers, such as `Authorization`, to send to specific hosts. This allows mod indexes
+//! and packages to be fetched from private mirrors and CDNs.
+//!
+//! Headers are configured by hand in `host_headers.json` in the config directory:
+//!
+//! ```json
+//! { "mods.example.com": { "Authorization": "Bearer <token>" } }
+//! ```
+//!
+//! Since the file holds credentials, it should only be readable by the current user. Header
+//! values are marked as sensitive so that reqwest redacts them from its debug output, must never
+//! be logged, and are only ever sent over HTTPS. Requests carrying them only follow redirects
+//! within the same origin, so that they never reach another host.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::{Context as _, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::redirect;
+use slog::{error, warn};
+use url::Url;
+
+use crate::paths::config_dir;
+use crate::util::IoErrorKindExt as _;
+
+const FILE_NAME: &str = "host_headers.json";
+
+static HOST_HEADERS: LazyLock<HashMap<String, HeaderMap>> = LazyLock::new(|| {
+    let path = config_dir().join(FILE_NAME);
+    match read(&path) {
+        Ok(t) => t,
+        Err(e) => {
+            error!(slog_scope::logger(), "Unable to read {path:?}: {e:#}");
+            HashMap::new()
+        }
+    }
+});
+
+/// The client for requests carrying configured headers. reqwest only strips the headers it knows
+/// to be sensitive, like `Authorization`, when redirected to another host, so redirects that
+/// leave the origin fail instead.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .gzip(true)
+        .brotli(true)
+        .redirect(redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() > 10 {
+                attempt.error("too many redirects")
+            } else if attempt
+                .previous()
+                .last()
+                .is_some_and(|prev| is_same_origin(prev, attempt.url()))
+            {
+                attempt.follow()
+            } else {
+                let error = format!(
+                    "refusing to send configured headers along a redirect to {}",
+                    attempt.url().origin().ascii_serialization()
+                );
+                attempt.error(error)
+            }
+        }))
+        .build()
+        .expect("Failed to build HTTP client")
+});
+
+fn is_same_origin(a: &Url, b: &Url) -> bool {
+    a.origin() == b.origin()
+}
+
+fn read(path: &Path) -> Result<HashMap<String, HeaderMap>> {
+    let bytes = match std::fs::read(path) {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        if std::fs::metadata(path)?.permissions().mode() & 0o077 != 0 {
+            warn!(
+                slog_scope::logger(),
+                "{path:?} is accessible by other users. Consider running `chmod 600` on it."
+            );
+        }
+    }
+
+    let raw = serde_json::from_slice::<HashMap<String, HashMap<String, String>>>(&bytes)
+        .context("Invalid host headers file")?;
+    let mut host_headers = HashMap::with_capacity(raw.len());
+    for (host, headers) in raw {
+        let mut map = HeaderMap::with_capacity(headers.len());
+        for (name, value) in headers {
+            let name = HeaderName::try_from(name)
+                .with_context(|| format!("Invalid header name for host {host:?}"))?;
+            // the error deliberately doesn't include the value
+            let mut value = HeaderValue::try_from(value)
+                .with_context(|| format!("Invalid value for header {name} of host {host:?}"))?;
+            value.set_sensitive(true);
+            map.insert(name, value);
+        }
+        host_headers.insert(host.to_ascii_lowercase(), map);
+    }
+    Ok(host_headers)
+}
+
+/// Returns the client to send requests carrying the headers from [`headers_for`] with.
+pub fn client() -> &'static reqwest::Client {
+    &CLIENT
+}
+
+/// Returns the headers configured for the host of `url`, if any.
+pub fn headers_for(url: &str) -> Option<&'static HeaderMap> {
+    if HOST_HEADERS.is_empty() {
+        return None;
+    }
+    let url = Url::parse(url).ok()?;
+    let headers = HOST_HEADERS.get(&url.host_str()?.to_ascii_lowercase())?;
+    if url.scheme() != "https" {
+        warn!(
+            slog_scope::logger(),
+            "Not sending configured headers to {:?} over insecure {:?}",
+            url.host_str(),
+            url.scheme()
+        );
+        return None;
+    }
+    Some(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::is_same_origin;
+
+    #[test]
+    fn test_is_same_origin() {
+        let origin = Url::parse("https://mods.example.com/a.zip").unwrap();
+        let same_origin = |url: &str| is_same_origin(&origin, &Url::parse(url).unwrap());
+        assert!(same_origin("https://mods.example.com:443/b.zip"));
+        assert!(!same_origin("http://mods.example.com/a.zip"));
+        assert!(!same_origin("https://cdn.example.com/a.zip"));
+        assert!(!same_origin("https://mods.example.com:8443/a.zip"));
+    }
+}
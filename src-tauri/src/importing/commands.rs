@@ -1,16 +1,19 @@
 use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use anyhow::{anyhow, bail, Context};
 use futures::stream::FuturesUnordered;
 use futures::TryStreamExt;
 use serde::Serialize;
-use tauri::ipc::{Channel, InvokeResponseBody};
+use slog::warn;
+use tauri::ipc::Channel;
 use tauri::{AppHandle, State};
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use uuid::Uuid;
 
 use crate::mod_index::fetch_mod_index;
-use crate::mods::{ModId, ModMetadata, ModVersion, Version};
+use crate::mods::{ModId, Version};
 use crate::profiles::profile_path;
 use crate::tasks::{TaskBuilder, TaskError, TaskHandle};
 use crate::{tasks, CommandError, Reqwest};
@@ -22,6 +25,8 @@ pub struct Modpack {
     pub name: String,
     pub mods: Vec<ModSpec>,
     pub diff: Vec<PathDiff>,
+    /// The mods that can't be installed. See [`thunderstore::resolve_profile_mods`].
+    pub unresolved: Vec<thunderstore::UnresolvedMod>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -59,9 +64,18 @@ pub async fn preview_import_modpack_from_thunderstore_code(
         thunderstore::lookup_profile(Some(&app), &log, &reqwest, thunderstore_id, Some(task_id))
             .await?;
 
+    fetch_mod_index(&app, game, false, None).await?;
+    let unresolved = {
+        let mod_index = crate::mod_index::read_mod_index(game).await?;
+        thunderstore::resolve_profile_mods(&mod_index, &profile.manifest).await?
+    };
+
     let mut mods = Vec::with_capacity(profile.manifest.mods.len());
 
     for m in profile.manifest.mods {
+        if m.install_source.is_some() {
+            continue;
+        }
         let (namespace, name) = m.full_name.components();
         mods.push_within_capacity(ModSpec::Online {
             url: format!(
@@ -100,13 +114,26 @@ pub async fn preview_import_modpack_from_thunderstore_code(
         name: profile.manifest.profile_name,
         mods,
         diff,
+        unresolved,
     })
 }
 
+/// The progress of a modpack import, from resolving its contents to the finished profile.
 #[derive(Clone, serde::Serialize)]
-struct ModProgressRegistration<'a> {
-    url: &'a str,
-    task: tasks::Id,
+#[serde(tag = "type")]
+pub enum ProfileInstallProgress {
+    /// The mod index and the modpack's contents are being fetched.
+    Resolving,
+    /// The modpack's mods are being downloaded and installed.
+    InstallingMods { total: usize },
+    /// Installation of the mod at `url` has started. Its progress is reported by `task`.
+    ModStarted { url: String, task: tasks::Id },
+    /// The mod at `url` has been installed, making it the `completed`th mod to finish.
+    ModInstalled { url: String, completed: usize },
+    /// The modpack's own files, such as configs, are being extracted into the profile.
+    ExtractingFiles { total: usize },
+    /// The modpack has been imported into `profile`.
+    Finished { profile: Uuid },
 }
 
 #[tauri::command]
@@ -116,71 +143,172 @@ pub async fn import_modpack_from_thunderstore_code(
     thunderstore_id: Uuid,
     game: &str,
     profile_id: Option<Uuid>,
-    // ModProgressRegistration, but can't express the lifetime
-    mod_progress_channel: Channel<InvokeResponseBody>,
+    progress_channel: Channel<ProfileInstallProgress>,
+    task_id: tasks::Id,
+) -> Result<Uuid, CommandError> {
+    import_modpack(
+        &app,
+        &reqwest,
+        ModpackSource::Thunderstore(thunderstore_id),
+        game,
+        profile_id,
+        progress_channel,
+        task_id,
+    )
+    .await
+}
+
+/// Imports a modpack from a profile exported to a file by r2modman or Gale.
+#[tauri::command]
+pub async fn import_modpack_from_file(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    path: PathBuf,
+    game: &str,
+    profile_id: Option<Uuid>,
+    progress_channel: Channel<ProfileInstallProgress>,
+    task_id: tasks::Id,
+) -> Result<Uuid, CommandError> {
+    import_modpack(
+        &app,
+        &reqwest,
+        ModpackSource::File(path),
+        game,
+        profile_id,
+        progress_channel,
+        task_id,
+    )
+    .await
+}
+
+enum ModpackSource {
+    Thunderstore(Uuid),
+    File(PathBuf),
+}
+
+impl std::fmt::Display for ModpackSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Thunderstore(id) => write!(f, "thunderstore:{id}"),
+            Self::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+async fn import_modpack(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    source: ModpackSource,
+    game: &str,
+    profile_id: Option<Uuid>,
+    progress_channel: Channel<ProfileInstallProgress>,
     task_id: tasks::Id,
 ) -> Result<Uuid, CommandError> {
     if profile_id.is_some() {
         return Err(anyhow!("Importing over existing profiles is not yet supported").into());
     }
 
-    let app = &app;
     let log = slog_scope::logger();
 
-    TaskBuilder::with_id(
-        task_id,
-        format!("Import modpack thunderstore:{thunderstore_id}"),
-    )
-    .kind(tasks::Kind::Aggregate)
-    .progress_unit(tasks::ProgressUnit::Bytes)
-    .run_with_handle(Some(app), |handle| async move {
-        fetch_mod_index(app, game, false, Some(handle.allocate_dependency(app)?)).await?;
-
-        _ = profile_id;
-        let profile = {
-            thunderstore::lookup_profile(
-                Some(app),
-                &log,
-                &reqwest,
-                thunderstore_id,
-                Some(handle.allocate_dependency(app)?),
+    TaskBuilder::with_id(task_id, format!("Import modpack {source}"))
+        .kind(tasks::Kind::Aggregate)
+        .progress_unit(tasks::ProgressUnit::Bytes)
+        .run_with_handle(Some(app), |handle| async move {
+            progress_channel.send(ProfileInstallProgress::Resolving)?;
+
+            fetch_mod_index(app, game, false, Some(handle.allocate_dependency(app)?)).await?;
+
+            _ = profile_id;
+            let profile = match source {
+                ModpackSource::Thunderstore(thunderstore_id) => {
+                    thunderstore::lookup_profile(
+                        Some(app),
+                        &log,
+                        reqwest,
+                        thunderstore_id,
+                        Some(handle.allocate_dependency(app)?),
+                    )
+                    .await?
+                }
+                ModpackSource::File(path) => thunderstore::import_profile_from_file(&path).await?,
+            };
+
+            // fail before creating the profile rather than part way through installing mods
+            let unresolved = {
+                let mod_index = crate::mod_index::read_mod_index(game).await?;
+                thunderstore::resolve_profile_mods(&mod_index, &profile.manifest).await?
+            };
+            if !unresolved.is_empty() {
+                let list = unresolved
+                    .iter()
+                    .map(|m| format!("{} {} ({:?})", m.full_name, m.version, m.problem))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(anyhow!("Some mods of the modpack can't be installed: {list}").into());
+            }
+
+            let (profile_id, is_new_profile) = match profile_id {
+                Some(profile_id) => (profile_id, false),
+                None => (
+                    crate::profiles::create_profile(
+                        game.into(),
+                        profile.manifest.profile_name.as_str().into(),
+                    )
+                    .await?,
+                    true,
+                ),
+            };
+
+            if let Err(e) = import_onto_profile(
+                app,
+                reqwest,
+                game,
+                profile,
+                profile_id,
+                &progress_channel,
+                handle,
             )
-            .await?
-        };
+            .await
+            {
+                if is_new_profile {
+                    crate::profiles::delete_profile(profile_id).await?;
+                }
+                return Err(e.into());
+            }
 
-        let (profile_id, is_new_profile) = match profile_id {
-            Some(profile_id) => (profile_id, false),
-            None => (
-                crate::profiles::create_profile(
-                    game.into(),
-                    profile.manifest.profile_name.as_str().into(),
-                )
-                .await?,
-                true,
-            ),
-        };
+            progress_channel.send(ProfileInstallProgress::Finished {
+                profile: profile_id,
+            })?;
 
-        if let Err(e) = import_onto_profile(
-            &app,
-            &*reqwest,
-            game,
-            profile,
-            profile_id,
-            mod_progress_channel,
-            handle,
-        )
+            Ok(profile_id)
+        })
         .await
-        {
-            if is_new_profile {
-                crate::profiles::delete_profile(profile_id).await?;
-            }
-            return Err(e.into());
-        }
+        .map_err(|e: TaskError<anyhow::Error>| anyhow::Error::from(e).into())
+}
 
-        Ok(profile_id)
-    })
+/// Like [`import_modpack_from_thunderstore_code`], but accepts a Thunderstore share URL in
+/// addition to the bare code.
+#[tauri::command]
+pub async fn import_modpack_from_thunderstore_url(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    url: &str,
+    game: &str,
+    profile_id: Option<Uuid>,
+    progress_channel: Channel<ProfileInstallProgress>,
+    task_id: tasks::Id,
+) -> Result<Uuid, CommandError> {
+    let thunderstore_id = thunderstore::parse_profile_code(url)?;
+    import_modpack_from_thunderstore_code(
+        app,
+        reqwest,
+        thunderstore_id,
+        game,
+        profile_id,
+        progress_channel,
+        task_id,
+    )
     .await
-    .map_err(|e: TaskError<anyhow::Error>| anyhow::Error::from(e).into())
 }
 
 async fn import_onto_profile(
@@ -189,17 +317,33 @@ async fn import_onto_profile(
     game: &str,
     profile: crate::importing::thunderstore::Profile,
     profile_id: Uuid,
-    mod_progress_channel: Channel<InvokeResponseBody>,
+    progress_channel: &Channel<ProfileInstallProgress>,
     handle: TaskHandle,
 ) -> Result<(), anyhow::Error> {
-    let mod_progress_channel = &mod_progress_channel;
-    profile
+    let log = slog_scope::logger();
+
+    // mods installed from elsewhere can't be fetched from Thunderstore
+    let mods = profile
         .manifest
         .mods
         .iter()
+        .filter(|m| {
+            if let Some(source) = &m.install_source {
+                warn!(
+                    log,
+                    "Skipping {}, which was installed from {source:?}", m.full_name
+                );
+            }
+            m.install_source.is_none()
+        })
+        .collect::<Vec<_>>();
+
+    progress_channel.send(ProfileInstallProgress::InstallingMods { total: mods.len() })?;
+    let completed = &AtomicUsize::new(0);
+    mods.into_iter()
         .map(|m| {
             async move {
-                let version = Version::try_from(m.version).context("Invalid version")?;
+                let version = Version::try_from(&m.version).context("Invalid version")?;
 
                 let mut mod_id_set = HashSet::with_capacity(1);
                 mod_id_set.insert(ModId {
@@ -228,60 +372,41 @@ async fn import_onto_profile(
                 };
 
                 let sub_task_id = handle.allocate_dependency(app)?;
-                mod_progress_channel.send(
-                    serde_json::to_string(&ModProgressRegistration {
-                        url: &format!(
-                            "https://gcdn.thunderstore.io/live/repository/packages/{}-{}-{}.zip",
-                            &*m.owner, &*m.name, version.version_number
-                        ),
-                        task: sub_task_id,
-                    })?
-                    .into(),
-                )?;
+                let url = format!(
+                    "https://gcdn.thunderstore.io/live/repository/packages/{}-{}-{}.zip",
+                    &*m.owner, &*m.name, version.version_number
+                );
+                progress_channel.send(ProfileInstallProgress::ModStarted {
+                    url: url.clone(),
+                    task: sub_task_id,
+                })?;
                 crate::profiles::install_profile_mod(
                     app,
                     reqwest,
                     profile_id,
-                    // this is kinda gross
-                    ModMetadata {
-                        name: &m.metadata.name,
-                        full_name: Default::default(),
-                        owner: &m.metadata.owner,
-                        package_url: Default::default(),
-                        donation_link: m.metadata.donation_link.as_ref().map(|s| (**s).into()),
-                        date_created: m.date_created.into(),
-                        date_updated: m.date_updated.into(),
-                        rating_score: m.rating_score.into(),
-                        is_pinned: m.is_pinned,
-                        is_deprecated: m.is_deprecated,
-                        has_nsfw_content: m.has_nsfw_content,
-                        categories: m.categories.iter().map(|s| (**s).into()).collect(),
-                        uuid4: Default::default(),
-                    },
-                    ModVersion {
-                        name: Default::default(),
-                        full_name: Default::default(),
-                        description: (*version.description).into(),
-                        icon: Default::default(),
-                        version_number: version.version_number.get(),
-                        dependencies: version.dependencies.iter().map(|s| (**s).into()).collect(),
-                        download_url: Default::default(),
-                        downloads: version.downloads.into(),
-                        date_created: version.date_created.into(),
-                        website_url: version.website_url.as_ref().map(|s| (**s).into()),
-                        is_active: version.is_active,
-                        uuid4: Default::default(),
-                        file_size: version.file_size.into(),
-                    },
+                    (&m.metadata).into(),
+                    version.into(),
+                    &[],
+                    // the modpack's author chose its mods together
+                    true,
                     Some(sub_task_id),
                 )
-                .await
+                .await?;
+                progress_channel.send(ProfileInstallProgress::ModInstalled {
+                    url,
+                    completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+                })?;
+                Ok::<_, anyhow::Error>(())
             }
         })
         .collect::<FuturesUnordered<_>>()
         .try_collect::<()>()
         .await?;
 
+    progress_channel.send(ProfileInstallProgress::ExtractingFiles {
+        total: profile.archive.len(),
+    })?;
+
     let profile_path = profile_path(profile_id);
 
     let rt = tokio::runtime::Handle::current();
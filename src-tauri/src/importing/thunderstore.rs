@@ -2,19 +2,22 @@
 
 use std::{
     borrow::Cow,
+    collections::HashSet,
     io::Read,
     ops::Deref,
     path::{Path, PathBuf},
 };
 
-use anyhow::{bail, ensure, Context, Result};
-use base64::prelude::BASE64_STANDARD;
+use anyhow::{ensure, Context, Result};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
 use serde::{Deserialize, Serialize};
 use tauri::AppHandle;
 use triomphe::Arc;
 use uuid::Uuid;
 use zip::read::ZipFile;
 
+use crate::mod_index::{get_from_mod_index, ModIndexReadGuard};
+use crate::mods::ModId;
 use crate::Reqwest;
 use crate::{installing::fetch_resource_as_bytes, profiles::MODS_FOLDER, tasks};
 
@@ -85,12 +88,7 @@ impl<'de> serde::Deserialize<'de> for FullName {
             where
                 E: serde::de::Error,
             {
-                let split = v.find('-').ok_or_else(|| {
-                    E::invalid_value(
-                        serde::de::Unexpected::Str(v),
-                        &"a hyphen separated namespace and name",
-                    )
-                })?;
+                let split = find_split(v)?;
                 Ok(FullName {
                     value: v.to_owned(),
                     split,
@@ -101,16 +99,23 @@ impl<'de> serde::Deserialize<'de> for FullName {
             where
                 E: serde::de::Error,
             {
-                let split = v.find('-').ok_or_else(|| {
-                    E::invalid_value(
-                        serde::de::Unexpected::Str(&v),
-                        &"a hyphen separated namespace and name",
-                    )
-                })?;
+                let split = find_split(&v)?;
                 Ok(FullName { value: v, split })
             }
         }
 
+        /// Returns the index of the first hyphen, which must have a non-empty namespace before it
+        /// and a non-empty name after it.
+        fn find_split<E: serde::de::Error>(v: &str) -> std::result::Result<usize, E> {
+            match v.find('-') {
+                Some(split) if split != 0 && split + 1 != v.len() => Ok(split),
+                _ => Err(E::invalid_value(
+                    serde::de::Unexpected::Str(v),
+                    &"a hyphen separated namespace and name, both of which must be non-empty",
+                )),
+            }
+        }
+
         deserializer.deserialize_string(Visitor)
     }
 }
@@ -124,25 +129,171 @@ impl serde::Serialize for FullName {
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+/// A semantic version, as found in profile manifests. It is deserialized from either an object
+/// with numeric `major`, `minor`, and `patch` fields and optional `prerelease` and `build`
+/// strings, or a version string.
+///
+/// Comparisons follow semver precedence, so versions that only differ in build metadata are
+/// equal.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "VersionRepr")]
 pub struct Version {
     pub major: u64,
     pub minor: u64,
     pub patch: u64,
+    pub pre: semver::Prerelease,
+    pub build: semver::BuildMetadata,
+}
+
+impl Version {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre: semver::Prerelease::EMPTY,
+            build: semver::BuildMetadata::EMPTY,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VersionRepr {
+    Object {
+        major: u64,
+        minor: u64,
+        patch: u64,
+        #[serde(default)]
+        prerelease: String,
+        #[serde(default)]
+        build: String,
+    },
+    String(String),
+}
+
+impl TryFrom<VersionRepr> for Version {
+    type Error = semver::Error;
+
+    fn try_from(value: VersionRepr) -> Result<Self, Self::Error> {
+        match value {
+            VersionRepr::Object {
+                major,
+                minor,
+                patch,
+                prerelease,
+                build,
+            } => Ok(Self {
+                pre: semver::Prerelease::new(&prerelease)?,
+                build: semver::BuildMetadata::new(&build)?,
+                ..Self::new(major, minor, patch)
+            }),
+            VersionRepr::String(s) => s.parse(),
+        }
+    }
+}
+
+impl std::str::FromStr for Version {
+    type Err = semver::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let semver::Version {
+            major,
+            minor,
+            patch,
+            pre,
+            build,
+        } = s.parse()?;
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            pre,
+            build,
+        })
+    }
+}
+
+impl serde::Serialize for Version {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct as _;
+
+        let mut ser = serializer.serialize_struct("Version", 5)?;
+        ser.serialize_field("major", &self.major)?;
+        ser.serialize_field("minor", &self.minor)?;
+        ser.serialize_field("patch", &self.patch)?;
+        if self.pre.is_empty() {
+            ser.skip_field("prerelease")?;
+        } else {
+            ser.serialize_field("prerelease", self.pre.as_str())?;
+        }
+        if self.build.is_empty() {
+            ser.skip_field("build")?;
+        } else {
+            ser.serialize_field("build", self.build.as_str())?;
+        }
+        ser.end()
+    }
 }
 
 impl std::fmt::Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre)?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
     }
 }
 
-impl TryFrom<Version> for crate::mods::Version {
-    type Error = crate::mods::TooManyBitsError;
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-    fn try_from(value: Version) -> Result<Self, Self::Error> {
-        Self::new(value.major, value.minor, value.patch)
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.major
+            .cmp(&other.major)
+            .then(self.minor.cmp(&other.minor))
+            .then(self.patch.cmp(&other.patch))
+            // a version without a prerelease is greater than one with
+            .then_with(|| self.pre.cmp(&other.pre))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UnsupportedVersionError {
+    #[error("Prerelease versions are not supported")]
+    Prerelease,
+    #[error(transparent)]
+    TooManyBits(#[from] crate::mods::TooManyBitsError),
+}
+
+impl TryFrom<&Version> for crate::mods::Version {
+    type Error = UnsupportedVersionError;
+
+    /// Build metadata is dropped, since it doesn't affect precedence.
+    fn try_from(value: &Version) -> Result<Self, Self::Error> {
+        if !value.pre.is_empty() {
+            return Err(UnsupportedVersionError::Prerelease);
+        }
+        Ok(Self::new(value.major, value.minor, value.patch)?)
     }
 }
 
@@ -155,8 +306,14 @@ pub struct Profile {
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProfileManifest {
+    /// The version of the manifest's schema, which manifests from before it was versioned lack.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
     pub profile_name: String,
     pub mods: Vec<ProfileMod>,
+    /// Fields not known to us, kept so that exporting an imported profile doesn't lose them.
+    #[serde(flatten)]
+    pub extra: serde_yaml::Mapping,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -166,12 +323,52 @@ pub struct ProfileMod {
     #[serde(alias = "versionNumber")]
     pub version: Version,
     pub enabled: bool,
+    /// Where the mod was installed from, if not from Thunderstore. Its format is up to the
+    /// exporting mod manager.
+    #[serde(
+        rename = "installSource",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub install_source: Option<serde_yaml::Value>,
+    /// Fields not known to us, like `group`, kept for the same reason as
+    /// [`ProfileManifest::extra`].
+    #[serde(flatten)]
+    pub extra: serde_yaml::Mapping,
 }
 
 const R2_PROFILE_DATA_PREFIX: &str = "#r2modman\n";
 
+/// The signature at the start of a zip archive's first local file header.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
 pub const R2_PROFILE_MANIFEST_FILE_NAME: &str = "export.r2x";
 
+/// Extracts a profile id from a code entered by the user, which may be either the bare id or a
+/// Thunderstore URL ending with the id, such as the legacy profile API URL.
+pub fn parse_profile_code(input: &str) -> Result<Uuid> {
+    let input = input.trim();
+    if let Ok(id) = Uuid::try_parse(input) {
+        return Ok(id);
+    }
+    let url =
+        url::Url::parse(input).with_context(|| format!("Not a profile code or URL: {input:?}"))?;
+    ensure!(
+        matches!(url.scheme(), "http" | "https"),
+        "Unsupported URL scheme: {:?}",
+        url.scheme()
+    );
+    ensure!(
+        url.host_str()
+            .is_some_and(|host| host == "thunderstore.io" || host.ends_with(".thunderstore.io")),
+        "Not a Thunderstore URL: {input:?}"
+    );
+    url.path_segments()
+        .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+        .and_then(|segment| Uuid::try_parse(segment).ok())
+        .with_context(|| format!("Unrecognized Thunderstore profile URL: {input:?}"))
+}
+
 pub async fn lookup_profile(
     app: Option<&AppHandle>,
     log: &slog::Logger,
@@ -189,30 +386,147 @@ pub async fn lookup_profile(
     )
     .await?;
 
-    tokio::task::block_in_place(move || {
-        let Some((prefix, bytes)) = bytes.split_at_checked(R2_PROFILE_DATA_PREFIX.len()) else {
-            bail!("Invalid profile data")
-        };
-        ensure!(
-            prefix == R2_PROFILE_DATA_PREFIX.as_bytes(),
-            "Invalid profile data"
-        );
+    tokio::task::block_in_place(move || parse_profile(&bytes))
+}
+
+/// Reads a profile exported to a file by r2modman or Gale.
+pub async fn import_profile_from_file(path: &Path) -> Result<Profile> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read profile {path:?}"))?;
+    tokio::task::block_in_place(move || parse_profile(&bytes))
+}
+
+/// Parses exported profile data, which is either a zip archive encoded in base64 after
+/// [`R2_PROFILE_DATA_PREFIX`], as shared by r2modman, or the bare zip archive, as exported by
+/// Gale.
+pub fn parse_profile(bytes: &[u8]) -> Result<Profile> {
+    let buf = match bytes.strip_prefix(R2_PROFILE_DATA_PREFIX.as_bytes()) {
+        Some(bytes) => {
+            let mut buf = Vec::new();
+            base64::read::DecoderReader::new(std::io::Cursor::new(bytes), &BASE64_STANDARD)
+                .read_to_end(&mut buf)
+                .context("Failed to decode base64 data")?;
+            buf
+        }
+        None => {
+            ensure!(bytes.starts_with(ZIP_MAGIC), "Invalid profile data");
+            bytes.to_vec()
+        }
+    };
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(Arc::from(buf)))?;
 
-        let mut buf = Vec::new();
-        base64::read::DecoderReader::new(std::io::Cursor::new(bytes), &BASE64_STANDARD)
-            .read_to_end(&mut buf)
-            .context("Failed to decode base64 data")?;
+    let manifest_file = archive
+        .by_name(R2_PROFILE_MANIFEST_FILE_NAME)
+        .context("Profile archive is missing manifest file")?;
 
-        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(Arc::from(buf)))?;
+    let manifest = serde_yaml::from_reader(manifest_file)?;
 
-        let manifest_file = archive
-            .by_name("export.r2x")
-            .context("Profile archive is missing manifest file")?;
+    Ok(Profile { manifest, archive })
+}
 
-        let manifest = serde_yaml::from_reader(manifest_file)?;
+/// A mod of a profile that can't be installed from the mod index as it is.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnresolvedMod {
+    pub full_name: String,
+    pub version: String,
+    pub problem: ResolutionProblem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ResolutionProblem {
+    /// The mod isn't in the mod index.
+    MissingMod,
+    /// The mod is in the mod index, but not the version.
+    MissingVersion,
+    /// The version was removed from Thunderstore, and can no longer be downloaded.
+    InactiveVersion,
+    /// The version can't be represented by [`crate::mods::Version`], like a prerelease.
+    InvalidVersion,
+}
+
+/// Checks that every mod of `manifest` that comes from Thunderstore can be installed from
+/// `mod_index`, so that problems can be reported before anything is installed.
+pub async fn resolve_profile_mods(
+    mod_index: &ModIndexReadGuard,
+    manifest: &ProfileManifest,
+) -> Result<Vec<UnresolvedMod>> {
+    let mods = manifest
+        .mods
+        .iter()
+        .filter(|m| m.install_source.is_none())
+        .collect::<Vec<_>>();
+    let mod_ids = mods
+        .iter()
+        .map(|m| ModId {
+            owner: m.full_name.namespace().into(),
+            name: m.full_name.name().into(),
+        })
+        .collect::<HashSet<_>>();
+    let found = get_from_mod_index(mod_index, &mod_ids).await?;
+
+    let mut unresolved = Vec::new();
+    for m in mods {
+        let problem = match crate::mods::Version::try_from(&m.version) {
+            Err(_) => Some(ResolutionProblem::InvalidVersion),
+            Ok(version) => {
+                let (namespace, name) = m.full_name.components();
+                match found
+                    .iter()
+                    .find(|found| &*found.owner == namespace && &*found.name == name)
+                {
+                    None => Some(ResolutionProblem::MissingMod),
+                    Some(found) => match found
+                        .versions
+                        .iter()
+                        .find(|v| v.version_number.get() == version)
+                    {
+                        None => Some(ResolutionProblem::MissingVersion),
+                        Some(v) if !v.is_active => Some(ResolutionProblem::InactiveVersion),
+                        Some(_) => None,
+                    },
+                }
+            }
+        };
+        if let Some(problem) = problem {
+            unresolved.push(UnresolvedMod {
+                full_name: m.full_name.to_string(),
+                version: m.version.to_string(),
+                problem,
+            });
+        }
+    }
+    Ok(unresolved)
+}
+
+/// Exports a profile in the format shared on Thunderstore, which [`parse_profile`] reads back.
+/// The files of `archive`, other than its manifest, are included as they are.
+pub fn export_profile<R: std::io::Read + std::io::Seek>(
+    manifest: &ProfileManifest,
+    archive: &mut zip::ZipArchive<R>,
+) -> Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    writer.start_file(
+        R2_PROFILE_MANIFEST_FILE_NAME,
+        zip::write::SimpleFileOptions::default(),
+    )?;
+    serde_yaml::to_writer(&mut writer, manifest)?;
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index_raw(i)
+            .context("Failed to open file in archive")?;
+        if file.name() == R2_PROFILE_MANIFEST_FILE_NAME {
+            continue;
+        }
+        writer.raw_copy_file(file)?;
+    }
+    let zip = writer.finish()?.into_inner();
 
-        Ok(Profile { manifest, archive })
-    })
+    let mut buf = R2_PROFILE_DATA_PREFIX.to_owned();
+    BASE64_STANDARD.encode_string(&zip, &mut buf);
+    Ok(buf.into_bytes())
 }
 
 pub fn get_archive_file_path(file: &ZipFile<'_>) -> Result<Option<PathBuf>> {
@@ -244,3 +558,145 @@ pub fn get_archive_file_path(file: &ZipFile<'_>) -> Result<Option<PathBuf>> {
 
     Ok(Some(path.into_owned()))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read as _, Write as _};
+
+    use base64::prelude::{Engine as _, BASE64_STANDARD};
+    use uuid::Uuid;
+
+    use super::{
+        export_profile, parse_profile, parse_profile_code, FullName, Version,
+        R2_PROFILE_DATA_PREFIX,
+    };
+
+    #[test]
+    fn test_parse_profile_code() {
+        let id = Uuid::parse_str("0194f0b4-ffb1-6f94-4b2a-4bd1e4ab1c37").unwrap();
+        for input in [
+            "0194f0b4-ffb1-6f94-4b2a-4bd1e4ab1c37",
+            " 0194f0b4-ffb1-6f94-4b2a-4bd1e4ab1c37\n",
+            "https://thunderstore.io/api/experimental/legacyprofile/get/0194f0b4-ffb1-6f94-4b2a-4bd1e4ab1c37/",
+            "https://thunderstore.io/profile/0194f0b4-ffb1-6f94-4b2a-4bd1e4ab1c37",
+        ] {
+            assert_eq!(parse_profile_code(input).unwrap(), id, "{input:?}");
+        }
+        for input in [
+            "",
+            "not a code",
+            "https://example.com/0194f0b4-ffb1-6f94-4b2a-4bd1e4ab1c37/",
+            "https://thunderstore.io/profile/",
+            "ftp://thunderstore.io/0194f0b4-ffb1-6f94-4b2a-4bd1e4ab1c37/",
+        ] {
+            assert!(parse_profile_code(input).is_err(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn test_full_name() {
+        let parse = |s: &str| serde_json::from_value::<FullName>(serde_json::json!(s));
+        let name = parse("ns-name").unwrap();
+        assert_eq!(name.components(), ("ns", "name"));
+        let name = parse("ns-name-with-hyphens").unwrap();
+        assert_eq!(name.components(), ("ns", "name-with-hyphens"));
+        for input in ["-x", "x-", "-", "x", ""] {
+            assert!(parse(input).is_err(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file("export.r2x", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        let manifest = "
+profileName: Test
+mods:
+  - name: Owner-Mod
+    version: { major: 1, minor: 2, patch: 3 }
+    enabled: true
+  - name: Owner-Local
+    version: { major: 0, minor: 1, patch: 0 }
+    enabled: false
+    installSource: { type: local, path: Local.zip }
+    group: Extras
+";
+        writer.write_all(manifest.as_bytes()).unwrap();
+        writer
+            .start_file("config/Mod.cfg", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"[General]\n").unwrap();
+        let zip = writer.finish().unwrap().into_inner();
+
+        let mut encoded = R2_PROFILE_DATA_PREFIX.as_bytes().to_vec();
+        encoded.extend_from_slice(BASE64_STANDARD.encode(&zip).as_bytes());
+        for bytes in [&zip, &encoded] {
+            let profile = parse_profile(bytes).unwrap();
+            assert_eq!(profile.manifest.profile_name, "Test");
+            assert_eq!(&*profile.manifest.mods[0].full_name, "Owner-Mod");
+            assert_eq!(profile.manifest.version, None);
+            let local = &profile.manifest.mods[1];
+            assert!(local.install_source.is_some());
+            assert_eq!(
+                local.extra.get("group").and_then(|v| v.as_str()),
+                Some("Extras")
+            );
+        }
+        assert!(parse_profile(b"not a profile").is_err());
+
+        let mut profile = parse_profile(&zip).unwrap();
+        let exported = export_profile(&profile.manifest, &mut profile.archive).unwrap();
+        assert!(exported.starts_with(R2_PROFILE_DATA_PREFIX.as_bytes()));
+        let mut reimported = parse_profile(&exported).unwrap();
+        assert_eq!(
+            serde_yaml::to_string(&reimported.manifest).unwrap(),
+            serde_yaml::to_string(&profile.manifest).unwrap()
+        );
+        assert_eq!(reimported.archive.len(), 2);
+        let mut config = String::new();
+        reimported
+            .archive
+            .by_name("config/Mod.cfg")
+            .unwrap()
+            .read_to_string(&mut config)
+            .unwrap();
+        assert_eq!(config, "[General]\n");
+    }
+
+    #[test]
+    fn test_version() {
+        let parse = |s: &str| s.parse::<Version>().unwrap();
+        assert_eq!(
+            parse("1.2.3-beta.1+build.5").to_string(),
+            "1.2.3-beta.1+build.5"
+        );
+        assert_eq!(parse("1.2.3+build.5"), parse("1.2.3"));
+        assert!(parse("1.0.0-alpha") < parse("1.0.0-alpha.1"));
+        assert!(parse("1.0.0-alpha.1") < parse("1.0.0-beta"));
+        assert!(parse("1.0.0-beta.11") > parse("1.0.0-beta.2"));
+        assert!(parse("1.0.0-rc.1") < parse("1.0.0"));
+        assert!(parse("1.0.0") < parse("1.0.1-alpha"));
+
+        let from_yaml = |s: &str| serde_yaml::from_str::<Version>(s).unwrap().to_string();
+        assert_eq!(from_yaml("{ major: 1, minor: 2, patch: 3 }"), "1.2.3");
+        assert_eq!(
+            from_yaml("{ major: 1, minor: 2, patch: 3, prerelease: beta.1 }"),
+            "1.2.3-beta.1"
+        );
+        assert_eq!(from_yaml("1.2.3-beta.1"), "1.2.3-beta.1");
+        assert!(serde_yaml::from_str::<Version>("1.2").is_err());
+
+        let version = parse("1.2.3-beta.1");
+        let yaml = serde_yaml::to_string(&version).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Version>(&yaml).unwrap().pre,
+            version.pre
+        );
+        assert_eq!(
+            serde_yaml::to_string(&parse("1.2.3")).unwrap(),
+            "major: 1\nminor: 2\npatch: 3\n"
+        );
+    }
+}
@@ -0,0 +1,581 @@
+//! Package archives in formats other than zip.
+//!
+//! Some loaders and game-specific distributions ship as `.tar.gz` or `.tar.zst`. These are
+//! extracted with the same semantics as zip archives: a wrapper directory is stripped, optional
+//! components may be excluded, and symlinks and directories are recreated as they are.
+//!
+//! Unlike a zip archive, a tarball has no directory of its entries, so it is read twice: once to
+//! find the wrapper directory, check the exclusions and the space needed, and again to extract it.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, ensure, Context as _, Result};
+use zip::ZipArchive;
+
+use crate::util::UsizeExt as _;
+
+use super::{
+    apply_entry_mode, copy_entry, create_entry_file, ensure_extracted_size,
+    extract_zip_with_progress, find_wrapper, parse_optional_components, strip_wrapper,
+    CaseCollisions, ExtractedLinks, PACKAGE_MANIFEST_NAME,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// Identifies the format of an archive by its first bytes, falling back to the extension of
+    /// the URL it was downloaded from.
+    pub fn detect(header: &[u8], url: &str) -> Result<Self> {
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            return Ok(Self::Zip);
+        }
+        if header.starts_with(&[0x1f, 0x8b]) {
+            return Ok(Self::TarGz);
+        }
+        if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return Ok(Self::TarZst);
+        }
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        if path.ends_with(".zip") {
+            Ok(Self::Zip)
+        } else if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Ok(Self::TarGz)
+        } else if path.ends_with(".tar.zst") || path.ends_with(".tzst") {
+            Ok(Self::TarZst)
+        } else {
+            bail!("Unrecognized archive format of {url:?}: expected a zip, tar.gz, or tar.zst archive")
+        }
+    }
+
+    /// Like [`detect`](Self::detect), reading the first bytes from `rdr` and rewinding it.
+    pub fn detect_from<R: Read + Seek>(rdr: &mut R, url: &str) -> Result<Self> {
+        let mut header = [0u8; 4];
+        let mut len = 0;
+        while len < header.len() {
+            match rdr.read(&mut header[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        rdr.rewind()?;
+        Self::detect(&header[..len], url)
+    }
+}
+
+/// Extracts the archive read from `rdr` into `dest`, like [`extract_zip`](super::extract_zip),
+/// checking that the exclusions are valid and that there is enough space first. Fails if more
+/// than `max_size` bytes would be extracted. `rdr` should be buffered.
+///
+/// `progress` is called with the number of entries processed so far and the total number of
+/// entries as extraction proceeds.
+pub fn extract_archive<R: Read + Seek>(
+    format: ArchiveFormat,
+    rdr: R,
+    dest: &Path,
+    exclude: &[String],
+    max_size: u64,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<HashMap<PathBuf, blake3::Hash>> {
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = ZipArchive::new(rdr)?;
+            super::validate_exclusions(&mut archive, exclude)?;
+            super::check_extracted_size(&mut archive, dest, exclude, max_size)?;
+            extract_zip_with_progress(&mut archive, dest, exclude, max_size, progress)
+        }
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            extract_tar(format, rdr, dest, exclude, max_size, progress)
+        }
+    }
+}
+
+/// Reads the top-level entries that the package's manifest declares as optional, like
+/// [`read_optional_components`](super::read_optional_components).
+pub fn read_optional_components<R: Read + Seek>(
+    format: ArchiveFormat,
+    rdr: R,
+) -> Result<Vec<String>> {
+    match format {
+        ArchiveFormat::Zip => super::read_optional_components(&mut ZipArchive::new(rdr)?),
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            list_tar(format, rdr)?.optional_components()
+        }
+    }
+}
+
+/// The most symlinks followed when resolving one, like the limit of Linux.
+const MAX_LINK_DEPTH: usize = 40;
+
+/// Resolves the symlink at `rel_path` below `root` that points to `target`, following the
+/// symlinks below `root` whose targets `read_link` returns, by path relative to `root`. Returns
+/// the path, relative to `root`, that the link leads to, or `None` if it leads outside of `root`
+/// at any point.
+pub(crate) fn resolve_link(
+    root: &Path,
+    rel_path: &Path,
+    target: &Path,
+    mut read_link: impl FnMut(&Path) -> Option<PathBuf>,
+) -> Option<PathBuf> {
+    let mut resolved = rel_path.parent().map(Path::to_owned).unwrap_or_default();
+    // reversed, with `None` standing for `..`
+    let mut pending = Vec::<Option<OsString>>::new();
+    let mut target = target.to_owned();
+    let mut followed = 0;
+    loop {
+        if target.is_absolute() {
+            target = target.strip_prefix(root).ok()?.to_owned();
+            resolved.clear();
+        }
+        for c in target.components().rev() {
+            match c {
+                Component::Normal(c) => pending.push(Some(c.to_owned())),
+                Component::CurDir => {}
+                Component::ParentDir => pending.push(None),
+                Component::RootDir | Component::Prefix(_) => return None,
+            }
+        }
+        loop {
+            match pending.pop() {
+                None => return Some(resolved),
+                Some(None) => {
+                    if !resolved.pop() {
+                        return None;
+                    }
+                }
+                Some(Some(c)) => {
+                    resolved.push(c);
+                    if let Some(next) = read_link(&resolved) {
+                        followed += 1;
+                        if followed > MAX_LINK_DEPTH {
+                            return None;
+                        }
+                        // relative targets are relative to the directory containing the link
+                        resolved.pop();
+                        target = next;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn open_tar<'a, R: Read + 'a>(
+    format: ArchiveFormat,
+    rdr: R,
+) -> Result<tar::Archive<Box<dyn Read + 'a>>> {
+    let rdr: Box<dyn Read + 'a> = match format {
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(rdr)),
+        ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(rdr)?),
+        ArchiveFormat::Zip => bail!("Not a tarball"),
+    };
+    Ok(tar::Archive::new(rdr))
+}
+
+/// Returns the path of the entry relative to the root of the archive, or `None` for the root
+/// itself. Fails if the path is absolute or escapes the root.
+fn entry_path<R: Read>(entry: &tar::Entry<'_, R>) -> Result<Option<PathBuf>> {
+    let path = entry.path()?;
+    let mut rel_path = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(c) => rel_path.push(c),
+            Component::CurDir => {}
+            _ => bail!("Invalid path in tar archive: {path:?}"),
+        }
+    }
+    Ok((!rel_path.as_os_str().is_empty()).then_some(rel_path))
+}
+
+/// Returns the path, relative to the root of the archive, of the file the hard link entry links
+/// to.
+fn hard_link_target<R: Read>(entry: &tar::Entry<'_, R>, rel_path: &Path) -> Result<PathBuf> {
+    let link_name = entry
+        .link_name()?
+        .with_context(|| format!("Hard link {rel_path:?} has no target"))?;
+    let mut original = PathBuf::new();
+    for c in link_name.components() {
+        match c {
+            Component::Normal(c) => original.push(c),
+            Component::CurDir => {}
+            _ => bail!("Invalid hard link target in tar archive: {link_name:?}"),
+        }
+    }
+    Ok(original)
+}
+
+/// What the first pass over a tarball learns about it.
+struct Listing {
+    /// The entries, named like those of a zip archive, with their sizes. Hard links have the
+    /// size of the file they link to, since they are extracted as copies of it.
+    entries: Vec<(String, u64)>,
+    /// The contents of the manifests that could belong to the package, keyed by entry name.
+    manifests: HashMap<String, Vec<u8>>,
+}
+
+impl Listing {
+    fn wrapper(&self) -> Option<&str> {
+        find_wrapper(self.entries.iter().map(|(name, _)| name.as_str()), |name| {
+            self.manifests.contains_key(name)
+        })
+    }
+
+    fn optional_components(&self) -> Result<Vec<String>> {
+        let manifest = match self.wrapper() {
+            Some(wrapper) => self
+                .manifests
+                .get(&format!("{wrapper}/{PACKAGE_MANIFEST_NAME}")),
+            None => self.manifests.get(PACKAGE_MANIFEST_NAME),
+        };
+        match manifest {
+            Some(bytes) => parse_optional_components(bytes),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn list_tar<R: Read>(format: ArchiveFormat, rdr: R) -> Result<Listing> {
+    let mut listing = Listing {
+        entries: Vec::new(),
+        manifests: HashMap::new(),
+    };
+    let mut file_sizes = HashMap::<PathBuf, u64>::new();
+    let mut archive = open_tar(format, rdr)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        let Some(rel_path) = entry_path(&entry)? else {
+            continue;
+        };
+        let mut name = rel_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            name.push('/');
+        } else if entry_type.is_file()
+            && rel_path.file_name() == Some(PACKAGE_MANIFEST_NAME.as_ref())
+            && rel_path.components().count() <= 2
+        {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            listing.manifests.insert(name.clone(), bytes);
+        }
+        let size = if entry_type.is_hard_link() {
+            let original = hard_link_target(&entry, &rel_path)?;
+            file_sizes.get(&original).copied().unwrap_or(0)
+        } else {
+            entry.size()
+        };
+        if entry_type.is_file() {
+            file_sizes.insert(rel_path, size);
+        }
+        listing.entries.push((name, size));
+    }
+    Ok(listing)
+}
+
+fn extract_tar<R: Read + Seek>(
+    format: ArchiveFormat,
+    mut rdr: R,
+    dest: &Path,
+    exclude: &[String],
+    max_size: u64,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<HashMap<PathBuf, blake3::Hash>> {
+    let listing = list_tar(format, &mut rdr)?;
+    rdr.rewind()?;
+
+    let wrapper = listing.wrapper().map(str::to_owned);
+    let is_excluded = |name: &str| {
+        let top_level = name.split('/').next().unwrap_or_default();
+        exclude.iter().any(|e| e == top_level)
+    };
+    if !exclude.is_empty() {
+        let optional = listing.optional_components()?;
+        for component in exclude {
+            ensure!(
+                optional.contains(component),
+                "{component:?} is not an optional component of the package"
+            );
+        }
+    }
+    let required = listing
+        .entries
+        .iter()
+        .filter(|(name, _)| !is_excluded(strip_wrapper(name, wrapper.as_deref())))
+        .fold(0u64, |acc, (_, size)| acc.saturating_add(*size));
+    ensure_extracted_size(dest, required, max_size)?;
+    let mut collisions = CaseCollisions::new(dest)?;
+    for (name, _) in &listing.entries {
+        let name = strip_wrapper(name, wrapper.as_deref());
+        if !is_excluded(name) {
+            collisions.check(name)?;
+        }
+    }
+
+    let total = listing.entries.len().as_u64();
+    let mut done = 0;
+    let mut hashes = HashMap::with_capacity(listing.entries.len());
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut remaining = max_size;
+    let mut links = ExtractedLinks::default();
+    let mut archive = open_tar(format, rdr)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        let Some(mut rel_path) = entry_path(&entry)? else {
+            continue;
+        };
+        progress(done, total);
+        done += 1;
+        if let Some(wrapper) = &wrapper {
+            rel_path = rel_path.strip_prefix(wrapper)?.to_owned();
+            if rel_path.as_os_str().is_empty() {
+                continue;
+            }
+        }
+        if rel_path
+            .components()
+            .next()
+            .and_then(|c| c.as_os_str().to_str())
+            .is_some_and(is_excluded)
+        {
+            continue;
+        }
+        links.check(&rel_path)?;
+        let path = dest.join(&rel_path);
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if entry_type.is_symlink() {
+            let target = entry
+                .link_name()?
+                .with_context(|| format!("Symlink {rel_path:?} has no target"))?;
+            let target = target
+                .to_str()
+                .with_context(|| format!("Invalid symlink target {target:?}"))?;
+            links.create(dest, &rel_path, target)?;
+            continue;
+        }
+        if entry_type.is_hard_link() {
+            // tar stores later occurrences of a hard-linked file as links to the first, which
+            // are installed as copies, the same as a zip archive would have stored them
+            let mut original = hard_link_target(&entry, &rel_path)?;
+            if let Some(wrapper) = &wrapper {
+                original = original.strip_prefix(wrapper)?.to_owned();
+            }
+            let hash = *hashes
+                .get(&original)
+                .with_context(|| format!("Hard link {rel_path:?} to missing file {original:?}"))?;
+            // the copy counts against the limit like any other file
+            let size = std::fs::metadata(dest.join(&original))?.len();
+            ensure!(
+                size <= remaining,
+                "Archive extracts to more than the limit, at {rel_path:?}"
+            );
+            remaining -= size;
+            std::io::copy(
+                &mut std::fs::File::open(dest.join(&original))?,
+                &mut create_entry_file(&path)?,
+            )?;
+            hashes.insert(rel_path, hash);
+            continue;
+        }
+        ensure!(
+            entry_type.is_file(),
+            "Unsupported entry type {entry_type:?} of {rel_path:?} in tar archive"
+        );
+        let mut wtr = create_entry_file(&path)?;
+        let size = entry.size();
+        let hash = copy_entry(
+            &mut entry,
+            &mut wtr,
+            &mut buf,
+            &rel_path,
+            size,
+            &mut remaining,
+        )?;
+        apply_entry_mode(&path, Some(entry.header().mode()?))?;
+        hashes.insert(rel_path, hash);
+    }
+    links.finish(dest)?;
+    progress(total, total);
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{Cursor, Write as _};
+    use std::path::{Path, PathBuf};
+
+    use super::{extract_tar, resolve_link, ArchiveFormat};
+
+    #[test]
+    fn test_detect_archive_format() {
+        let detect = |header: &[u8], url| ArchiveFormat::detect(header, url).ok();
+        assert_eq!(
+            detect(b"PK\x03\x04", "https://x/a.tar.gz"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(
+            detect(&[0x1f, 0x8b, 8, 0], "https://x/a"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            detect(&[0x28, 0xb5, 0x2f, 0xfd], "https://x/a.zip"),
+            Some(ArchiveFormat::TarZst)
+        );
+        assert_eq!(
+            detect(b"", "https://x/a.tgz?v=1"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            detect(b"", "https://x/a.tar.zst"),
+            Some(ArchiveFormat::TarZst)
+        );
+        assert_eq!(detect(b"Rar!", "https://x/a.rar"), None);
+    }
+
+    /// Builds a tar.gz archive of files and, for entries with a target, symlinks.
+    fn tar_gz(entries: &[(&str, Option<&str>)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, target) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            match target {
+                Some(target) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_size(0);
+                    builder.append_link(&mut header, name, target).unwrap();
+                }
+                None => {
+                    header.set_size(2);
+                    builder.append_data(&mut header, name, &b"{}"[..]).unwrap();
+                }
+            }
+        }
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_tar_rejects_escaping_symlinks() {
+        let extract = |entries: &[(&str, Option<&str>)]| {
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("package");
+            std::fs::create_dir(&dest).unwrap();
+            let result = extract_tar(
+                ArchiveFormat::TarGz,
+                Cursor::new(tar_gz(entries)),
+                &dest,
+                &[],
+                u64::MAX,
+                &mut |_, _| {},
+            );
+            (dir, result)
+        };
+
+        let (_dir, result) = extract(&[("a/link", Some("../b")), ("b", None)]);
+        result.unwrap();
+        let (dir, result) = extract(&[("link", Some("..")), ("link/escaped.dll", None)]);
+        assert!(result.is_err());
+        assert!(!dir.path().join("escaped.dll").exists());
+        let (dir, result) = extract(&[
+            ("a/up", Some("..")),
+            ("a/up/link", Some("..")),
+            ("a/up/link/escaped.dll", None),
+        ]);
+        assert!(result.is_err());
+        assert!(!dir.path().join("escaped.dll").exists());
+        let (_dir, result) = extract(&[("a/up", Some("..")), ("evil", Some("a/up/../x"))]);
+        assert!(result.is_err());
+        let (_dir, result) = extract(&[("evil", Some("a/up/../x")), ("a/up", Some(".."))]);
+        assert!(result.is_err());
+        let (dir, result) = extract(&[("b", None), ("link", Some("b")), ("link", None)]);
+        assert!(result.is_err());
+        assert!(dir.path().join("package/link").is_symlink());
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        let links = [
+            ("a/up", ".."),
+            ("a/loop", "loop"),
+            ("abs", "/root/package/a"),
+        ]
+        .into_iter()
+        .map(|(path, target)| (PathBuf::from(path), PathBuf::from(target)))
+        .collect::<HashMap<_, _>>();
+        let resolve = |rel_path: &str, target: &str| {
+            resolve_link(
+                Path::new("/root/package"),
+                Path::new(rel_path),
+                Path::new(target),
+                |p| links.get(p).cloned(),
+            )
+        };
+        assert_eq!(resolve("a/b", "../c"), Some(PathBuf::from("c")));
+        assert_eq!(resolve("a/b", "up/c"), Some(PathBuf::from("c")));
+        assert_eq!(resolve("link", "a/up/c/../d"), Some(PathBuf::from("d")));
+        assert_eq!(resolve("link", "a/up/../x"), None);
+        assert_eq!(resolve("link", "a/up/up"), Some(PathBuf::from("up")));
+        assert_eq!(resolve("link", "abs/up/b"), Some(PathBuf::from("b")));
+        assert_eq!(resolve("link", "/etc/passwd"), None);
+        assert_eq!(resolve("link", "a/loop"), None);
+    }
+
+    #[test]
+    fn test_extract_tar_counts_hard_links() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(4);
+        builder.append_data(&mut header, "a", &b"1234"[..]).unwrap();
+        for name in ["b", "c"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_size(0);
+            builder.append_link(&mut header, name, "a").unwrap();
+        }
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        let archive = encoder.finish().unwrap();
+
+        let extract = |max_size| {
+            let dir = tempfile::tempdir().unwrap();
+            extract_tar(
+                ArchiveFormat::TarGz,
+                Cursor::new(&archive),
+                dir.path(),
+                &[],
+                max_size,
+                &mut |_, _| {},
+            )
+        };
+        assert_eq!(extract(12).unwrap().len(), 3);
+        assert!(extract(11).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! Moving the cache to a different directory without throwing away what has already been
+//! downloaded.
+//!
+//! Cached files are named after their content hash or the URL they were fetched from, so a file
+//! that already exists under the same name in the new directory is treated as a duplicate and
+//! the copy in the old directory is dropped.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context as _, Result};
+use slog::{debug, info};
+use tauri::AppHandle;
+use walkdir::WalkDir;
+
+use crate::paths::{cache_dir, set_cache_location};
+use crate::tasks::{self, TaskBuilder};
+
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheMigration {
+    pub moved_files: u64,
+    pub moved_bytes: u64,
+    /// Files skipped because the new directory already contained a file of the same name.
+    pub duplicate_files: u64,
+    pub duplicate_bytes: u64,
+}
+
+/// Copies a file to another device. The copy is written to a temporary file first, so that an
+/// interrupted copy never leaves behind a truncated file that would later be mistaken for a
+/// complete one.
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut tmp = tempfile::NamedTempFile::new_in(to.parent().unwrap_or(to))?;
+    io::copy(&mut std::fs::File::open(from)?, tmp.as_file_mut())?;
+    tmp.persist(to).map_err(|e| e.error)?;
+    std::fs::remove_file(from)
+}
+
+/// Moves the contents of the cache into `new_dir` and makes it the cache directory from the next
+/// start onwards.
+pub async fn migrate_cache(
+    app: &AppHandle,
+    log: &slog::Logger,
+    new_dir: PathBuf,
+    task_id: Option<tasks::Id>,
+) -> Result<CacheMigration> {
+    let old_dir = cache_dir();
+    ensure!(
+        new_dir.is_absolute(),
+        "Cache directory must be an absolute path"
+    );
+    ensure!(
+        !new_dir.starts_with(old_dir) && !old_dir.starts_with(&new_dir),
+        "The new cache directory must not contain or be inside of the current one"
+    );
+
+    TaskBuilder::with_id(
+        task_id.unwrap_or_else(tasks::allocate_task),
+        format!("Move cache to {new_dir:?}"),
+    )
+    .progress_unit(tasks::ProgressUnit::Bytes)
+    .run_with_handle(Some(app), |handle| async move {
+        tokio::task::block_in_place(|| {
+            let mut total = 0u64;
+            for e in WalkDir::new(old_dir) {
+                let e = e?;
+                if e.file_type().is_file() {
+                    total += e.metadata()?.len();
+                }
+            }
+
+            std::fs::create_dir_all(&new_dir)
+                .with_context(|| format!("Failed to create {new_dir:?}"))?;
+
+            let mut migration = CacheMigration::default();
+            let mut checked_space = false;
+            handle.send_progress_manually(app, 0, total)?;
+            for e in WalkDir::new(old_dir).min_depth(1) {
+                let e = e?;
+                let target = new_dir.join(e.path().strip_prefix(old_dir)?);
+                if e.file_type().is_dir() {
+                    std::fs::create_dir_all(&target)?;
+                    continue;
+                } else if !e.file_type().is_file() {
+                    continue;
+                }
+
+                let len = e.metadata()?.len();
+                if target.exists() {
+                    debug!(log, "{target:?} is already cached, dropping {:?}", e.path());
+                    std::fs::remove_file(e.path())?;
+                    migration.duplicate_files += 1;
+                    migration.duplicate_bytes += len;
+                } else {
+                    match std::fs::rename(e.path(), &target) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                            if !checked_space {
+                                checked_space = true;
+                                let remaining = total.saturating_sub(
+                                    migration.moved_bytes + migration.duplicate_bytes,
+                                );
+                                let available = fs4::available_space(&new_dir)?;
+                                ensure!(
+                                    available >= remaining,
+                                    "Not enough space in {new_dir:?}: {remaining} bytes needed, \
+                                     {available} bytes available"
+                                );
+                            }
+                            copy_file(e.path(), &target)
+                                .with_context(|| format!("Failed to copy {:?}", e.path()))?;
+                        }
+                        Err(err) => {
+                            return Err(err)
+                                .with_context(|| format!("Failed to move {:?}", e.path()))
+                        }
+                    }
+                    migration.moved_files += 1;
+                    migration.moved_bytes += len;
+                }
+                handle.send_progress_manually(
+                    app,
+                    migration.moved_bytes + migration.duplicate_bytes,
+                    total,
+                )?;
+            }
+
+            set_cache_location(&new_dir)?;
+
+            // only remove directories that are empty, in case anything was cached in the
+            // meantime
+            for e in WalkDir::new(old_dir)
+                .min_depth(1)
+                .contents_first(true)
+                .into_iter()
+                .flatten()
+            {
+                if e.file_type().is_dir() {
+                    _ = std::fs::remove_dir(e.path());
+                }
+            }
+
+            info!(
+                log,
+                "Moved cache from {old_dir:?} to {new_dir:?}: {migration:?}"
+            );
+
+            Ok::<_, anyhow::Error>(migration)
+        })
+    })
+    .await
+    .map_err(Into::into)
+}
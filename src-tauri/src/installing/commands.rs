@@ -1,8 +1,113 @@
-use anyhow::Result;
+use std::path::PathBuf;
 
-use crate::CommandError;
+use anyhow::{Context as _, Result};
+use tauri::AppHandle;
+
+use crate::games::games_by_id;
+use crate::stores::steam::paths::resolve_steam_app_install_directory;
+use crate::{tasks, CommandError};
+
+use super::cache_migration::CacheMigration;
+use super::ignore::IgnoreList;
+use super::target_validation::Validation;
+use super::{PrunedCache, Status};
 
 #[tauri::command]
 pub async fn clear_cache() -> Result<(), CommandError> {
     super::clear_cache().await.map_err(Into::into)
 }
+
+/// Deletes individual entries from the cache, as listed by
+/// [`find_orphaned_cache_entries`](crate::profiles::commands::find_orphaned_cache_entries).
+#[tauri::command]
+pub async fn delete_cache_entries(file_names: Vec<String>) -> Result<(), CommandError> {
+    super::delete_cache_entries(&file_names)
+        .await
+        .map_err(Into::into)
+}
+
+/// Deletes the least recently used archives from the cache until the rest take up at most
+/// `max_bytes`.
+#[tauri::command]
+pub async fn prune_cache(max_bytes: u64) -> Result<PrunedCache, CommandError> {
+    let log = slog_scope::logger();
+
+    super::prune_cache(&log, max_bytes)
+        .await
+        .map_err(Into::into)
+}
+
+/// Lists the changes made to the installed package at `path` since it was installed, as paths
+/// relative to the package.
+#[tauri::command]
+pub async fn scan_package_changes(path: PathBuf) -> Result<Vec<(PathBuf, Status)>, CommandError> {
+    let log = slog_scope::logger();
+
+    let mut changes = Vec::new();
+    let ignore = IgnoreList::packages();
+    super::scan_installed_package_for_changes(&log, &path, ignore, false, &mut changes)
+        .await
+        .map_err(anyhow::Error::from)?;
+    // changes are found at their full path
+    changes
+        .into_iter()
+        .map(|(changed, status)| {
+            Ok::<_, anyhow::Error>((changed.strip_prefix(&path)?.to_owned(), status))
+        })
+        .collect::<Result<_>>()
+        .map_err(Into::into)
+}
+
+/// Returns the number of bytes available on the filesystem containing `path`.
+#[tauri::command]
+pub async fn free_space(path: PathBuf) -> Result<u64, CommandError> {
+    tokio::task::block_in_place(|| super::free_space(&path))
+        .with_context(|| format!("Failed to query free space of {path:?}"))
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn migrate_cache(
+    app: AppHandle,
+    new_dir: PathBuf,
+    task_id: tasks::Id,
+) -> Result<CacheMigration, CommandError> {
+    let log = slog_scope::logger();
+
+    super::cache_migration::migrate_cache(&app, &log, new_dir, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+/// Checks whether `target` is safe to install into. `required_bytes` is the uncompressed size of
+/// what is about to be installed, and `game`, if given, is the game the install is for.
+#[tauri::command]
+pub async fn validate_install_target(
+    target: PathBuf,
+    required_bytes: Option<u64>,
+    game: Option<String>,
+) -> Result<Validation, CommandError> {
+    let game_dir = match game {
+        Some(game) => {
+            let game = *games_by_id()?.get(&*game).context("No such game")?;
+            match game
+                .store_platform_metadata
+                .iter()
+                .find_map(|m| m.steam_or_direct())
+            {
+                // the game not being installed through Steam doesn't make the target unsafe
+                Some(m) => resolve_steam_app_install_directory(m.id).await.ok(),
+                None => None,
+            }
+        }
+        None => None,
+    };
+    tokio::task::block_in_place(|| {
+        super::target_validation::validate_install_target(
+            &target,
+            required_bytes,
+            game_dir.as_deref(),
+        )
+    })
+    .map_err(Into::into)
+}
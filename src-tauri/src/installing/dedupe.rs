@@ -0,0 +1,335 @@
+//! Sharing identical files between installed packages.
+//!
+//! With the `dedupe_installed_files` setting, the files of a package that is finishing installing
+//! are hard linked to a content-addressed store, keyed by the hash recorded in the package's
+//! content index. A file whose content is already in the store is replaced with a link to it, so
+//! that every profile with the same version of a mod shares a single copy. Files that can't be
+//! linked, like when the store is on another filesystem, are left as copies.
+//!
+//! Linked files are marked in the content index. Removing a package only ever unlinks its files,
+//! and the copy in the store is removed once no package links to it anymore.
+//!
+//! Since linked files are the same file, a file modified in place in one profile is modified in
+//! every profile that shares it. Files in the loaders' config directories, which are meant to be
+//! edited, are never linked, and a copy in the store is checked against its hash before another
+//! file is linked to it, so that a modified copy doesn't spread to new installs.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+
+use anyhow::{Context as _, Result};
+use parking_lot::Mutex;
+use slog::{debug, warn};
+use strum::VariantArray as _;
+
+use crate::games::PackageLoader;
+use crate::paths::local_data_dir;
+use crate::util::IoErrorKindExt as _;
+
+use super::index::{self, FileStat, Index, IndexEntryV4, NativePath};
+use super::{encode_index, hash_file, unix_mode, INDEX_FILE_NAME};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The stats of the copies in the store whose content was last found to match their hash.
+static VERIFIED: LazyLock<Mutex<HashMap<blake3::Hash, FileStat>>> = LazyLock::new(Default::default);
+
+/// Applies the `dedupe_installed_files` setting.
+pub fn configure(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn store_dir() -> PathBuf {
+    local_data_dir().join("content")
+}
+
+fn store_path(hash: &blake3::Hash) -> PathBuf {
+    store_dir().join(hash.to_hex().as_str())
+}
+
+async fn read_index(package: &Path) -> Result<Option<HashMap<NativePath, IndexEntryV4>>> {
+    let mut bytes = match tokio::fs::read(package.join(INDEX_FILE_NAME)).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read package index"),
+    };
+    tokio::task::block_in_place(|| index::decompress_in_place(&mut bytes))?;
+    let index = rkyv::from_bytes::<Index, rkyv::rancor::Error>(&bytes)
+        .context("Failed to read package index")?;
+    Ok(Some(index.into_latest()))
+}
+
+/// Returns the hashes of the files of the package at `package` that are linked to the store.
+pub async fn linked_hashes(package: &Path) -> Result<HashSet<blake3::Hash>> {
+    let Some(entries) = read_index(package).await? else {
+        return Ok(HashSet::new());
+    };
+    Ok(entries
+        .into_values()
+        .filter_map(|entry| match entry {
+            IndexEntryV4::File {
+                hash, linked: true, ..
+            } => Some(blake3::Hash::from_bytes(hash)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Links the unmodified files of the package at `package` to the store, adding those that aren't
+/// in it yet, and marks them in the package's index. Returns the number of files that were
+/// replaced with a link to a copy already in the store.
+pub async fn link_package_files(log: &slog::Logger, package: &Path) -> Result<usize> {
+    let Some(mut entries) = read_index(package).await? else {
+        return Ok(0);
+    };
+    tokio::fs::create_dir_all(store_dir()).await?;
+
+    let mut shared = 0usize;
+    let mut changed = false;
+    tokio::task::block_in_place(|| {
+        for (rel_path, entry) in &mut entries {
+            let IndexEntryV4::File {
+                hash,
+                mode,
+                stat,
+                linked: linked @ false,
+            } = entry
+            else {
+                continue;
+            };
+            let rel_path = rel_path.to_path_buf();
+            if is_config(&rel_path) {
+                continue;
+            }
+            let path = package.join(rel_path);
+            let hash = blake3::Hash::from_bytes(*hash);
+            match link_file(&path, &store_path(&hash), &hash, *mode, *stat) {
+                Ok(Some(Linked::Added)) => {}
+                Ok(Some(Linked::Shared(new_stat))) => {
+                    shared += 1;
+                    *stat = new_stat;
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!(log, "Unable to link {path:?} to the content store: {e}");
+                    continue;
+                }
+            }
+            *linked = true;
+            changed = true;
+        }
+    });
+
+    if changed {
+        let compressed = encode_index(log, &Index::V5(entries))?;
+        tokio::fs::write(package.join(INDEX_FILE_NAME), compressed).await?;
+    }
+    debug!(
+        log,
+        "Linked {shared} files of {package:?} to copies in the content store"
+    );
+    Ok(shared)
+}
+
+/// Returns whether `rel_path` is in a directory that some loader keeps configuration files in.
+fn is_config(rel_path: &Path) -> bool {
+    PackageLoader::VARIANTS
+        .iter()
+        .flat_map(|loader| loader.config_dirs())
+        .any(|dir| rel_path.starts_with(dir))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Linked {
+    /// The file was added to the store.
+    Added,
+    /// The file was replaced with a link to the copy in the store, changing its stat to this.
+    Shared(Option<FileStat>),
+}
+
+/// Links the file at `path` with `stored`, the copy of its content with `hash` in the store, if
+/// the file is unmodified. Returns `None` if it wasn't linked.
+fn link_file(
+    path: &Path,
+    stored: &Path,
+    hash: &blake3::Hash,
+    mode: Option<u32>,
+    stat: Option<FileStat>,
+) -> std::io::Result<Option<Linked>> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    // a file without a recorded stat, or that doesn't match it, may have been changed since
+    if stat.is_none() || FileStat::from_metadata(&metadata) != stat {
+        return Ok(None);
+    }
+    match std::fs::symlink_metadata(stored) {
+        Ok(stored_metadata) if !is_intact(stored, &stored_metadata, hash)? => {
+            // modified in place through a package linked to it, which keeps the modified copy
+            std::fs::remove_file(stored)?;
+            std::fs::hard_link(path, stored)?;
+            Ok(Some(Linked::Added))
+        }
+        Ok(stored_metadata) => {
+            // linking would change the permissions of one of them
+            if stored_metadata.len() != metadata.len() || unix_mode(&stored_metadata) != mode {
+                return Ok(None);
+            }
+            let mut temp_path = path.as_os_str().to_owned();
+            temp_path.push(".link");
+            std::fs::hard_link(stored, &temp_path)?;
+            if let Err(e) = std::fs::rename(&temp_path, path) {
+                _ = std::fs::remove_file(&temp_path);
+                return Err(e);
+            }
+            let metadata = std::fs::symlink_metadata(path)?;
+            Ok(Some(Linked::Shared(FileStat::from_metadata(&metadata))))
+        }
+        Err(e) if e.is_not_found() => {
+            std::fs::hard_link(path, stored)?;
+            Ok(Some(Linked::Added))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns whether the content of `stored` still has the `hash` it is stored as, only hashing it
+/// again if its stat changed since it was last checked.
+fn is_intact(
+    stored: &Path,
+    metadata: &std::fs::Metadata,
+    hash: &blake3::Hash,
+) -> std::io::Result<bool> {
+    let stat = FileStat::from_metadata(metadata);
+    if stat.is_some() && VERIFIED.lock().get(hash) == stat.as_ref() {
+        return Ok(true);
+    }
+    if hash_file(stored)? != *hash {
+        VERIFIED.lock().remove(hash);
+        return Ok(false);
+    }
+    if let Some(stat) = stat {
+        VERIFIED.lock().insert(*hash, stat);
+    }
+    Ok(true)
+}
+
+/// Gives the file at `path`, which was linked to the copy of `hash` in the store, its own copy of
+/// its content, and removes the copy from the store if no package links to it anymore.
+pub async fn unlink(log: &slog::Logger, path: &Path, hash: &blake3::Hash) -> Result<()> {
+    tokio::task::block_in_place(|| {
+        if link_count(path)? > 1 {
+            let mut temp_path = path.as_os_str().to_owned();
+            temp_path.push(".unlink");
+            std::fs::copy(path, &temp_path)?;
+            if let Err(e) = std::fs::rename(&temp_path, path) {
+                _ = std::fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        }
+        Ok(())
+    })
+    .with_context(|| format!("Failed to unlink {path:?} from the content store"))?;
+    if let Err(e) = release(log, &HashSet::from([*hash])).await {
+        warn!(
+            log,
+            "Failed to release {hash} from the content store: {e:?}"
+        );
+    }
+    Ok(())
+}
+
+/// Removes the copies of `hashes` from the store that no package links to anymore.
+pub async fn release(log: &slog::Logger, hashes: &HashSet<blake3::Hash>) -> Result<()> {
+    tokio::task::block_in_place(|| {
+        for hash in hashes {
+            let path = store_path(hash);
+            match link_count(&path) {
+                Ok(1) => {
+                    debug!(log, "Removing {path:?} from the content store");
+                    crate::util::fs::remove_file_blocking(&path)?;
+                }
+                Ok(_) => {}
+                Err(e) if e.is_not_found() => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(unix)]
+fn link_count(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::fs::MetadataExt as _;
+    Ok(std::fs::symlink_metadata(path)?.nlink())
+}
+
+#[cfg(windows)]
+fn link_count(path: &Path) -> std::io::Result<u64> {
+    use std::os::windows::io::AsRawHandle as _;
+
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+
+    let file = std::fs::File::open(path)?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle()), &mut info) }?;
+    Ok(info.nNumberOfLinks.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{is_config, link_file, Linked};
+    use crate::installing::index::FileStat;
+    use crate::installing::unix_mode;
+
+    #[test]
+    fn test_link_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let stored = dir.path().join("stored");
+        let a = dir.path().join("a.dll");
+        let b = dir.path().join("b.dll");
+        std::fs::write(&a, b"content").unwrap();
+        std::fs::write(&b, b"content").unwrap();
+        let hash = blake3::hash(b"content");
+        let metadata = |path: &Path| std::fs::metadata(path).unwrap();
+        let stat = |path: &Path| FileStat::from_metadata(&metadata(path));
+        let link = |path: &Path, indexed| {
+            link_file(path, &stored, &hash, unix_mode(&metadata(path)), indexed)
+        };
+
+        assert_eq!(link(&a, stat(&a)).unwrap(), Some(Linked::Added));
+
+        // modified since it was indexed
+        let indexed = stat(&b);
+        std::fs::write(&b, b"changed").unwrap();
+        assert_eq!(link(&b, indexed).unwrap(), None);
+
+        std::fs::write(&b, b"content").unwrap();
+        assert_eq!(link(&b, stat(&b)).unwrap(), Some(Linked::Shared(stat(&a))));
+        std::fs::write(&a, b"same file").unwrap();
+        assert_eq!(std::fs::read(&b).unwrap(), b"same file");
+
+        // the stored copy was modified through a linked file, so it is replaced
+        let c = dir.path().join("c.dll");
+        std::fs::write(&c, b"content").unwrap();
+        assert_eq!(link(&c, stat(&c)).unwrap(), Some(Linked::Added));
+        assert_eq!(std::fs::read(&stored).unwrap(), b"content");
+        assert_eq!(std::fs::read(&a).unwrap(), b"same file");
+    }
+
+    #[test]
+    fn test_is_config() {
+        assert!(is_config(Path::new("BepInEx/config/Foo.cfg")));
+        assert!(is_config(Path::new("config/Foo.cfg")));
+        assert!(!is_config(Path::new("BepInEx/plugins/Foo.dll")));
+    }
+}
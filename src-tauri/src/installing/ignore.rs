@@ -0,0 +1,80 @@
+//! Paths in installed packages that are neither indexed nor reported as changes.
+//!
+//! Mod loaders write logs and caches next to their files, and rewrite some of their own
+//! configuration, none of which the user changed. Tracking them would bury the user's own changes
+//! in transient files.
+
+use std::path::Path;
+use std::sync::LazyLock;
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+use super::index::IndexPath;
+
+/// The patterns ignored in every package.
+pub const DEFAULT_PATTERNS: &[&str] = &["*.log", "BepInEx/cache/**", "doorstop_config.ini"];
+
+static DEFAULT: LazyLock<IgnoreList> =
+    LazyLock::new(|| IgnoreList::new(DEFAULT_PATTERNS.iter().copied()).unwrap());
+
+/// Glob patterns matched against paths relative to a package, with `/` separated components.
+///
+/// Like in a `.gitignore`, a pattern without a `/` matches a file or directory of that name at
+/// any depth, and a pattern ending in `/**` also matches the directory itself.
+#[derive(Debug, Clone)]
+pub struct IgnoreList(GlobSet);
+
+impl IgnoreList {
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.trim_start_matches('/');
+            if pattern.contains('/') {
+                builder.add(glob(pattern)?);
+            } else {
+                builder.add(glob(&format!("**/{pattern}"))?);
+            }
+            if let Some(dir) = pattern.strip_suffix("/**") {
+                builder.add(glob(dir)?);
+            }
+        }
+        Ok(Self(builder.build()?))
+    }
+
+    /// The list of [`DEFAULT_PATTERNS`].
+    pub fn packages() -> &'static Self {
+        &DEFAULT
+    }
+
+    /// Returns whether `rel_path`, relative to the package, is ignored. Paths that aren't valid
+    /// Unicode, and so can't be an [`IndexPath`], never are.
+    pub fn is_ignored(&self, rel_path: &Path) -> bool {
+        match IndexPath::try_from(rel_path) {
+            Ok(IndexPath(components)) => self.0.is_match(components.join("/")),
+            Err(_) => false,
+        }
+    }
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::IgnoreList;
+
+    #[test]
+    fn test_ignore_list() {
+        let list = IgnoreList::packages();
+        assert!(list.is_ignored(Path::new("LogOutput.log")));
+        assert!(list.is_ignored(Path::new("BepInEx/LogOutput.log")));
+        assert!(list.is_ignored(Path::new("BepInEx/cache")));
+        assert!(list.is_ignored(Path::new("BepInEx/cache/chainloader_typeloader.dat")));
+        assert!(list.is_ignored(Path::new("doorstop_config.ini")));
+        assert!(!list.is_ignored(Path::new("BepInEx/config/BepInEx.cfg")));
+        assert!(!list.is_ignored(Path::new("BepInEx/plugins/cache/Foo.dll")));
+    }
+}
@@ -1,6 +1,13 @@
-use std::{borrow::Cow, collections::HashMap, ffi::OsStr, hash::Hash, path::Path};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ffi::OsStr,
+    hash::Hash,
+    path::{Path, PathBuf},
+};
 
 use itertools::Itertools;
+use rkyv::option::ArchivedOption;
 use rkyv::vec::ArchivedVec;
 
 #[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
@@ -18,6 +25,25 @@ impl NativePath {
             Self::Windows(vec) => vec.len(),
         }
     }
+
+    pub fn to_path_buf(&self) -> PathBuf {
+        match self {
+            #[cfg(unix)]
+            Self::Unix(components) => {
+                use std::os::unix::ffi::OsStrExt;
+                components.iter().map(|c| OsStr::from_bytes(c)).collect()
+            }
+            #[cfg(windows)]
+            Self::Windows(components) => {
+                use std::os::windows::ffi::OsStringExt;
+                components
+                    .iter()
+                    .map(|c| std::ffi::OsString::from_wide(c))
+                    .collect()
+            }
+            _ => panic!("Attempted to use an index across operating systems"),
+        }
+    }
 }
 
 impl ArchivedNativePath {
@@ -191,6 +217,37 @@ impl<'a> PartialEq<ArchivedNativePath> for Path {
 pub enum Index {
     V1(HashMap<IndexPath, IndexEntryV1>),
     V2(HashMap<NativePath, IndexEntryV1>),
+    V3(HashMap<NativePath, IndexEntryV2>),
+    V4(HashMap<NativePath, IndexEntryV3>),
+    V5(HashMap<NativePath, IndexEntryV4>),
+}
+
+impl Index {
+    /// Converts the index to the latest version, for modification.
+    pub fn into_latest(self) -> HashMap<NativePath, IndexEntryV4> {
+        match self {
+            Index::V1(entries) => entries
+                .into_iter()
+                .map(|(path, entry)| {
+                    let entry = IndexEntryV3::from(IndexEntryV2::from(entry));
+                    (path.0.iter().collect::<PathBuf>().into(), entry.into())
+                })
+                .collect(),
+            Index::V2(entries) => entries
+                .into_iter()
+                .map(|(path, entry)| (path, IndexEntryV3::from(IndexEntryV2::from(entry)).into()))
+                .collect(),
+            Index::V3(entries) => entries
+                .into_iter()
+                .map(|(path, entry)| (path, IndexEntryV3::from(entry).into()))
+                .collect(),
+            Index::V4(entries) => entries
+                .into_iter()
+                .map(|(path, entry)| (path, entry.into()))
+                .collect(),
+            Index::V5(entries) => entries,
+        }
+    }
 }
 
 impl ArchivedIndex {
@@ -202,6 +259,15 @@ impl ArchivedIndex {
             ArchivedIndex::V2(entries) => entries
                 .get_with(&PathAsNativePath(path), |a, b| a.0 == b)
                 .map(IndexEntryRef::V1),
+            ArchivedIndex::V3(entries) => entries
+                .get_with(&PathAsNativePath(path), |a, b| a.0 == b)
+                .map(IndexEntryRef::V2),
+            ArchivedIndex::V4(entries) => entries
+                .get_with(&PathAsNativePath(path), |a, b| a.0 == b)
+                .map(IndexEntryRef::V3),
+            ArchivedIndex::V5(entries) => entries
+                .get_with(&PathAsNativePath(path), |a, b| a.0 == b)
+                .map(IndexEntryRef::V4),
         }
     }
 }
@@ -209,6 +275,51 @@ impl ArchivedIndex {
 #[derive(Debug, Clone)]
 pub enum IndexEntryRef<'a> {
     V1(&'a ArchivedIndexEntryV1),
+    V2(&'a ArchivedIndexEntryV2),
+    V3(&'a ArchivedIndexEntryV3),
+    V4(&'a ArchivedIndexEntryV4),
+}
+
+impl IndexEntryRef<'_> {
+    /// The Unix mode recorded for the entry, if any.
+    pub fn mode(&self) -> Option<u32> {
+        match self {
+            IndexEntryRef::V1(_)
+            | IndexEntryRef::V2(ArchivedIndexEntryV2::Directory)
+            | IndexEntryRef::V3(ArchivedIndexEntryV3::Directory)
+            | IndexEntryRef::V4(ArchivedIndexEntryV4::Directory) => None,
+            IndexEntryRef::V2(
+                ArchivedIndexEntryV2::File { mode, .. }
+                | ArchivedIndexEntryV2::Symlink { mode, .. },
+            )
+            | IndexEntryRef::V3(
+                ArchivedIndexEntryV3::File { mode, .. }
+                | ArchivedIndexEntryV3::Symlink { mode, .. },
+            )
+            | IndexEntryRef::V4(
+                ArchivedIndexEntryV4::File { mode, .. }
+                | ArchivedIndexEntryV4::Symlink { mode, .. },
+            ) => mode.as_ref().map(|mode| mode.to_native()),
+        }
+    }
+
+    /// The size and modification time recorded for a file, if any.
+    pub fn stat(&self) -> Option<FileStat> {
+        match self {
+            IndexEntryRef::V3(ArchivedIndexEntryV3::File {
+                stat: ArchivedOption::Some(stat),
+                ..
+            })
+            | IndexEntryRef::V4(ArchivedIndexEntryV4::File {
+                stat: ArchivedOption::Some(stat),
+                ..
+            }) => Some(FileStat {
+                len: stat.len.to_native(),
+                modified: stat.modified.to_native(),
+            }),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
@@ -224,6 +335,128 @@ pub enum IndexEntryV1 {
     Directory,
 }
 
+/// Like [`IndexEntryV1`], but also recording the Unix mode of files and symlinks, which is `None`
+/// on other platforms.
+#[derive(Debug, Clone, rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+#[rkyv(derive(Debug))]
+pub enum IndexEntryV2 {
+    File {
+        hash: [u8; blake3::OUT_LEN],
+        mode: Option<u32>,
+    },
+    Symlink {
+        /// This will be relative if it points inside the package directory.
+        target: String,
+        mode: Option<u32>,
+    },
+    Directory,
+}
+
+/// Like [`IndexEntryV2`], but also recording the size and modification time of files, so that
+/// they don't need to be hashed again to tell that they are unchanged.
+#[derive(Debug, Clone, rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+#[rkyv(derive(Debug))]
+pub enum IndexEntryV3 {
+    File {
+        hash: [u8; blake3::OUT_LEN],
+        mode: Option<u32>,
+        /// `None` if the file was indexed before this was recorded, or its modification time is
+        /// unavailable.
+        stat: Option<FileStat>,
+    },
+    Symlink {
+        /// This will be relative if it points inside the package directory.
+        target: String,
+        mode: Option<u32>,
+    },
+    Directory,
+}
+
+/// Like [`IndexEntryV3`], but also recording which files are hard links into the shared
+/// [content store](super::dedupe).
+#[derive(Debug, Clone, rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+#[rkyv(derive(Debug))]
+pub enum IndexEntryV4 {
+    File {
+        hash: [u8; blake3::OUT_LEN],
+        mode: Option<u32>,
+        /// `None` if the file was indexed before this was recorded, or its modification time is
+        /// unavailable.
+        stat: Option<FileStat>,
+        /// Whether the file is the same file as the copy in the content store, which other
+        /// packages may share.
+        linked: bool,
+    },
+    Symlink {
+        /// This will be relative if it points inside the package directory.
+        target: String,
+        mode: Option<u32>,
+    },
+    Directory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
+#[rkyv(derive(Debug))]
+pub struct FileStat {
+    pub len: u64,
+    /// Nanoseconds since the Unix epoch.
+    pub modified: u64,
+}
+
+impl FileStat {
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> Option<Self> {
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+        Some(Self {
+            len: metadata.len(),
+            modified: modified.try_into().ok()?,
+        })
+    }
+}
+
+impl From<IndexEntryV3> for IndexEntryV4 {
+    fn from(value: IndexEntryV3) -> Self {
+        match value {
+            IndexEntryV3::File { hash, mode, stat } => Self::File {
+                hash,
+                mode,
+                stat,
+                linked: false,
+            },
+            IndexEntryV3::Symlink { target, mode } => Self::Symlink { target, mode },
+            IndexEntryV3::Directory => Self::Directory,
+        }
+    }
+}
+
+impl From<IndexEntryV2> for IndexEntryV3 {
+    fn from(value: IndexEntryV2) -> Self {
+        match value {
+            IndexEntryV2::File { hash, mode } => Self::File {
+                hash,
+                mode,
+                stat: None,
+            },
+            IndexEntryV2::Symlink { target, mode } => Self::Symlink { target, mode },
+            IndexEntryV2::Directory => Self::Directory,
+        }
+    }
+}
+
+impl From<IndexEntryV1> for IndexEntryV2 {
+    fn from(value: IndexEntryV1) -> Self {
+        match value {
+            IndexEntryV1::File { hash } => Self::File { hash, mode: None },
+            IndexEntryV1::Symlink { target } => Self::Symlink { target, mode: None },
+            IndexEntryV1::Directory => Self::Directory,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Deserialize, rkyv::Serialize)]
 #[rkyv(derive(Debug, PartialEq, Eq, Hash))]
 #[rkyv(compare(PartialEq))]
@@ -249,3 +482,45 @@ impl<'a> TryFrom<&'a Path> for IndexPath {
             .map(IndexPath)
     }
 }
+
+/// Prefix identifying a zstd-compressed index. Indexes written before compression was introduced
+/// are raw rkyv bytes and do not have it.
+const COMPRESSED_MAGIC: &[u8; 4] = b"MCI\x01";
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Compresses the serialized bytes of an [`Index`] for storage on disk.
+pub fn compress(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut buf = COMPRESSED_MAGIC.to_vec();
+    zstd::stream::copy_encode(bytes, &mut buf, COMPRESSION_LEVEL)?;
+    Ok(buf)
+}
+
+/// Decompresses the contents of an index file read into `buf`, if it is compressed. Legacy
+/// uncompressed indexes are left as they are.
+pub fn decompress_in_place(buf: &mut Vec<u8>) -> std::io::Result<()> {
+    if let Some(data) = buf.strip_prefix(COMPRESSED_MAGIC) {
+        *buf = zstd::stream::decode_all(data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress_in_place};
+
+    #[test]
+    fn test_compression_round_trip() {
+        let bytes = b"some index bytes, some index bytes, some index bytes".to_vec();
+
+        let mut buf = compress(&bytes).unwrap();
+        assert_ne!(buf, bytes);
+        decompress_in_place(&mut buf).unwrap();
+        assert_eq!(buf, bytes);
+
+        // legacy indexes are stored uncompressed
+        let mut buf = bytes.clone();
+        decompress_in_place(&mut buf).unwrap();
+        assert_eq!(buf, bytes);
+    }
+}
@@ -2,14 +2,24 @@
 //!
 //! Never make changes to `IndexEntryV*` or [`Index`] variants. Make a new version instead.
 
+pub mod archive;
+pub mod cache_migration;
 pub mod commands;
+pub mod dedupe;
+pub mod ignore;
 mod index;
+mod streaming;
+pub mod target_validation;
+pub mod test_extract;
+mod walk;
 
 use std::ffi::OsString;
 use std::io::Write;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{hash_map, HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
@@ -17,23 +27,33 @@ use anyhow::{anyhow, bail, ensure, Context, Result};
 use base64::Engine;
 use bytes::{Bytes, BytesMut};
 use fs4::tokio::AsyncFileExt;
-use index::{ArchivedIndex, ArchivedIndexEntryV1, Index, IndexEntryRef, IndexEntryV1, IndexPath};
-use slog::{debug, trace};
+use index::{
+    ArchivedIndex, ArchivedIndexEntryV1, ArchivedIndexEntryV2, ArchivedIndexEntryV3,
+    ArchivedIndexEntryV4, FileStat, Index, IndexEntryRef, IndexEntryV4, IndexPath, NativePath,
+};
+use parking_lot::Mutex;
+use slog::{debug, trace, warn};
+use strum::VariantArray as _;
 use tauri::AppHandle;
 use tempfile::TempDir;
 use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
 use trie_rs::TrieBuilder;
-use walkdir::WalkDir;
-use zip::{result::ZipError, ZipArchive};
+use zip::ZipArchive;
+
+use archive::{extract_archive, ArchiveFormat};
+use ignore::IgnoreList;
+use walk::{walk, walk_sorted, WalkError};
 
+use crate::games::PackageLoader;
 use crate::tasks::{self, TaskBuilder, TaskHandle};
-use crate::util::UsizeExt;
+use crate::util::http::retry_download;
+use crate::util::{fs, UsizeExt};
 use crate::Reqwest;
 use crate::{paths::cache_dir, util::IoErrorKindExt};
 
-const INDEX_FILE_NAME: &str = ".manderrow_content_index";
+pub const INDEX_FILE_NAME: &str = ".manderrow_content_index";
 
-#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub enum Status {
     /// A file had its content modified from that which came with the package.
     ContentModified,
@@ -45,6 +65,11 @@ pub enum Status {
     LinkTargetChanged,
     /// A filesystem object that came with the package was deleted.
     Deleted,
+    /// A symlink points outside of the package's directory. Reported instead of any other status
+    /// of the symlink, whether or not it came with the package.
+    EscapingSymlink,
+    /// Only the Unix permissions of a file or symlink differ from those it came with.
+    PermissionsChanged,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -58,6 +83,9 @@ pub enum ScanError {
     #[error(transparent)]
     WalkDirError(#[from] walkdir::Error),
 
+    #[error(transparent)]
+    WalkError(#[from] WalkError),
+
     #[error("Unable to read package content index: {0}")]
     ReadIndexError(#[source] std::io::Error),
 
@@ -68,23 +96,133 @@ pub enum ScanError {
     Internal(#[source] anyhow::Error),
 }
 
-fn hash_file(path: &Path) -> std::io::Result<blake3::Hash> {
-    Ok(blake3::Hasher::new().update_mmap(&path)?.finalize())
+/// Files at least this large are hashed on multiple threads. The blake3 docs put the crossover
+/// point at around 128 KiB, but that doesn't account for contention with other work on the rayon
+/// pool, so we stay well above it.
+const PARALLEL_HASH_THRESHOLD: u64 = 1024 * 1024;
+
+pub(crate) fn hash_file(path: &Path) -> std::io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    if std::fs::metadata(path)?.len() >= PARALLEL_HASH_THRESHOLD {
+        hasher.update_mmap_rayon(path)?;
+    } else {
+        hasher.update_mmap(path)?;
+    }
+    Ok(hasher.finalize())
+}
+
+/// Computes a hash of the current contents of the package installed at `path`, covering the
+/// relative path, type, and content of every entry. Entries whose relative path satisfies `skip`
+/// and the package's content index are left out. The result doesn't depend on the platform or
+/// file system order, so it can be compared across machines.
+pub fn package_content_hash(path: &Path, skip: impl Fn(&Path) -> bool) -> Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut iter = walk_sorted(path);
+    ensure!(
+        iter.next().context("Expected root entry")??.path() == path,
+        "First entry was not root"
+    );
+    while let Some(r) = iter.next() {
+        let e = r?;
+        let rel_path = e.path().strip_prefix(path)?;
+        if rel_path == Path::new(INDEX_FILE_NAME) || skip(rel_path) {
+            if e.file_type().is_dir() {
+                iter.skip_current_dir();
+            }
+            continue;
+        }
+        let index_path = IndexPath::try_from(rel_path)?;
+        // length-prefix each component so that different paths can't produce the same input
+        hasher.update(&index_path.0.len().as_u64().to_le_bytes());
+        for comp in &index_path.0 {
+            hasher.update(&comp.len().as_u64().to_le_bytes());
+            hasher.update(comp.as_bytes());
+        }
+        if e.file_type().is_dir() {
+            hasher.update(b"d");
+        } else if e.file_type().is_symlink() {
+            let target = std::fs::read_link(e.path())?;
+            let target = target.to_string_lossy();
+            hasher.update(b"l");
+            hasher.update(&target.len().as_u64().to_le_bytes());
+            hasher.update(target.as_bytes());
+        } else {
+            hasher.update(b"f");
+            hasher.update(hash_file(e.path())?.as_bytes());
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+/// Lists the files of the package installed at `path`, relative to `path`, along with whether the
+/// package's content index lists them, i.e. whether they were provided by the package rather than
+/// created afterwards. Returns `None` if the package has no index.
+pub async fn classify_package_files(path: &Path) -> Result<Option<Vec<(PathBuf, bool)>>> {
+    let mut index_buf = match tokio::fs::read(path.join(INDEX_FILE_NAME)).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read package content index"),
+    };
+    tokio::task::block_in_place(|| {
+        index::decompress_in_place(&mut index_buf)?;
+        let index = rkyv::access::<ArchivedIndex, rkyv::rancor::Error>(&index_buf)?;
+        let mut files = Vec::new();
+        let mut iter = walk(path);
+        ensure!(
+            iter.next().context("Expected root entry")??.path() == path,
+            "First entry was not root"
+        );
+        for r in iter {
+            let e = r?;
+            let rel_path = e.path().strip_prefix(path)?;
+            if e.file_type().is_dir() || rel_path == Path::new(INDEX_FILE_NAME) {
+                continue;
+            }
+            files.push((rel_path.to_owned(), index.get(rel_path).is_some()));
+        }
+        Ok(Some(files))
+    })
 }
 
+/// Compares the package installed at `path` against its content index, adding what changed to
+/// `buf`.
+///
+/// Files whose size and modification time match those recorded in the index are assumed to be
+/// unchanged without hashing them, unless `verify` is set.
 pub async fn scan_installed_package_for_changes<'i>(
     log: &slog::Logger,
     path: &Path,
+    ignore: &IgnoreList,
+    verify: bool,
     buf: &mut impl Extend<(PathBuf, Status)>,
 ) -> Result<(), ScanError> {
     let mut index_buf = Vec::new();
-    scan_installed_package_for_changes_with_index_buf(log, path, buf, &mut index_buf).await?;
+    scan_installed_package_for_changes_with_index_buf(
+        log,
+        path,
+        ignore,
+        verify,
+        buf,
+        &mut index_buf,
+    )
+    .await?;
     Ok(())
 }
 
+/// Returns whether the symlink at `rel_path` in the package at `root` points outside of it,
+/// directly or through the other symlinks in the package.
+fn is_escaping_link(root: &Path, rel_path: &Path, target: &Path) -> bool {
+    archive::resolve_link(root, rel_path, target, |p| {
+        std::fs::read_link(root.join(p)).ok()
+    })
+    .is_none()
+}
+
 async fn scan_installed_package_for_changes_with_index_buf<'i>(
     log: &slog::Logger,
     path: &Path,
+    ignore: &IgnoreList,
+    verify: bool,
     buf: &mut impl Extend<(PathBuf, Status)>,
     index_buf: &'i mut Vec<u8>,
 ) -> Result<Option<&'i ArchivedIndex>, ScanError> {
@@ -105,6 +243,8 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
             f.read_to_end(index_buf)
                 .await
                 .map_err(ScanError::ReadIndexError)?;
+            tokio::task::block_in_place(|| index::decompress_in_place(index_buf))
+                .map_err(ScanError::ReadIndexError)?;
             Some(index_buf)
         }
         Err(e) if e.is_not_found() => None,
@@ -118,7 +258,7 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
         None => None,
     };
 
-    let mut iter = WalkDir::new(path).into_iter();
+    let mut iter = walk(path);
     if iter
         .next()
         .context("Expected root entry")
@@ -137,9 +277,18 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
         if rel_path == Path::new(INDEX_FILE_NAME) {
             continue;
         }
+        if ignore.is_ignored(rel_path) {
+            if dir_entry.file_type().is_dir() {
+                iter.skip_current_dir();
+            }
+            continue;
+        }
         if let Some(entry) = index.and_then(|index| index.get(&rel_path)) {
             match entry {
-                IndexEntryRef::V1(ArchivedIndexEntryV1::File { hash }) => {
+                IndexEntryRef::V1(ArchivedIndexEntryV1::File { hash })
+                | IndexEntryRef::V2(ArchivedIndexEntryV2::File { hash, .. })
+                | IndexEntryRef::V3(ArchivedIndexEntryV3::File { hash, .. })
+                | IndexEntryRef::V4(ArchivedIndexEntryV4::File { hash, .. }) => {
                     let hash = blake3::Hash::from_bytes(*hash);
                     if !dir_entry.file_type().is_file() {
                         if dir_entry.file_type().is_dir() {
@@ -147,12 +296,26 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
                             iter.skip_current_dir();
                         }
                         buf.extend_one((dir_entry.path().to_owned(), Status::TypeChanged));
-                    } else if tokio::task::block_in_place(|| hash_file(dir_entry.path()))? != hash {
+                    } else if (verify || !stat_unchanged(&dir_entry, &entry)?)
+                        && tokio::task::block_in_place(|| hash_file(dir_entry.path()))? != hash
+                    {
                         buf.extend_one((dir_entry.path().to_owned(), Status::ContentModified))
+                    } else if permissions_changed(&dir_entry, &entry)? {
+                        buf.extend_one((dir_entry.path().to_owned(), Status::PermissionsChanged))
                     }
                 }
-                IndexEntryRef::V1(ArchivedIndexEntryV1::Symlink { target }) => {
+                IndexEntryRef::V1(ArchivedIndexEntryV1::Symlink { target })
+                | IndexEntryRef::V2(ArchivedIndexEntryV2::Symlink { target, .. })
+                | IndexEntryRef::V3(ArchivedIndexEntryV3::Symlink { target, .. })
+                | IndexEntryRef::V4(ArchivedIndexEntryV4::Symlink { target, .. }) => {
                     match tokio::fs::read_link(dir_entry.path()).await {
+                        Ok(real_target)
+                            if tokio::task::block_in_place(|| {
+                                is_escaping_link(path, rel_path, &real_target)
+                            }) =>
+                        {
+                            buf.extend_one((dir_entry.path().to_owned(), Status::EscapingSymlink));
+                        }
                         Ok(real_target) => {
                             let target = Path::new(target.as_str());
                             let real_target = if target.is_relative() {
@@ -164,11 +327,16 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
                             } else {
                                 &real_target
                             };
-                            if real_target == target {
+                            if real_target != target {
                                 buf.extend_one((
                                     dir_entry.path().to_owned(),
                                     Status::LinkTargetChanged,
                                 ));
+                            } else if permissions_changed(&dir_entry, &entry)? {
+                                buf.extend_one((
+                                    dir_entry.path().to_owned(),
+                                    Status::PermissionsChanged,
+                                ));
                             }
                         }
                         Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
@@ -181,7 +349,10 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
                         Err(e) => return Err(e.into()),
                     }
                 }
-                IndexEntryRef::V1(ArchivedIndexEntryV1::Directory) => {
+                IndexEntryRef::V1(ArchivedIndexEntryV1::Directory)
+                | IndexEntryRef::V2(ArchivedIndexEntryV2::Directory)
+                | IndexEntryRef::V3(ArchivedIndexEntryV3::Directory)
+                | IndexEntryRef::V4(ArchivedIndexEntryV4::Directory) => {
                     if !dir_entry.file_type().is_dir() {
                         buf.extend_one((dir_entry.path().to_owned(), Status::TypeChanged));
                     }
@@ -192,7 +363,15 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
                 // new directory, don't create an entry for each child
                 iter.skip_current_dir();
             }
-            buf.extend_one((dir_entry.path().to_owned(), Status::Created));
+            let status = if dir_entry.file_type().is_symlink() && {
+                let target = tokio::fs::read_link(dir_entry.path()).await?;
+                tokio::task::block_in_place(|| is_escaping_link(path, rel_path, &target))
+            } {
+                Status::EscapingSymlink
+            } else {
+                Status::Created
+            };
+            buf.extend_one((dir_entry.path().to_owned(), status));
         }
     }
 
@@ -204,6 +383,9 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
                 for comp in &*e_path.0 {
                     p.push(comp.as_str());
                 }
+                if ignore.is_ignored(p.strip_prefix(path).unwrap_or(&p)) {
+                    continue;
+                }
                 if !tokio::fs::try_exists(&p).await? {
                     // skip recording if a parent has been deleted.
                     if let Some((entry, _)) = entries.iter().find(|(e_p, _)| {
@@ -222,9 +404,21 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
                 }
             }
         }
-        Some(ArchivedIndex::V2(entries)) => {
+        Some(
+            index @ (ArchivedIndex::V2(_)
+            | ArchivedIndex::V3(_)
+            | ArchivedIndex::V4(_)
+            | ArchivedIndex::V5(_)),
+        ) => {
             // TODO: remove collect when https://github.com/rkyv/rkyv/issues/578 is fixed
-            for indexed_path in entries.iter().map(|(p, _)| p).collect::<Vec<_>>() {
+            let indexed_paths = match index {
+                ArchivedIndex::V2(entries) => entries.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+                ArchivedIndex::V3(entries) => entries.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+                ArchivedIndex::V4(entries) => entries.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+                ArchivedIndex::V5(entries) => entries.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+                ArchivedIndex::V1(_) => unreachable!(),
+            };
+            for &indexed_path in &indexed_paths {
                 let mut p: PathBuf = path.to_owned();
                 for comp in indexed_path.components() {
                     match comp {
@@ -232,9 +426,12 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
                         Cow::Owned(comp) => p.push(comp),
                     }
                 }
+                if ignore.is_ignored(p.strip_prefix(path).unwrap_or(&p)) {
+                    continue;
+                }
                 if !tokio::fs::try_exists(&p).await? {
                     // skip recording if a parent has been deleted.
-                    if let Some((entry, _)) = entries.iter().find(|(e_p, _)| {
+                    if let Some(entry) = indexed_paths.iter().find(|e_p| {
                         e_p.component_count() >= p.components().count()
                             && e_p.components().zip(p.components()).all(|(a, b)| {
                                 b.as_os_str().to_str().map(|b| &*a == b).unwrap_or(false)
@@ -255,11 +452,22 @@ async fn scan_installed_package_for_changes_with_index_buf<'i>(
     Ok(index)
 }
 
-async fn generate_package_index(log: &slog::Logger, path: &Path) -> Result<()> {
+/// Generates the content index of the package at `path`, leaving out the paths that `ignore`
+/// matches. Files with an entry in `known_hashes`, keyed by path relative to `path`, are not read
+/// back to compute their hash.
+async fn generate_package_index(
+    log: &slog::Logger,
+    path: &Path,
+    ignore: &IgnoreList,
+    known_hashes: &HashMap<PathBuf, blake3::Hash>,
+) -> Result<()> {
     debug!(log, "Generating package index for {path:?}");
 
+    let _permit = crate::governor::cpu().await;
+
+    let mut reused = 0usize;
     let mut buf = HashMap::new();
-    let mut iter = WalkDir::new(path).into_iter();
+    let mut iter = walk(path);
     ensure!(
         iter.next().context("Expected root entry")??.path() == path,
         "First entry was not root"
@@ -267,38 +475,170 @@ async fn generate_package_index(log: &slog::Logger, path: &Path) -> Result<()> {
     while let Some(r) = iter.next() {
         let e = r?;
         let rel_path = e.path().strip_prefix(path)?;
-        let index_path = IndexPath::try_from(rel_path)?;
-        let metadata = tokio::fs::symlink_metadata(e.path()).await?;
-        let entry = if metadata.is_file() {
-            IndexEntryV1::File {
-                hash: tokio::task::block_in_place(|| hash_file(e.path()))?.into(),
-            }
-        } else if metadata.is_dir() {
-            IndexEntryV1::Directory
-        } else if metadata.is_symlink() {
-            let target = tokio::fs::read_link(e.path()).await?;
-            let target = if let Ok(rel_target) = target.strip_prefix(path) {
-                rel_target.to_owned()
-            } else {
-                target
-            };
-            IndexEntryV1::Symlink {
-                target: target
-                    .into_os_string()
-                    .into_string()
-                    .map_err(|s| anyhow!("Unsupported path in zip archive: {s:?}"))?,
+        if ignore.is_ignored(rel_path) {
+            if e.file_type().is_dir() {
+                iter.skip_current_dir();
             }
+            continue;
+        }
+        let known_hash = known_hashes.get(rel_path);
+        let entry = index_entry(path, e.path(), known_hash).await?;
+        if known_hash.is_some() && matches!(entry, IndexEntryV4::File { .. }) {
+            reused += 1;
+        }
+        buf.insert(rel_path.into(), entry);
+    }
+    debug!(log, "Reused {reused} known hashes of {} entries", buf.len());
+    let compressed = encode_index(log, &Index::V5(buf))?;
+    tokio::fs::write(path.join(INDEX_FILE_NAME), compressed).await?;
+    Ok(())
+}
+
+/// Builds the index entry for the filesystem object at `path` in the package at `root`. The hash
+/// of a file is only computed if `known_hash` is not given.
+async fn index_entry(
+    root: &Path,
+    path: &Path,
+    known_hash: Option<&blake3::Hash>,
+) -> Result<IndexEntryV4> {
+    let metadata = tokio::fs::symlink_metadata(path).await?;
+    let mode = unix_mode(&metadata);
+    Ok(if metadata.is_file() {
+        let hash = match known_hash {
+            Some(hash) => *hash,
+            None => tokio::task::block_in_place(|| hash_file(path))?,
+        };
+        IndexEntryV4::File {
+            hash: hash.into(),
+            mode,
+            stat: FileStat::from_metadata(&metadata),
+            linked: false,
+        }
+    } else if metadata.is_dir() {
+        IndexEntryV4::Directory
+    } else if metadata.is_symlink() {
+        let target = tokio::fs::read_link(path).await?;
+        let target = if let Ok(rel_target) = target.strip_prefix(root) {
+            rel_target.to_owned()
         } else {
-            bail!(
-                "Unsupported file type in newly extracted package directory: {:?}",
-                metadata.file_type()
-            )
+            target
         };
-        buf.insert(index_path, entry);
+        IndexEntryV4::Symlink {
+            target: target
+                .into_os_string()
+                .into_string()
+                .map_err(|s| anyhow!("Unsupported path in zip archive: {s:?}"))?,
+            mode,
+        }
+    } else {
+        bail!(
+            "Unsupported file type in package directory: {:?}",
+            metadata.file_type()
+        )
+    })
+}
+
+/// The bits of a Unix mode that are recorded and applied: the permissions of the owner, group,
+/// and others, but not setuid, setgid, or sticky, which a package has no business setting.
+const MODE_MASK: u32 = 0o777;
+
+/// Returns the permission bits of the Unix mode in `metadata`, or `None` on other platforms.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn unix_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & MODE_MASK)
     }
-    let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&Index::V1(buf))?;
-    tokio::fs::write(path.join(INDEX_FILE_NAME), bytes).await?;
-    Ok(())
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Returns whether the Unix mode of `dir_entry` differs from the one recorded in its index
+/// `entry`. Entries without a recorded mode never differ.
+fn permissions_changed(
+    dir_entry: &walkdir::DirEntry,
+    entry: &IndexEntryRef<'_>,
+) -> std::io::Result<bool> {
+    let Some(mode) = entry.mode() else {
+        return Ok(false);
+    };
+    Ok(unix_mode(&dir_entry.metadata()?).is_some_and(|actual| actual != mode & MODE_MASK))
+}
+
+/// Returns whether the size and modification time of `dir_entry` match those recorded in its index
+/// `entry`, meaning its contents can be assumed to be unchanged. Entries without them never match.
+fn stat_unchanged(
+    dir_entry: &walkdir::DirEntry,
+    entry: &IndexEntryRef<'_>,
+) -> std::io::Result<bool> {
+    let Some(stat) = entry.stat() else {
+        return Ok(false);
+    };
+    Ok(FileStat::from_metadata(&dir_entry.metadata()?) == Some(stat))
+}
+
+/// Applies the Unix modes recorded in the index of the package at `path` to its files, undoing
+/// any changes to their permissions.
+#[cfg_attr(not(unix), allow(unused_variables))]
+async fn restore_permissions(log: &slog::Logger, path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut index_buf = match tokio::fs::read(path.join(INDEX_FILE_NAME)).await {
+            Ok(t) => t,
+            Err(e) if e.is_not_found() => return Ok(()),
+            Err(e) => return Err(e).context("Failed to read package content index"),
+        };
+        tokio::task::block_in_place(|| {
+            index::decompress_in_place(&mut index_buf)?;
+            let index = rkyv::access::<ArchivedIndex, rkyv::rancor::Error>(&index_buf)?;
+            let mut iter = walk(path);
+            ensure!(
+                iter.next().context("Expected root entry")??.path() == path,
+                "First entry was not root"
+            );
+            for r in iter {
+                let e = r?;
+                // symlinks don't have permissions of their own on Linux
+                if !e.file_type().is_file() {
+                    continue;
+                }
+                let Some(mode) = index
+                    .get(e.path().strip_prefix(path)?)
+                    .and_then(|entry| entry.mode())
+                else {
+                    continue;
+                };
+                let mode = mode & MODE_MASK;
+                if unix_mode(&e.metadata()?) != Some(mode) {
+                    debug!(log, "Restoring mode {mode:o} of {:?}", e.path());
+                    std::fs::set_permissions(e.path(), std::fs::Permissions::from_mode(mode))?;
+                }
+            }
+            Ok(())
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(())
+    }
+}
+
+/// Serializes and compresses an index for storage on disk.
+fn encode_index(log: &slog::Logger, index: &Index) -> Result<Vec<u8>> {
+    let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(index)?;
+    let compressed = tokio::task::block_in_place(|| index::compress(&bytes))?;
+    debug!(
+        log,
+        "Compressed package index from {} to {} bytes",
+        bytes.len(),
+        compressed.len()
+    );
+    Ok(compressed)
 }
 
 fn append_random(buf: &mut OsString, count: usize) {
@@ -309,11 +649,15 @@ fn append_random(buf: &mut OsString, count: usize) {
     }
 }
 
-async fn generate_deletion_path(path: &Path) -> Result<PathBuf, AtomicReplaceError> {
-    // tbd => to be deleted
-    const PREFIX: &str = ".tbd-";
+/// Prefix of the backups of the originals replaced by [`atomic_replace`], which are followed by
+/// [`BACKUP_RAND_COUNT`] random characters, a `-`, and the name of the original.
+const BACKUP_PREFIX: &str = ".bak-";
+const BACKUP_RAND_COUNT: usize = 6;
+
+async fn generate_backup_path(path: &Path) -> Result<PathBuf, AtomicReplaceError> {
+    const PREFIX: &str = BACKUP_PREFIX;
     const SUFFIX: &str = "-";
-    const RAND_COUNT: usize = 6;
+    const RAND_COUNT: usize = BACKUP_RAND_COUNT;
     let mut buf =
         OsString::with_capacity(path.as_os_str().len() + PREFIX.len() + RAND_COUNT + SUFFIX.len());
     buf.push(
@@ -389,9 +733,11 @@ impl<'a> std::fmt::Display for AtomicReplaceMoveReplacementDisplay<'a> {
 }
 
 /// "Atomically" replaces `target` with `from`, which must be on the same file
-/// system. If the operation fails, the original directory at `target`, if any,
-/// will be left behind at a hidden path in the same parent directory as
-/// `target`.
+/// system. The original at `target`, if any, is first moved to a hidden backup
+/// in the same parent directory as `target`, and is only deleted once `from` is
+/// in place. If moving `from` into place fails, the original is moved back. If
+/// the process is interrupted part way, [`recover_interrupted_replace`] restores
+/// the original.
 async fn atomic_replace(target: &Path, from: &Path) -> Result<(), AtomicReplaceError> {
     let metadata = match tokio::fs::metadata(target).await {
         Ok(t) => Some(t),
@@ -400,7 +746,7 @@ async fn atomic_replace(target: &Path, from: &Path) -> Result<(), AtomicReplaceE
     };
     let is_dir = metadata.map(|m| m.is_dir());
     let deletion_path = if is_dir.is_some() {
-        let deletion_path = generate_deletion_path(target).await?;
+        let deletion_path = generate_backup_path(target).await?;
         // Move the original to a hidden file just in case replacing it fails.
         if let Err(cause) = tokio::fs::rename(target, &deletion_path).await {
             return Err(AtomicReplaceError::StageForDeletion {
@@ -412,9 +758,15 @@ async fn atomic_replace(target: &Path, from: &Path) -> Result<(), AtomicReplaceE
     } else {
         None
     };
-    // If this fails, we will likely fail to restore the original, so don't
-    // bother trying. Just let the user know where to find it.
     if let Err(cause) = tokio::fs::rename(from, target).await {
+        // If restoring the original fails too, let the user know where to find it.
+        let deletion_path = match deletion_path {
+            Some(deletion_path) => match tokio::fs::rename(&deletion_path, target).await {
+                Ok(()) => None,
+                Err(_) => Some(deletion_path),
+            },
+            None => None,
+        };
         return Err(AtomicReplaceError::MoveReplacement {
             deletion_path,
             cause,
@@ -422,11 +774,18 @@ async fn atomic_replace(target: &Path, from: &Path) -> Result<(), AtomicReplaceE
     }
     if let Some(deletion_path) = deletion_path {
         let is_dir = is_dir.unwrap();
+        // The backup must not be deleted before the replacement is in place on disk.
+        if let Err(cause) = sync_parent_dir(target).await {
+            return Err(AtomicReplaceError::CleanUp {
+                deletion_path,
+                cause,
+            });
+        }
         // The replacement has succeeded. Delete the original.
         if let Err(cause) = if is_dir {
-            tokio::fs::remove_dir_all(&deletion_path).await
+            fs::remove_dir_all(&deletion_path).await
         } else {
-            tokio::fs::remove_file(&deletion_path).await
+            fs::remove_file(&deletion_path).await
         } {
             return Err(AtomicReplaceError::CleanUp {
                 deletion_path,
@@ -437,28 +796,269 @@ async fn atomic_replace(target: &Path, from: &Path) -> Result<(), AtomicReplaceE
     Ok(())
 }
 
+/// Makes the renames in the parent directory of `path` durable. Does nothing on Windows, where
+/// directories can't be opened like files.
+#[cfg_attr(not(unix), allow(unused_variables))]
+async fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        tokio::fs::File::open(parent).await?.sync_all().await?;
+    }
+    Ok(())
+}
+
+/// Cleans up after an [`atomic_replace`] of `target` that was interrupted, like by a crash or
+/// power loss. If `target` is missing, the most recent backup of the original is moved back into
+/// place. Any other backups are deleted.
+async fn recover_interrupted_replace(log: &slog::Logger, target: &Path) -> Result<()> {
+    let parent = target
+        .parent()
+        .context("Target must not be a filesystem root")?;
+    let Some(target_name) = target.file_name().and_then(|name| name.to_str()) else {
+        return Ok(());
+    };
+    let mut iter = match tokio::fs::read_dir(parent).await {
+        Ok(iter) => iter,
+        Err(e) if e.is_not_found() => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups = Vec::new();
+    while let Some(e) = iter.next_entry().await? {
+        let file_name = e.file_name();
+        let Some(rest) = file_name
+            .to_str()
+            .and_then(|s| s.strip_prefix(BACKUP_PREFIX))
+        else {
+            continue;
+        };
+        if rest.get(BACKUP_RAND_COUNT..) != Some(&format!("-{target_name}")) {
+            continue;
+        }
+        let metadata = e.metadata().await?;
+        backups.push((metadata.modified()?, metadata.is_dir(), e.path()));
+    }
+    if backups.is_empty() {
+        return Ok(());
+    }
+    backups.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+
+    let mut backups = backups.into_iter();
+    if !tokio::fs::try_exists(target).await? {
+        let (_, _, latest) = backups.next().unwrap();
+        warn!(
+            log,
+            "Restoring {target:?} from {latest:?}, left by an interrupted install"
+        );
+        tokio::fs::rename(&latest, target).await?;
+    }
+    for (_, is_dir, path) in backups {
+        debug!(log, "Removing leftover backup {path:?}");
+        if is_dir {
+            fs::remove_dir_all(&path).await?;
+        } else {
+            fs::remove_file(&path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Prefix of the resumable staging directories created next to install targets by
+/// [`install_zip`].
+const STAGING_DIR_PREFIX: &str = ".manderrow-staged-";
+
+/// Resumable staging directories that haven't been touched for this long are assumed to be
+/// abandoned.
+const STAGING_DIR_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+enum StagingDir {
+    Temp(TempDir),
+    /// Named after the archive it was extracted from, and left behind if the install fails so
+    /// that a retry can skip extracting the archive again.
+    Resumable(PathBuf),
+}
+
+impl StagingDir {
+    fn path(&self) -> &Path {
+        match self {
+            Self::Temp(temp_dir) => temp_dir.path(),
+            Self::Resumable(path) => path,
+        }
+    }
+}
+
+/// A file that both a staged package and an installed one provide.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileConflict {
+    /// The path of the file, relative to either package.
+    pub path: PathBuf,
+    /// Where the staged package is being installed.
+    pub package: PathBuf,
+    /// Where the installed package is.
+    pub other: PathBuf,
+}
+
 #[must_use]
 pub struct StagedPackage<'a> {
     target: &'a Path,
-    temp_dir: TempDir,
+    staging_dir: StagingDir,
+    archive_hash: Option<blake3::Hash>,
 }
 
 impl StagedPackage<'_> {
     pub fn path(&self) -> &Path {
-        self.temp_dir.path()
+        self.staging_dir.path()
+    }
+
+    /// Where the package is being installed.
+    pub fn target(&self) -> &Path {
+        self.target
+    }
+
+    /// The hash of the archive the package was extracted from, unless it was extracted while
+    /// being downloaded.
+    pub fn archive_hash(&self) -> Option<blake3::Hash> {
+        self.archive_hash
+    }
+
+    /// Finds the files that the package shares with the installed packages at `others`, according
+    /// to their content indexes, other than those for which `ignore` returns `true`. Only files
+    /// that came with each package count, and packages without an index are skipped.
+    pub async fn conflicts_with(
+        &self,
+        others: &[PathBuf],
+        ignore: impl Fn(&Path) -> bool,
+    ) -> Result<Vec<FileConflict>> {
+        let ignore = &ignore;
+        let provided = move |files: Vec<(PathBuf, bool)>| {
+            files
+                .into_iter()
+                .filter(move |(path, indexed)| *indexed && !ignore(path))
+                .map(|(path, _)| path)
+        };
+        let Some(files) = classify_package_files(self.path()).await? else {
+            return Ok(Vec::new());
+        };
+        let files = provided(files).collect::<HashSet<_>>();
+        let mut conflicts = Vec::new();
+        for other in others {
+            if other == self.target {
+                continue;
+            }
+            let Some(other_files) = classify_package_files(other).await? else {
+                continue;
+            };
+            for path in provided(other_files).filter(|path| files.contains(path)) {
+                conflicts.push(FileConflict {
+                    path,
+                    package: self.target.to_owned(),
+                    other: other.clone(),
+                });
+            }
+        }
+        conflicts.sort_unstable_by(|a, b| (&a.path, &a.other).cmp(&(&b.path, &b.other)));
+        Ok(conflicts)
     }
 
     /// Finishes installing the package by moving the staging directory into place,
     pub async fn finish(self, log: &slog::Logger) -> anyhow::Result<()> {
-        atomic_replace(self.target, self.temp_dir.path()).await?;
-        // the temp directory doesn't exist anymore.
-        // without this, TempDir::drop would try to delete it
-        _ = self.temp_dir.into_path();
+        // the package being replaced may have been linked to the store even if linking is off now
+        let replaced = dedupe::linked_hashes(self.target).await?;
+        if dedupe::is_enabled() {
+            dedupe::link_package_files(log, self.staging_dir.path()).await?;
+        }
+        atomic_replace(self.target, self.staging_dir.path()).await?;
+        if let StagingDir::Temp(temp_dir) = self.staging_dir {
+            // the temp directory doesn't exist anymore.
+            // without this, TempDir::drop would try to delete it
+            _ = temp_dir.into_path();
+        }
         debug!(log, "Installed package to {:?}", self.target);
+        if !replaced.is_empty() {
+            dedupe::release(log, &replaced).await?;
+        }
         Ok(())
     }
 }
 
+fn staging_dir_path(target: &Path, key: blake3::Hash) -> Result<PathBuf> {
+    let mut name = OsString::from(STAGING_DIR_PREFIX);
+    name.push(key.to_hex().as_str());
+    name.push(".");
+    name.push(target.file_name().context("Target must have a file name")?);
+    Ok(target.with_file_name(name))
+}
+
+/// Removes resumable staging directories next to `target` that are either for `target` but not
+/// `keep`, or abandoned.
+async fn gc_staging_dirs(log: &slog::Logger, target: &Path, keep: Option<&Path>) -> Result<()> {
+    let parent = target
+        .parent()
+        .context("Target must not be a filesystem root")?;
+    let target_name = target.file_name().context("Target must have a file name")?;
+    let mut iter = tokio::fs::read_dir(parent).await?;
+    while let Some(e) = iter.next_entry().await? {
+        let file_name = e.file_name();
+        let Some((_, name)) = file_name
+            .to_str()
+            .and_then(|s| s.strip_prefix(STAGING_DIR_PREFIX))
+            .and_then(|s| s.split_once('.'))
+        else {
+            continue;
+        };
+        let path = e.path();
+        if Some(&*path) == keep {
+            continue;
+        }
+        let abandoned = e
+            .metadata()
+            .await?
+            .modified()?
+            .elapsed()
+            .is_ok_and(|age| age > STAGING_DIR_MAX_AGE);
+        if abandoned || target_name == name {
+            debug!(log, "Removing stale staging directory {path:?}");
+            fs::remove_dir_all(&path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Determines whether the staging directory at `staging_path`, left behind by a failed attempt to
+/// install to `target`, can be reused. `changes` are the changes to the installed package that
+/// are about to be merged into it.
+async fn is_reusable_staging_dir(
+    log: &slog::Logger,
+    staging_path: &Path,
+    target: &Path,
+    changes: Option<&[(PathBuf, Status)]>,
+) -> Result<bool> {
+    // the index is generated after extraction completes
+    if !tokio::fs::try_exists(staging_path.join(INDEX_FILE_NAME)).await? {
+        return Ok(false);
+    }
+    let mut leftovers = Vec::new();
+    let ignore = IgnoreList::packages();
+    if let Err(e) =
+        scan_installed_package_for_changes(log, staging_path, ignore, false, &mut leftovers).await
+    {
+        debug!(
+            log,
+            "Unable to reuse staging directory {staging_path:?}: {e}"
+        );
+        return Ok(false);
+    }
+    // changes from a previous attempt at merging are fine, as long as they will be overwritten
+    Ok(leftovers.iter().all(|(path, _)| {
+        let rel_path = path.strip_prefix(staging_path).ok();
+        changes.is_some_and(|changes| {
+            changes
+                .iter()
+                .any(|(path, _)| path.strip_prefix(target).ok() == rel_path)
+        })
+    }))
+}
+
 pub enum CacheKey<'a> {
     Hash(&'a str),
     Url,
@@ -535,36 +1135,41 @@ pub async fn fetch_resource_uncached<'a>(
         .run_with_handle(app, |handle| async move {
             debug!(log, "Fetching resource from {url:?} without caching");
 
-            let mut resp = reqwest.get(url).send().await?.error_for_status()?;
-            let len = resp.content_length();
-            let bytes = if let Some(len) = len {
-                let len = usize::try_from(len).context("Too large to fit in memory")?;
-                let mut bytes = BytesMut::with_capacity(len);
-                let mut total = 0;
-                while let Some(chunk) = resp.chunk().await? {
-                    bytes.extend_from_slice(&chunk);
-                    if let Some(app) = app {
-                        total += chunk.len();
-                        handle.send_progress_manually(app, total.as_u64(), len.as_u64())?;
+            let _permit = crate::governor::network().await;
+            let bytes = retry_download(log, url, || async move {
+                let mut resp = reqwest.get(url).send().await?.error_for_status()?;
+                let len = resp.content_length();
+                let bytes = if let Some(len) = len {
+                    let len = usize::try_from(len).context("Too large to fit in memory")?;
+                    let mut bytes = BytesMut::with_capacity(len);
+                    let mut total = 0;
+                    while let Some(chunk) = resp.chunk().await? {
+                        bytes.extend_from_slice(&chunk);
+                        if let Some(app) = app {
+                            total += chunk.len();
+                            handle.send_progress_manually(app, total.as_u64(), len.as_u64())?;
+                        }
                     }
-                }
-                bytes
-            } else {
-                let mut buf = Vec::new();
-                let mut total = 0;
-                while let Some(chunk) = resp.chunk().await? {
-                    if let Some(app) = app {
-                        total += chunk.len();
-                        handle.send_progress_manually(app, total.as_u64(), 0)?;
+                    bytes
+                } else {
+                    let mut buf = Vec::new();
+                    let mut total = 0;
+                    while let Some(chunk) = resp.chunk().await? {
+                        if let Some(app) = app {
+                            total += chunk.len();
+                            handle.send_progress_manually(app, total.as_u64(), 0)?;
+                        }
+                        buf.push(chunk);
                     }
-                    buf.push(chunk);
-                }
-                let mut bytes = BytesMut::with_capacity(total);
-                for chunk in buf {
-                    bytes.extend_from_slice(&chunk);
-                }
-                bytes
-            };
+                    let mut bytes = BytesMut::with_capacity(total);
+                    for chunk in buf {
+                        bytes.extend_from_slice(&chunk);
+                    }
+                    bytes
+                };
+                Ok(bytes)
+            })
+            .await?;
 
             Ok::<_, anyhow::Error>(bytes)
         })
@@ -589,43 +1194,53 @@ pub async fn fetch_resource_cached_by_hash(
 
             let mut path = cache_dir().join(hash_str);
             path.as_mut_os_string().push(suffix);
+            let _guard = DownloadGuard::new(&path);
             let hash = blake3::Hash::from_hex(hash_str)?;
-            let hash_on_disk = {
-                let mut hsr = blake3::Hasher::new();
-                match hsr.update_mmap(&path) {
-                    Ok(_) => Some(hsr.finalize()),
+            let verified = verified_hash(&path).await == Some(hash);
+            let hash_on_disk = if verified {
+                Some(hash)
+            } else {
+                match tokio::task::block_in_place(|| hash_file(&path)) {
+                    Ok(hash) => Some(hash),
                     Err(e) if e.is_not_found() => None,
                     Err(e) => return Err(e.into()),
                 }
             };
             if hash_on_disk.map(|h| h != hash).unwrap_or(true) {
-                let mut resp = reqwest.get(url).send().await?.error_for_status()?;
                 tokio::fs::create_dir_all(cache_dir()).await?;
-                // TODO: should this be buffered?
-                let mut wtr = tokio::fs::File::create(&path).await?;
-                let mut written = 0u64;
-                let len = resp.content_length();
-                if let (Some(app), Some(total)) = (app, len) {
-                    handle.send_progress_manually(app, written, total)?;
-                }
-                while let Some(chunk) = resp.chunk().await? {
-                    wtr.write_all(&chunk).await?;
-                    if let Some(app) = app {
-                        written += chunk.len().as_u64();
-                        handle.send_progress_manually(app, written, len.unwrap_or(0))?;
-                    }
-                }
-                let hash_on_disk = {
-                    let mut hsr = blake3::Hasher::new();
-                    hsr.update_mmap(&path)?;
-                    hsr.finalize()
+                // a file with the wrong hash is most likely what an interrupted download left
+                let mut offset = match tokio::fs::metadata(&path).await {
+                    Ok(metadata) => metadata.len(),
+                    Err(e) if e.is_not_found() => 0,
+                    Err(e) => return Err(e.into()),
                 };
-                debug!(log, "Cached resource at {path:?}");
-                if hash_on_disk != hash {
+                let _permit = crate::governor::network().await;
+                loop {
+                    let mut from = offset;
+                    let resumed = retry_download(log, url, || {
+                        // a retry starts over, so that a partly written body isn't appended to
+                        let offset = std::mem::take(&mut from);
+                        download_to_file(app, handle, log, reqwest, url, &path, offset)
+                    })
+                    .await?;
+                    let hash_on_disk = tokio::task::block_in_place(|| hash_file(&path))?;
+                    debug!(log, "Cached resource at {path:?}");
+                    if hash_on_disk == hash {
+                        break;
+                    }
+                    if resumed {
+                        warn!(log, "Resumed download of {url:?} is corrupt, starting over");
+                        offset = 0;
+                        continue;
+                    }
                     bail!("Bad hash of downloaded resource: expected {hash}, found {hash_on_disk}");
                 }
+                mark_verified(log, &path, &hash).await;
             } else {
                 debug!(log, "Resource is cached at {path:?}");
+                if !verified {
+                    mark_verified(log, &path, &hash).await;
+                }
                 let metadata = tokio::fs::metadata(&path).await?;
                 report_progress_from_file_metadata(app, handle, metadata)?;
             }
@@ -635,83 +1250,231 @@ pub async fn fetch_resource_cached_by_hash(
         .map_err(Into::into)
 }
 
-fn report_progress_from_file_metadata(
-    app: Option<&AppHandle>,
-    handle: TaskHandle,
-    metadata: std::fs::Metadata,
-) -> Result<(), anyhow::Error> {
-    #[cfg(windows)]
-    let size = {
-        use std::os::windows::fs::MetadataExt;
-        metadata.file_size()
-    };
-    #[cfg(unix)]
-    let size = {
-        use std::os::unix::fs::MetadataExt;
-        metadata.size()
-    };
-    if let Some(app) = app {
-        handle.send_progress_manually(app, size, size)?;
+/// Suffix of the file recording the hash of a cached resource, next to the resource. While the
+/// resource's size and modification time are unchanged, it is trusted without being hashed again.
+const VERIFIED_SUFFIX: &str = ".verified";
+
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct VerifiedMarker {
+    hash: String,
+    len: u64,
+    /// Nanoseconds since the Unix epoch.
+    modified: u64,
+}
+
+impl VerifiedMarker {
+    fn new(hash: &blake3::Hash, metadata: &std::fs::Metadata) -> Option<Self> {
+        let stat = FileStat::from_metadata(metadata)?;
+        Some(Self {
+            hash: hash.to_hex().to_string(),
+            len: stat.len,
+            modified: stat.modified,
+        })
     }
-    Ok(())
 }
 
-pub async fn fetch_resource_cached_by_url(
-    app: Option<&AppHandle>,
-    log: &slog::Logger,
-    reqwest: &Reqwest,
-    url: &str,
-    suffix: &str,
-    task_id: Option<tasks::Id>,
-) -> Result<PathBuf> {
-    TaskBuilder::with_id(task_id.unwrap_or_else(tasks::allocate_task), url.to_owned())
-        .kind(tasks::Kind::Download)
-        .progress_unit(tasks::ProgressUnit::Bytes)
-        .run_with_handle(app, |handle| async move {
-            debug!(log, "Fetching resource from {url:?} cached by url");
+fn verified_marker_path(path: &Path) -> PathBuf {
+    let mut path = path.to_owned();
+    path.as_mut_os_string().push(VERIFIED_SUFFIX);
+    path
+}
 
-            let mut path = cache_dir().join("url.");
-            path.as_mut_os_string()
-                .push(base64::engine::general_purpose::URL_SAFE.encode(url));
-            path.as_mut_os_string().push(suffix);
-            match tokio::fs::metadata(&path).await {
-                Ok(metadata) => {
-                    debug!(log, "Resource is cached at {path:?}");
-                    report_progress_from_file_metadata(app, handle, metadata)?;
-                }
-                Err(e) if e.is_not_found() => {
-                    tokio::fs::create_dir_all(cache_dir()).await?;
+/// Returns the hash the resource cached at `path` was verified to have, unless it has been
+/// modified since.
+async fn verified_hash(path: &Path) -> Option<blake3::Hash> {
+    let bytes = tokio::fs::read(verified_marker_path(path)).await.ok()?;
+    let marker = serde_json::from_slice::<VerifiedMarker>(&bytes).ok()?;
+    let hash = blake3::Hash::from_hex(&marker.hash).ok()?;
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    (VerifiedMarker::new(&hash, &metadata)? == marker).then_some(hash)
+}
 
-                    let (tmp_file, tmp_path) = tokio::task::block_in_place(|| {
-                        tempfile::NamedTempFile::new_in(
-                            path.parent().context("path must have a parent")?,
-                        )
-                        .map_err(anyhow::Error::from)
-                    })?
-                    .into_parts();
+/// Records that the resource cached at `path` was just verified to have `hash`. Failing to do so
+/// only means it will be hashed again next time.
+async fn mark_verified(log: &slog::Logger, path: &Path, hash: &blake3::Hash) {
+    let result = async {
+        let metadata = tokio::fs::metadata(path).await?;
+        let marker =
+            VerifiedMarker::new(hash, &metadata).context("Modification time unavailable")?;
+        tokio::fs::write(verified_marker_path(path), serde_json::to_vec(&marker)?).await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+    if let Err(e) = result {
+        debug!(log, "Failed to record verification of {path:?}: {e}");
+    }
+}
 
-                    let mut resp = reqwest.get(url).send().await?.error_for_status()?;
+/// Paths in the cache that are being downloaded to or checked, which [`prune_cache`] must leave
+/// alone, with the number of [`DownloadGuard`]s for each.
+static DOWNLOADING: LazyLock<Mutex<HashMap<PathBuf, usize>>> = LazyLock::new(Default::default);
 
-                    let tmp_file = tokio::fs::File::from_std(tmp_file);
+/// Marks a path in the cache as being downloaded to until dropped. A path may be marked more than
+/// once at a time, and stays marked until every guard for it is dropped.
+struct DownloadGuard(PathBuf);
 
-                    let len = resp.content_length();
-                    if let Some(len) = len {
-                        tmp_file.allocate(len).await?;
-                    }
+impl DownloadGuard {
+    fn new(path: &Path) -> Self {
+        *DOWNLOADING.lock().entry(path.to_owned()).or_default() += 1;
+        Self(path.to_owned())
+    }
+}
 
-                    // TODO: should this be buffered?
-                    let mut wtr = tmp_file;
-                    let mut written = 0u64;
-                    if let (Some(app), Some(total)) = (app, len) {
-                        handle.send_progress_manually(app, written, total)?;
-                    }
-                    while let Some(chunk) = resp.chunk().await? {
-                        wtr.write_all(&chunk).await?;
-                        if let Some(app) = app {
-                            written += chunk.len().as_u64();
-                            handle.send_progress_manually(app, written, len.unwrap_or(0))?;
-                        }
-                    }
+impl Drop for DownloadGuard {
+    fn drop(&mut self) {
+        let mut downloading = DOWNLOADING.lock();
+        if let Some(count) = downloading.get_mut(&self.0) {
+            *count -= 1;
+            if *count == 0 {
+                downloading.remove(&self.0);
+            }
+        }
+    }
+}
+
+/// Downloads `url` to `path`. If `offset` is nonzero, the download continues from that byte of
+/// the existing file, unless the server doesn't support range requests, in which case the file is
+/// downloaded in full. Returns whether the download was resumed.
+async fn download_to_file(
+    app: Option<&AppHandle>,
+    handle: TaskHandle,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    path: &Path,
+    offset: u64,
+) -> Result<bool> {
+    let request = |offset: u64| {
+        let request = reqwest.get(url);
+        if offset == 0 {
+            request
+        } else {
+            request.header(reqwest::header::RANGE, format!("bytes={offset}-"))
+        }
+    };
+    let mut resp = request(offset).send().await?;
+    if offset != 0 && resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // the existing file is at least as long as the resource
+        resp = request(0).send().await?;
+    }
+    let mut resp = resp.error_for_status()?;
+    let resumed = offset != 0 && resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let (mut wtr, mut written) = if resumed {
+        debug!(log, "Resuming download of {url:?} from byte {offset}");
+        let wtr = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .await?;
+        (wtr, offset)
+    } else {
+        (tokio::fs::File::create(path).await?, 0)
+    };
+    // TODO: should this be buffered?
+    let len = resp.content_length().map(|len| written + len);
+    if let (Some(app), Some(total)) = (app, len) {
+        handle.send_progress_manually(app, written, total)?;
+    }
+    while let Some(chunk) = resp.chunk().await? {
+        wtr.write_all(&chunk).await?;
+        if let Some(app) = app {
+            written += chunk.len().as_u64();
+            handle.send_progress_manually(app, written, len.unwrap_or(0))?;
+        }
+    }
+    wtr.flush().await?;
+    Ok(resumed)
+}
+
+fn report_progress_from_file_metadata(
+    app: Option<&AppHandle>,
+    handle: TaskHandle,
+    metadata: std::fs::Metadata,
+) -> Result<(), anyhow::Error> {
+    #[cfg(windows)]
+    let size = {
+        use std::os::windows::fs::MetadataExt;
+        metadata.file_size()
+    };
+    #[cfg(unix)]
+    let size = {
+        use std::os::unix::fs::MetadataExt;
+        metadata.size()
+    };
+    if let Some(app) = app {
+        handle.send_progress_manually(app, size, size)?;
+    }
+    Ok(())
+}
+
+const URL_CACHE_PREFIX: &str = "url.";
+
+/// Returns the path at which [`fetch_resource_cached_by_url`] caches the resource at `url`.
+pub fn url_cache_path(url: &str, suffix: &str) -> PathBuf {
+    let mut path = cache_dir().join(URL_CACHE_PREFIX);
+    path.as_mut_os_string()
+        .push(base64::engine::general_purpose::URL_SAFE.encode(url));
+    path.as_mut_os_string().push(suffix);
+    path
+}
+
+/// Recovers the URL of a resource from the file name of its entry in the cache, if it was cached
+/// by URL with the given `suffix`.
+pub fn url_from_cache_file_name(file_name: &str, suffix: &str) -> Option<String> {
+    let encoded = file_name
+        .strip_prefix(URL_CACHE_PREFIX)?
+        .strip_suffix(suffix)?;
+    let url = base64::engine::general_purpose::URL_SAFE
+        .decode(encoded)
+        .ok()?;
+    String::from_utf8(url).ok()
+}
+
+/// Deletes the given entries from the cache. Each entry is a file name in the cache directory.
+pub async fn delete_cache_entries(file_names: &[String]) -> Result<()> {
+    for file_name in file_names {
+        ensure!(
+            matches!(
+                Path::new(file_name).components().collect::<Vec<_>>()[..],
+                [std::path::Component::Normal(_)]
+            ),
+            "Invalid cache entry {file_name:?}"
+        );
+        match fs::remove_file(&cache_dir().join(file_name)).await {
+            Ok(()) => {}
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+pub async fn fetch_resource_cached_by_url(
+    app: Option<&AppHandle>,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    suffix: &str,
+    task_id: Option<tasks::Id>,
+) -> Result<PathBuf> {
+    TaskBuilder::with_id(task_id.unwrap_or_else(tasks::allocate_task), url.to_owned())
+        .kind(tasks::Kind::Download)
+        .progress_unit(tasks::ProgressUnit::Bytes)
+        .run_with_handle(app, |handle| async move {
+            debug!(log, "Fetching resource from {url:?} cached by url");
+
+            let path = url_cache_path(url, suffix);
+            match tokio::fs::metadata(&path).await {
+                Ok(metadata) => {
+                    debug!(log, "Resource is cached at {path:?}");
+                    report_progress_from_file_metadata(app, handle, metadata)?;
+                }
+                Err(e) if e.is_not_found() => {
+                    tokio::fs::create_dir_all(cache_dir()).await?;
+
+                    let dir = path.parent().context("path must have a parent")?;
+                    let _permit = crate::governor::network().await;
+                    let tmp_path =
+                        download_to_temp_file(app, handle, log, reqwest, url, dir).await?;
 
                     let tmp_path = tmp_path.keep()?;
                     tokio::fs::rename(&tmp_path, &path)
@@ -728,6 +1491,74 @@ pub async fn fetch_resource_cached_by_url(
         .map_err(Into::into)
 }
 
+/// Downloads the resource at `url` to a temp file in `dir`, which is deleted when the returned
+/// path is dropped. Unlike [`fetch_resource_uncached`], the memory used doesn't grow with the size
+/// of the resource.
+pub async fn fetch_resource_to_temp_file(
+    app: Option<&AppHandle>,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    dir: &Path,
+    task_id: Option<tasks::Id>,
+) -> Result<tempfile::TempPath> {
+    TaskBuilder::with_id(task_id.unwrap_or_else(tasks::allocate_task), url.to_owned())
+        .kind(tasks::Kind::Download)
+        .progress_unit(tasks::ProgressUnit::Bytes)
+        .run_with_handle(app, |handle| async move {
+            debug!(log, "Fetching resource from {url:?} to a temp file");
+
+            let _permit = crate::governor::network().await;
+            download_to_temp_file(app, handle, log, reqwest, url, dir).await
+        })
+        .await
+        .map_err(Into::into)
+}
+
+/// Downloads the resource at `url` to a new temp file in `dir`, retrying on transient errors.
+async fn download_to_temp_file(
+    app: Option<&AppHandle>,
+    handle: TaskHandle,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    dir: &Path,
+) -> Result<tempfile::TempPath> {
+    // every attempt writes a new temp file, so a partly written body is discarded
+    retry_download(log, url, || async move {
+        let (tmp_file, tmp_path) = tokio::task::block_in_place(|| {
+            tempfile::NamedTempFile::new_in(dir).map_err(anyhow::Error::from)
+        })?
+        .into_parts();
+
+        let mut resp = reqwest.get(url).send().await?.error_for_status()?;
+
+        let tmp_file = tokio::fs::File::from_std(tmp_file);
+
+        let len = resp.content_length();
+        if let Some(len) = len {
+            tmp_file.allocate(len).await?;
+        }
+
+        // TODO: should this be buffered?
+        let mut wtr = tmp_file;
+        let mut written = 0u64;
+        if let (Some(app), Some(total)) = (app, len) {
+            handle.send_progress_manually(app, written, total)?;
+        }
+        while let Some(chunk) = resp.chunk().await? {
+            wtr.write_all(&chunk).await?;
+            if let Some(app) = app {
+                written += chunk.len().as_u64();
+                handle.send_progress_manually(app, written, len.unwrap_or(0))?;
+            }
+        }
+
+        Ok(tmp_path)
+    })
+    .await
+}
+
 pub async fn fetch_resource_as_bytes<'a>(
     app: Option<&AppHandle>,
     log: &slog::Logger,
@@ -744,7 +1575,489 @@ pub async fn fetch_resource_as_bytes<'a>(
     }
 }
 
-/// Downloads a zip file from `url` and installs it into the `target` directory.
+/// The name of the package manifest at the root of a package archive.
+const PACKAGE_MANIFEST_NAME: &str = "manifest.json";
+
+/// How many bytes [`install_zip`] extracts from an archive at most by default. Anything larger is
+/// most likely malformed or malicious, like a zip bomb.
+pub const DEFAULT_MAX_EXTRACTED_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Returns the name of the directory that wraps all of the archive's content, if any.
+///
+/// Packages are expected to have their manifest at the top level, but some are zipped together
+/// with their containing directory, which must be stripped so that their files land where the mod
+/// loader looks for them. An archive is only considered wrapped if its only top-level entry is a
+/// directory containing the manifest, and that directory isn't one that a mod loader expects at
+/// the top level of a package, like `BepInExPack`.
+fn find_wrapper_dir<R: std::io::Read + std::io::Seek>(archive: &ZipArchive<R>) -> Option<String> {
+    find_wrapper(archive.file_names(), |name| {
+        archive.index_for_name(name).is_some()
+    })
+    .map(str::to_owned)
+}
+
+/// Implements [`find_wrapper_dir`] for any archive format, given the names of the entries, with
+/// directories ending in `/`, and a way to check whether an entry exists.
+fn find_wrapper<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    contains: impl FnOnce(&str) -> bool,
+) -> Option<&'a str> {
+    let mut wrapper = None;
+    for name in names {
+        // a top-level file means there is no wrapper
+        let (top_level, _) = name.split_once('/')?;
+        match wrapper {
+            None => wrapper = Some(top_level),
+            Some(w) if w == top_level => {}
+            Some(_) => return None,
+        }
+    }
+    let wrapper = wrapper?;
+    if PackageLoader::VARIANTS
+        .iter()
+        .any(|loader| loader.required_package_entries().contains(&wrapper))
+    {
+        return None;
+    }
+    contains(&format!("{wrapper}/{PACKAGE_MANIFEST_NAME}")).then_some(wrapper)
+}
+
+/// Strips the wrapper directory found by [`find_wrapper_dir`] from the name of an archive entry.
+fn strip_wrapper<'a>(name: &'a str, wrapper: Option<&str>) -> &'a str {
+    wrapper
+        .and_then(|w| name.strip_prefix(w)?.strip_prefix('/'))
+        .unwrap_or(name)
+}
+
+/// Reads the top-level entries that the package's manifest declares as optional in its
+/// `optional_paths` array. Returns an empty list if there is no manifest.
+fn read_optional_components<R: std::io::Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<String>> {
+    use std::io::Read as _;
+
+    let manifest_name = match find_wrapper_dir(archive) {
+        Some(wrapper) => Cow::Owned(format!("{wrapper}/{PACKAGE_MANIFEST_NAME}")),
+        None => Cow::Borrowed(PACKAGE_MANIFEST_NAME),
+    };
+    let mut bytes = Vec::new();
+    match archive.by_name(&manifest_name) {
+        Ok(mut file) => file.read_to_end(&mut bytes)?,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    parse_optional_components(&bytes)
+}
+
+/// Parses the `optional_paths` array of a package manifest.
+fn parse_optional_components(bytes: &[u8]) -> Result<Vec<String>> {
+    #[derive(serde::Deserialize)]
+    struct Manifest {
+        #[serde(default)]
+        optional_paths: Vec<String>,
+    }
+
+    // manifests are frequently written with a BOM
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let Manifest { optional_paths } =
+        serde_json::from_slice(bytes).context("Invalid package manifest")?;
+    Ok(optional_paths
+        .into_iter()
+        .map(|path| path.trim_matches('/').to_owned())
+        .collect())
+}
+
+/// Lists the optional components of the package archive at `url`, which may be excluded when
+/// installing it with [`install_zip`].
+pub async fn list_optional_components(
+    app: Option<&AppHandle>,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    cache: Option<CacheOptions<'_>>,
+    task_id: Option<tasks::Id>,
+) -> Result<Vec<String>> {
+    let cache = cache.map(|c| c.with_suffix(".zip"));
+    match fetch_resource(app, log, reqwest, url, cache, task_id).await? {
+        FetchedResource::Bytes(bytes) => tokio::task::block_in_place(|| {
+            let mut rdr = std::io::Cursor::new(bytes);
+            let format = ArchiveFormat::detect_from(&mut rdr, url)?;
+            archive::read_optional_components(format, rdr)
+        }),
+        FetchedResource::File(path) => tokio::task::block_in_place(|| {
+            let mut rdr = std::io::BufReader::new(std::fs::File::open(&path)?);
+            let format = ArchiveFormat::detect_from(&mut rdr, url)?;
+            archive::read_optional_components(format, rdr)
+        }),
+    }
+}
+
+/// Checks that every component in `exclude` is declared optional by the package.
+fn validate_exclusions<R: std::io::Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    exclude: &[String],
+) -> Result<()> {
+    if exclude.is_empty() {
+        return Ok(());
+    }
+    let optional = read_optional_components(archive)?;
+    for component in exclude {
+        ensure!(
+            optional.contains(component),
+            "{component:?} is not an optional component of the package"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the number of bytes available to unprivileged users on the filesystem containing
+/// `path`. The path doesn't need to exist yet, in which case its closest existing ancestor is
+/// used.
+pub fn free_space(path: &Path) -> std::io::Result<u64> {
+    let mut path = path;
+    loop {
+        match fs4::available_space(path) {
+            Err(e) if e.is_not_found() => match path.parent() {
+                Some(parent) => path = parent,
+                None => return Err(e),
+            },
+            r => return r,
+        }
+    }
+}
+
+/// Fails early if the extracted contents of `archive` would be larger than `max_size`, or if the
+/// filesystem containing `dest` cannot hold them, rather than letting extraction run out of space
+/// halfway through. Sizes are as declared by the archive, which [`copy_entry`] holds entries to.
+fn check_extracted_size<R: std::io::Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    dest: &Path,
+    exclude: &[String],
+    max_size: u64,
+) -> Result<()> {
+    let wrapper = find_wrapper_dir(archive);
+    let mut collisions = CaseCollisions::new(dest)?;
+    let mut required = 0u64;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let name = strip_wrapper(file.name(), wrapper.as_deref());
+        let top_level = name.split('/').next().unwrap_or_default();
+        if !exclude.iter().any(|e| e == top_level) {
+            collisions.check(name)?;
+            required = required.saturating_add(file.size());
+        }
+    }
+    ensure_extracted_size(dest, required, max_size)
+}
+
+/// Detects archive entries that would be extracted to the same path, because they differ only in
+/// case and the filesystem they are extracted to ignores it.
+struct CaseCollisions {
+    /// The names of the entries seen so far by their lowercase form, or `None` if the filesystem
+    /// is case-sensitive.
+    seen: Option<HashMap<String, String>>,
+}
+
+impl CaseCollisions {
+    /// Checks the case sensitivity of the filesystem containing the directory `dir`, rather than
+    /// assuming it from the platform, since macOS and Windows both support case-sensitive ones.
+    fn new(dir: &Path) -> Result<Self> {
+        let case_sensitive = is_case_sensitive(dir)
+            .with_context(|| format!("Failed to check the case sensitivity of {dir:?}"))?;
+        Ok(Self {
+            seen: (!case_sensitive).then(HashMap::new),
+        })
+    }
+
+    /// Fails if the entry named `name`, with `/` separated components, collides with one checked
+    /// before. Directories only collide with files, since their contents are merged.
+    fn check(&mut self, name: &str) -> Result<()> {
+        let Some(seen) = &mut self.seen else {
+            return Ok(());
+        };
+        let path = name.trim_end_matches('/');
+        if path.is_empty() {
+            return Ok(());
+        }
+        match seen.entry(path.to_lowercase()) {
+            hash_map::Entry::Occupied(e) => {
+                let other = e.get();
+                let both_dirs = name.ends_with('/') && other.ends_with('/');
+                ensure!(
+                    other.trim_end_matches('/') == path || both_dirs,
+                    "Archive entries {other:?} and {name:?} differ only in case, and would \
+                     overwrite each other on a case-insensitive filesystem"
+                );
+            }
+            hash_map::Entry::Vacant(e) => {
+                e.insert(name.to_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether the filesystem containing the directory `dir` distinguishes file names that
+/// differ only in case.
+fn is_case_sensitive(dir: &Path) -> std::io::Result<bool> {
+    let probe = tempfile::Builder::new()
+        .prefix(".manderrow-case-probe-")
+        .tempfile_in(dir)?;
+    let name = probe
+        .path()
+        .file_name()
+        .expect("temp file must have a name")
+        .to_string_lossy()
+        .to_uppercase();
+    match std::fs::symlink_metadata(dir.join(name)) {
+        Ok(_) => Ok(false),
+        Err(e) if e.is_not_found() => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn ensure_extracted_size(dest: &Path, required: u64, max_size: u64) -> Result<()> {
+    ensure!(
+        required <= max_size,
+        "Archive would extract to {required} bytes, more than the limit of {max_size} bytes"
+    );
+    let available = free_space(dest)?;
+    ensure!(
+        available >= required,
+        "Not enough disk space to install to {dest:?}: {required} bytes needed, {available} bytes \
+         available"
+    );
+    Ok(())
+}
+
+/// Copies the contents of an archive entry to `wtr`, returning their hash. Fails if the entry
+/// turns out to be larger than its declared `size`, or than the `remaining` number of bytes that
+/// may still be extracted from the archive, which is reduced by the entry's size.
+fn copy_entry(
+    rdr: &mut impl std::io::Read,
+    wtr: &mut impl Write,
+    buf: &mut [u8],
+    name: &Path,
+    size: u64,
+    remaining: &mut u64,
+) -> Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut written = 0u64;
+    loop {
+        let n = rdr.read(buf)?;
+        if n == 0 {
+            break;
+        }
+        written += n.as_u64();
+        ensure!(
+            written <= size,
+            "{name:?} in archive is larger than its declared size of {size} bytes"
+        );
+        ensure!(
+            written <= *remaining,
+            "Archive extracts to more than the limit, at {name:?}"
+        );
+        hasher.update(&buf[..n]);
+        wtr.write_all(&buf[..n])?;
+    }
+    *remaining -= written;
+    Ok(hasher.finalize())
+}
+
+/// The symlinks extracted from an archive so far and their targets, by path relative to the
+/// destination.
+///
+/// Links may only lead within the destination, following the other links, and no entry may be
+/// extracted through or over one.
+#[derive(Default)]
+struct ExtractedLinks(HashMap<PathBuf, PathBuf>);
+
+impl ExtractedLinks {
+    /// Fails if the entry at `rel_path` would be extracted through or over a symlink from the
+    /// archive.
+    fn check(&self, rel_path: &Path) -> Result<()> {
+        if self.0.contains_key(rel_path) {
+            bail!("{rel_path:?} in archive would replace the symlink extracted there");
+        }
+        if let Some(link) = rel_path
+            .ancestors()
+            .skip(1)
+            .find(|p| self.0.contains_key(*p))
+        {
+            bail!("{rel_path:?} in archive is inside the symlink {link:?}");
+        }
+        Ok(())
+    }
+
+    fn is_contained(&self, dest: &Path, rel_path: &Path, target: &Path) -> bool {
+        archive::resolve_link(dest, rel_path, target, |p| self.0.get(p).cloned()).is_some()
+    }
+
+    /// Creates the symlink at `rel_path` in `dest`, failing if `target` leads outside of `dest`.
+    fn create(&mut self, dest: &Path, rel_path: &Path, target: &str) -> Result<()> {
+        ensure!(
+            self.is_contained(dest, rel_path, Path::new(target)),
+            "Symlink {rel_path:?} in archive points outside of the package: {target:?}"
+        );
+        create_symlink(target, &dest.join(rel_path), dest)?;
+        self.0.insert(rel_path.to_owned(), target.into());
+        Ok(())
+    }
+
+    /// Fails if any of the links leads outside of `dest`, now that all of them are extracted. A
+    /// link extracted later can redirect one extracted earlier.
+    fn finish(&self, dest: &Path) -> Result<()> {
+        for (rel_path, target) in &self.0 {
+            ensure!(
+                self.is_contained(dest, rel_path, target),
+                "Symlink {rel_path:?} in archive points outside of the package: {target:?}"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Creates the file for an archive entry at `path`, replacing rather than following whatever is
+/// there already.
+fn create_entry_file(path: &Path) -> std::io::Result<std::fs::File> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.is_not_found() => {}
+        Err(e) => return Err(e),
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+/// Creates a symlink extracted from an archive at `path` in `dest`.
+#[cfg_attr(not(windows), allow(unused_variables))]
+fn create_symlink(target: &str, path: &Path, dest: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, path)?;
+    #[cfg(windows)]
+    if path.parent().unwrap_or(dest).join(target).is_dir() {
+        std::os::windows::fs::symlink_dir(target, path)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, path)?;
+    }
+    Ok(())
+}
+
+/// Extracts `archive` into `dest`, hashing files as they are written. Returns the hashes keyed by
+/// path relative to `dest` so that the package index can be generated without reading the files
+/// back. Top-level entries named in `exclude` are skipped. If the archive's content is wrapped in
+/// a directory (see [`find_wrapper_dir`]), it is extracted as if it wasn't.
+pub(crate) fn extract_zip<R: std::io::Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    dest: &Path,
+    exclude: &[String],
+) -> Result<HashMap<PathBuf, blake3::Hash>> {
+    extract_zip_with_progress(archive, dest, exclude, u64::MAX, &mut |_, _| {})
+}
+
+/// Like [`extract_zip`], failing if more than `max_size` bytes would be extracted, and calling
+/// `progress` with the number of entries processed so far and the total number of entries as
+/// extraction proceeds.
+fn extract_zip_with_progress<R: std::io::Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    dest: &Path,
+    exclude: &[String],
+    max_size: u64,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<HashMap<PathBuf, blake3::Hash>> {
+    use std::io::Read as _;
+
+    let wrapper = find_wrapper_dir(archive);
+    let mut hashes = HashMap::with_capacity(archive.len());
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut remaining = max_size;
+    let mut links = ExtractedLinks::default();
+    let total = archive.len().as_u64();
+    for i in 0..archive.len() {
+        progress(i.as_u64(), total);
+        let mut file = archive.by_index(i)?;
+        let mut rel_path = file
+            .enclosed_name()
+            .with_context(|| format!("Invalid path in zip archive: {:?}", file.name()))?
+            .components()
+            // a leading `.` would make the path compare unequal to the same path without it
+            .filter(|c| !matches!(c, std::path::Component::CurDir))
+            .collect::<PathBuf>();
+        if let Some(wrapper) = &wrapper {
+            rel_path = rel_path.strip_prefix(wrapper)?.to_owned();
+            if rel_path.as_os_str().is_empty() {
+                continue;
+            }
+        }
+        if rel_path
+            .components()
+            .next()
+            .and_then(|c| c.as_os_str().to_str())
+            .is_some_and(|top_level| exclude.iter().any(|e| e == top_level))
+        {
+            continue;
+        }
+        links.check(&rel_path)?;
+        let path = dest.join(&rel_path);
+        if file.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            links.create(dest, &rel_path, &target)?;
+            continue;
+        }
+        let mut wtr = create_entry_file(&path)?;
+        let size = file.size();
+        let hash = copy_entry(
+            &mut file,
+            &mut wtr,
+            &mut buf,
+            &rel_path,
+            size,
+            &mut remaining,
+        )?;
+        apply_entry_mode(&path, file.unix_mode())?;
+        hashes.insert(rel_path, hash);
+    }
+    links.finish(dest)?;
+    progress(total, total);
+    Ok(hashes)
+}
+
+/// Applies the Unix mode stored for an archive entry to the file extracted from it at `path`, so
+/// that executables, like the native launchers of mod loaders, stay executable. Does nothing on
+/// other platforms.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn apply_entry_mode(path: &Path, mode: Option<u32>) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        // the file type bits stored along with the permissions are already known
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & MODE_MASK))?;
+    }
+    Ok(())
+}
+
+/// Downloads a zip file from `url` and installs it into the `target` directory. Tarballs
+/// compressed with gzip or zstd are installed the same way, identified by their first bytes or
+/// else the extension in `url`.
+///
+/// Top-level entries of the archive named in `exclude` are not installed. They must be declared
+/// optional by the package, as listed by [`list_optional_components`].
+///
+/// Installation fails if more than `max_size` bytes would be extracted, by default
+/// [`DEFAULT_MAX_EXTRACTED_SIZE`].
+///
+/// `task_id` is used for the download. Extraction is reported as a separate task that declares
+/// the download as its dependency, unless the archive is extracted as it is downloaded.
 pub async fn install_zip<'a>(
     app: Option<&AppHandle>,
     log: &slog::Logger,
@@ -752,12 +2065,17 @@ pub async fn install_zip<'a>(
     url: &str,
     cache: Option<CacheOptions<'_>>,
     target: &'a Path,
+    exclude: &[String],
+    max_size: Option<u64>,
     task_id: Option<tasks::Id>,
 ) -> anyhow::Result<StagedPackage<'a>> {
     debug!(log, "Installing zip from {url:?} to {target:?}");
 
+    let max_size = max_size.unwrap_or(DEFAULT_MAX_EXTRACTED_SIZE);
+
     let cache = cache.map(|c| c.with_suffix(".zip"));
 
+    recover_interrupted_replace(log, target).await?;
     tokio::fs::create_dir_all(target)
         .await
         .context("Failed to create target directory")?;
@@ -767,58 +2085,198 @@ pub async fn install_zip<'a>(
         .context("Target must not be a filesystem root")?;
 
     let mut changes = Vec::new();
-    let changes = match scan_installed_package_for_changes(log, target, &mut changes).await {
-        Ok(()) => Some(changes),
-        Err(ScanError::IndexNotFoundError) => None,
-        Err(e) => return Err(e.into()),
-    };
+    let ignore = IgnoreList::packages();
+    let changes =
+        match scan_installed_package_for_changes(log, target, ignore, false, &mut changes).await {
+            Ok(()) => Some(changes),
+            Err(ScanError::IndexNotFoundError) => None,
+            Err(e) => return Err(e.into()),
+        };
     if let Some(changes) = &changes {
         debug!(log, "Zip is already installed to {target:?}");
 
         trace!(log, "Changes: {changes:#?}");
     }
 
-    let temp_dir: TempDir;
-    match fetch_resource(app, log, reqwest, url, cache, task_id).await? {
-        FetchedResource::Bytes(bytes) => {
-            temp_dir = tempfile::tempdir_in(target_parent)?;
-            tokio::task::block_in_place(|| {
-                let mut archive =
-                    ZipArchive::new(std::io::BufReader::new(std::io::Cursor::new(bytes)))?;
-                archive.extract(temp_dir.path())?;
-                Ok::<_, ZipError>(())
-            })?;
+    // with nothing to cache and nothing to leave out, there is no need to have the whole archive
+    // before extracting it
+    if cache.is_none() && exclude.is_empty() {
+        gc_staging_dirs(log, target, None).await?;
+        let temp_dir = tempfile::tempdir_in(target_parent)?;
+        let hashes = streaming::download_and_extract_zip(
+            app,
+            log,
+            reqwest,
+            url,
+            temp_dir.path(),
+            max_size,
+            task_id,
+        )
+        .await?;
+        let staging_dir = StagingDir::Temp(temp_dir);
+        generate_package_index(log, staging_dir.path(), ignore, &hashes).await?;
+        return preserve_changes(log, target, staging_dir, None, changes).await;
+    }
+
+    let task_id = task_id.unwrap_or_else(tasks::allocate_task);
+    // without a cache to keep the archive in, it goes to a temp file rather than into memory, since
+    // packages can be large
+    let mut temp_download = None;
+    let cached = cache.is_some();
+    let resource = match cache {
+        Some(cache) => fetch_resource(app, log, reqwest, url, Some(cache), Some(task_id)).await?,
+        None => {
+            let temp_path =
+                fetch_resource_to_temp_file(app, log, reqwest, url, target_parent, Some(task_id))
+                    .await?;
+            FetchedResource::File(temp_download.insert(temp_path).to_path_buf())
         }
+    };
+    let (staging_dir, archive_hash, hashes) = match resource {
+        FetchedResource::Bytes(_) => unreachable!("cached resources are files"),
         FetchedResource::File(path) => {
-            temp_dir = tempfile::tempdir_in(target_parent)?;
-            tokio::task::block_in_place(|| {
-                let mut archive =
-                    ZipArchive::new(std::io::BufReader::new(std::fs::File::open(&path)?))?;
-                archive.extract(temp_dir.path())?;
-                Ok::<_, ZipError>(())
-            })?;
+            let archive_hash = match verified_hash(&path).await {
+                Some(hash) if cached => hash,
+                _ => {
+                    let hash = tokio::task::block_in_place(|| hash_file(&path))?;
+                    if cached {
+                        mark_verified(log, &path, &hash).await;
+                    }
+                    hash
+                }
+            };
+            // a staging directory is only reusable for the same selection of components
+            let staging_key = if exclude.is_empty() {
+                archive_hash
+            } else {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update(archive_hash.as_bytes());
+                let mut exclude = exclude.to_owned();
+                exclude.sort_unstable();
+                for component in exclude {
+                    hasher.update(component.as_bytes());
+                    hasher.update(b"\0");
+                }
+                hasher.finalize()
+            };
+            let staging_path = staging_dir_path(target, staging_key)?;
+            gc_staging_dirs(log, target, Some(&staging_path)).await?;
+            if is_reusable_staging_dir(log, &staging_path, target, changes.as_deref()).await? {
+                debug!(log, "Reusing staging directory {staging_path:?}");
+                (StagingDir::Resumable(staging_path), archive_hash, None)
+            } else {
+                match fs::remove_dir_all(&staging_path).await {
+                    Ok(()) => {}
+                    Err(e) if e.is_not_found() => {}
+                    Err(e) => return Err(e.into()),
+                }
+                tokio::fs::create_dir(&staging_path).await?;
+                let _permit = crate::governor::cpu().await;
+                let hashes = run_extraction_task(app, url, task_id, |progress| {
+                    let mut rdr = std::io::BufReader::new(std::fs::File::open(&path)?);
+                    let format = ArchiveFormat::detect_from(&mut rdr, url)?;
+                    extract_archive(format, rdr, &staging_path, exclude, max_size, progress)
+                })
+                .await?;
+                (
+                    StagingDir::Resumable(staging_path),
+                    archive_hash,
+                    Some(hashes),
+                )
+            }
         }
+    };
+
+    if let Some(hashes) = hashes {
+        generate_package_index(log, staging_dir.path(), ignore, &hashes).await?;
     }
 
-    generate_package_index(log, temp_dir.path()).await?;
+    preserve_changes(log, target, staging_dir, Some(archive_hash), changes).await
+}
+
+const EXTRACTION_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Runs `extract` as a task following the download task `download`, reporting the number of
+/// archive entries extracted. Progress is reported at most every
+/// [`EXTRACTION_PROGRESS_INTERVAL`], except for the last update.
+async fn run_extraction_task<T>(
+    app: Option<&AppHandle>,
+    url: &str,
+    download: tasks::Id,
+    extract: impl FnOnce(&mut dyn FnMut(u64, u64)) -> Result<T>,
+) -> Result<T> {
+    TaskBuilder::new(url.to_owned())
+        .kind(tasks::Kind::Extract)
+        .run_with_handle(app, |handle| async move {
+            let Some(app) = app else {
+                return tokio::task::block_in_place(|| extract(&mut |_, _| {}));
+            };
+            handle.send_dependency(app, download)?;
+            let mut reported_at = None::<Instant>;
+            tokio::task::block_in_place(|| {
+                extract(&mut |done, total| {
+                    if done < total
+                        && reported_at.is_some_and(|t| t.elapsed() < EXTRACTION_PROGRESS_INTERVAL)
+                    {
+                        return;
+                    }
+                    reported_at = Some(Instant::now());
+                    _ = handle.send_progress_manually(app, done, total);
+                })
+            })
+        })
+        .await
+        .map_err(Into::into)
+}
+
+/// Carries the changes the user made to the package installed at `target` over to its new
+/// version in `staging_dir`.
+async fn preserve_changes<'a>(
+    log: &slog::Logger,
+    target: &'a Path,
+    staging_dir: StagingDir,
+    archive_hash: Option<blake3::Hash>,
+    changes: Option<Vec<(PathBuf, Status)>>,
+) -> Result<StagedPackage<'a>> {
+    // a staging directory reused from a previous attempt may have had permissions merged into it
+    restore_permissions(log, staging_dir.path()).await?;
     if let Some(changes) = changes {
-        let mut buf = temp_dir.path().to_owned();
+        let mut buf = staging_dir.path().to_owned();
         for (path, status) in changes {
             let rel_path = path.strip_prefix(target)?;
+            if status == Status::EscapingSymlink {
+                warn!(
+                    log,
+                    "Not preserving {rel_path:?} across update, because it links outside of the package"
+                );
+                continue;
+            }
             buf.push(rel_path);
             debug!(log, "Preserving {rel_path:?} {status:?} across update");
             if matches!(status, Status::Deleted) {
-                let metadata = tokio::fs::symlink_metadata(&buf).await?;
-                match if metadata.is_dir() {
-                    tokio::fs::remove_dir_all(&buf).await
-                } else {
-                    tokio::fs::remove_file(&buf).await
-                } {
+                // may have already been deleted by a previous attempt using the same staging
+                // directory
+                let result = match tokio::fs::symlink_metadata(&buf).await {
+                    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&buf).await,
+                    Ok(_) => fs::remove_file(&buf).await,
+                    Err(e) => Err(e),
+                };
+                match result {
                     Ok(()) => {}
                     Err(e) if e.is_not_found() => {}
                     Err(e) => return Err(e.into()),
                 }
+            } else if matches!(status, Status::PermissionsChanged) {
+                // the new version of the file is kept, with the permissions of the old one
+                let permissions = tokio::fs::symlink_metadata(&path).await?.permissions();
+                match tokio::fs::symlink_metadata(&buf).await {
+                    Ok(metadata) if metadata.is_file() => {
+                        tokio::fs::set_permissions(&buf, permissions).await?;
+                    }
+                    Ok(_) => {}
+                    Err(e) if e.is_not_found() => {}
+                    Err(e) => return Err(e.into()),
+                }
             } else {
                 merge_paths(log, &path, &buf).await?;
             }
@@ -828,7 +2286,11 @@ pub async fn install_zip<'a>(
         }
     }
 
-    Ok(StagedPackage { target, temp_dir })
+    Ok(StagedPackage {
+        target,
+        staging_dir,
+        archive_hash,
+    })
 }
 
 /// Downloads a file from `url` and installs it at the `target` path.
@@ -869,11 +2331,350 @@ pub async fn install_file<'a>(
     Ok(())
 }
 
+/// Adopts the current state of the files at `rel_paths`, relative to the package installed at
+/// `target`, into the package's content index, so that changes the user made to them are no
+/// longer reported. Directories are adopted along with everything inside of them. Other entries
+/// of the index are left untouched.
+pub async fn accept_package_changes(
+    log: &slog::Logger,
+    target: &Path,
+    rel_paths: &[PathBuf],
+) -> Result<()> {
+    let index_path = target.join(INDEX_FILE_NAME);
+    let mut bytes = match tokio::fs::read(&index_path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.is_not_found() => bail!("No package index was found at {target:?}"),
+        Err(e) => return Err(e).context("Failed to read package index"),
+    };
+    tokio::task::block_in_place(|| index::decompress_in_place(&mut bytes))?;
+    let mut entries = rkyv::from_bytes::<Index, rkyv::rancor::Error>(&bytes)
+        .context("Failed to read package index")?
+        .into_latest();
+
+    for rel_path in rel_paths {
+        ensure!(
+            rel_path != Path::new(INDEX_FILE_NAME)
+                && rel_path.components().next().is_some()
+                && rel_path
+                    .components()
+                    .all(|c| matches!(c, std::path::Component::Normal(_))),
+            "Invalid path {rel_path:?}"
+        );
+        let path = target.join(rel_path);
+        let metadata = match tokio::fs::symlink_metadata(&path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.is_not_found() => bail!("{rel_path:?} does not exist"),
+            Err(e) => return Err(e.into()),
+        };
+        let mut paths = vec![path.clone()];
+        if metadata.is_dir() {
+            let mut iter = walk(&path);
+            iter.next().context("Expected root entry")??;
+            for e in iter {
+                paths.push(e?.into_path());
+            }
+        }
+        for path in paths {
+            let rel_path = path.strip_prefix(target)?;
+            if let Some(IndexEntryV4::File {
+                hash, linked: true, ..
+            }) = entries.get(&NativePath::from(rel_path))
+            {
+                // the accepted content is the package's own, not the stored copy's
+                dedupe::unlink(log, &path, &blake3::Hash::from_bytes(*hash)).await?;
+            }
+            let entry = index_entry(target, &path, None).await?;
+            debug!(log, "Accepting {rel_path:?} as {entry:?}");
+            entries.insert(rel_path.into(), entry);
+        }
+    }
+
+    let compressed = encode_index(log, &Index::V5(entries))?;
+    // written outside of the package so that it can't be mistaken for a file of the package
+    let target_parent = target
+        .parent()
+        .context("Target must not be a filesystem root")?;
+    tokio::task::block_in_place(|| {
+        let mut temp_file = tempfile::NamedTempFile::new_in(target_parent)?;
+        temp_file.write_all(&compressed)?;
+        temp_file.as_file().sync_all()?;
+        temp_file.persist(&index_path)?;
+        Ok::<_, anyhow::Error>(())
+    })?;
+
+    debug!(
+        log,
+        "Accepted changes to {} paths in {target:?}",
+        rel_paths.len()
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum IndexRepair {
+    /// The index was readable, so nothing was done.
+    NotNeeded,
+    /// The index was regenerated. `changes` lists the differences between the installed files and
+    /// the package, as found with the regenerated index.
+    Repaired { changes: Vec<(PathBuf, Status)> },
+}
+
+/// Regenerates the content index of the package installed at `target` from the package archive
+/// at `url` if the index is missing or corrupt. Only the index is written. The installed files
+/// are left untouched. `exclude` must name the components left out when the package was
+/// installed, as given to [`install_zip`].
+///
+/// Fails, leaving the index as it was, if the content of any installed file doesn't match the
+/// archive.
+pub async fn repair_package_index(
+    app: Option<&AppHandle>,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    cache: Option<CacheOptions<'_>>,
+    target: &Path,
+    exclude: &[String],
+    task_id: Option<tasks::Id>,
+) -> Result<IndexRepair> {
+    ensure!(
+        tokio::fs::metadata(target).await?.is_dir(),
+        "No package is installed at {target:?}"
+    );
+    let ignore = IgnoreList::packages();
+    let index_path = target.join(INDEX_FILE_NAME);
+    if tokio::fs::try_exists(&index_path).await? {
+        match scan_installed_package_for_changes(log, target, ignore, false, &mut Vec::new()).await
+        {
+            Ok(()) => return Ok(IndexRepair::NotNeeded),
+            Err(ScanError::InvalidIndexError(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    debug!(log, "Repairing package index of {target:?}");
+
+    let cache = cache.map(|c| c.with_suffix(".zip"));
+    let target_parent = target
+        .parent()
+        .context("Target must not be a filesystem root")?;
+    let temp_dir = tempfile::tempdir_in(target_parent)?;
+    let hashes = match fetch_resource(app, log, reqwest, url, cache, task_id).await? {
+        FetchedResource::Bytes(bytes) => tokio::task::block_in_place(|| {
+            let mut rdr = std::io::Cursor::new(bytes);
+            let format = ArchiveFormat::detect_from(&mut rdr, url)?;
+            extract_archive(
+                format,
+                rdr,
+                temp_dir.path(),
+                exclude,
+                DEFAULT_MAX_EXTRACTED_SIZE,
+                &mut |_, _| {},
+            )
+        })?,
+        FetchedResource::File(path) => tokio::task::block_in_place(|| {
+            let mut rdr = std::io::BufReader::new(std::fs::File::open(&path)?);
+            let format = ArchiveFormat::detect_from(&mut rdr, url)?;
+            extract_archive(
+                format,
+                rdr,
+                temp_dir.path(),
+                exclude,
+                DEFAULT_MAX_EXTRACTED_SIZE,
+                &mut |_, _| {},
+            )
+        })?,
+    };
+    generate_package_index(log, temp_dir.path(), ignore, &hashes).await?;
+
+    let backup_path = target.join(format!("{INDEX_FILE_NAME}.corrupt"));
+    let had_index = match tokio::fs::rename(&index_path, &backup_path).await {
+        Ok(()) => true,
+        Err(e) if e.is_not_found() => false,
+        Err(e) => return Err(e.into()),
+    };
+    // the temp dir is on the same file system, so this won't leave a partial index behind
+    tokio::fs::rename(temp_dir.path().join(INDEX_FILE_NAME), &index_path).await?;
+
+    let mut changes = Vec::new();
+    let verified = scan_installed_package_for_changes(log, target, ignore, true, &mut changes)
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|()| {
+            // the scan rehashes every file, so these are the files whose content hash doesn't
+            // match the archive's
+            let mismatched = changes
+                .iter()
+                .filter(|(_, status)| {
+                    matches!(status, Status::ContentModified | Status::TypeChanged)
+                })
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>();
+            ensure!(
+                mismatched.is_empty(),
+                "Installed files do not match the package: {mismatched:?}"
+            );
+            Ok(())
+        });
+    if let Err(e) = verified {
+        if had_index {
+            tokio::fs::rename(&backup_path, &index_path).await?;
+        } else {
+            tokio::fs::remove_file(&index_path).await?;
+        }
+        return Err(e.context("Repaired index failed verification"));
+    }
+    if had_index {
+        fs::remove_file(&backup_path).await?;
+    }
+    changes.retain(|(path, _)| path != &backup_path);
+
+    debug!(
+        log,
+        "Repaired package index of {target:?} with {} changes",
+        changes.len()
+    );
+
+    Ok(IndexRepair::Repaired { changes })
+}
+
+/// Restores files in the package installed at `target` that were modified or deleted since it
+/// was installed, using the package archive at `url`. Only changes whose path relative to
+/// `target` satisfies `filter` are restored. Everything else is left untouched.
+///
+/// Returns the paths of the restored files, relative to `target`.
+pub async fn restore_package_files(
+    app: Option<&AppHandle>,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    cache: Option<CacheOptions<'_>>,
+    target: &Path,
+    filter: impl Fn(&Path) -> bool,
+    task_id: Option<tasks::Id>,
+) -> Result<Vec<PathBuf>> {
+    let cache = cache.map(|c| c.with_suffix(".zip"));
+
+    let mut changes = Vec::new();
+    scan_installed_package_for_changes(log, target, IgnoreList::packages(), false, &mut changes)
+        .await?;
+    let mut changed = Vec::new();
+    for (path, status) in changes {
+        if !matches!(
+            status,
+            Status::ContentModified | Status::TypeChanged | Status::Deleted
+        ) {
+            continue;
+        }
+        let rel_path = path.strip_prefix(target)?;
+        if filter(rel_path) {
+            changed.push(rel_path.to_owned());
+        }
+    }
+    if changed.is_empty() {
+        debug!(log, "Nothing to restore in {target:?}");
+        return Ok(Vec::new());
+    }
+
+    // the archive is extracted in full, with the same checks as when installing, and only then are
+    // the changed paths moved into place
+    let target_parent = target
+        .parent()
+        .context("Target must not be a filesystem root")?;
+    let temp_dir = tempfile::tempdir_in(target_parent)?;
+    match fetch_resource(app, log, reqwest, url, cache, task_id).await? {
+        FetchedResource::Bytes(bytes) => tokio::task::block_in_place(|| {
+            let mut rdr = std::io::Cursor::new(bytes);
+            let format = ArchiveFormat::detect_from(&mut rdr, url)?;
+            extract_archive(
+                format,
+                rdr,
+                temp_dir.path(),
+                &[],
+                DEFAULT_MAX_EXTRACTED_SIZE,
+                &mut |_, _| {},
+            )
+        })?,
+        FetchedResource::File(path) => tokio::task::block_in_place(|| {
+            let mut rdr = std::io::BufReader::new(std::fs::File::open(&path)?);
+            let format = ArchiveFormat::detect_from(&mut rdr, url)?;
+            extract_archive(
+                format,
+                rdr,
+                temp_dir.path(),
+                &[],
+                DEFAULT_MAX_EXTRACTED_SIZE,
+                &mut |_, _| {},
+            )
+        })?,
+    };
+    tokio::task::block_in_place(|| restore_from_extracted(log, temp_dir.path(), target, &changed))
+}
+
+/// Moves the entries at the `changed` paths of the package extracted to `extracted` over those of
+/// the package installed at `target`, which must be on the same file system. Returns the paths of
+/// the files and symlinks that were restored.
+fn restore_from_extracted(
+    log: &slog::Logger,
+    extracted: &Path,
+    target: &Path,
+    changed: &[PathBuf],
+) -> Result<Vec<PathBuf>> {
+    let mut restored = Vec::new();
+    for rel_path in changed {
+        let src = extracted.join(rel_path);
+        let is_dir = match std::fs::symlink_metadata(&src) {
+            Ok(m) => m.is_dir(),
+            // moved along with a changed directory containing it
+            Err(e) if e.is_not_found() => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(link) = rel_path
+            .ancestors()
+            .skip(1)
+            .find(|p| target.join(p).is_symlink())
+        {
+            bail!("Cannot restore {rel_path:?} through the symlink {link:?}");
+        }
+        if is_dir {
+            for e in walkdir::WalkDir::new(&src).min_depth(1) {
+                let e = e?;
+                if !e.file_type().is_dir() {
+                    restored.push(rel_path.join(e.path().strip_prefix(&src)?));
+                }
+            }
+        } else {
+            restored.push(rel_path.clone());
+        }
+        let path = target.join(rel_path);
+        match std::fs::symlink_metadata(&path) {
+            Ok(m) if m.is_dir() => fs::remove_dir_all_blocking(&path)?,
+            Ok(_) => fs::remove_file_blocking(&path)?,
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&src, &path)?;
+        debug!(log, "Restored {rel_path:?}");
+    }
+    Ok(restored)
+}
+
+/// Removes the package installed at `path`. With `keep_changes`, only the files that still match
+/// the package's index are removed, and anything created or modified since it was installed is
+/// left in place, along with the directories containing it. Directories left empty are pruned.
+///
+/// Returns the paths that were preserved, relative to `path`.
 pub async fn uninstall_package<'a>(
     log: &slog::Logger,
     path: &'a Path,
     keep_changes: bool,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Vec<PathBuf>> {
+    let linked = dedupe::linked_hashes(path).await?;
+    let mut preserved = Vec::new();
     if keep_changes {
         let mut changes = TrieBuilder::new();
         struct ExtendByFn<F>(F);
@@ -888,9 +2689,19 @@ pub async fn uninstall_package<'a>(
         scan_installed_package_for_changes(
             log,
             path,
-            &mut ExtendByFn(|(path, status): (PathBuf, _)| {
-                if !matches!(status, Status::Deleted) {
-                    changes.insert(path.components().map(|c| c.as_os_str().to_owned()));
+            IgnoreList::packages(),
+            false,
+            &mut ExtendByFn(|(p, status): (PathBuf, _)| {
+                // links outside of the package are removed along with it, and files that were
+                // only made executable or read-only aren't worth keeping
+                if !matches!(
+                    status,
+                    Status::Deleted | Status::EscapingSymlink | Status::PermissionsChanged
+                ) {
+                    changes.insert(p.components().map(|c| c.as_os_str().to_owned()));
+                    if let Ok(rel_path) = p.strip_prefix(path) {
+                        preserved.push(rel_path.to_owned());
+                    }
                 }
             }),
         )
@@ -899,7 +2710,9 @@ pub async fn uninstall_package<'a>(
 
         debug!(log, "Changes: {changes:?}");
 
-        let mut iter = WalkDir::new(path).into_iter();
+        // directories that were kept for their contents, in the order they were walked
+        let mut kept_dirs = Vec::new();
+        let mut iter = walk(path);
         ensure!(
             iter.next().context("Expected root entry")??.path() == path,
             "First entry was not root"
@@ -915,37 +2728,53 @@ pub async fn uninstall_package<'a>(
                 }
             }
             // TODO: avoid cloning and collecting
+            let components = e
+                .path()
+                .components()
+                .map(|c| c.as_os_str().to_owned())
+                .collect::<Vec<_>>();
             if changes
-                .predictive_search::<Discard, _>(
-                    e.path()
-                        .components()
-                        .map(|c| c.as_os_str().to_owned())
-                        .collect::<Vec<_>>(),
-                )
+                .predictive_search::<Discard, _>(&components)
                 .next()
                 .is_none()
             {
                 if e.file_type().is_dir() {
                     debug!(log, "Removing directory tree at {:?}", e.path());
-                    tokio::fs::remove_dir_all(e.path()).await?;
+                    fs::remove_dir_all(e.path()).await?;
                     iter.skip_current_dir();
                 } else {
                     debug!(log, "Removing file at {:?}", e.path());
-                    tokio::fs::remove_file(e.path()).await?;
+                    fs::remove_file(e.path()).await?;
                 }
+            } else if e.file_type().is_dir() && !changes.exact_match(&components) {
+                kept_dirs.push(e.into_path());
+            }
+        }
+
+        // children are walked after their parents
+        for dir in kept_dirs.iter().rev().map(PathBuf::as_path).chain([path]) {
+            match tokio::fs::remove_dir(dir).await {
+                Ok(()) => debug!(log, "Pruned empty directory at {dir:?}"),
+                Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => {}
+                Err(e) => return Err(e.into()),
             }
         }
     } else {
-        tokio::fs::remove_dir_all(path).await?;
+        fs::remove_dir_all(path).await?;
     }
 
     debug!(log, "Uninstalled package from {path:?}");
+    // preserved files that are linked keep their copy in the store
+    dedupe::release(log, &linked).await?;
+    if !preserved.is_empty() {
+        debug!(log, "Preserved {preserved:?}");
+    }
 
-    Ok(())
+    Ok(preserved)
 }
 
 async fn merge_paths(log: &slog::Logger, from: &Path, to: &Path) -> Result<()> {
-    let mut iter = WalkDir::new(from).into_iter();
+    let mut iter = walk(from);
     while let Some(r) = iter.next() {
         let dir_entry = r?;
         let rel_path = dir_entry.path().strip_prefix(from).context("unreachable")?;
@@ -979,7 +2808,7 @@ async fn merge_paths(log: &slog::Logger, from: &Path, to: &Path) -> Result<()> {
                     // target is not a directory, so skip walking and we'll just rename
                     // iter.skip_current_dir();
                     // remove the target file and we'll just rename
-                    tokio::fs::remove_file(&to).await?;
+                    fs::remove_file(&to).await?;
                 }
                 (true, None) => {
                     // target is not a directory, so skip walking and we'll just rename
@@ -987,7 +2816,7 @@ async fn merge_paths(log: &slog::Logger, from: &Path, to: &Path) -> Result<()> {
                 }
                 (false, Some(FileType::Dir)) => {
                     // source is not a directory, so remove the target directory and we'll just rename
-                    tokio::fs::remove_dir_all(&to).await?;
+                    fs::remove_dir_all(&to).await?;
                 }
                 (false, Some(FileType::FileLike) | None) => {}
             }
@@ -1023,3 +2852,451 @@ pub async fn clear_cache() -> Result<()> {
     tokio::fs::create_dir(&cache_dir).await?;
     Ok(())
 }
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrunedCache {
+    pub removed_files: usize,
+    pub freed_bytes: u64,
+    /// The size of the archives left in the cache.
+    pub remaining_bytes: u64,
+}
+
+/// Deletes the least recently used archives in the cache until the rest take up at most
+/// `max_bytes`. Archives that are being downloaded are left alone, as are any other files.
+pub async fn prune_cache(log: &slog::Logger, max_bytes: u64) -> Result<PrunedCache> {
+    let cache_dir = cache_dir();
+    let mut iter = match tokio::fs::read_dir(&cache_dir).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(PrunedCache::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries = Vec::new();
+    while let Some(e) = iter.next_entry().await? {
+        let path = e.path();
+        if path.extension() != Some("zip".as_ref()) {
+            continue;
+        }
+        let metadata = e.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        // access times aren't tracked on every file system
+        let last_used = metadata.accessed().or_else(|_| metadata.modified())?;
+        entries.push((last_used, metadata.len(), path));
+    }
+    entries.sort_unstable_by_key(|(last_used, ..)| *last_used);
+
+    let mut pruned = PrunedCache::default();
+    let mut size = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+    for (_, len, path) in entries {
+        if size <= max_bytes {
+            break;
+        }
+        let result = tokio::task::block_in_place(|| {
+            // held while removing, so that a download can't start in between
+            let downloading = DOWNLOADING.lock();
+            if downloading.contains_key(&path) {
+                return Ok(false);
+            }
+            fs::remove_file_blocking(&path).map(|()| true)
+        });
+        match result {
+            Ok(true) => {
+                debug!(log, "Pruned {path:?} from the cache");
+                if let Err(e) = fs::remove_file(&verified_marker_path(&path)).await {
+                    if !e.is_not_found() {
+                        warn!(log, "Failed to remove verification marker of {path:?}: {e}");
+                    }
+                }
+                pruned.removed_files += 1;
+                pruned.freed_bytes += len;
+                size -= len;
+            }
+            Ok(false) => debug!(log, "Not pruning {path:?}, which is being downloaded"),
+            Err(e) if e.is_not_found() => size -= len,
+            // likely in use on Windows
+            Err(e) => warn!(log, "Failed to prune {path:?} from the cache: {e}"),
+        }
+    }
+    pruned.remaining_bytes = size;
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write as _};
+    use std::path::Path;
+
+    use zip::write::SimpleFileOptions;
+    use zip::{ZipArchive, ZipWriter};
+
+    use super::{
+        copy_entry, extract_zip, find_wrapper_dir, is_escaping_link, CaseCollisions, IgnoreList,
+    };
+
+    fn archive(files: &[&str]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(b"{}").unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_find_wrapper_dir() {
+        assert_eq!(
+            find_wrapper_dir(&archive(&["Foo/manifest.json", "Foo/plugins/Foo.dll"])).as_deref(),
+            Some("Foo")
+        );
+        // not wrapped
+        assert_eq!(
+            find_wrapper_dir(&archive(&["manifest.json", "plugins/Foo.dll"])),
+            None
+        );
+        // a single top-level directory that isn't the package's root
+        assert_eq!(find_wrapper_dir(&archive(&["plugins/Foo.dll"])), None);
+        // expected at the top level by the mod loader
+        assert_eq!(
+            find_wrapper_dir(&archive(&[
+                "BepInExPack/manifest.json",
+                "BepInExPack/winhttp.dll"
+            ])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_escaping_link() {
+        let root = Path::new("/profiles/a/mods/Owner-Example");
+        assert!(!is_escaping_link(root, Path::new("a/b"), Path::new("../c")));
+        assert!(is_escaping_link(
+            root,
+            Path::new("a/b"),
+            Path::new("../../c")
+        ));
+        assert!(!is_escaping_link(
+            root,
+            Path::new("a"),
+            Path::new("/profiles/a/mods/Owner-Example/b")
+        ));
+        assert!(is_escaping_link(
+            root,
+            Path::new("a"),
+            Path::new("/etc/passwd")
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_escaping_link_through_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("a")).unwrap();
+        std::os::unix::fs::symlink("..", root.join("a/up")).unwrap();
+        std::os::unix::fs::symlink("a/up/..", root.join("evil")).unwrap();
+        assert!(!is_escaping_link(root, Path::new("a/up"), Path::new("..")));
+        assert!(is_escaping_link(
+            root,
+            Path::new("evil"),
+            Path::new("a/up/..")
+        ));
+        assert!(!is_escaping_link(
+            root,
+            Path::new("fine"),
+            Path::new("a/up/a")
+        ));
+    }
+
+    #[test]
+    fn test_copy_entry_limits() {
+        let copy = |size, remaining: &mut u64| {
+            let mut buf = [0u8; 4];
+            let name = Path::new("plugins/Foo.dll");
+            copy_entry(
+                &mut &b"0123456789"[..],
+                &mut Vec::new(),
+                &mut buf,
+                name,
+                size,
+                remaining,
+            )
+        };
+
+        let mut remaining = 100;
+        assert_eq!(
+            copy(10, &mut remaining).unwrap(),
+            blake3::hash(b"0123456789")
+        );
+        assert_eq!(remaining, 90);
+        // lies about its size
+        assert!(copy(5, &mut remaining).is_err());
+        // over the limit for the whole archive
+        assert!(copy(10, &mut 8).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_keeps_executable_bit() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        writer
+            .start_file("run_bepinex.sh", options.unix_permissions(0o755))
+            .unwrap();
+        writer.write_all(b"#!/bin/sh").unwrap();
+        writer
+            .start_file("manifest.json", options.unix_permissions(0o644))
+            .unwrap();
+        writer.write_all(b"{}").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        extract_zip(&mut archive, dir.path(), &[]).unwrap();
+        let mode = |name| {
+            let metadata = std::fs::metadata(dir.path().join(name)).unwrap();
+            metadata.permissions().mode() & 0o777
+        };
+        assert_eq!(mode("run_bepinex.sh"), 0o755);
+        assert_eq!(mode("manifest.json"), 0o644);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_rejects_escaping_symlinks() {
+        let extract = |entries: &[(&str, Option<&str>)]| {
+            let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+            let options = SimpleFileOptions::default();
+            for (name, target) in entries {
+                match target {
+                    Some(target) => writer.add_symlink(*name, *target, options).unwrap(),
+                    None => {
+                        writer.start_file(*name, options).unwrap();
+                        writer.write_all(b"{}").unwrap();
+                    }
+                }
+            }
+            let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("package");
+            let result = extract_zip(&mut archive, &dest, &[]);
+            (dir, result)
+        };
+
+        let (_dir, result) = extract(&[("a/link", Some("../b")), ("b", None)]);
+        result.unwrap();
+        let (dir, result) = extract(&[("link", Some("..")), ("link/escaped.dll", None)]);
+        assert!(result.is_err());
+        assert!(!dir.path().join("escaped.dll").exists());
+        // contained on its own, but not through the first link
+        let (dir, result) = extract(&[
+            ("a/up", Some("..")),
+            ("a/up/link", Some("..")),
+            ("a/up/link/escaped.dll", None),
+        ]);
+        assert!(result.is_err());
+        assert!(!dir.path().join("escaped.dll").exists());
+        let (_dir, result) = extract(&[("a/up", Some("..")), ("evil", Some("a/up/../x"))]);
+        assert!(result.is_err());
+        // redirected by a link extracted after it
+        let (_dir, result) = extract(&[("evil", Some("a/up/../x")), ("a/up", Some(".."))]);
+        assert!(result.is_err());
+        // written over the link rather than through it
+        let (dir, result) = extract(&[("b", None), ("link", Some("b")), ("./link", None)]);
+        assert!(result.is_err());
+        assert!(dir.path().join("package/link").is_symlink());
+    }
+
+    #[test]
+    fn test_case_collisions() {
+        let mut collisions = CaseCollisions {
+            seen: Some(Default::default()),
+        };
+        collisions.check("BepInEx/").unwrap();
+        collisions.check("bepinex/").unwrap();
+        collisions.check("BepInEx/config/Foo.cfg").unwrap();
+        collisions.check("BepInEx/config/Foo.cfg").unwrap();
+        assert!(collisions.check("BepInEx/config/foo.cfg").is_err());
+        assert!(collisions.check("BEPINEX").is_err());
+
+        let mut collisions = CaseCollisions { seen: None };
+        collisions.check("Config.json").unwrap();
+        collisions.check("config.json").unwrap();
+    }
+
+    /// Indexes a package with a symlink, applies `change` to it, and returns the scanned changes.
+    #[cfg(unix)]
+    fn scan_changes(
+        verify: bool,
+        change: impl FnOnce(&Path),
+    ) -> Vec<(std::path::PathBuf, super::Status)> {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.dll"), b"a").unwrap();
+        std::fs::write(root.join("b.dll"), b"b").unwrap();
+        std::os::unix::fs::symlink("a.dll", root.join("link.dll")).unwrap();
+
+        tokio::runtime::Builder::new_multi_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let ignore = IgnoreList::packages();
+                super::generate_package_index(&log, root, ignore, &Default::default())
+                    .await
+                    .unwrap();
+                change(root);
+                let mut changes = Vec::new();
+                super::scan_installed_package_for_changes(&log, root, ignore, verify, &mut changes)
+                    .await
+                    .unwrap();
+                changes
+            })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_link_target_changed() {
+        assert_eq!(scan_changes(false, |_| {}), []);
+
+        let changes = scan_changes(false, |root| {
+            std::fs::remove_file(root.join("link.dll")).unwrap();
+            std::os::unix::fs::symlink("b.dll", root.join("link.dll")).unwrap();
+        });
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].0.ends_with("link.dll"));
+        assert_eq!(changes[0].1, super::Status::LinkTargetChanged);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_permissions_changed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let changes = scan_changes(false, |root| {
+            std::fs::set_permissions(root.join("a.dll"), std::fs::Permissions::from_mode(0o755))
+                .unwrap();
+        });
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].0.ends_with("a.dll"));
+        assert_eq!(changes[0].1, super::Status::PermissionsChanged);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_entry_mode_drops_special_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.dll");
+        std::fs::write(&path, b"").unwrap();
+        super::apply_entry_mode(&path, Some(0o104755)).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+
+    #[test]
+    fn test_download_guards_nest() {
+        let path = Path::new("/cache/test_download_guards_nest.zip");
+        let outer = super::DownloadGuard::new(path);
+        let inner = super::DownloadGuard::new(path);
+        drop(inner);
+        assert!(super::DOWNLOADING.lock().contains_key(path));
+        drop(outer);
+        assert!(!super::DOWNLOADING.lock().contains_key(path));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_from_extracted() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let dir = tempfile::tempdir().unwrap();
+        let extracted = dir.path().join("extracted");
+        let target = dir.path().join("target");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(extracted.join("plugins/Foo")).unwrap();
+        std::fs::create_dir_all(extracted.join("config")).unwrap();
+        std::fs::write(extracted.join("a.cfg"), b"new").unwrap();
+        std::fs::write(extracted.join("plugins/Foo/Foo.dll"), b"dll").unwrap();
+        std::fs::write(extracted.join("config/b.cfg"), b"new").unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("a.cfg"), b"old").unwrap();
+        std::os::unix::fs::symlink(outside.join("a.cfg"), target.join("a.cfg")).unwrap();
+        std::os::unix::fs::symlink(&outside, target.join("config")).unwrap();
+
+        let restored = super::restore_from_extracted(
+            &log,
+            &extracted,
+            &target,
+            &["a.cfg".into(), "plugins".into()],
+        )
+        .unwrap();
+        assert_eq!(
+            restored,
+            [Path::new("a.cfg"), Path::new("plugins/Foo/Foo.dll")]
+        );
+        assert!(!target.join("a.cfg").is_symlink());
+        assert_eq!(std::fs::read(target.join("a.cfg")).unwrap(), b"new");
+        assert_eq!(std::fs::read(outside.join("a.cfg")).unwrap(), b"old");
+        assert!(target.join("plugins/Foo/Foo.dll").is_file());
+
+        assert!(
+            super::restore_from_extracted(&log, &extracted, &target, &["config/b.cfg".into()])
+                .is_err()
+        );
+        assert!(!outside.join("b.cfg").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_trusts_unchanged_stat() {
+        // same size and modification time, but different contents
+        let change = |root: &Path| {
+            let path = root.join("a.dll");
+            let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+            let file = std::fs::File::create(&path).unwrap();
+            (&file).write_all(b"c").unwrap();
+            file.set_modified(modified).unwrap();
+        };
+        assert_eq!(scan_changes(false, change), []);
+
+        let changes = scan_changes(true, change);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].1, super::Status::ContentModified);
+    }
+
+    #[test]
+    fn test_atomic_replace_recovery() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("Owner-Mod");
+        let from = dir.path().join("staged");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::write(target.join("old.dll"), b"old").unwrap();
+        std::fs::create_dir(&from).unwrap();
+        std::fs::write(from.join("new.dll"), b"new").unwrap();
+
+        tokio::runtime::Builder::new_multi_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                // interrupted after the original was moved aside
+                let backup = super::generate_backup_path(&target).await.unwrap();
+                std::fs::rename(&target, &backup).unwrap();
+                super::recover_interrupted_replace(&log, &target)
+                    .await
+                    .unwrap();
+                assert_eq!(std::fs::read(target.join("old.dll")).unwrap(), b"old");
+                assert!(!backup.exists());
+
+                super::atomic_replace(&target, &from).await.unwrap();
+                assert_eq!(std::fs::read(target.join("new.dll")).unwrap(), b"new");
+                assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+            });
+    }
+}
@@ -0,0 +1,247 @@
+//! Extracting a zip archive while it is being downloaded.
+//!
+//! Zip archives list their entries in a central directory at the end, but each entry's data is
+//! also preceded by a local header with its name, so entries can be extracted as they arrive.
+//! This overlaps extraction with the download, and bounds memory use by a few network chunks
+//! rather than the size of the archive.
+//!
+//! Local headers lack some of what the central directory records, like which entries are
+//! symlinks and their permissions, so the raw archive is also spooled to an anonymous temporary
+//! file. Once the download completes, the spooled central directory is checked against what was
+//! extracted and permissions are applied from it. Archives that can't be handled from their local
+//! headers alone, like those with symlinks, a wrapper directory, or entries whose sizes are only
+//! known after their data, are extracted again from the spooled file the usual way, as are
+//! archives in other formats.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek as _, Write as _};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use bytes::{Buf as _, Bytes};
+use slog::debug;
+use tauri::AppHandle;
+use tokio::sync::mpsc;
+use zip::ZipArchive;
+
+use crate::tasks::{self, TaskBuilder};
+use crate::util::http::retry_download;
+use crate::util::UsizeExt as _;
+use crate::Reqwest;
+
+use super::archive::{extract_archive, ArchiveFormat};
+use super::{
+    apply_entry_mode, check_extracted_size, copy_entry, create_entry_file,
+    extract_zip_with_progress, find_wrapper_dir, CaseCollisions,
+};
+
+/// How many chunks of the response may be waiting to be extracted before the download pauses.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Feeds the chunks of the response to the extractor, copying everything it reads to the spool.
+struct SpoolingReader {
+    rx: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+    spool: std::io::BufWriter<std::fs::File>,
+}
+
+impl Read for SpoolingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.spool.write_all(&buf[..n])?;
+        self.chunk.advance(n);
+        Ok(n)
+    }
+}
+
+/// Extracts the entries of the archive from their local headers until the central directory is
+/// reached, failing if more than `max_size` bytes would be extracted.
+fn extract_entries(
+    rdr: &mut SpoolingReader,
+    dest: &Path,
+    max_size: u64,
+) -> Result<HashMap<PathBuf, blake3::Hash>> {
+    let mut hashes = HashMap::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut remaining = max_size;
+    let mut collisions = CaseCollisions::new(dest)?;
+    while let Some(mut file) = zip::read::read_zipfile_from_stream(&mut *rdr)? {
+        let rel_path = file
+            .enclosed_name()
+            .with_context(|| format!("Invalid path in zip archive: {:?}", file.name()))?;
+        collisions.check(file.name())?;
+        let path = dest.join(&rel_path);
+        if file.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut wtr = create_entry_file(&path)?;
+        let size = file.size();
+        let hash = copy_entry(
+            &mut file,
+            &mut wtr,
+            &mut buf,
+            &rel_path,
+            size,
+            &mut remaining,
+        )?;
+        hashes.insert(rel_path, hash);
+    }
+    Ok(hashes)
+}
+
+/// Checks the extracted entries against the central directory, applying permissions from it.
+/// Returns `false` if the archive must be extracted from the central directory instead.
+fn reconcile<R: Read + std::io::Seek>(
+    log: &slog::Logger,
+    archive: &mut ZipArchive<R>,
+    dest: &Path,
+    hashes: &HashMap<PathBuf, blake3::Hash>,
+) -> Result<bool> {
+    if find_wrapper_dir(archive).is_some() {
+        debug!(log, "Archive has a wrapper directory");
+        return Ok(false);
+    }
+    let mut files = 0usize;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        if file.is_symlink() {
+            debug!(log, "Archive contains symlinks");
+            return Ok(false);
+        }
+        files += 1;
+        let Some(rel_path) = file.enclosed_name() else {
+            return Ok(false);
+        };
+        if !hashes.contains_key(&rel_path) {
+            debug!(log, "{rel_path:?} is only listed in the central directory");
+            return Ok(false);
+        }
+        apply_entry_mode(&dest.join(&rel_path), file.unix_mode())?;
+    }
+    if files != hashes.len() {
+        debug!(log, "Local headers don't match the central directory");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Downloads the zip archive at `url`, extracting it into `dest` as it arrives. Returns the
+/// hashes of the extracted files, keyed by path relative to `dest`, like
+/// [`extract_zip`](super::extract_zip). Fails if more than `max_size` bytes would be extracted.
+pub async fn download_and_extract_zip(
+    app: Option<&AppHandle>,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    dest: &Path,
+    max_size: u64,
+    task_id: Option<tasks::Id>,
+) -> Result<HashMap<PathBuf, blake3::Hash>> {
+    TaskBuilder::with_id(task_id.unwrap_or_else(tasks::allocate_task), url.to_owned())
+        .kind(tasks::Kind::Download)
+        .progress_unit(tasks::ProgressUnit::Bytes)
+        .run_with_handle(app, |handle| async move {
+            debug!(
+                log,
+                "Fetching resource from {url:?} and extracting it as it arrives"
+            );
+
+            let _permit = crate::governor::network().await;
+            // only the request is retried, since the body is consumed as it is extracted
+            let mut resp = retry_download(log, url, || async move {
+                Ok(reqwest.get(url).send().await?.error_for_status()?)
+            })
+            .await?;
+            let len = resp.content_length();
+
+            let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+            let spool = tempfile::tempfile_in(dest.parent().unwrap_or(dest))?;
+            let extractor = tokio::task::spawn_blocking({
+                let log = log.clone();
+                let url = url.to_owned();
+                let dest = dest.to_owned();
+                move || {
+                    let mut rdr = SpoolingReader {
+                        rx,
+                        chunk: Bytes::new(),
+                        spool: std::io::BufWriter::new(spool),
+                    };
+                    let streamed = extract_entries(&mut rdr, &dest, max_size);
+                    // the rest of the archive is needed for its central directory
+                    std::io::copy(&mut rdr, &mut std::io::sink())?;
+                    let spool = rdr.spool.into_inner().map_err(|e| e.into_error())?;
+                    let mut spool = std::io::BufReader::new(spool);
+                    spool.rewind()?;
+                    let format = ArchiveFormat::detect_from(&mut spool, &url)?;
+                    if format != ArchiveFormat::Zip {
+                        debug!(
+                            log,
+                            "Extracting {format:?} archive from the spooled download"
+                        );
+                        crate::util::fs::remove_dir_all_blocking(&dest)?;
+                        std::fs::create_dir(&dest)?;
+                        return extract_archive(
+                            format,
+                            spool,
+                            &dest,
+                            &[],
+                            max_size,
+                            &mut |_, _| {},
+                        );
+                    }
+                    let mut archive = ZipArchive::new(spool)?;
+                    match streamed {
+                        Ok(hashes) if reconcile(&log, &mut archive, &dest, &hashes)? => {
+                            return Ok(hashes);
+                        }
+                        Ok(_) => {}
+                        Err(e) => debug!(log, "Unable to extract archive as it arrives: {e}"),
+                    }
+                    debug!(log, "Extracting archive again from its central directory");
+                    crate::util::fs::remove_dir_all_blocking(&dest)?;
+                    std::fs::create_dir(&dest)?;
+                    check_extracted_size(&mut archive, &dest, &[], max_size)?;
+                    extract_zip_with_progress(&mut archive, &dest, &[], max_size, &mut |_, _| {})
+                }
+            });
+
+            let download = async {
+                let mut received = 0u64;
+                while let Some(chunk) = resp.chunk().await? {
+                    received += chunk.len().as_u64();
+                    if let Some(app) = app {
+                        handle.send_progress_manually(app, received, len.unwrap_or(0))?;
+                    }
+                    if tx.send(chunk).await.is_err() {
+                        // the extractor failed, which is reported below
+                        break;
+                    }
+                }
+                drop(tx);
+                Ok::<_, anyhow::Error>(())
+            };
+            let (downloaded, extracted) = tokio::join!(download, extractor);
+            downloaded?;
+            let hashes = extracted.context("Extraction panicked")??;
+
+            debug!(log, "Extracted {} files from {url:?}", hashes.len());
+
+            Ok::<_, anyhow::Error>(hashes)
+        })
+        .await
+        .map_err(Into::into)
+}
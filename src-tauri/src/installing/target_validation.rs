@@ -0,0 +1,158 @@
+//! Pre-flight checks of a directory before installing into it, so that the UI can refuse or
+//! confirm risky installs before anything is written.
+
+use std::path::{Component, Path, PathBuf, Prefix};
+
+use anyhow::{Context as _, Result};
+
+use crate::paths::home_dir;
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum Issue {
+    /// The target is, or is inside of, a directory belonging to the operating system.
+    SystemDirectory { path: PathBuf },
+    /// The target is the game's own installation directory.
+    GameDirectory,
+    /// The target cannot be written to.
+    NotWritable { error: String },
+    /// There is not enough free space on the target's filesystem.
+    InsufficientSpace { required: u64, available: u64 },
+    /// The target is on a network filesystem, which tends to be slow and unreliable.
+    NetworkPath { filesystem: String },
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Validation {
+    /// Issues that make installing into the target a bad idea.
+    pub errors: Vec<Issue>,
+    /// Issues the user should confirm before installing.
+    pub warnings: Vec<Issue>,
+}
+
+fn system_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(windows) {
+        if let Some(root) = std::env::var_os("SystemRoot") {
+            dirs.push(PathBuf::from(root));
+        }
+    } else {
+        for dir in [
+            "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/proc", "/sbin", "/sys",
+            "/usr",
+        ] {
+            dirs.push(PathBuf::from(dir));
+        }
+        if cfg!(target_os = "macos") {
+            dirs.push(PathBuf::from("/System"));
+            dirs.push(PathBuf::from("/Library"));
+        }
+    }
+    dirs
+}
+
+/// Returns the closest ancestor of `path`, including itself, that exists.
+fn existing_ancestor(path: &Path) -> Result<&Path> {
+    let mut ancestor = path;
+    while !ancestor.try_exists()? {
+        ancestor = ancestor
+            .parent()
+            .with_context(|| format!("No part of {path:?} exists"))?;
+    }
+    Ok(ancestor)
+}
+
+/// Returns the type of the network filesystem `path` is on, if it is on one.
+fn network_filesystem(path: &Path) -> Result<Option<String>> {
+    if let Some(Component::Prefix(prefix)) = path.components().next() {
+        if matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..)) {
+            return Ok(Some("smb".to_owned()));
+        }
+    }
+    if !cfg!(target_os = "linux") {
+        return Ok(None);
+    }
+    const NETWORK_FILESYSTEMS: &[&str] = &[
+        "nfs",
+        "nfs4",
+        "cifs",
+        "smbfs",
+        "smb3",
+        "9p",
+        "afs",
+        "ceph",
+        "fuse.sshfs",
+        "davfs",
+        "fuse.rclone",
+    ];
+    let mounts = std::fs::read_to_string("/proc/mounts")?;
+    // the most specific mount point containing the path is the one it is on
+    let filesystem = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_ascii_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?;
+            let filesystem = fields.next()?;
+            path.starts_with(mount_point)
+                .then_some((mount_point.len(), filesystem))
+        })
+        .max_by_key(|&(len, _)| len)
+        .map(|(_, filesystem)| filesystem);
+    Ok(filesystem
+        .filter(|fs| NETWORK_FILESYSTEMS.contains(fs))
+        .map(ToOwned::to_owned))
+}
+
+/// Checks whether `target` is a reasonable directory to install `required_bytes` into. If
+/// `game_dir` is given, installing directly into it is flagged for confirmation.
+pub fn validate_install_target(
+    target: &Path,
+    required_bytes: Option<u64>,
+    game_dir: Option<&Path>,
+) -> Result<Validation> {
+    let mut validation = Validation::default();
+
+    let ancestor = existing_ancestor(target)?;
+    let canonical_ancestor = std::fs::canonicalize(ancestor)?;
+    let target = canonical_ancestor.join(target.strip_prefix(ancestor)?);
+
+    if target.parent().is_none() || target == *home_dir() {
+        validation.errors.push(Issue::SystemDirectory {
+            path: target.clone(),
+        });
+    } else if let Some(dir) = system_dirs()
+        .into_iter()
+        .find(|dir| target.starts_with(dir))
+    {
+        validation.errors.push(Issue::SystemDirectory { path: dir });
+    }
+
+    if let Some(game_dir) = game_dir {
+        if std::fs::canonicalize(game_dir).is_ok_and(|game_dir| game_dir == target) {
+            validation.warnings.push(Issue::GameDirectory);
+        }
+    }
+
+    if let Err(e) = tempfile::tempfile_in(&canonical_ancestor) {
+        validation.errors.push(Issue::NotWritable {
+            error: e.to_string(),
+        });
+    }
+
+    if let Some(required) = required_bytes {
+        let available = super::free_space(&canonical_ancestor)?;
+        if available < required {
+            validation.errors.push(Issue::InsufficientSpace {
+                required,
+                available,
+            });
+        }
+    }
+
+    if let Some(filesystem) = network_filesystem(&canonical_ancestor)? {
+        validation.warnings.push(Issue::NetworkPath { filesystem });
+    }
+
+    Ok(validation)
+}
@@ -0,0 +1,159 @@
+//! Diagnosing why a package fails to install, without touching any profile.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use slog::debug;
+use tauri::AppHandle;
+use zip::ZipArchive;
+
+use crate::tasks;
+use crate::Reqwest;
+
+use super::archive::resolve_link;
+use super::{extract_zip, fetch_resource, read_optional_components, CacheOptions, FetchedResource};
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum Problem {
+    /// The entry's path is absolute or escapes the package. [`install_zip`](super::install_zip)
+    /// refuses to install such packages.
+    UnsafePath { name: String },
+    /// The entry is a symlink pointing outside of the package.
+    EscapingSymlink { name: String, target: String },
+    /// The entry is encrypted, which is not supported.
+    Encrypted { name: String },
+    /// The entry is neither a file, a directory, nor a symlink.
+    UnsupportedFileType { name: String },
+    /// The package's `manifest.json` could not be read.
+    InvalidManifest { error: String },
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractionReport {
+    pub entries: usize,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub optional_components: Vec<String>,
+    pub problems: Vec<Problem>,
+    /// The error extraction failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl ExtractionReport {
+    fn failed(error: anyhow::Error) -> Self {
+        Self {
+            entries: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            optional_components: Vec::new(),
+            problems: Vec::new(),
+            error: Some(format!("{error:#}")),
+        }
+    }
+}
+
+fn inspect<R: std::io::Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<ExtractionReport> {
+    use std::io::Read as _;
+
+    let mut report = ExtractionReport {
+        entries: archive.len(),
+        compressed_size: 0,
+        uncompressed_size: 0,
+        optional_components: Vec::new(),
+        problems: Vec::new(),
+        error: None,
+    };
+    match read_optional_components(archive) {
+        Ok(t) => report.optional_components = t,
+        Err(e) => report.problems.push(Problem::InvalidManifest {
+            error: format!("{e:#}"),
+        }),
+    }
+    let mut links = BTreeMap::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let name = file.name().to_owned();
+        report.compressed_size += file.compressed_size();
+        report.uncompressed_size += file.size();
+        let Some(rel_path) = file.enclosed_name() else {
+            report.problems.push(Problem::UnsafePath { name });
+            continue;
+        };
+        if file.encrypted() {
+            report.problems.push(Problem::Encrypted { name });
+        } else if file.is_symlink() {
+            drop(file);
+            let mut target = String::new();
+            archive.by_index(i)?.read_to_string(&mut target)?;
+            links.insert(rel_path, (name, PathBuf::from(target)));
+        } else if !file.is_dir() && !file.is_file() {
+            report.problems.push(Problem::UnsupportedFileType { name });
+        }
+    }
+    // checked once all are known, since any of them can lead through the others
+    for (rel_path, (name, target)) in &links {
+        if resolve_link(Path::new(""), rel_path, target, |p| {
+            links.get(p).map(|(_, target)| target.clone())
+        })
+        .is_none()
+        {
+            report.problems.push(Problem::EscapingSymlink {
+                name: name.clone(),
+                target: target.to_string_lossy().into_owned(),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Downloads the package archive at `url` and extracts it into a temporary directory, reporting
+/// everything that would make [`install_zip`](super::install_zip) fail or misbehave.
+pub async fn test_extract_zip(
+    app: Option<&AppHandle>,
+    log: &slog::Logger,
+    reqwest: &Reqwest,
+    url: &str,
+    cache: Option<CacheOptions<'_>>,
+    task_id: Option<tasks::Id>,
+) -> Result<ExtractionReport> {
+    let cache = cache.map(|c| c.with_suffix(".zip"));
+    let resource = fetch_resource(app, log, reqwest, url, cache, task_id).await?;
+    let temp_dir = tempfile::tempdir()?;
+    let report = tokio::task::block_in_place(|| {
+        fn run<R: std::io::Read + std::io::Seek>(
+            archive: Result<ZipArchive<R>, zip::result::ZipError>,
+            dest: &Path,
+        ) -> ExtractionReport {
+            let mut archive = match archive {
+                Ok(t) => t,
+                Err(e) => return ExtractionReport::failed(e.into()),
+            };
+            let mut report = match inspect(&mut archive) {
+                Ok(t) => t,
+                Err(e) => return ExtractionReport::failed(e),
+            };
+            if let Err(e) = extract_zip(&mut archive, dest, &[]) {
+                report.error = Some(format!("{e:#}"));
+            }
+            report
+        }
+
+        Ok::<_, anyhow::Error>(match resource {
+            FetchedResource::Bytes(bytes) => run(
+                ZipArchive::new(std::io::Cursor::new(bytes)),
+                temp_dir.path(),
+            ),
+            FetchedResource::File(path) => run(
+                ZipArchive::new(std::io::BufReader::new(std::fs::File::open(&path)?)),
+                temp_dir.path(),
+            ),
+        })
+    })?;
+    debug!(log, "Test extraction of {url:?}: {report:?}");
+    Ok(report)
+}
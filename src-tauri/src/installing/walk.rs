@@ -0,0 +1,137 @@
+//! Defensive traversal of package directory trees.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use walkdir::{DirEntry, WalkDir};
+
+/// Maximum depth of a package's directory tree. Anything deeper is almost certainly malicious.
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WalkError {
+    #[error(transparent)]
+    WalkDir(#[from] walkdir::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Directory cycle detected at {0:?}")]
+    Cycle(PathBuf),
+
+    #[error("Directory tree is nested too deeply at {0:?}")]
+    TooDeep(PathBuf),
+}
+
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(entry: &DirEntry) -> Result<DirId, WalkError> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = entry.metadata()?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(entry: &DirEntry) -> Result<DirId, WalkError> {
+    Ok(std::fs::canonicalize(entry.path())?)
+}
+
+/// Walks the tree at a root without following symlinks, refusing to descend into a directory that
+/// has already been visited or past [`MAX_DEPTH`].
+pub struct Walk {
+    iter: walkdir::IntoIter,
+    visited: HashSet<DirId>,
+}
+
+pub fn walk(root: &Path) -> Walk {
+    Walk {
+        iter: WalkDir::new(root).follow_links(false).into_iter(),
+        visited: HashSet::new(),
+    }
+}
+
+/// Like [`walk`], but yields the entries of each directory sorted by file name, so that the order
+/// is the same on every platform.
+pub fn walk_sorted(root: &Path) -> Walk {
+    Walk {
+        iter: WalkDir::new(root)
+            .follow_links(false)
+            .sort_by_file_name()
+            .into_iter(),
+        visited: HashSet::new(),
+    }
+}
+
+impl Walk {
+    /// See [`walkdir::IntoIter::skip_current_dir`].
+    pub fn skip_current_dir(&mut self) {
+        self.iter.skip_current_dir();
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<DirEntry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.iter.next()? {
+            Ok(t) => t,
+            Err(e) => return Some(Err(e.into())),
+        };
+        if entry.file_type().is_dir() {
+            if entry.depth() > MAX_DEPTH {
+                self.iter.skip_current_dir();
+                return Some(Err(WalkError::TooDeep(entry.into_path())));
+            }
+            match dir_id(&entry) {
+                Ok(id) => {
+                    if !self.visited.insert(id) {
+                        self.iter.skip_current_dir();
+                        return Some(Err(WalkError::Cycle(entry.into_path())));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{walk, WalkError, MAX_DEPTH};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_self_referential_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        std::os::unix::fs::symlink(".", dir.path().join("a").join("self")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a").join("root")).unwrap();
+
+        let entries = walk(dir.path()).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries
+            .iter()
+            .filter(|e| e.depth() == 2)
+            .all(|e| e.file_type().is_symlink()));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut path = dir.path().to_owned();
+        for _ in 0..=MAX_DEPTH {
+            path.push("d");
+        }
+        std::fs::create_dir_all(&path).unwrap();
+
+        assert!(matches!(
+            walk(dir.path()).collect::<Result<Vec<_>, _>>(),
+            Err(WalkError::TooDeep(_))
+        ));
+    }
+}
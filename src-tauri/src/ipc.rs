@@ -12,6 +12,9 @@ use tauri::{AppHandle, Manager};
 use triomphe::Arc;
 use uuid::Uuid;
 
+use crate::games::PackageLoader;
+use crate::launching::running::RunningGuard;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
 pub enum SafeOsString {
     Unicode(String),
@@ -110,6 +113,12 @@ pub enum C2SMessage {
         command: SafeOsString,
         args: Vec<SafeOsString>,
         env: HashMap<String, SafeOsString>,
+        /// The process the wrapper started.
+        pid: Option<u32>,
+    },
+    /// The mod loader was set up to be loaded by the game.
+    LoaderInjected {
+        loader: PackageLoader,
     },
     Log {
         level: LogLevel,
@@ -129,6 +138,37 @@ pub enum C2SMessage {
     DoctorReport(DoctorReport),
 }
 
+/// What the wrapper sends over its IPC channel, tagged with the version of the protocol.
+///
+/// The wrapper that runs may be left over from another version of Manderrow, like one named in a
+/// game's Steam launch options, and lay out its messages differently. Each version of the protocol
+/// is its own variant, so that such messages are rejected instead of misread.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub enum WrapMessage {
+    /// The first message of a wrapper from before the protocol was versioned, a
+    /// [`C2SMessage::Connect`], which is encoded starting with the same variant index.
+    Unversioned,
+    V1(C2SMessage),
+}
+
+impl WrapMessage {
+    /// Returns the message, if it is of the version of the protocol spoken by this build.
+    pub fn into_current(self) -> Result<C2SMessage> {
+        match self {
+            Self::V1(msg) => Ok(msg),
+            Self::Unversioned => {
+                bail!("The game was launched by a wrapper from an older version of Manderrow")
+            }
+        }
+    }
+}
+
+impl From<C2SMessage> for WrapMessage {
+    fn from(value: C2SMessage) -> Self {
+        Self::V1(value)
+    }
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub enum S2CMessage {
     Connect,
@@ -161,18 +201,40 @@ pub fn spawn_c2s_pipe(
     log: slog::Logger,
     app_handle: AppHandle,
     c2s_channel: Channel<C2SMessage>,
-    c2s_rx: IpcOneShotServer<C2SMessage>,
+    c2s_rx: IpcOneShotServer<WrapMessage>,
+    // dropped once the wrapper disconnects
+    running: Option<RunningGuard>,
 ) -> anyhow::Result<()> {
     std::thread::Builder::new()
         .name("ipc-receiver".to_owned())
         .spawn(move || {
-            let (rx, mut msg) = match c2s_rx.accept() {
+            let send = |msg: C2SMessage| {
+                if let Err(e) = c2s_channel.send(msg) {
+                    // log this to the global logger because if we can't send messages on the channel, the local logger will fail
+                    error!(
+                        slog_scope::logger(),
+                        "Unable to emit ipc-message event to webview: {e}"
+                    );
+                }
+            };
+            let crash = |e: anyhow::Error| {
+                error!(log, "{e}");
+                send(C2SMessage::Crash {
+                    error: format!("{e:?}"),
+                });
+                send(C2SMessage::Disconnect {});
+            };
+            let (rx, msg) = match c2s_rx.accept() {
                 Ok(t) => t,
                 Err(e) => {
                     error!(log, "Unable to receive IPC message: {e}");
                     return;
                 }
             };
+            let mut msg = match msg.into_current() {
+                Ok(t) => t,
+                Err(e) => return crash(e),
+            };
             let mut exited = false;
             loop {
                 match msg {
@@ -183,20 +245,20 @@ pub fn spawn_c2s_pipe(
                             error!(log, "Failed to spawn S2C IPC pipe: {e}");
                         }
                     }
+                    C2SMessage::Start { pid: Some(pid), .. } => {
+                        if let Some(running) = &running {
+                            running.set_pid(pid);
+                        }
+                    }
                     C2SMessage::Crash { .. } | C2SMessage::Exit { .. } => {
                         exited = true;
                     }
                     _ => {}
                 }
-                if let Err(e) = c2s_channel.send(msg) {
-                    // log this to the global logger because if we can't send messages on the channel, the local logger will fail
-                    error!(
-                        slog_scope::logger(),
-                        "Unable to emit ipc-message event to webview: {e}"
-                    );
-                }
-                msg = match rx.recv() {
-                    Ok(t) => t,
+                send(msg);
+                msg = match rx.recv().map(WrapMessage::into_current) {
+                    Ok(Ok(t)) => t,
+                    Ok(Err(e)) => return crash(e),
                     Err(IpcError::Disconnected) if exited => break,
                     Err(IpcError::Disconnected) => {
                         error!(log, "Unexpected IPC disconnection");
@@ -208,12 +270,7 @@ pub fn spawn_c2s_pipe(
                     }
                 };
             }
-            if let Err(e) = c2s_channel.send(C2SMessage::Disconnect {}) {
-                error!(
-                    slog_scope::logger(),
-                    "Unable to emit ipc-message event to webview: {e}"
-                );
-            }
+            send(C2SMessage::Disconnect {});
         })?;
     Ok(())
 }
@@ -238,13 +295,13 @@ fn spawn_s2c_pipe(log: slog::Logger, app_handle: &AppHandle, s2c_tx: String) ->
 
 /// Inter-process communication.
 pub struct Ipc {
-    pub c2s_tx: IpcSender<C2SMessage>,
+    pub c2s_tx: IpcSender<WrapMessage>,
     pub s2c_rx: Arc<tokio::sync::Mutex<IpcReceiver<S2CMessage>>>,
 }
 
 impl Drop for Ipc {
     fn drop(&mut self) {
-        _ = self.c2s_tx.send(C2SMessage::Disconnect {});
+        _ = self.c2s_tx.send(C2SMessage::Disconnect {}.into());
     }
 }
 
@@ -256,7 +313,7 @@ pub struct IpcBiState<'a> {
 impl Ipc {
     pub async fn send(&self, message: C2SMessage) -> Result<()> {
         let c2s_tx = self.c2s_tx.clone();
-        Ok(tokio::task::spawn_blocking(move || c2s_tx.send(message)).await??)
+        Ok(tokio::task::spawn_blocking(move || c2s_tx.send(message.into())).await??)
     }
 
     pub async fn recv(&self) -> Result<S2CMessage> {
@@ -331,3 +388,87 @@ impl<'a> IpcBiState<'a> {
         Ok(serde_json::from_value(serde_json::Value::String(choice))?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ipc_channel::ipc::{IpcOneShotServer, IpcSender};
+
+    use crate::games::PackageLoader;
+
+    use super::{
+        C2SMessage, DoctorReport, LogLevel, OutputLine, SafeOsString, StandardOutputChannel,
+        WrapMessage,
+    };
+
+    #[test]
+    fn test_wrap_message_round_trip() {
+        let messages = [
+            C2SMessage::Connect {
+                s2c_tx: "s2c".to_owned(),
+            },
+            C2SMessage::Disconnect {},
+            C2SMessage::Start {
+                command: SafeOsString::Unicode("steam".to_owned()),
+                args: vec![SafeOsString::NonUnicodeBytes(vec![0xff])],
+                env: HashMap::from([(
+                    "DOORSTOP_ENABLED".to_owned(),
+                    SafeOsString::Unicode("1".to_owned()),
+                )]),
+                pid: Some(1234),
+            },
+            C2SMessage::LoaderInjected {
+                loader: PackageLoader::BepInEx,
+            },
+            C2SMessage::Log {
+                level: LogLevel::Warning,
+                scope: "BepInEx".into(),
+                message: "Loading plugins".to_owned(),
+            },
+            C2SMessage::Output {
+                channel: StandardOutputChannel::Err,
+                line: OutputLine::Bytes(vec![0xff, b'\n']),
+            },
+            C2SMessage::Exit { code: Some(1) },
+            C2SMessage::Crash {
+                error: "Failed to launch".to_owned(),
+            },
+            C2SMessage::DoctorReport(DoctorReport {
+                id: uuid::Uuid::nil(),
+                translation_key: "doctor.key".to_owned(),
+                message: None,
+                message_args: None,
+                fixes: Vec::new(),
+            }),
+        ];
+        let (tx, rx) = ipc_channel::ipc::channel::<WrapMessage>().unwrap();
+        for msg in messages {
+            tx.send(msg.clone().into()).unwrap();
+            let received = rx.recv().unwrap().into_current().unwrap();
+            assert_eq!(format!("{received:?}"), format!("{msg:?}"));
+        }
+    }
+
+    #[test]
+    fn test_unversioned_wrap_message() {
+        #[derive(serde::Serialize)]
+        enum UnversionedMessage {
+            Connect { s2c_tx: String },
+        }
+
+        let (server, name) = IpcOneShotServer::<WrapMessage>::new().unwrap();
+        let sender = std::thread::spawn(move || {
+            IpcSender::<UnversionedMessage>::connect(name)
+                .unwrap()
+                .send(UnversionedMessage::Connect {
+                    s2c_tx: "s2c".to_owned(),
+                })
+                .unwrap();
+        });
+        let (_, msg) = server.accept().unwrap();
+        sender.join().unwrap();
+        assert!(matches!(msg, WrapMessage::Unversioned));
+        assert!(msg.into_current().is_err());
+    }
+}
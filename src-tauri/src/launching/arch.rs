@@ -0,0 +1,256 @@
+//! Detection of the CPU architecture a game executable was built for.
+//!
+//! Loaders like BepInEx and UnityDoorstop ship separate builds per architecture, and injecting
+//! the wrong one fails silently, so we inspect the executable's header instead of assuming the
+//! game matches the host.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, ensure, Context as _, Result};
+use slog::debug;
+
+use crate::games::{Game, Platform};
+
+use super::resolve_game_executable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Arch {
+    X86,
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    /// Returns the name of the architecture as used by [`std::env::consts::ARCH`].
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::X86 => "x86",
+            Self::X86_64 => "x86_64",
+            Self::Aarch64 => "aarch64",
+        }
+    }
+
+    pub fn host() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86" => Some(Self::X86),
+            "x86_64" => Some(Self::X86_64),
+            "aarch64" => Some(Self::Aarch64),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Arch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+fn read_u16_le(buf: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        buf.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32_le(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        buf.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32_be(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        buf.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn pe_machine_to_arch(machine: u16) -> Result<Arch> {
+    Ok(match machine {
+        0x014c => Arch::X86,
+        0x8664 => Arch::X86_64,
+        0xaa64 => Arch::Aarch64,
+        _ => bail!("Unsupported PE machine type {machine:#06x}"),
+    })
+}
+
+fn elf_machine_to_arch(machine: u16) -> Result<Arch> {
+    Ok(match machine {
+        3 => Arch::X86,
+        62 => Arch::X86_64,
+        183 => Arch::Aarch64,
+        _ => bail!("Unsupported ELF machine type {machine}"),
+    })
+}
+
+fn mach_o_cpu_type_to_arch(cpu_type: u32) -> Result<Arch> {
+    Ok(match cpu_type {
+        0x0000_0007 => Arch::X86,
+        0x0100_0007 => Arch::X86_64,
+        0x0100_000c => Arch::Aarch64,
+        _ => bail!("Unsupported Mach-O CPU type {cpu_type:#010x}"),
+    })
+}
+
+/// Determines the architecture of the executable in `rdr` by inspecting its PE, ELF, or Mach-O
+/// header.
+///
+/// For universal (fat) Mach-O binaries, the host architecture is preferred if present.
+pub fn detect_arch<R: Read + Seek>(mut rdr: R) -> Result<Arch> {
+    let mut header = [0u8; 64];
+    let mut len = 0;
+    while len < header.len() {
+        match rdr.read(&mut header[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    let header = &header[..len];
+
+    if header.starts_with(b"MZ") {
+        let pe_offset = read_u32_le(header, 0x3c).context("Truncated DOS header")?;
+        rdr.seek(SeekFrom::Start(pe_offset.into()))?;
+        let mut pe_header = [0u8; 6];
+        rdr.read_exact(&mut pe_header)
+            .context("Truncated PE header")?;
+        if pe_header[..4] != *b"PE\0\0" {
+            bail!("Invalid PE signature");
+        }
+        return pe_machine_to_arch(read_u16_le(&pe_header, 4).unwrap());
+    }
+
+    if header.starts_with(b"\x7fELF") {
+        // EI_DATA: 1 is little-endian, 2 is big-endian
+        let machine = match header.get(5) {
+            Some(1) => read_u16_le(header, 18),
+            Some(2) => header
+                .get(18..20)
+                .map(|b| u16::from_be_bytes(b.try_into().unwrap())),
+            _ => bail!("Invalid ELF data encoding"),
+        }
+        .context("Truncated ELF header")?;
+        return elf_machine_to_arch(machine);
+    }
+
+    // MH_MAGIC and MH_MAGIC_64 as written by little-endian targets
+    if let Some(0xfeed_face | 0xfeed_facf) = read_u32_le(header, 0) {
+        return mach_o_cpu_type_to_arch(read_u32_le(header, 4).context("Truncated Mach-O header")?);
+    }
+
+    // FAT_MAGIC is always stored big-endian
+    if read_u32_be(header, 0) == Some(0xcafe_babe) {
+        let count = read_u32_be(header, 4).context("Truncated Mach-O fat header")?;
+        let mut archs = Vec::new();
+        rdr.seek(SeekFrom::Start(8))?;
+        for _ in 0..count {
+            let mut entry = [0u8; 20];
+            rdr.read_exact(&mut entry)
+                .context("Truncated Mach-O fat header")?;
+            if let Ok(arch) = mach_o_cpu_type_to_arch(read_u32_be(&entry, 0).unwrap()) {
+                archs.push(arch);
+            }
+        }
+        let host = Arch::host();
+        return archs
+            .iter()
+            .copied()
+            .find(|&arch| Some(arch) == host)
+            .or_else(|| archs.first().copied())
+            .context("Universal binary does not contain a supported architecture");
+    }
+
+    Err(anyhow!("Unrecognized executable format"))
+}
+
+/// Resolves the file that actually contains the machine code for `path`, which may be a macOS
+/// `.app` bundle.
+fn resolve_executable_file(path: &Path) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_owned());
+    }
+    let macos_dir = path.join("Contents").join("MacOS");
+    if let Some(stem) = path.file_stem() {
+        let candidate = macos_dir.join(stem);
+        if candidate.is_file() {
+            return Ok(candidate);
+        }
+    }
+    for e in std::fs::read_dir(&macos_dir)
+        .with_context(|| format!("Failed to read app bundle at {path:?}"))?
+    {
+        let e = e?;
+        if e.file_type()?.is_file() {
+            return Ok(e.path());
+        }
+    }
+    bail!("No executable found in app bundle at {path:?}")
+}
+
+pub fn detect_executable_arch(path: &Path) -> Result<Arch> {
+    let path = resolve_executable_file(path)?;
+    let file = std::fs::File::open(&path)
+        .with_context(|| format!("Failed to open executable {path:?}"))?;
+    detect_arch(std::io::BufReader::new(file))
+        .with_context(|| format!("Unable to determine the architecture of {path:?}"))
+}
+
+/// Determines the architecture of `game` by inspecting `exe`, if one of its executables was
+/// selected, or else the executable that would be launched on `platform` from `install_dir`.
+pub async fn detect_game_arch(
+    log: &slog::Logger,
+    game: &Game<'_>,
+    install_dir: &Path,
+    platform: Platform,
+    exe: Option<&str>,
+) -> Result<Arch> {
+    let exe = match exe {
+        Some(exe) => {
+            ensure!(
+                game.exe_names.iter().any(|name| name == exe),
+                "{exe:?} is not an executable of {:?}",
+                game.id
+            );
+            exe
+        }
+        None => resolve_game_executable(game, install_dir, platform)
+            .await
+            .with_context(|| format!("Unable to determine the architecture of {:?}", game.id))?,
+    };
+    let path = install_dir.join(exe);
+    let arch = tokio::task::block_in_place(|| detect_executable_arch(&path))?;
+    debug!(log, "Detected architecture {arch} for {path:?}");
+    Ok(arch)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{detect_arch, Arch};
+
+    #[test]
+    fn test_detect_arch() {
+        let mut pe = vec![0u8; 0x80];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        pe[0x44..0x46].copy_from_slice(&0x014cu16.to_le_bytes());
+        assert_eq!(detect_arch(Cursor::new(&pe)).unwrap(), Arch::X86);
+        pe[0x44..0x46].copy_from_slice(&0x8664u16.to_le_bytes());
+        assert_eq!(detect_arch(Cursor::new(&pe)).unwrap(), Arch::X86_64);
+
+        let mut elf = vec![0u8; 64];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[4] = 2;
+        elf[5] = 1;
+        elf[18..20].copy_from_slice(&62u16.to_le_bytes());
+        assert_eq!(detect_arch(Cursor::new(&elf)).unwrap(), Arch::X86_64);
+
+        let mut mach_o = vec![0u8; 32];
+        mach_o[..4].copy_from_slice(&0xfeed_facfu32.to_le_bytes());
+        mach_o[4..8].copy_from_slice(&0x0100_000cu32.to_le_bytes());
+        assert_eq!(detect_arch(Cursor::new(&mach_o)).unwrap(), Arch::Aarch64);
+
+        assert!(detect_arch(Cursor::new(b"#!/bin/sh\n")).is_err());
+    }
+}
@@ -1,27 +1,21 @@
-use std::ffi::{OsStr, OsString};
+use std::ffi::OsString;
 use std::path::PathBuf;
 
 use anyhow::{bail, Context as _, Result};
-use slog::debug;
+use slog::{debug, warn};
 use tempfile::tempdir;
 use uuid::Uuid;
 
-use crate::games::games_by_id;
+use crate::games::{games_by_id, Platform};
 use crate::installing::{fetch_resource_cached_by_hash, install_file, install_zip};
+use crate::launching::arch::{detect_game_arch, Arch};
+use crate::launching::loaders::CommandBuilder;
 use crate::profiles::{profile_path, MODS_FOLDER};
 use crate::stores::steam::paths::resolve_steam_app_install_directory;
 use crate::stores::steam::proton::{ensure_wine_will_load_dll_override, uses_proton};
 use crate::Reqwest;
 
-pub trait CommandBuilder {
-    fn env(&mut self, key: impl AsRef<str>, value: impl AsRef<OsStr>);
-
-    fn args(&mut self, args: impl IntoIterator<Item = impl AsRef<OsStr>>);
-
-    fn arg(&mut self, arg: impl AsRef<std::ffi::OsStr>);
-}
-
-fn get_url_and_hash(uses_proton: bool) -> Result<(&'static str, &'static str)> {
+fn get_url_and_hash(uses_proton: bool, arch: Arch) -> Result<(&'static str, &'static str)> {
     macro_rules! artifact {
         ($target:literal, $hash:literal) => {
             (concat!(
@@ -32,18 +26,36 @@ fn get_url_and_hash(uses_proton: bool) -> Result<(&'static str, &'static str)> {
         };
     }
 
-    Ok(match (std::env::consts::OS, std::env::consts::ARCH, uses_proton) {
-        ("linux", "x86_64", false) => artifact!("linux_x64", "e4ab751df846565012f75979b55ee4bc0b8232c7cb7227bea073a3e1dddeaf95"),
-        ("linux", "x86", false) => artifact!("linux_x86", "fccc407923e92b18e2d00b71685b5e7721ea5fe5264bbc951269c7452a672bcc"),
-        ("macos", "x86_64", false) => artifact!("macos_x64", "0eced505910fe7c48a2d1e4690d1c9616204ad015c13f8a3cb2d8926903216a5"),
-        ("linux", "x86_64", true) | ("windows", "x86_64", false) => artifact!("win_x64", "cee8243e7333aaf716b4f950b9043df94d5763cb5ff0d486a82bd5671cbafa98"),
-        ("linux", "x86", true) | ("windows", "x86", false) => artifact!("win_x86", "db3649c65243dc78441abc19334016faf4755a5c3fbe9a1a6e1e3142665db925"),
-        (os, arch, uses_proton) => bail!("Unsupported platform combo: (os: {os:?}, arch: {arch:?}, uses_proton: {uses_proton})"),
+    Ok(match (std::env::consts::OS, arch.as_str(), uses_proton) {
+        ("linux", "x86_64", false) => artifact!(
+            "linux_x64",
+            "e4ab751df846565012f75979b55ee4bc0b8232c7cb7227bea073a3e1dddeaf95"
+        ),
+        ("linux", "x86", false) => artifact!(
+            "linux_x86",
+            "fccc407923e92b18e2d00b71685b5e7721ea5fe5264bbc951269c7452a672bcc"
+        ),
+        ("macos", "x86_64", false) => artifact!(
+            "macos_x64",
+            "0eced505910fe7c48a2d1e4690d1c9616204ad015c13f8a3cb2d8926903216a5"
+        ),
+        ("linux", "x86_64", true) | ("windows", "x86_64", false) => artifact!(
+            "win_x64",
+            "cee8243e7333aaf716b4f950b9043df94d5763cb5ff0d486a82bd5671cbafa98"
+        ),
+        ("linux", "x86", true) | ("windows", "x86", false) => artifact!(
+            "win_x86",
+            "db3649c65243dc78441abc19334016faf4755a5c3fbe9a1a6e1e3142665db925"
+        ),
+        (os, arch, uses_proton) => bail!(
+            "Unsupported platform combo: (os: {os:?}, arch: {arch:?}, uses_proton: {uses_proton})"
+        ),
     })
 }
 
 fn get_doorstop_url_and_hash(
     uses_proton: bool,
+    arch: Arch,
 ) -> Result<(&'static str, &'static str, &'static str)> {
     macro_rules! doorstop_artifact {
         ($artifact:literal, $suffix:literal, $hash:literal) => {
@@ -55,18 +67,34 @@ fn get_doorstop_url_and_hash(
         };
     }
 
-    Ok(match (std::env::consts::OS, std::env::consts::ARCH, uses_proton) {
-        ("linux", "x86_64", false) => doorstop_artifact!("libUnityDoorstop", ".so", "a317dd1b96a3537ad5cf09a322af9d5090d100e0cb8b484cddafa4fc16035245"),
-        ("linux", "x86", false) => todo!(),
-        ("macos", "x86_64", false) => doorstop_artifact!("libUnityDoorstop", ".dylib", "c36f3802db74b2d5cf3211e4251d33bd7e2df118941c59073a4a6372ccec838f"),
-        ("linux", "x86_64", true) | ("windows", "x86_64", false) => doorstop_artifact!("UnityDoorstop", ".dll", "ff121c3cea9f9d50e9119ebc828ffc1e5aac741ef5649477f0d53108f5b23a4e"),
-        ("linux", "x86", true) | ("windows", "x86", false) => todo!(),
-        (os, arch, uses_proton) => bail!("Unsupported platform combo: (os: {os:?}, arch: {arch:?}, uses_proton: {uses_proton})"),
+    Ok(match (std::env::consts::OS, arch.as_str(), uses_proton) {
+        ("linux", "x86_64", false) => doorstop_artifact!(
+            "libUnityDoorstop",
+            ".so",
+            "a317dd1b96a3537ad5cf09a322af9d5090d100e0cb8b484cddafa4fc16035245"
+        ),
+        ("macos", "x86_64", false) => doorstop_artifact!(
+            "libUnityDoorstop",
+            ".dylib",
+            "c36f3802db74b2d5cf3211e4251d33bd7e2df118941c59073a4a6372ccec838f"
+        ),
+        ("linux", "x86_64", true) | ("windows", "x86_64", false) => doorstop_artifact!(
+            "UnityDoorstop",
+            ".dll",
+            "ff121c3cea9f9d50e9119ebc828ffc1e5aac741ef5649477f0d53108f5b23a4e"
+        ),
+        (os, arch, uses_proton) => bail!(
+            "Unsupported platform combo: (os: {os:?}, arch: {arch:?}, uses_proton: {uses_proton})"
+        ),
     })
 }
 
-pub async fn get_bep_in_ex_path(log: &slog::Logger, uses_proton: bool) -> Result<PathBuf> {
-    let (url, hash) = get_url_and_hash(uses_proton)?;
+pub async fn get_bep_in_ex_path(
+    log: &slog::Logger,
+    uses_proton: bool,
+    arch: Arch,
+) -> Result<PathBuf> {
+    let (url, hash) = get_url_and_hash(uses_proton, arch)?;
     let path = crate::launching::LOADERS_DIR.join(hash);
 
     install_zip(
@@ -77,6 +105,8 @@ pub async fn get_bep_in_ex_path(log: &slog::Logger, uses_proton: bool) -> Result
         url,
         Some(crate::installing::CacheOptions::by_hash(hash)),
         &path,
+        &[],
+        None,
         None,
     )
     .await?
@@ -93,10 +123,11 @@ pub async fn configure_command(
     profile_id: Uuid,
     doorstop_path: Option<PathBuf>,
     legacy_doorstop: bool,
+    mods_disabled: bool,
+    exe: Option<&str>,
 ) -> anyhow::Result<()> {
-    let steam_metadata = games_by_id()?
-        .get(game)
-        .context("No such game")?
+    let game = games_by_id()?.get(game).context("No such game")?;
+    let steam_metadata = game
         .store_platform_metadata
         .iter()
         .find_map(|m| m.steam_or_direct())
@@ -104,7 +135,23 @@ pub async fn configure_command(
 
     let uses_proton = uses_proton(log, steam_metadata.id).await?;
 
-    let bep_in_ex = get_bep_in_ex_path(log, uses_proton).await?;
+    let game_dir = resolve_steam_app_install_directory(steam_metadata.id).await?;
+
+    let platform = if uses_proton {
+        Platform::Windows
+    } else {
+        Platform::host()
+    };
+    let arch = match detect_game_arch(log, game, &game_dir, platform, exe).await {
+        Ok(arch) => arch,
+        Err(e) => {
+            let arch = Arch::host().context("Unsupported host architecture")?;
+            warn!(log, "{e:#}, assuming the host's architecture {arch}");
+            arch
+        }
+    };
+
+    let bep_in_ex = get_bep_in_ex_path(log, uses_proton, arch).await?;
 
     let profile_path = profile_path(profile_id);
 
@@ -133,7 +180,8 @@ pub async fn configure_command(
         p
     };
 
-    command.env("DOORSTOP_ENABLED", "1");
+    // with mods disabled, doorstop is still injected, but doesn't load BepInEx
+    command.env("DOORSTOP_ENABLED", if mods_disabled { "0" } else { "1" });
     command.env("DOORSTOP_TARGET_ASSEMBLY", &target_assembly);
     command.env("DOORSTOP_IGNORE_DISABLED_ENV", "0");
     // specify these only if they have values
@@ -145,7 +193,8 @@ pub async fn configure_command(
     // command.env("DOORSTOP_CLR_CORLIB_DIR", "");
     // command.env("DOORSTOP_CLR_RUNTIME_CORECLR_PATH", "");
 
-    let (doorstop_url, doorstop_hash, doorstop_suffix) = get_doorstop_url_and_hash(uses_proton)?;
+    let (doorstop_url, doorstop_hash, doorstop_suffix) =
+        get_doorstop_url_and_hash(uses_proton, arch)?;
 
     if cfg!(windows) || uses_proton {
         if uses_proton {
@@ -162,15 +211,14 @@ pub async fn configure_command(
             doorstop_url,
             // suffix is unnecessary here
             Some(crate::installing::CacheOptions::by_hash(doorstop_hash)),
-            &resolve_steam_app_install_directory(steam_metadata.id)
-                .await?
-                .join("winhttp.dll"),
+            &game_dir.join("winhttp.dll"),
             None,
         )
         .await?;
 
         if legacy_doorstop {
-            command.args(["--doorstop-enable", "true"]);
+            command.arg("--doorstop-enable");
+            command.arg(if mods_disabled { "false" } else { "true" });
 
             command.arg("--doorstop-target-assembly");
             command.arg(&target_assembly);
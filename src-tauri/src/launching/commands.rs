@@ -1,10 +1,15 @@
+use std::path::PathBuf;
+
 use tauri::ipc::Channel;
 use tauri::{AppHandle, State};
+use uuid::Uuid;
 
 use crate::ipc::{C2SMessage, IpcState, S2CMessage};
+use crate::settings::SettingsState;
+use crate::stores::steam::proton::CompatTool;
 use crate::CommandError;
 
-use super::LaunchTarget;
+use super::{LaunchOverrides, LaunchTarget, SteamLaunchMode};
 
 #[tauri::command]
 pub async fn send_s2c_message(
@@ -16,15 +21,69 @@ pub async fn send_s2c_message(
         .map_err(Into::into)
 }
 
+#[tauri::command]
+pub async fn get_game_executables(game: &str) -> Result<Vec<String>, CommandError> {
+    super::get_game_executables(game).await.map_err(Into::into)
+}
+
+/// Lists the Proton builds and Wine installations that a profile can be launched with.
+#[tauri::command]
+pub async fn get_compat_tools() -> Result<Vec<CompatTool>, CommandError> {
+    let log = slog_scope::logger();
+
+    crate::stores::steam::proton::find_compat_tools(&log)
+        .await
+        .map_err(Into::into)
+}
+
+/// Lists the logs of the output of the profile's launches, newest first.
+#[tauri::command]
+pub async fn get_launch_logs(id: Uuid) -> Result<Vec<PathBuf>, CommandError> {
+    super::logs::get_launch_logs(id).await.map_err(Into::into)
+}
+
+/// Stops the game launched for the profile.
+#[tauri::command]
+pub async fn stop_profile(ipc_state: State<'_, IpcState>, id: Uuid) -> Result<(), CommandError> {
+    let log = slog_scope::logger();
+
+    super::running::stop(&log, &*ipc_state, id)
+        .await
+        .map_err(Into::into)
+}
+
 #[tauri::command]
 pub async fn launch_profile(
     app_handle: AppHandle,
     ipc_state: State<'_, IpcState>,
+    settings: SettingsState<'_>,
     target: LaunchTarget<'_>,
     modded: bool,
+    overrides: Option<LaunchOverrides>,
+    force: Option<bool>,
     channel: Channel<C2SMessage>,
 ) -> Result<(), CommandError> {
-    super::launch_profile(app_handle, &*ipc_state, target, modded, channel)
-        .await
-        .map_err(Into::into)
+    let (steam_launch_mode, update_loader) = {
+        let settings = settings.read().await;
+        let settings = settings.as_ref().map_err(Clone::clone)?;
+        let steam_launch_mode = if settings.launch_via_steam_url().value {
+            SteamLaunchMode::Url
+        } else {
+            SteamLaunchMode::AppLaunch
+        };
+        (steam_launch_mode, settings.update_loader_on_launch().value)
+    };
+    super::launch_profile(
+        app_handle,
+        &*ipc_state,
+        target,
+        modded,
+        overrides.unwrap_or_default(),
+        force.unwrap_or(false),
+        steam_launch_mode,
+        update_loader,
+        channel,
+    )
+    .await
+    .map_err(Into::into)
 }
@@ -0,0 +1,113 @@
+//! Commands run before a profile's game is launched and after it exits.
+//!
+//! Hooks are configured per profile and run arbitrary commands through the system shell (`sh` or
+//! `cmd`), with the privileges of the user. They are never set up implicitly, only by the user
+//! through [`set_profile_hooks`](crate::profiles::set_profile_hooks), and are removed from
+//! profiles restored from a backup.
+//!
+//! Hooks run from the wrapper, in the directory the game is launched from, with the profile's
+//! environment variables and the following:
+//!
+//! | Variable                 | Value                                        |
+//! |--------------------------|----------------------------------------------|
+//! | `MANDERROW_GAME`         | The id of the game                           |
+//! | `MANDERROW_PROFILE_ID`   | The id of the profile                        |
+//! | `MANDERROW_PROFILE_DIR`  | The profile's directory                      |
+//! | `MANDERROW_EXIT_CODE`    | The game's exit code, for `post_exit` only   |
+//!
+//! Their output is logged. A `pre_launch` hook that fails or times out prevents the game from
+//! being launched. A failing `post_exit` hook is only logged, since the game has already exited.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{ensure, Context as _, Result};
+use slog::{info, warn};
+use tokio::io::{AsyncBufReadExt as _, AsyncRead, BufReader};
+use tokio::process::Command;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Hook {
+    /// The command line, interpreted by the system shell.
+    pub command: String,
+    /// How long the command may run before it is killed. Defaults to [`DEFAULT_TIMEOUT`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+impl Hook {
+    fn timeout(&self) -> Duration {
+        self.timeout_secs
+            .map_or(DEFAULT_TIMEOUT, Duration::from_secs)
+    }
+
+    fn command(&self) -> Command {
+        #[cfg(windows)]
+        let mut command = {
+            let mut command = Command::new("cmd");
+            command.arg("/C").raw_arg(&self.command);
+            command
+        };
+        #[cfg(not(windows))]
+        let mut command = {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&self.command);
+            command
+        };
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        command
+    }
+}
+
+async fn log_lines(log: &slog::Logger, name: &str, rdr: impl AsyncRead + Unpin) -> Result<()> {
+    let mut lines = BufReader::new(rdr).lines();
+    while let Some(line) = lines.next_line().await? {
+        info!(log, "[{name}] {line}");
+    }
+    Ok(())
+}
+
+/// Runs the hook named `name` with the extra environment variables `env`, logging its output.
+/// Fails if the command can't be started, exits unsuccessfully, or runs for longer than its
+/// timeout.
+pub async fn run(
+    log: &slog::Logger,
+    name: &str,
+    hook: &Hook,
+    env: &HashMap<String, OsString>,
+) -> Result<()> {
+    info!(log, "Running {name} hook: {:?}", hook.command);
+    let mut child = hook
+        .command()
+        .envs(env)
+        .spawn()
+        .with_context(|| format!("Failed to start {name} hook"))?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let timeout = hook.timeout();
+    let status = tokio::time::timeout(timeout, async {
+        let (out, err, status) = tokio::join!(
+            log_lines(log, name, stdout),
+            log_lines(log, name, stderr),
+            child.wait()
+        );
+        if let Err(e) = out.and(err) {
+            warn!(log, "Failed to read output of {name} hook: {e}");
+        }
+        status
+    })
+    .await
+    .with_context(|| format!("The {name} hook did not finish within {timeout:?}"))?
+    .with_context(|| format!("Failed to wait for {name} hook"))?;
+    ensure!(status.success(), "The {name} hook failed with {status}");
+    info!(log, "The {name} hook finished");
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! The launch arguments and environment of each mod loader.
+//!
+//! Every loader is injected into the game its own way, like BepInEx through doorstop's
+//! environment variables, or Northstar through a launcher of its own. These quirks are all
+//! dispatched from [`configure_loader`] by [`PackageLoader`], so supporting another loader means
+//! adding an arm there.
+
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use uuid::Uuid;
+
+use crate::games::PackageLoader;
+
+use super::{bep_in_ex, north_star, proxy_dll};
+
+pub trait CommandBuilder {
+    fn env(&mut self, key: impl AsRef<str>, value: impl AsRef<OsStr>);
+
+    fn args(&mut self, args: impl IntoIterator<Item = impl AsRef<OsStr>>);
+
+    fn arg(&mut self, arg: impl AsRef<std::ffi::OsStr>);
+}
+
+#[derive(Debug, Default)]
+pub struct LoaderOptions {
+    /// Overrides the doorstop library injected for BepInEx.
+    pub doorstop_path: Option<PathBuf>,
+    /// Whether doorstop is configured through command-line arguments, as before doorstop 4.
+    pub legacy_doorstop: bool,
+    /// Whether the loader should be injected without loading any mods.
+    pub mods_disabled: bool,
+    /// The executable of the game selected to be launched, if not the default.
+    pub exe: Option<String>,
+}
+
+/// What a loader changes about a launch other than its arguments and environment.
+#[derive(Debug, Default)]
+pub struct LoaderLaunch {
+    /// The executable to launch in place of the one that would be otherwise.
+    pub exe: Option<&'static str>,
+}
+
+/// Adds the arguments and environment `loader` needs to load the mods of the profile to
+/// `command`.
+pub async fn configure_loader(
+    log: &slog::Logger,
+    command: &mut impl CommandBuilder,
+    loader: PackageLoader,
+    game: &str,
+    profile_id: Uuid,
+    options: LoaderOptions,
+) -> Result<LoaderLaunch> {
+    match loader {
+        PackageLoader::BepInEx => {
+            bep_in_ex::configure_command(
+                log,
+                command,
+                game,
+                profile_id,
+                options.doorstop_path,
+                options.legacy_doorstop,
+                options.mods_disabled,
+                options.exe.as_deref(),
+            )
+            .await?;
+            Ok(LoaderLaunch::default())
+        }
+        PackageLoader::NorthStar => {
+            north_star::configure_command(log, command, game, profile_id).await?;
+            Ok(LoaderLaunch {
+                // mods are loaded by Northstar's launcher, never by the game's own executable
+                exe: Some(north_star::LAUNCHER_EXE),
+            })
+        }
+        PackageLoader::MelonLoader | PackageLoader::Lovely | PackageLoader::ReturnOfModding => {
+            proxy_dll::configure_command(
+                log,
+                command,
+                loader,
+                game,
+                profile_id,
+                options.mods_disabled,
+            )
+            .await?;
+            Ok(LoaderLaunch::default())
+        }
+        PackageLoader::GodotML
+        | PackageLoader::AncientDungeonVR
+        | PackageLoader::ShimLoader
+        | PackageLoader::GDWeave => {
+            bail!("The mod loader {loader:?} is not yet supported by the wrap command")
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! A log of the output of every launch of a profile, so that a game that closes on its own can
+//! still be diagnosed once it's gone.
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use slog::debug;
+use uuid::Uuid;
+
+use crate::ipc::StandardOutputChannel;
+use crate::profiles::profile_path;
+use crate::util::IoErrorKindExt as _;
+
+pub const LOGS_DIR_NAME: &str = ".manderrow_launch_logs";
+
+/// How many logs are kept, including that of the current launch.
+const MAX_LOGS: usize = 10;
+
+fn logs_dir(id: Uuid) -> PathBuf {
+    profile_path(id).join(LOGS_DIR_NAME)
+}
+
+/// Returns the paths of the logs in `dir`, oldest first.
+fn list_logs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let iter = match std::fs::read_dir(dir) {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut logs = Vec::new();
+    for e in iter {
+        let e = e?;
+        if let Some(timestamp) = e
+            .file_name()
+            .to_str()
+            .and_then(|s| s.strip_suffix(".log"))
+            .and_then(|s| s.parse::<u128>().ok())
+        {
+            logs.push((timestamp, e.path()));
+        }
+    }
+    logs.sort_unstable_by_key(|(timestamp, _)| *timestamp);
+    Ok(logs.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Returns the paths of the profile's launch logs, newest first.
+pub async fn get_launch_logs(id: Uuid) -> Result<Vec<PathBuf>> {
+    let dir = logs_dir(id);
+    let mut logs = tokio::task::block_in_place(|| list_logs(&dir))?;
+    logs.reverse();
+    Ok(logs)
+}
+
+pub struct LaunchLog {
+    file: Mutex<std::fs::File>,
+}
+
+impl LaunchLog {
+    /// Creates the log of a new launch of the profile, and removes all but the latest
+    /// [`MAX_LOGS`] logs.
+    pub fn create(log: &slog::Logger, id: Uuid) -> Result<(PathBuf, Self)> {
+        let dir = logs_dir(id);
+        tokio::task::block_in_place(|| {
+            std::fs::create_dir_all(&dir)?;
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+            let path = dir.join(format!("{timestamp}.log"));
+            let file = std::fs::File::create_new(&path)?;
+
+            let logs = list_logs(&dir)?;
+            for path in &logs[..logs.len().saturating_sub(MAX_LOGS)] {
+                debug!(log, "Pruning launch log {path:?}");
+                std::fs::remove_file(path)?;
+            }
+
+            Ok((
+                path,
+                Self {
+                    file: Mutex::new(file),
+                },
+            ))
+        })
+    }
+
+    /// Appends a line of output to the log, tagged with the channel it was written to.
+    pub fn write_line(&self, channel: StandardOutputChannel, line: &[u8]) -> std::io::Result<()> {
+        let tag: &[u8] = match channel {
+            StandardOutputChannel::Out => b"[out] ",
+            StandardOutputChannel::Err => b"[err] ",
+        };
+        let mut buf = Vec::with_capacity(tag.len() + line.len() + 1);
+        buf.extend_from_slice(tag);
+        buf.extend_from_slice(line);
+        buf.push(b'\n');
+        // written unbuffered, so that nothing is lost if the wrapper is killed along with the game
+        tokio::task::block_in_place(|| self.file.lock().write_all(&buf))
+    }
+}
@@ -1,18 +1,31 @@
+pub mod arch;
 pub mod bep_in_ex;
 pub mod commands;
+pub mod hooks;
+pub mod loaders;
+pub mod logs;
+pub mod north_star;
+pub mod pending;
+pub mod proxy_dll;
+pub mod running;
 
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
 use std::sync::LazyLock;
 use std::{panic::AssertUnwindSafe, path::PathBuf};
 
-use anyhow::{anyhow, Context, Result};
-use slog::{info, o};
+use anyhow::{anyhow, bail, Context, Result};
+use slog::{info, o, warn};
 use tauri::{ipc::Channel, AppHandle};
 use tokio::process::Command;
 use uuid::Uuid;
 
-use crate::games::games_by_id;
-use crate::ipc::S2CMessage;
+use crate::games::{games_by_id, Game, GameSupport, Platform};
+use crate::ipc::{S2CMessage, WrapMessage};
 use crate::profiles::{profile_path, read_profile_file};
+use crate::stores::steam::paths::resolve_steam_app_install_directory;
+use crate::stores::steam::proton::{compat_tool_command, uses_proton};
 use crate::util::hyphenated_uuid;
 use crate::{
     ipc::{C2SMessage, IpcState},
@@ -39,11 +52,158 @@ pub enum LaunchTarget<'a> {
     Vanilla(&'a str),
 }
 
+/// Environment variables and arguments for a single launch, which take precedence over those of
+/// the profile.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct LaunchOverrides {
+    pub env: BTreeMap<String, String>,
+    pub args: Vec<String>,
+}
+
+/// How a game is asked to start through Steam.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SteamLaunchMode {
+    /// Runs the Steam executable with `-applaunch`, which forwards our arguments to the wrapper.
+    AppLaunch,
+    /// Opens a `steam://rungameid` URL, as if the game had been started from the Steam library.
+    /// The wrapper's arguments are handed off through [`pending`].
+    Url,
+}
+
+/// Returns those of `game`'s executables that are present in `install_dir`. Those built for
+/// `platform` come first, and otherwise they are in the order they are listed for the game.
+pub async fn find_game_executables<'a>(
+    game: &'a Game<'_>,
+    install_dir: &Path,
+    platform: Platform,
+) -> Result<Vec<&'a str>> {
+    let mut found = Vec::new();
+    for name in &game.exe_names {
+        if tokio::fs::try_exists(install_dir.join(&**name)).await? {
+            found.push(&**name);
+        }
+    }
+    // stable, so that the listed order is kept otherwise
+    found.sort_by_key(|name| Platform::of_executable(name) != Some(platform));
+    Ok(found)
+}
+
+/// Returns the platform that the Steam app `steam_id` runs on, which is Windows when it is run
+/// through Proton.
+async fn game_platform(log: &slog::Logger, steam_id: &str) -> Result<Platform> {
+    Ok(if uses_proton(log, steam_id).await? {
+        Platform::Windows
+    } else {
+        Platform::host()
+    })
+}
+
+/// Selects the executable of `game` to launch from `install_dir` on `platform`. See
+/// [`find_game_executables`].
+pub async fn resolve_game_executable<'a>(
+    game: &'a Game<'_>,
+    install_dir: &Path,
+    platform: Platform,
+) -> Result<&'a str> {
+    find_game_executables(game, install_dir, platform)
+        .await?
+        .first()
+        .copied()
+        .with_context(|| {
+            format!(
+                "None of the executables {:?} of {:?} were found in {install_dir:?}",
+                game.exe_names, game.id
+            )
+        })
+}
+
+/// Returns the executables of the game that are present in its installation, to choose from for
+/// [`Profile::exe`](crate::profiles::Profile::exe). The first one is the default.
+pub async fn get_game_executables(game: &str) -> Result<Vec<String>> {
+    let game = *games_by_id()?.get(game).context("No such game")?;
+    let steam_metadata = game
+        .store_platform_metadata
+        .iter()
+        .find_map(|m| m.steam_or_direct())
+        .context("Unsupported store platform")?;
+    let install_dir = resolve_steam_app_install_directory(steam_metadata.id).await?;
+    let platform = game_platform(&slog_scope::logger(), steam_metadata.id).await?;
+    Ok(find_game_executables(game, &install_dir, platform)
+        .await?
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Identifies the state of a game's installation, to tell when the game has been updated.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct GameInstallFingerprint {
+    /// The executable the hash was computed from.
+    pub exe: String,
+    pub size: u64,
+    /// The BLAKE3 hash of the executable.
+    pub hash: String,
+}
+
+/// Computes the fingerprint of the game's current installation from its default executable.
+pub async fn game_install_fingerprint(game: &Game<'_>) -> Result<GameInstallFingerprint> {
+    let steam_metadata = game
+        .store_platform_metadata
+        .iter()
+        .find_map(|m| m.steam_or_direct())
+        .context("Unsupported store platform")?;
+    let install_dir = resolve_steam_app_install_directory(steam_metadata.id).await?;
+    let platform = game_platform(&slog_scope::logger(), steam_metadata.id).await?;
+    let exe = resolve_game_executable(game, &install_dir, platform).await?;
+    let path = install_dir.join(exe);
+    let size = tokio::fs::metadata(&path).await?.len();
+    let hash = tokio::task::block_in_place(|| crate::installing::hash_file(&path))?;
+    Ok(GameInstallFingerprint {
+        exe: exe.to_owned(),
+        size,
+        hash: hash.to_hex().to_string(),
+    })
+}
+
+/// Builds the command that asks Steam to start the game.
+fn steam_command(store_identifier: &str, steam_launch_mode: SteamLaunchMode) -> Result<Command> {
+    let mut command = if cfg!(windows) {
+        #[cfg(windows)]
+        {
+            let mut p = crate::stores::steam::paths::get_steam_install_path_from_registry()?;
+            p.push("steam.exe");
+            Command::new(p)
+        }
+        #[cfg(not(windows))]
+        unreachable!()
+    } else if cfg!(target_os = "macos") {
+        Command::new("/Applications/Steam.app/Contents/MacOS/steam_osx")
+    } else if cfg!(unix) {
+        Command::new("steam")
+    } else {
+        bail!("Unsupported platform for Steam");
+    };
+    match steam_launch_mode {
+        SteamLaunchMode::AppLaunch => {
+            command.arg("-applaunch").arg(store_identifier);
+        }
+        SteamLaunchMode::Url => {
+            command.arg(format!("steam://rungameid/{store_identifier}"));
+        }
+    }
+    Ok(command)
+}
+
 pub async fn launch_profile(
     app_handle: AppHandle,
     ipc_state: &IpcState,
     target: LaunchTarget<'_>,
     modded: bool,
+    overrides: LaunchOverrides,
+    force: bool,
+    steam_launch_mode: SteamLaunchMode,
+    update_loader: bool,
     channel: Channel<C2SMessage>,
 ) -> Result<(), CommandError> {
     struct Logger {
@@ -77,7 +237,7 @@ pub async fn launch_profile(
         o!(),
     );
 
-    let game = match target {
+    let (game, mut exe, compat_tool) = match target {
         LaunchTarget::Profile(id) => {
             let mut path = profile_path(id);
             path.push("profile.json");
@@ -85,101 +245,212 @@ pub async fn launch_profile(
                 .await
                 .context("Failed to read profile")?;
             path.pop();
-            games_by_id()?
+            let game = games_by_id()?
                 .get(&*metadata.game)
                 .copied()
-                .with_context(|| format!("Unrecognized game {:?}", metadata.game))?
+                .with_context(|| format!("Unrecognized game {:?}", metadata.game))?;
+            (game, metadata.exe, metadata.compat_tool)
+        }
+        LaunchTarget::Vanilla(id) => {
+            let game = games_by_id()?
+                .get(id)
+                .copied()
+                .with_context(|| format!("Unrecognized game {:?}", id))?;
+            (game, None, None)
         }
-        LaunchTarget::Vanilla(id) => games_by_id()?
-            .get(id)
-            .copied()
-            .with_context(|| format!("Unrecognized game {:?}", id))?,
     };
+    if modded {
+        if let GameSupport {
+            supported: false,
+            reason,
+        } = game.support()
+        {
+            return Err(anyhow!(
+                "{} cannot be launched with mods: {}",
+                game.name,
+                reason.unwrap_or_default()
+            )
+            .into());
+        }
+    }
+    // a second instance would share the loader's caches and the wrapper's IPC with the first
+    if !force {
+        let exe_names = game
+            .exe_names
+            .iter()
+            .map(|name| name.rsplit('/').next().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let running = crate::util::process::find_processes_by_name(&exe_names).await?;
+        let tracked = matches!(target, LaunchTarget::Profile(id) if running::is_running(id));
+        if !running.is_empty() || tracked {
+            return Err(crate::Error::GameAlreadyRunning {
+                game: game.name.to_string(),
+            }
+            .into());
+        }
+    }
+    if let LaunchTarget::Profile(id) = target {
+        if let Err(e) = crate::profiles::snapshot::take(&log, id).await {
+            warn!(log, "Failed to snapshot profile before launch: {e:?}");
+        }
+        // after the snapshot, so that a bad update can be rolled back
+        if modded && update_loader {
+            crate::profiles::loader_update::update_before_launch(&app_handle, &log, id).await;
+        }
+    }
     let Some(store_metadata) = game.store_platform_metadata.iter().next() else {
         return Err(anyhow!("Unable to launch game").into());
     };
     let mut command: Command;
+    let mut direct_launch = false;
     match store_metadata {
         crate::games::StorePlatformMetadata::Steam {
             store_identifier, ..
-        } => {
+        }
+        | crate::games::StorePlatformMetadata::SteamDirect { store_identifier } => {
             let steam_metadata = game
                 .store_platform_metadata
                 .iter()
                 .find_map(|m| m.steam_or_direct())
                 .context("Unsupported store platform")?;
 
-            crate::stores::steam::launching::ensure_launch_args_are_applied(
-                &log,
-                Some(ipc_state.bi(&channel)),
-                game.id,
-                steam_metadata.id,
-            )
-            .await?;
-
-            command = if cfg!(windows) {
-                #[cfg(windows)]
-                {
-                    let mut p =
-                        crate::stores::steam::paths::get_steam_install_path_from_registry()?;
-                    p.push("steam.exe");
-                    Command::new(p)
+            let install_dir = resolve_steam_app_install_directory(steam_metadata.id).await?;
+            let proton = cfg!(target_os = "linux") && uses_proton(&log, steam_metadata.id).await?;
+            if exe.is_none() {
+                let platform = if proton {
+                    Platform::Windows
+                } else {
+                    Platform::host()
+                };
+                exe = find_game_executables(game, &install_dir, platform)
+                    .await?
+                    .first()
+                    .map(|&exe| exe.to_owned());
+                if exe.is_none() {
+                    warn!(
+                        log,
+                        "None of the executables {:?} were found in {install_dir:?}, launching the default",
+                        game.exe_names
+                    );
                 }
-                #[cfg(not(windows))]
-                unreachable!()
-            } else if cfg!(target_os = "macos") {
-                Command::new("/Applications/Steam.app/Contents/MacOS/steam_osx")
-            } else if cfg!(unix) {
-                Command::new("steam")
-            } else {
-                return Err(anyhow!("Unsupported platform for Steam").into());
+            }
+
+            // Windows-only games are run through Proton ourselves, which Steam only allows for
+            // the game's own executable
+            let compat_command = match &exe {
+                Some(exe) if modded && proton => compat_tool_command(
+                    &log,
+                    steam_metadata.id,
+                    compat_tool.as_deref(),
+                    &install_dir.join(exe),
+                )
+                .await?
+                .map(|compat_command| (install_dir, compat_command)),
+                _ => None,
             };
-            command.arg("-applaunch").arg(&**store_identifier);
+
+            if let Some((install_dir, compat_command)) = compat_command {
+                command = Command::new(std::env::current_exe()?);
+                command
+                    .arg("wrap")
+                    .args(compat_command.args)
+                    .envs(compat_command.env)
+                    .current_dir(install_dir);
+                direct_launch = true;
+            } else {
+                crate::stores::steam::launching::ensure_launch_args_are_applied(
+                    &log,
+                    Some(ipc_state.bi(&channel)),
+                    game.id,
+                    steam_metadata.id,
+                )
+                .await?;
+
+                command = steam_command(store_identifier, steam_launch_mode)?;
+            }
         }
         _ => return Err(anyhow!("Unsupported game store: {store_metadata:?}").into()),
     }
 
-    let (c2s_rx, c2s_tx) = ipc_channel::ipc::IpcOneShotServer::<C2SMessage>::new()
+    let (c2s_rx, c2s_tx) = ipc_channel::ipc::IpcOneShotServer::<WrapMessage>::new()
         .context("Failed to create IPC channel")?;
 
-    command.arg(";");
-    command.arg("--c2s-tx");
-    command.arg(c2s_tx);
+    let mut wrapper_args = Vec::<OsString>::new();
+    wrapper_args.push("--c2s-tx".into());
+    wrapper_args.push(c2s_tx.into());
     if let LaunchTarget::Profile(id) = target {
-        command.arg("--profile");
-        command.arg(hyphenated_uuid!(id));
+        wrapper_args.push("--profile".into());
+        wrapper_args.push(hyphenated_uuid!(id).to_owned().into());
     }
 
     // TODO: use Tauri sidecar
     if let Some(path) = std::env::var_os("MANDERROW_WRAPPER_STAGE2_PATH") {
-        command.arg("--wrapper-stage2");
-        command.arg(path);
+        wrapper_args.push("--wrapper-stage2".into());
+        wrapper_args.push(path);
     }
 
     if let Some(path) = std::env::var_os("OVERRIDE_DOORSTOP_LIBRARY_PATH") {
-        command.arg("--doorstop-path");
-        command.arg(path);
+        wrapper_args.push("--doorstop-path".into());
+        wrapper_args.push(path);
     }
 
     if std::env::var_os("LEGACY_DOORSTOP").is_some() {
-        command.arg("--legacy-doorstop");
+        wrapper_args.push("--legacy-doorstop".into());
+    }
+
+    if let Some(exe) = exe {
+        wrapper_args.push("--exe".into());
+        wrapper_args.push(exe.into());
     }
 
     if modded {
-        command.arg("--loader");
-        command.arg(game.package_loader.as_str());
+        wrapper_args.push("--loader".into());
+        wrapper_args.push(game.package_loader.as_str().into());
+    }
+
+    for (key, value) in overrides.env {
+        wrapper_args.push("--env".into());
+        wrapper_args.push(format!("{key}={value}").into());
+    }
+
+    for arg in overrides.args {
+        wrapper_args.push("--arg".into());
+        wrapper_args.push(arg.into());
     }
 
-    command.arg(";");
+    if direct_launch {
+        command.arg(";");
+        command.arg("--game").arg(game.id);
+        command.args(wrapper_args);
+        command.arg(";");
+    } else {
+        match steam_launch_mode {
+            SteamLaunchMode::AppLaunch => {
+                command.arg(";");
+                command.args(wrapper_args);
+                command.arg(";");
+            }
+            SteamLaunchMode::Url => pending::store(game.id, wrapper_args).await?,
+        }
+    }
 
-    // TODO: find a way to stop this if the launch fails
-    crate::ipc::spawn_c2s_pipe(log.clone(), app_handle, channel, c2s_rx)?;
+    let running = match target {
+        LaunchTarget::Profile(id) => Some(running::track(id)),
+        LaunchTarget::Vanilla(_) => None,
+    };
+    let launch = running.as_ref().map(running::RunningGuard::launch);
+    crate::ipc::spawn_c2s_pipe(log.clone(), app_handle, channel, c2s_rx, running)?;
 
     info!(log, "Launching game: {command:?}");
-    let status = command
-        .status()
-        .await
-        .context("Failed to wait for subprocess to exit")?;
+    let status = command.status().await;
+    if let Some(launch) = launch {
+        match &status {
+            // the launcher may exit before the game is started, like Steam does
+            Ok(status) if status.success() => launch.expire(),
+            _ => launch.fail(),
+        }
+    }
+    let status = status.context("Failed to wait for subprocess to exit")?;
     info!(log, "Launcher exited with status code {status}");
 
     Ok(())
@@ -0,0 +1,161 @@
+//! Titanfall 2 is modded through Northstar, whose launcher is started in place of the game's own
+//! executable and loads mods from a profile directory passed to it with `-profile=`.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _, Result};
+use slog::debug;
+use uuid::Uuid;
+
+use crate::games::games_by_id;
+use crate::installing::hash_file;
+use crate::launching::loaders::CommandBuilder;
+use crate::profiles::{
+    is_hidden_mod_entry, profile_path, read_installed_mod_manifest, MODS_FOLDER,
+};
+use crate::stores::steam::paths::resolve_steam_app_install_directory;
+use crate::stores::steam::proton::uses_proton;
+use crate::util::IoErrorKindExt as _;
+
+pub const LAUNCHER_EXE: &str = "NorthstarLauncher.exe";
+
+/// The top-level directory of the Northstar core package.
+const CORE_DIR: &str = "Northstar";
+
+/// The directory within a profile that Northstar is pointed at. It is laid out the way Northstar
+/// expects, with the core mods in `mods` and every other package in `packages`.
+const NORTHSTAR_PROFILE_DIR: &str = "R2Northstar";
+
+/// Returns the `Northstar` directory of the core package installed in the profile.
+pub async fn find_core(profile_id: Uuid) -> Result<PathBuf> {
+    let mods_dir = profile_path(profile_id).join(MODS_FOLDER);
+    let mut iter = match tokio::fs::read_dir(&mods_dir).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => bail!("The Northstar core package is not installed"),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {mods_dir:?}")),
+    };
+    while let Some(e) = iter.next_entry().await? {
+        if is_hidden_mod_entry(&e) {
+            continue;
+        }
+        let core = e.path().join(CORE_DIR);
+        if tokio::fs::try_exists(core.join(LAUNCHER_EXE)).await? {
+            return Ok(core);
+        }
+    }
+    bail!("The Northstar core package is not installed")
+}
+
+/// Links `link` to the directory `target`, with a junction on Windows, since creating a symlink
+/// needs elevated privileges there.
+fn link_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    return junction::create(target, link);
+}
+
+/// Returns whether the files at `a` and `b` have the same content.
+fn same_content(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let b_metadata = match std::fs::metadata(b) {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    Ok(std::fs::metadata(a)?.len() == b_metadata.len() && hash_file(a)? == hash_file(b)?)
+}
+
+/// Lays out `<profile>/R2Northstar`, linking the core mods and every installed package that
+/// contains Northstar mods.
+async fn prepare_profile_dir(log: &slog::Logger, profile_id: Uuid, core: &Path) -> Result<PathBuf> {
+    let dir = profile_path(profile_id).join(NORTHSTAR_PROFILE_DIR);
+    // only links are removed here, never their targets
+    match tokio::fs::remove_dir_all(&dir).await {
+        Ok(()) => {}
+        Err(e) if e.is_not_found() => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to clear {dir:?}")),
+    }
+    let packages_dir = dir.join("packages");
+    tokio::fs::create_dir_all(&packages_dir).await?;
+
+    let core_package = core.parent().context("Core directory has no parent")?;
+    let core_mods = core.join(NORTHSTAR_PROFILE_DIR).join("mods");
+    tokio::task::block_in_place(|| link_dir(&core_mods, &dir.join("mods")))
+        .context("Failed to link Northstar core mods")?;
+
+    let mut iter = tokio::fs::read_dir(profile_path(profile_id).join(MODS_FOLDER)).await?;
+    while let Some(e) = iter.next_entry().await? {
+        let package = e.path();
+        if is_hidden_mod_entry(&e)
+            || package == core_package
+            || !tokio::fs::try_exists(package.join("mods")).await?
+        {
+            continue;
+        }
+        let manifest = read_installed_mod_manifest(&package).await?;
+        let link = packages_dir.join(format!(
+            "{}-{}-{}",
+            manifest.owner, manifest.name, manifest.version.version_number
+        ));
+        debug!(log, "Linking {package:?} to {link:?}");
+        tokio::task::block_in_place(|| link_dir(&package, &link))
+            .with_context(|| format!("Failed to link {package:?}"))?;
+    }
+    Ok(dir)
+}
+
+/// Copies the launcher and the files it loads from the core package into the game's directory,
+/// which is where the launcher has to run from. Files that are already up to date are left
+/// alone.
+async fn install_launcher(log: &slog::Logger, core: &Path, game_dir: &Path) -> Result<()> {
+    let mut iter = tokio::fs::read_dir(core).await?;
+    while let Some(e) = iter.next_entry().await? {
+        if !e.file_type().await?.is_file() {
+            continue;
+        }
+        let dest = game_dir.join(e.file_name());
+        if tokio::task::block_in_place(|| same_content(&e.path(), &dest))? {
+            continue;
+        }
+        debug!(log, "Copying {:?} to {dest:?}", e.path());
+        tokio::fs::copy(e.path(), &dest)
+            .await
+            .with_context(|| format!("Failed to copy {:?} to {dest:?}", e.path()))?;
+    }
+    Ok(())
+}
+
+pub async fn configure_command(
+    log: &slog::Logger,
+    command: &mut impl CommandBuilder,
+    game: &str,
+    profile_id: Uuid,
+) -> Result<()> {
+    let game = games_by_id()?.get(game).context("No such game")?;
+    let steam_metadata = game
+        .store_platform_metadata
+        .iter()
+        .find_map(|m| m.steam_or_direct())
+        .context("Unsupported store platform")?;
+
+    let core = find_core(profile_id).await?;
+
+    let uses_proton = uses_proton(log, steam_metadata.id).await?;
+
+    let game_dir = resolve_steam_app_install_directory(steam_metadata.id).await?;
+
+    install_launcher(log, &core, &game_dir).await?;
+
+    let profile_dir = prepare_profile_dir(log, profile_id, &core).await?;
+
+    let mut arg = OsString::from("-profile=");
+    if uses_proton {
+        arg.push("Z:");
+    }
+    arg.push(profile_dir.as_os_str());
+    command.arg("-northstar");
+    command.arg(arg);
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Hand-off of the wrapper's arguments for launches started through a `steam://rungameid` URL.
+//!
+//! Unlike `-applaunch`, Steam URLs cannot carry extra arguments for the game. Steam still starts
+//! the game through the launch options we install (`wrap %command% ; --game <id> ;`), so the
+//! wrapper knows which game it is launching and picks up the rest of its arguments from here.
+//!
+//! Nothing the loader needs has to survive Steam's relaunch of the game: the wrapper sets
+//! Doorstop's environment variables and arguments on the process it spawns itself, after Steam
+//! has already set up its own environment (overlay, cloud saves, etc.).
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context as _, Result};
+
+use crate::paths::cache_dir;
+use crate::util::IoErrorKindExt;
+
+static PENDING_DIR: LazyLock<PathBuf> = LazyLock::new(|| cache_dir().join("pending_launches"));
+
+/// How long a pending launch remains valid. Anything older was most likely left behind by a
+/// launch that never reached the wrapper, and must not be applied to an unrelated launch of the
+/// game from the Steam library.
+const MAX_AGE: Duration = Duration::from_secs(60);
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct PendingLaunch {
+    created: SystemTime,
+    args: Vec<OsString>,
+}
+
+fn pending_path(game: &str) -> PathBuf {
+    PENDING_DIR.join(format!("{game}.json"))
+}
+
+/// Stores the wrapper arguments for the next launch of `game`.
+pub async fn store(game: &str, args: Vec<OsString>) -> Result<()> {
+    let path = pending_path(game);
+    tokio::fs::create_dir_all(&*PENDING_DIR).await?;
+    let pending = PendingLaunch {
+        created: SystemTime::now(),
+        args,
+    };
+    tokio::fs::write(&path, serde_json::to_vec(&pending)?)
+        .await
+        .with_context(|| format!("Failed to write pending launch to {path:?}"))?;
+    Ok(())
+}
+
+/// Removes and returns the wrapper arguments for the launch of `game`, if there is a recent one.
+pub async fn take(game: &str) -> Result<Option<Vec<OsString>>> {
+    let path = pending_path(game);
+    let bytes = match tokio::fs::read(&path).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    tokio::fs::remove_file(&path).await?;
+    let pending = serde_json::from_slice::<PendingLaunch>(&bytes)
+        .with_context(|| format!("Invalid pending launch at {path:?}"))?;
+    if pending.created.elapsed().map_or(true, |age| age > MAX_AGE) {
+        return Ok(None);
+    }
+    Ok(Some(pending.args))
+}
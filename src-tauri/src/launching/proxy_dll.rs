@@ -0,0 +1,105 @@
+//! MelonLoader, Lovely and ReturnOfModding are injected by a proxy `version.dll` shipped in their
+//! package, which the game loads from its own directory, and are pointed at the profile with
+//! command-line arguments. They only run on Windows, or through Proton.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context as _, Result};
+use slog::debug;
+use uuid::Uuid;
+
+use crate::games::{games_by_id, PackageLoader};
+use crate::launching::loaders::CommandBuilder;
+use crate::profiles::{is_hidden_mod_entry, profile_path, MODS_FOLDER};
+use crate::stores::steam::paths::resolve_steam_app_install_directory;
+use crate::stores::steam::proton::{ensure_wine_will_load_dll_override, uses_proton};
+use crate::util::IoErrorKindExt as _;
+
+const PROXY_DLL: &str = "version";
+
+/// Returns the proxy DLL of the loader package installed in the profile.
+async fn find_proxy_dll(profile_id: Uuid, loader: PackageLoader) -> Result<PathBuf> {
+    let file_name = format!("{PROXY_DLL}.dll");
+    let mods_dir = profile_path(profile_id).join(MODS_FOLDER);
+    let mut iter = match tokio::fs::read_dir(&mods_dir).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => bail!("The {loader:?} package is not installed"),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {mods_dir:?}")),
+    };
+    while let Some(e) = iter.next_entry().await? {
+        if is_hidden_mod_entry(&e) {
+            continue;
+        }
+        let path = e.path().join(&file_name);
+        if tokio::fs::try_exists(&path).await? {
+            return Ok(path);
+        }
+    }
+    bail!("The {loader:?} package is not installed")
+}
+
+pub async fn configure_command(
+    log: &slog::Logger,
+    command: &mut impl CommandBuilder,
+    loader: PackageLoader,
+    game: &str,
+    profile_id: Uuid,
+    mods_disabled: bool,
+) -> Result<()> {
+    let game = games_by_id()?.get(game).context("No such game")?;
+    let steam_metadata = game
+        .store_platform_metadata
+        .iter()
+        .find_map(|m| m.steam_or_direct())
+        .context("Unsupported store platform")?;
+
+    let uses_proton = uses_proton(log, steam_metadata.id).await?;
+    ensure!(
+        cfg!(windows) || uses_proton,
+        "{loader:?} can only be launched on Windows or through Proton"
+    );
+
+    let game_dir = resolve_steam_app_install_directory(steam_metadata.id).await?;
+
+    let proxy_dll = find_proxy_dll(profile_id, loader).await?;
+    if uses_proton {
+        ensure_wine_will_load_dll_override(log, steam_metadata.id, PROXY_DLL).await?;
+    }
+    let dest = game_dir.join(format!("{PROXY_DLL}.dll"));
+    debug!(log, "Copying {proxy_dll:?} to {dest:?}");
+    tokio::fs::copy(&proxy_dll, &dest)
+        .await
+        .with_context(|| format!("Failed to copy {proxy_dll:?} to {dest:?}"))?;
+
+    let game_path = |path: &Path| {
+        let mut buf = OsString::new();
+        if uses_proton {
+            buf.push("Z:");
+        }
+        buf.push(path.as_os_str());
+        buf
+    };
+    let profile_dir = profile_path(profile_id);
+
+    match (loader, mods_disabled) {
+        (PackageLoader::MelonLoader, false) => {
+            command.arg("--melonloader.basedir");
+            command.arg(game_path(&profile_dir));
+        }
+        (PackageLoader::MelonLoader, true) => command.arg("--no-mods"),
+        (PackageLoader::Lovely, false) => {
+            command.arg("--lovely-mod-dir");
+            command.arg(game_path(&profile_dir.join(MODS_FOLDER)));
+        }
+        (PackageLoader::Lovely, true) => command.arg("--vanilla"),
+        (PackageLoader::ReturnOfModding, false) => {
+            command.arg("--rom_modding_root_folder");
+            command.arg(game_path(&profile_dir));
+        }
+        (PackageLoader::ReturnOfModding, true) => command.args(["--rom_enabled", "false"]),
+        _ => bail!("{loader:?} is not injected through a proxy DLL"),
+    }
+
+    Ok(())
+}
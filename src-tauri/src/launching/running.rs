@@ -0,0 +1,141 @@
+//! The profiles whose games are running, so that they can be stopped.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context as _, Result};
+use parking_lot::Mutex;
+use slog::info;
+use uuid::Uuid;
+
+use crate::ipc::{IpcState, S2CMessage};
+use crate::util::process::Pid;
+
+/// How long the game is given to exit on its own before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the wrapper is given to report the game's process once the launcher has exited.
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Running {
+    /// Tells launches of the same profile apart.
+    generation: u64,
+    /// The process the wrapper started, once it has reported it.
+    pid: Option<u32>,
+}
+
+static RUNNING: LazyLock<Mutex<HashMap<Uuid, Running>>> = LazyLock::new(Default::default);
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Marks the profile as running until dropped.
+#[must_use]
+pub struct RunningGuard {
+    id: Uuid,
+    generation: u64,
+}
+
+impl RunningGuard {
+    pub fn set_pid(&self, pid: u32) {
+        if let Some(running) = RUNNING.lock().get_mut(&self.id) {
+            if running.generation == self.generation {
+                running.pid = Some(pid);
+            }
+        }
+    }
+
+    /// Returns a handle to the launch that remains usable once the guard has been handed off.
+    pub fn launch(&self) -> Launch {
+        Launch {
+            id: self.id,
+            generation: self.generation,
+        }
+    }
+}
+
+/// A launch of a profile that may never start the game, in which case the wrapper never connects
+/// and the [`RunningGuard`] waiting for it is never dropped.
+#[derive(Clone, Copy)]
+pub struct Launch {
+    id: Uuid,
+    generation: u64,
+}
+
+impl Launch {
+    /// Marks the profile as not running if the game's process hasn't been reported, because the
+    /// launcher failed.
+    pub fn fail(self) {
+        remove_unstarted(self.id, self.generation);
+    }
+
+    /// Marks the profile as not running if the game's process isn't reported within
+    /// [`START_TIMEOUT`].
+    pub fn expire(self) {
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(START_TIMEOUT).await;
+            remove_unstarted(self.id, self.generation);
+        });
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        remove(self.id, self.generation);
+    }
+}
+
+fn remove(id: Uuid, generation: u64) {
+    let mut running = RUNNING.lock();
+    if running.get(&id).is_some_and(|r| r.generation == generation) {
+        running.remove(&id);
+    }
+}
+
+fn remove_unstarted(id: Uuid, generation: u64) {
+    let mut running = RUNNING.lock();
+    if running
+        .get(&id)
+        .is_some_and(|r| r.generation == generation && r.pid.is_none())
+    {
+        running.remove(&id);
+    }
+}
+
+/// Marks the profile as running, replacing any earlier launch of it.
+pub fn track(id: Uuid) -> RunningGuard {
+    let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    RUNNING.lock().insert(
+        id,
+        Running {
+            generation,
+            pid: None,
+        },
+    );
+    RunningGuard { id, generation }
+}
+
+pub fn is_running(id: Uuid) -> bool {
+    RUNNING.lock().contains_key(&id)
+}
+
+/// Stops the game launched for the profile, and marks the profile as not running. If the wrapper
+/// has yet to report the game's process, it is asked to kill it instead.
+pub async fn stop(log: &slog::Logger, ipc_state: &IpcState, id: Uuid) -> Result<()> {
+    let (generation, pid) = RUNNING
+        .lock()
+        .get(&id)
+        .map(|r| (r.generation, r.pid))
+        .ok_or_else(|| anyhow!("The profile is not running"))?;
+    match pid {
+        Some(pid) => {
+            let pid = Pid::from_raw(pid).context("Invalid process id")?;
+            info!(log, "Stopping the game of profile {id}");
+            pid.terminate(log, STOP_TIMEOUT).await?;
+        }
+        None => super::send_s2c_message(ipc_state, S2CMessage::Kill).await?,
+    }
+    remove(id, generation);
+    Ok(())
+}
@@ -15,11 +15,14 @@
 mod app_commands;
 mod error;
 mod games;
+mod governor;
+mod host_headers;
 mod i18n;
 mod importing;
 mod installing;
 mod ipc;
 mod launching;
+mod logging;
 mod mod_index;
 mod mods;
 mod paths;
@@ -53,6 +56,17 @@ fn identifier() -> &'static str {
 
 struct Reqwest(reqwest::Client);
 
+impl Reqwest {
+    /// Like [`reqwest::Client::get`], but also attaches any headers configured for the URL's host
+    /// in [`host_headers`], in which case the request is sent with [`host_headers::client`].
+    fn get<U: reqwest::IntoUrl + AsRef<str>>(&self, url: U) -> reqwest::RequestBuilder {
+        match host_headers::headers_for(url.as_ref()) {
+            Some(headers) => host_headers::client().get(url).headers(headers.clone()),
+            None => self.0.get(url),
+        }
+    }
+}
+
 impl Deref for Reqwest {
     type Target = reqwest::Client;
 
@@ -87,7 +101,13 @@ fn run_app(ctx: tauri::Context<tauri::Wry>) -> anyhow::Result<()> {
         })
         .manage(settings::try_read())
         .manage(IpcState::default())
-        .manage(Reqwest(reqwest::Client::builder().build()?))
+        .manage(profiles::watcher::ProfileWatcher::default())
+        .manage(Reqwest(
+            reqwest::Client::builder()
+                .gzip(true)
+                .brotli(true)
+                .build()?,
+        ))
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_shell::init())
@@ -96,29 +116,83 @@ fn run_app(ctx: tauri::Context<tauri::Wry>) -> anyhow::Result<()> {
             app_commands::close_splashscreen,
             app_commands::relaunch,
             games::commands::get_games,
+            games::commands::get_games_by_loader,
+            games::commands::get_games_support,
+            games::commands::get_game_store_platforms,
+            games::commands::find_heroic_installation,
+            games::commands::find_gog_installation,
             games::commands::search_games,
             games::commands::get_games_popularity,
             games::commands::get_game_mods_downloads,
             i18n::get_preferred_locales,
             importing::commands::preview_import_modpack_from_thunderstore_code,
             importing::commands::import_modpack_from_thunderstore_code,
+            importing::commands::import_modpack_from_thunderstore_url,
+            importing::commands::import_modpack_from_file,
             installing::commands::clear_cache,
+            installing::commands::delete_cache_entries,
+            installing::commands::free_space,
+            installing::commands::migrate_cache,
+            installing::commands::prune_cache,
+            installing::commands::scan_package_changes,
+            installing::commands::validate_install_target,
             launching::commands::send_s2c_message,
+            launching::commands::get_game_executables,
+            launching::commands::get_compat_tools,
+            launching::commands::get_launch_logs,
+            launching::commands::stop_profile,
             launching::commands::launch_profile,
             mod_index::commands::fetch_mod_index,
             mod_index::commands::count_mod_index,
+            mod_index::commands::get_game_mod_counts,
             mod_index::commands::query_mod_index,
             mod_index::commands::get_from_mod_index,
             mod_index::thunderstore::commands::thunderstore_fetch_mod_markdown,
+            mod_index::thunderstore::commands::thunderstore_fetch_mod_changelog,
+            mod_index::thunderstore::commands::thunderstore_preview_update_changelogs,
+            mod_index::thunderstore::commands::thunderstore_check_connectivity,
             profiles::commands::get_profiles,
             profiles::commands::create_profile,
+            profiles::commands::set_profile_executable,
+            profiles::commands::set_profile_mods_enabled,
+            profiles::commands::set_profile_compat_tool,
+            profiles::commands::set_profile_launch_options,
+            profiles::commands::set_profile_hooks,
             profiles::commands::delete_profile,
+            profiles::commands::export_profile_as_folder,
+            profiles::commands::move_profile,
+            profiles::commands::backup_profile,
+            profiles::commands::restore_profile,
             profiles::commands::get_profile_mods,
+            profiles::commands::list_installed_mods,
+            profiles::commands::get_file_provenance,
+            profiles::commands::check_game_updated,
+            profiles::commands::check_load_order,
+            profiles::commands::get_dependency_graph,
+            profiles::commands::find_orphaned_cache_entries,
             profiles::commands::install_profile_mod,
+            profiles::commands::get_mod_optional_components,
+            profiles::commands::get_profile_lockfile,
+            profiles::commands::apply_profile_lockfile,
+            profiles::commands::test_extract_mod,
+            profiles::commands::downgrade_profile_loader,
+            profiles::commands::check_profile_loader_update,
+            profiles::commands::update_profile_loader,
+            profiles::commands::decline_profile_loader_update,
+            profiles::commands::set_profile_loader_pin,
             profiles::commands::uninstall_profile_mod,
+            profiles::commands::reset_profile_mod_config,
+            profiles::commands::repair_profile_mod_index,
+            profiles::commands::accept_profile_mod_changes,
+            profiles::commands::get_profile_changes_since_launch,
+            profiles::commands::get_profile_fingerprint,
+            profiles::commands::watch_profile,
+            profiles::commands::unwatch_profile,
             settings::commands::get_settings,
             settings::commands::get_settings_ui,
             settings::commands::update_settings,
+            settings::commands::export_settings,
+            settings::commands::import_settings,
             tasks::commands::allocate_task,
             tasks::commands::cancel_task,
         ])
@@ -154,18 +228,7 @@ pub fn main() -> anyhow::Result<()> {
     while let Some(arg) = args.next()? {
         match arg {
             Value(cmd) if cmd == "wrap" => {
-                return tauri::async_runtime::block_on(async move {
-                    match wrap::run(args).await {
-                        Ok(()) => Ok(()),
-                        Err(e) => {
-                            if cfg!(debug_assertions) {
-                                tokio::fs::write("/tmp/manderrow-wrap-crash", &format!("{e:?}"))
-                                    .await?;
-                            }
-                            Err(e)
-                        }
-                    }
-                })
+                return tauri::async_runtime::block_on(wrap::run(args));
             }
             Value(cmd) => bail!("Unrecognized command {cmd:?}"),
             Long("relaunch") => relaunch = Some(args.value()?.parse()?),
@@ -173,7 +236,10 @@ pub fn main() -> anyhow::Result<()> {
         }
     }
 
-    let _guard = slog_envlogger::init()?;
+    let _guard = logging::init()?;
+
+    // load the user's games now, so that problems with them are logged at startup
+    _ = games::games();
 
     // TODO: remove this when https://github.com/tauri-apps/tauri/pull/12313 is released
     if let Some(pid) = relaunch {
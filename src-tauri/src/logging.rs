@@ -0,0 +1,136 @@
+//! Logging setup.
+//!
+//! Logs are filtered with the `RUST_LOG` environment variable, using the same syntax as
+//! `env_logger`. Every module logs under its own path, so that a single subsystem can be debugged
+//! without drowning in logs from the rest of the app:
+//!
+//! | Subsystem                   | Target                                            |
+//! | --------------------------- | ------------------------------------------------- |
+//! | Package installation        | `manderrow_lib::installing`                       |
+//! | Launching and the wrapper   | `manderrow_lib::launching`, `manderrow_lib::wrap` |
+//! | Communication with the game | `manderrow_lib::ipc`                              |
+//! | Mod index                   | `manderrow_lib::mod_index`                        |
+//! | Profiles                    | `manderrow_lib::profiles`                         |
+//! | Game stores                 | `manderrow_lib::stores`                           |
+//!
+//! For example, to trace installation while keeping everything else quiet:
+//!
+//! ```sh
+//! RUST_LOG=warn,manderrow_lib=info,manderrow_lib::installing=trace manderrow
+//! ```
+//!
+//! If `RUST_LOG` is not set, [`DEFAULT_FILTER`] is used. `trace` and `debug` logs are compiled
+//! out of release builds entirely (see the `slog` features in `Cargo.toml`), so they cannot be
+//! enabled there.
+//!
+//! Logs are written in a human-readable format to stderr. If `MANDERROW_JSON_LOG` is set to a
+//! path, they are additionally appended to that file as JSON lines, one object per record:
+//!
+//! ```json
+//! {"timestamp":1739318400123,"level":"INFO","target":"manderrow_lib::installing","message":"Installing package","fields":{"url":"..."}}
+//! ```
+//!
+//! `timestamp` is in milliseconds since the Unix epoch, and `fields` holds the record's key-value
+//! pairs, if any. The same filter applies to both outputs.
+
+use std::fmt;
+use std::io::{BufWriter, Write as _};
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::Context as _;
+use parking_lot::Mutex;
+use slog::{o, Drain as _};
+
+/// The filter used when `RUST_LOG` is not set.
+const DEFAULT_FILTER: &str = if cfg!(debug_assertions) {
+    "warn,manderrow_lib=trace"
+} else {
+    "warn,manderrow_lib=info"
+};
+
+pub fn init() -> anyhow::Result<slog_scope::GlobalLoggerGuard> {
+    // Only provide a default value, don't override the user's choice.
+    if std::env::var_os("RUST_LOG").is_none() {
+        // SAFETY: no other threads have been spawned yet.
+        unsafe {
+            std::env::set_var("RUST_LOG", DEFAULT_FILTER);
+        }
+    }
+
+    let term = slog_term::CompactFormat::new(slog_term::TermDecorator::new().stderr().build())
+        .build()
+        .fuse();
+    let drain: Box<dyn slog::Drain<Ok = (), Err = slog::Never> + Send> =
+        match std::env::var_os("MANDERROW_JSON_LOG").filter(|path| !path.is_empty()) {
+            Some(path) => {
+                let json = JsonDrain::open(Path::new(&path))?;
+                // a failure to write the JSON log must not take down the human-readable one
+                Box::new(slog::Duplicate::new(term, json.ignore_res()).ignore_res())
+            }
+            None => Box::new(term),
+        };
+    let drain = slog_async::Async::default(slog_envlogger::new(drain).ignore_res());
+    let guard = slog_scope::set_global_logger(slog::Logger::root(drain.fuse(), o!()));
+    slog_stdlog::init()?;
+    Ok(guard)
+}
+
+/// Writes records to a file as JSON lines.
+struct JsonDrain {
+    file: Mutex<BufWriter<std::fs::File>>,
+}
+
+impl JsonDrain {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open JSON log file at {path:?}"))?;
+        Ok(Self {
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+impl slog::Drain for JsonDrain {
+    type Ok = ();
+    type Err = slog::Error;
+
+    fn log(&self, record: &slog::Record, values: &slog::OwnedKVList) -> slog::Result {
+        let mut fields = FieldSerializer(serde_json::Map::new());
+        slog::KV::serialize(values, record, &mut fields)?;
+        slog::KV::serialize(&record.kv(), record, &mut fields)?;
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let line = serde_json::json!({
+            "timestamp": timestamp,
+            "level": record.level().as_str(),
+            "target": record.module(),
+            "message": record.msg().to_string(),
+            "fields": fields.0,
+        });
+
+        let mut file = self.file.lock();
+        serde_json::to_writer(&mut *file, &line).map_err(std::io::Error::from)?;
+        file.write_all(b"\n")?;
+        // records are infrequent enough, and losing the last ones on a crash would defeat the
+        // purpose of the file
+        file.flush()?;
+        Ok(())
+    }
+}
+
+struct FieldSerializer(serde_json::Map<String, serde_json::Value>);
+
+impl slog::Serializer for FieldSerializer {
+    fn emit_arguments(&mut self, key: slog::Key, val: &fmt::Arguments) -> slog::Result {
+        self.0.insert(key.to_string(), val.to_string().into());
+        Ok(())
+    }
+}
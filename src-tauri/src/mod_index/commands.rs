@@ -4,9 +4,12 @@ use tauri::AppHandle;
 
 use crate::{
     mods::{ArchivedModRef, ModId},
-    tasks, CommandError,
+    tasks,
+    util::search::SearchMode,
+    CommandError,
 };
 
+use super::counts::ModCount;
 use super::{read_mod_index, SortColumn, SortOption};
 
 #[tauri::command]
@@ -31,38 +34,59 @@ fn map_to_json<'a>(buf: &mut Vec<u8>, it: impl Iterator<Item = &'a ArchivedModRe
     }
 }
 
+/// Returns the number of mods available for each game, in the same order as
+/// [`get_games`](crate::games::commands::get_games). See [`super::counts`].
 #[tauri::command]
-pub async fn count_mod_index(game: &str, query: &str) -> Result<usize, CommandError> {
+pub async fn get_game_mod_counts() -> Result<Vec<Option<ModCount>>, CommandError> {
+    Ok(super::counts::get_mod_counts().await?)
+}
+
+#[tauri::command]
+pub async fn count_mod_index(
+    game: &str,
+    query: &str,
+    mode: Option<SearchMode>,
+) -> Result<usize, CommandError> {
     let mod_index = read_mod_index(game).await?;
 
-    Ok(super::count_mod_index(&mod_index, query).await?)
+    Ok(super::count_mod_index(&mod_index, query, mode.unwrap_or_default()).await?)
 }
 
 #[tauri::command]
 pub async fn query_mod_index(
     game: &str,
     query: &str,
+    mode: Option<SearchMode>,
     sort: Vec<SortOption<SortColumn>>,
     skip: Option<usize>,
     limit: Option<NonZeroUsize>,
 ) -> Result<tauri::ipc::Response, CommandError> {
     let mod_index = read_mod_index(game).await?;
 
-    let buf = super::query_mod_index(&mod_index, query, &sort).await?;
+    let skip = skip.unwrap_or(0);
+    let sorted_len = limit.map(|limit| skip.saturating_add(limit.get()));
+    let mode = mode.unwrap_or_default();
+    let buf = super::query_mod_index(&mod_index, query, mode, &sort, sorted_len).await?;
 
     let count = buf.len();
 
     let mut out_buf = br#"{"count":"#.as_slice().to_owned();
     simd_json::serde::to_writer(&mut out_buf, &count).unwrap();
     out_buf.extend(br#","mods":["#);
-    let mods = buf.into_iter().map(|(m, _)| m);
-    match (skip.unwrap_or(0), limit) {
-        (0, Some(limit)) => map_to_json(&mut out_buf, mods.take(limit.get())),
-        (0, None) => map_to_json(&mut out_buf, mods),
-        (skip, Some(limit)) => map_to_json(&mut out_buf, mods.skip(skip).take(limit.get())),
-        (skip, None) => map_to_json(&mut out_buf, mods.skip(skip)),
+    let page = buf.get(skip..).unwrap_or_default();
+    let page = match limit {
+        Some(limit) => &page[..page.len().min(limit.get())],
+        None => page,
     };
-    out_buf.extend(b"]}");
+    map_to_json(&mut out_buf, page.iter().map(|&(m, _)| m));
+    // every mod matches an empty query, so there is no score to report
+    out_buf.extend(br#"],"scores":"#);
+    let scores = page
+        .iter()
+        .map(|&(_, score)| (!query.is_empty()).then_some(score))
+        .collect::<Vec<_>>();
+    simd_json::serde::to_writer(&mut out_buf, &scores).unwrap();
+    out_buf.extend(b"}");
     // SAFETY: simd_json only writes valid UTF-8
     Ok(tauri::ipc::Response::new(unsafe {
         String::from_utf8_unchecked(out_buf)
@@ -0,0 +1,143 @@
+//! The number of mods available for each game, for display in the game selection.
+//!
+//! Counts are taken whenever a game's mod index is fetched and persisted, so that they are
+//! available for every game whose index has been fetched before, without fetching or keeping all
+//! of those indexes in memory. A count older than [`TTL`] is still returned, but marked as stale,
+//! and is retaken from the mod index if that happens to be in memory.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use slog::warn;
+use tokio::sync::Mutex;
+
+use crate::games::games;
+use crate::paths::cache_dir;
+use crate::util::IoErrorKindExt as _;
+
+use super::MOD_INDEXES;
+
+pub const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+struct CachedCount {
+    count: usize,
+    /// When the count was taken, in seconds since the Unix epoch.
+    counted_at: u64,
+}
+
+impl CachedCount {
+    fn is_stale(&self, now: u64) -> bool {
+        now.saturating_sub(self.counted_at) > TTL.as_secs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ModCount {
+    pub count: usize,
+    /// Whether the count is older than [`TTL`].
+    pub stale: bool,
+}
+
+/// The cached counts keyed by game id, loaded from disk on first use.
+static COUNTS: Mutex<Option<HashMap<String, CachedCount>>> = Mutex::const_new(None);
+
+fn counts_path() -> PathBuf {
+    cache_dir().join("mod_counts.json")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+async fn load() -> HashMap<String, CachedCount> {
+    let path = counts_path();
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!(slog_scope::logger(), "Discarding invalid {path:?}: {e}");
+            HashMap::new()
+        }),
+        Err(e) if e.is_not_found() => HashMap::new(),
+        Err(e) => {
+            warn!(slog_scope::logger(), "Failed to read {path:?}: {e}");
+            HashMap::new()
+        }
+    }
+}
+
+async fn save(counts: &HashMap<String, CachedCount>) -> Result<()> {
+    tokio::fs::create_dir_all(cache_dir()).await?;
+    tokio::fs::write(counts_path(), serde_json::to_vec(counts)?).await?;
+    Ok(())
+}
+
+/// Records the number of mods in a freshly fetched mod index.
+pub async fn record(game: &str, count: usize) -> Result<()> {
+    let mut counts = COUNTS.lock().await;
+    if counts.is_none() {
+        *counts = Some(load().await);
+    }
+    let counts = counts.as_mut().unwrap();
+    counts.insert(
+        game.to_owned(),
+        CachedCount {
+            count,
+            counted_at: now(),
+        },
+    );
+    save(counts).await
+}
+
+/// Returns the number of mods available for each game, in the same order as
+/// [`games`](crate::games::games), or `None` for games whose mod index has never been fetched.
+pub async fn get_mod_counts() -> Result<Vec<Option<ModCount>>> {
+    let now = now();
+    let mut counts = COUNTS.lock().await;
+    if counts.is_none() {
+        *counts = Some(load().await);
+    }
+    let counts = counts.as_mut().unwrap();
+    let mut changed = false;
+    let result = games()?
+        .iter()
+        .map(|game| {
+            let cached = counts.get(game.id);
+            if cached.is_none_or(|c| c.is_stale(now)) {
+                // never wait on a fetch in progress, since that could take a while
+                let in_memory = MOD_INDEXES
+                    .get(&*game.thunderstore_url)
+                    .and_then(|mi| mi.data.try_read().ok())
+                    .filter(|data| !data.is_empty())
+                    .map(|data| data.iter().map(|mi| mi.mods().len()).sum::<usize>());
+                if let Some(count) = in_memory {
+                    counts.insert(
+                        game.id.to_owned(),
+                        CachedCount {
+                            count,
+                            counted_at: now,
+                        },
+                    );
+                    changed = true;
+                    return Some(ModCount {
+                        count,
+                        stale: false,
+                    });
+                }
+            }
+            counts.get(game.id).map(|c| ModCount {
+                count: c.count,
+                stale: c.is_stale(now),
+            })
+        })
+        .collect();
+    if changed {
+        if let Err(e) = save(counts).await {
+            warn!(slog_scope::logger(), "Failed to save mod counts: {e}");
+        }
+    }
+    Ok(result)
+}
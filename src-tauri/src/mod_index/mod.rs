@@ -1,5 +1,7 @@
 pub mod commands;
+pub mod counts;
 mod memory;
+pub mod schema;
 pub mod thunderstore;
 
 use std::collections::{HashMap, HashSet};
@@ -7,20 +9,20 @@ use std::sync::LazyLock;
 
 use anyhow::{Context as _, Result};
 use async_compression::tokio::bufread::GzipDecoder;
+use reqwest::Response;
 use rkyv_intern::Interner;
-use slog::{debug, info};
+use slog::{debug, info, warn};
 use tauri::{AppHandle, Manager};
 use tokio::io::AsyncReadExt;
 use tokio::select;
 use tokio::sync::{Mutex, RwLock, RwLockReadGuard};
-use url::Url;
 
 use crate::games::{games, games_by_id};
-use crate::mods::{ArchivedModRef, ModId, ModRef};
+use crate::mods::{ArchivedModRef, ModId};
 use crate::tasks::{self, TaskBuilder};
 use crate::util::http::ResponseExt;
 use crate::util::rkyv::InternedString;
-use crate::util::search::{Score, SortOption};
+use crate::util::search::{Score, SearchMode, SortOption};
 use crate::util::{search, Progress};
 use crate::Reqwest;
 
@@ -86,8 +88,7 @@ pub async fn fetch_mod_index(
                 };
 
                 let new_mod_index = async {
-                    let mut chunk_urls = Vec::new();
-                    GzipDecoder::new(
+                    let (mut chunk_urls, _) = read_gzip_body(
                         app
                             .state::<Reqwest>()
                             .get(&*game.thunderstore_url)
@@ -95,14 +96,13 @@ pub async fn fetch_mod_index(
                             .await
                             .context("Failed to fetch chunk URLs from Thunderstore")?
                             .error_for_status()
-                            .context("Failed to fetch chunk URLs from Thunderstore")?
-                            .reader_with_progress(&mod_index.progress),
+                            .context("Failed to fetch chunk URLs from Thunderstore")?,
+                        &mod_index.progress,
                     )
-                    .read_to_end(&mut chunk_urls)
                     .await
                     .context("Failed to fetch chunk URLs from Thunderstore")?;
                     let chunk_urls =
-                        tokio::task::block_in_place(|| simd_json::from_slice::<Vec<Url>>(&mut chunk_urls))
+                        tokio::task::block_in_place(|| schema::decode_chunk_urls(&mut chunk_urls))
                             .context("Unable to decode chunk URLs from Thunderstore")?;
 
                     let started_at = std::time::Instant::now();
@@ -111,28 +111,28 @@ pub async fn fetch_mod_index(
                         let log = log.clone();
                         let app_handle = app.clone();
                         tokio::task::spawn(async move {
+                            let _permit = crate::governor::network().await;
                             let spawned_at = std::time::Instant::now();
                             let latency = spawned_at.duration_since(started_at);
-                            let mut buf = Vec::new();
-                            {
-                                let mut rdr = GzipDecoder::new(
-                                    app_handle
-                                        .state::<Reqwest>()
-                                        .get(url.clone())
-                                        .send()
-                                        .await
-                                        .context("Failed to fetch chunk from Thunderstore")?
-                                        .error_for_status()
-                                        .context("Failed to fetch chunk from Thunderstore")?
-                                        .reader_with_progress(&mod_index.progress),
-                                );
-                                rdr.read_to_end(&mut buf).await?;
-                            }
+                            let (mut buf, received) = read_gzip_body(
+                                app_handle
+                                    .state::<Reqwest>()
+                                    .get(url.clone())
+                                    .send()
+                                    .await
+                                    .context("Failed to fetch chunk from Thunderstore")?
+                                    .error_for_status()
+                                    .context("Failed to fetch chunk from Thunderstore")?,
+                                &mod_index.progress,
+                            )
+                            .await
+                            .context("Failed to fetch chunk from Thunderstore")?;
                             let fetched_at = std::time::Instant::now();
                             let fetched_in = fetched_at.duration_since(spawned_at);
                             tokio::task::block_in_place(move || {
                                 let buf_len = buf.len();
-                                let mods = simd_json::from_slice::<Vec<ModRef>>(&mut buf)?;
+                                let mods = schema::decode_chunk(&mut buf)
+                                    .with_context(|| format!("Unable to decode chunk at {url:?}"))?;
                                 let decoded_at = std::time::Instant::now();
                                 let decoded_in = decoded_at.duration_since(fetched_at);
 
@@ -211,7 +211,8 @@ pub async fn fetch_mod_index(
                                 let stats_prefix = if cfg!(feature = "statistics") { ", " } else { "" };
                                 info!(
                                     log,
-                                    "{buf_len} bytes of JSON -> {} bytes in memory ({:.2}%{stats_prefix}{stats}), {latency:?} spawning, {fetched_in:?} fetching, {decoded_in:?} decoding, {encoded_in:?} encoding",
+                                    "{received} bytes received -> {buf_len} bytes of JSON ({:.2}%) -> {} bytes in memory ({:.2}%{stats_prefix}{stats}), {latency:?} spawning, {fetched_in:?} fetching, {decoded_in:?} decoding, {encoded_in:?} encoding",
+                                    (received as f64 / buf_len as f64) * 100.0,
                                     buf.len(),
                                     (buf.len() as f64 / buf_len as f64) * 100.0
                                 );
@@ -237,7 +238,11 @@ pub async fn fetch_mod_index(
                     _ = progress_updater => unreachable!(),
                     r = new_mod_index => r?,
                 };
+                let count = new_mod_index.iter().map(|mi| mi.mods().len()).sum();
                 *mod_index.data.write().await = new_mod_index;
+                if let Err(e) = counts::record(game.id, count).await {
+                    warn!(log, "Failed to record mod count: {e}");
+                }
 
                 #[cfg(feature = "statistics")]
                 let (inline_version_count, out_of_line_version_count) =
@@ -255,6 +260,29 @@ pub async fn fetch_mod_index(
     }
 }
 
+/// Reads a gzip file from `response` and returns its decompressed contents, along with the number
+/// of bytes read from the response.
+///
+/// The client transparently decodes any `Content-Encoding`, so if the server decided to compress
+/// the file again for transport, it may arrive already decompressed. JSON never starts with the
+/// gzip magic bytes, so this is detected by sniffing them rather than trusting the headers.
+async fn read_gzip_body(response: Response, progress: &Progress) -> Result<(Vec<u8>, usize)> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+    let mut raw = Vec::new();
+    response
+        .reader_with_progress(progress)
+        .read_to_end(&mut raw)
+        .await?;
+    if !raw.starts_with(&GZIP_MAGIC) {
+        let len = raw.len();
+        return Ok((raw, len));
+    }
+    let mut buf = Vec::with_capacity(raw.len() * 4);
+    GzipDecoder::new(&raw[..]).read_to_end(&mut buf).await?;
+    Ok((buf, raw.len()))
+}
+
 #[derive(Clone, Copy, serde::Deserialize)]
 pub enum SortColumn {
     Relevance,
@@ -275,84 +303,121 @@ pub async fn read_mod_index(game: &str) -> Result<ModIndexReadGuard> {
         .await)
 }
 
-pub async fn count_mod_index<'a>(mod_index: &'a ModIndexReadGuard, query: &str) -> Result<usize> {
+pub async fn count_mod_index<'a>(
+    mod_index: &'a ModIndexReadGuard,
+    query: &str,
+    mode: SearchMode,
+) -> Result<usize> {
     let log = slog_scope::logger();
 
     debug!(log, "Counting mods");
 
+    let _permit = crate::governor::cpu().await;
+
     Ok(mod_index
         .iter()
         .flat_map(|mi| {
             mi.mods()
                 .iter()
-                .filter_map(|m| score_mod(&log, query, m))
+                .filter_map(|m| score_mod(&log, query, mode, m))
                 .filter(|&(_, score)| search::should_include(score))
         })
         .count())
 }
 
+/// Returns every mod that matches `query` with `mode`, sorted by `sort`. If `sorted_len` is given,
+/// only that many mods at the start are sorted, and the rest are in no particular order, which
+/// saves sorting every match when only a page of them is needed.
 pub async fn query_mod_index<'a>(
     mod_index: &'a ModIndexReadGuard,
     query: &str,
+    mode: SearchMode,
     sort: &[SortOption<SortColumn>],
+    sorted_len: Option<usize>,
 ) -> Result<Vec<(&'a ArchivedModRef<'a>, Score)>> {
     let log = slog_scope::logger();
 
     debug!(log, "Querying mods");
 
+    let _permit = crate::governor::cpu().await;
+
     let mut buf = mod_index
         .iter()
         .flat_map(|mi| {
             mi.mods()
                 .iter()
-                .filter_map(|m| score_mod(&log, query, m))
+                .filter_map(|m| score_mod(&log, query, mode, m))
                 .filter(|&(_, score)| search::should_include(score))
         })
         .collect::<Vec<_>>();
     if !sort.is_empty() {
-        buf.sort_unstable_by(|(m1, score1), (m2, score2)| {
-            let mut ordering = std::cmp::Ordering::Equal;
-            for &SortOption { column, descending } in sort {
-                ordering = match column {
-                    SortColumn::Relevance => score1.cmp(score2),
-                    SortColumn::Name => m1.name.cmp(&m2.name),
-                    SortColumn::Owner => m1.owner.cmp(&m2.owner),
-                    SortColumn::Downloads => {
-                        let sum_downloads = |m: &ArchivedModRef| {
-                            m.versions
-                                .iter()
-                                .map(|v| u64::from(v.downloads))
-                                .sum::<u64>()
-                        };
-                        sum_downloads(m1).cmp(&sum_downloads(m2))
-                    }
-                };
-                if descending {
-                    ordering = ordering.reverse();
-                }
-                if ordering.is_ne() {
-                    break;
-                }
+        let compare = |a: &_, b: &_| compare_mods(sort, a, b);
+        match sorted_len {
+            Some(len) if len < buf.len() => {
+                let (sorted, _, _) = buf.select_nth_unstable_by(len, compare);
+                sorted.sort_unstable_by(compare);
             }
-            ordering
-        });
+            _ => buf.sort_unstable_by(compare),
+        }
     }
 
     Ok(buf)
 }
 
+fn compare_mods(
+    sort: &[SortOption<SortColumn>],
+    (m1, score1): &(&ArchivedModRef, Score),
+    (m2, score2): &(&ArchivedModRef, Score),
+) -> std::cmp::Ordering {
+    let mut ordering = std::cmp::Ordering::Equal;
+    for &SortOption { column, descending } in sort {
+        ordering = match column {
+            SortColumn::Relevance => score1.cmp(score2),
+            SortColumn::Name => m1.name.cmp(&m2.name),
+            SortColumn::Owner => m1.owner.cmp(&m2.owner),
+            SortColumn::Downloads => {
+                let sum_downloads = |m: &ArchivedModRef| {
+                    m.versions
+                        .iter()
+                        .map(|v| u64::from(v.downloads))
+                        .sum::<u64>()
+                };
+                sum_downloads(m1).cmp(&sum_downloads(m2))
+            }
+        };
+        if descending {
+            ordering = ordering.reverse();
+        }
+        if ordering.is_ne() {
+            break;
+        }
+    }
+    ordering
+}
+
 fn score_mod<'a, 'b>(
     _log: &slog::Logger,
     query: &str,
+    mode: SearchMode,
     m: &'a ArchivedModRef<'b>,
 ) -> Option<(&'a ArchivedModRef<'b>, Score)> {
     if query.is_empty() {
         Some((m, Score::MAX))
     } else {
-        let owner_score =
-            search::score(&query, &m.owner).map(|s| std::cmp::max(s / 8, Score::ZERO));
-        let name_score = search::score(&query, &m.name);
-        let score = search::add_scores(name_score, owner_score)?;
+        let owner_score = mode
+            .score(query, &m.owner)
+            .map(|s| std::cmp::max(s / 8, Score::ZERO));
+        let name_score = mode.score(query, &m.name);
+        // a fuzzy match of a query's characters somewhere in a long description is meaningless
+        let description_score = m
+            .versions
+            .first()
+            .and_then(|v| search::substring_score(query, &v.description))
+            .map(|s| std::cmp::max(s / 16, Score::ZERO));
+        let score = search::add_scores(
+            search::add_scores(name_score, owner_score),
+            description_score,
+        )?;
         Some((m, score))
     }
 }
@@ -0,0 +1,112 @@
+//! Decoding of the mod index, with errors that tell schema changes apart from corrupt data.
+//!
+//! Thunderstore doesn't version the schema of its package listings, so the schema is detected
+//! from the shape of the data instead. Fields that are added upstream are ignored, as are missing
+//! fields that we don't use. Anything else that doesn't fit [`ModRef`] means the schema changed
+//! incompatibly, which is reported as [`DecodeError::UnsupportedSchema`] rather than as a bare
+//! deserialization error, so that it can be recognized as requiring an update of the app.
+
+use simd_json::ErrorType;
+use url::Url;
+
+use crate::mods::ModRef;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    /// The data is valid JSON, but doesn't match the schema of the mod index.
+    #[error("Unsupported mod index schema: {0}")]
+    UnsupportedSchema(String),
+
+    #[error("Malformed mod index: {0}")]
+    Malformed(#[source] simd_json::Error),
+}
+
+impl From<simd_json::Error> for DecodeError {
+    fn from(e: simd_json::Error) -> Self {
+        match e.error() {
+            // raised by the Deserialize implementations, like for missing fields
+            ErrorType::Serde(msg) => Self::UnsupportedSchema(msg.clone()),
+            ErrorType::ExpectedArray
+            | ErrorType::ExpectedMap
+            | ErrorType::ExpectedString
+            | ErrorType::ExpectedBoolean
+            | ErrorType::ExpectedUnsigned
+            | ErrorType::ExpectedSigned => Self::UnsupportedSchema(e.to_string()),
+            _ => Self::Malformed(e),
+        }
+    }
+}
+
+/// Decodes the list of chunk URLs that the mod index is split into.
+pub fn decode_chunk_urls(buf: &mut [u8]) -> Result<Vec<Url>, DecodeError> {
+    Ok(simd_json::from_slice(buf)?)
+}
+
+/// Decodes a chunk of the mod index. The result borrows from `buf`.
+pub fn decode_chunk(buf: &mut [u8]) -> Result<Vec<ModRef<'_>>, DecodeError> {
+    Ok(simd_json::from_slice(buf)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_chunk, DecodeError};
+
+    const PACKAGE: &str = r#"{
+        "name": "Example",
+        "full_name": "Owner-Example",
+        "owner": "Owner",
+        "package_url": "https://thunderstore.io/c/lethal-company/p/Owner/Example/",
+        "donation_link": "",
+        "date_created": "2024-01-01T00:00:00.000000Z",
+        "date_updated": "2024-01-02T00:00:00.000000Z",
+        "rating_score": 3,
+        "is_pinned": false,
+        "is_deprecated": false,
+        "has_nsfw_content": false,
+        "categories": ["Mods"],
+        "uuid4": "00000000-0000-0000-0000-000000000000",
+        "versions": [{
+            "name": "Example",
+            "full_name": "Owner-Example-1.0.0",
+            "description": "An example",
+            "icon": "https://example.com/icon.png",
+            "version_number": "1.0.0",
+            "dependencies": [],
+            "download_url": "https://example.com/Example.zip",
+            "downloads": 42,
+            "date_created": "2024-01-01T00:00:00.000000Z",
+            "website_url": "",
+            "is_active": true,
+            "uuid4": "00000000-0000-0000-0000-000000000000",
+            "file_size": 1024
+        }]
+    }"#;
+
+    fn decode(json: &str) -> Result<usize, DecodeError> {
+        decode_chunk(&mut json.as_bytes().to_owned()).map(|mods| mods.len())
+    }
+
+    #[test]
+    fn test_decode_chunk() {
+        assert_eq!(decode(&format!("[{PACKAGE}]")).unwrap(), 1);
+
+        let added_field = PACKAGE.replacen('{', r#"{"team_name": "Owner","#, 1);
+        assert_eq!(decode(&format!("[{added_field}]")).unwrap(), 1);
+
+        let removed_field = PACKAGE.replacen(r#""rating_score": 3,"#, "", 1);
+        assert!(matches!(
+            decode(&format!("[{removed_field}]")),
+            Err(DecodeError::UnsupportedSchema(_))
+        ));
+
+        assert!(matches!(
+            decode(r#"{"packages": []}"#),
+            Err(DecodeError::UnsupportedSchema(_))
+        ));
+
+        assert!(matches!(
+            decode(&format!("[{PACKAGE}")),
+            Err(DecodeError::Malformed(_))
+        ));
+    }
+}
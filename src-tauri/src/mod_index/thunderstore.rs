@@ -1,11 +1,15 @@
 pub mod commands;
 
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
-use slog::Logger;
+use slog::{debug, Logger};
 use tauri::AppHandle;
 
+use crate::governor::Throttle;
 use crate::installing::{fetch_resource_as_bytes, CacheOptions};
 use crate::mods::Version;
+use crate::util::http::error_status;
 use crate::{tasks, Reqwest};
 
 #[derive(Clone, Copy, serde::Deserialize)]
@@ -43,3 +47,210 @@ pub async fn fetch_mod_markdown(
     .await?;
     Ok(String::from_utf8(Vec::from(bytes))?)
 }
+
+#[derive(serde::Deserialize)]
+struct MarkdownResponse {
+    markdown: String,
+}
+
+/// Fetches the changelog of a version of a mod, or `None` if the mod does not have one.
+/// Changelogs are cached by version, so each is only fetched once.
+pub async fn fetch_mod_changelog(
+    app: Option<&AppHandle>,
+    log: &Logger,
+    reqwest: &Reqwest,
+    owner: &str,
+    name: &str,
+    version: Version,
+    task_id: Option<tasks::Id>,
+) -> Result<Option<String>> {
+    match fetch_mod_markdown(
+        app,
+        log,
+        reqwest,
+        owner,
+        name,
+        version,
+        ModMarkdown::Changelog,
+        task_id,
+    )
+    .await
+    {
+        Ok(json) => Ok(Some(
+            serde_json::from_str::<MarkdownResponse>(&json)?.markdown,
+        )),
+        Err(e) if error_status(&e) == Some(reqwest::StatusCode::NOT_FOUND) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ModUpdate {
+    pub owner: String,
+    pub name: String,
+    pub from: Version,
+    pub to: Version,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ChangelogEntry {
+    pub version: Version,
+    pub markdown: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModUpdateChangelog {
+    pub owner: String,
+    pub name: String,
+    pub from: Version,
+    pub to: Version,
+    pub has_changelog: bool,
+    /// The sections of the changelog for the versions after `from`, up to and including `to`,
+    /// newest first. Empty if the mod has no changelog or its sections aren't headed by version
+    /// numbers, in which case only the version bump can be shown.
+    pub entries: Vec<ChangelogEntry>,
+}
+
+/// Finds the version number a changelog heading is about, e.g. `## [1.2.3] - 2024-01-01`.
+fn heading_version(heading: &str) -> Option<Version> {
+    heading
+        .split(|c: char| c.is_whitespace() || matches!(c, '[' | ']' | '(' | ')' | ':' | ','))
+        .map(|word| word.strip_prefix(['v', 'V']).unwrap_or(word))
+        .find_map(|word| Version::from_str(word).ok())
+}
+
+/// Splits a changelog into its sections headed by version numbers, keeping those in `(from, to]`.
+fn changelog_entries(markdown: &str, from: Version, to: Version) -> Vec<ChangelogEntry> {
+    let mut entries = Vec::new();
+    let mut current = None::<ChangelogEntry>;
+    for line in markdown.lines() {
+        if line.trim_start().starts_with('#') {
+            if let Some(version) = heading_version(line) {
+                entries.extend(current.take());
+                current = Some(ChangelogEntry {
+                    version,
+                    markdown: String::new(),
+                });
+                continue;
+            }
+        }
+        if let Some(entry) = &mut current {
+            entry.markdown.push_str(line);
+            entry.markdown.push('\n');
+        }
+    }
+    entries.extend(current);
+    entries.retain_mut(|entry| {
+        entry.markdown.truncate(entry.markdown.trim_end().len());
+        entry.version.components() > from.components()
+            && entry.version.components() <= to.components()
+    });
+    entries.sort_unstable_by_key(|entry| std::cmp::Reverse(entry.version.components()));
+    entries
+}
+
+/// Collects the changelog entries of every mod in `updates` between the installed and target
+/// versions, so that a whole profile's update can be reviewed at once.
+pub async fn preview_update_changelogs(
+    app: Option<&AppHandle>,
+    log: &Logger,
+    reqwest: &Reqwest,
+    updates: Vec<ModUpdate>,
+) -> Result<Vec<ModUpdateChangelog>> {
+    let throttle = Throttle::new("changelog fetching");
+    let throttle = &throttle;
+    futures::future::try_join_all(updates.into_iter().map(|update| async move {
+        // the latest changelog covers every version before it
+        let changelog = throttle
+            .run(log, || {
+                fetch_mod_changelog(
+                    app,
+                    log,
+                    reqwest,
+                    &update.owner,
+                    &update.name,
+                    update.to,
+                    None,
+                )
+            })
+            .await?;
+        Ok::<_, anyhow::Error>(ModUpdateChangelog {
+            has_changelog: changelog.is_some(),
+            entries: changelog
+                .map(|markdown| changelog_entries(&markdown, update.from, update.to))
+                .unwrap_or_default(),
+            owner: update.owner,
+            name: update.name,
+            from: update.from,
+            to: update.to,
+        })
+    }))
+    .await
+}
+
+/// How long to wait for Thunderstore to respond before considering it unreachable.
+const CONNECTIVITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum Connectivity {
+    Reachable {
+        #[serde(rename = "latencyMs")]
+        latency_ms: u64,
+    },
+    Unreachable {
+        error: String,
+    },
+}
+
+/// Checks whether the Thunderstore API can be reached by making a cheap request to it. Any HTTP
+/// response, even an error status, counts as reachable.
+pub async fn check_connectivity(log: &Logger, reqwest: &Reqwest) -> Connectivity {
+    let started_at = Instant::now();
+    let result = reqwest
+        .head("https://thunderstore.io/api/")
+        .timeout(CONNECTIVITY_TIMEOUT)
+        .send()
+        .await;
+    let connectivity = match result {
+        Ok(_) => Connectivity::Reachable {
+            latency_ms: started_at
+                .elapsed()
+                .as_millis()
+                .try_into()
+                .unwrap_or(u64::MAX),
+        },
+        Err(e) => Connectivity::Unreachable {
+            error: format!("{:#}", anyhow::Error::from(e)),
+        },
+    };
+    debug!(log, "Thunderstore connectivity: {connectivity:?}");
+    connectivity
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mods::Version;
+
+    use super::changelog_entries;
+
+    #[test]
+    fn test_changelog_entries() {
+        let markdown =
+            "# Changelog\n\n## v1.2.0\n- c\n\n## [1.1.0] - 2024-01-01\n- b\n\n## 1.0.0\n- a\n";
+        let entries = changelog_entries(
+            markdown,
+            Version::new(1, 0, 0).unwrap(),
+            Version::new(1, 2, 0).unwrap(),
+        );
+        let entries = entries
+            .iter()
+            .map(|e| (e.version.to_string(), e.markdown.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [("1.2.0".to_owned(), "- c"), ("1.1.0".to_owned(), "- b")]
+        );
+    }
+}
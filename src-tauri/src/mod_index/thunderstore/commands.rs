@@ -4,7 +4,7 @@ use tauri::{AppHandle, State};
 use crate::mods::Version;
 use crate::{tasks, CommandError, Reqwest};
 
-use super::ModMarkdown;
+use super::{Connectivity, ModMarkdown, ModUpdate, ModUpdateChangelog};
 
 #[tauri::command]
 pub async fn thunderstore_fetch_mod_markdown(
@@ -30,3 +30,43 @@ pub async fn thunderstore_fetch_mod_markdown(
     .map_err(Into::into)
     .map(InvokeResponseBody::Json)
 }
+
+#[tauri::command]
+pub async fn thunderstore_fetch_mod_changelog(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    owner: &str,
+    name: &str,
+    version: Version,
+    task_id: tasks::Id,
+) -> Result<Option<String>, CommandError> {
+    super::fetch_mod_changelog(
+        Some(&app),
+        &slog_scope::logger(),
+        &reqwest,
+        owner,
+        name,
+        version,
+        Some(task_id),
+    )
+    .await
+    .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn thunderstore_preview_update_changelogs(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    updates: Vec<ModUpdate>,
+) -> Result<Vec<ModUpdateChangelog>, CommandError> {
+    super::preview_update_changelogs(Some(&app), &slog_scope::logger(), &reqwest, updates)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn thunderstore_check_connectivity(
+    reqwest: State<'_, Reqwest>,
+) -> Result<Connectivity, CommandError> {
+    Ok(super::check_connectivity(&slog_scope::logger(), &reqwest).await)
+}
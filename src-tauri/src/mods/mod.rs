@@ -17,7 +17,6 @@ use crate::util::rkyv::{InternedString, InternedStringNiche, StringIntern};
 use crate::util::serde::{empty_string_as_none, IgnoredAny, SerializeArchivedVec};
 
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, serde::Deserialize, serde::Serialize)]
-#[serde(deny_unknown_fields)]
 pub struct ModRef<'a> {
     #[serde(flatten)]
     pub metadata: ModMetadataRef<'a>,
@@ -80,17 +79,16 @@ pub struct ModMetadata<'a> {
 }
 
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, serde::Deserialize, serde::Serialize)]
-#[serde(deny_unknown_fields)]
 pub struct ModMetadataRef<'a> {
     #[rkyv(with = StringIntern)]
     pub name: &'a str,
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub full_name: IgnoredAny,
     #[rkyv(with = StringIntern)]
     pub owner: &'a str,
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub package_url: IgnoredAny,
     #[rkyv(with = NicheInto<InternedStringNiche>)]
     #[serde(deserialize_with = "empty_string_as_none")]
@@ -103,7 +101,7 @@ pub struct ModMetadataRef<'a> {
     pub has_nsfw_content: bool,
     pub categories: Vec<InternedString<'a>>,
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub uuid4: IgnoredAny,
 }
 
@@ -158,24 +156,23 @@ pub struct ModVersion<'a> {
 }
 
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, serde::Deserialize, serde::Serialize)]
-#[serde(deny_unknown_fields)]
 pub struct ModVersionRef<'a> {
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub name: IgnoredAny,
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub full_name: IgnoredAny,
     #[rkyv(with = StringIntern)]
     pub description: &'a str,
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub icon: IgnoredAny,
     pub version_number: Version,
     #[serde(borrow)]
     pub dependencies: Vec<InternedString<'a>>,
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub download_url: IgnoredAny,
     pub downloads: u64,
     pub date_created: Timestamp,
@@ -184,7 +181,7 @@ pub struct ModVersionRef<'a> {
     pub website_url: Option<InternedString<'a>>,
     pub is_active: bool,
     #[allow(unused)]
-    #[serde(skip_serializing)]
+    #[serde(default, skip_serializing)]
     pub uuid4: IgnoredAny,
     pub file_size: u64,
 }
@@ -207,6 +204,46 @@ impl<'a> serde::Serialize for ArchivedModVersionRef<'a> {
     }
 }
 
+impl<'a> From<&'a ArchivedModMetadataRef<'_>> for ModMetadata<'a> {
+    fn from(m: &'a ArchivedModMetadataRef<'_>) -> Self {
+        Self {
+            name: &m.name,
+            full_name: Default::default(),
+            owner: &m.owner,
+            package_url: Default::default(),
+            donation_link: m.donation_link.as_ref().map(|s| (**s).into()),
+            date_created: m.date_created.into(),
+            date_updated: m.date_updated.into(),
+            rating_score: m.rating_score.into(),
+            is_pinned: m.is_pinned,
+            is_deprecated: m.is_deprecated,
+            has_nsfw_content: m.has_nsfw_content,
+            categories: m.categories.iter().map(|s| (**s).into()).collect(),
+            uuid4: Default::default(),
+        }
+    }
+}
+
+impl<'a> From<&'a ArchivedModVersionRef<'_>> for ModVersion<'a> {
+    fn from(version: &'a ArchivedModVersionRef<'_>) -> Self {
+        Self {
+            name: Default::default(),
+            full_name: Default::default(),
+            description: (*version.description).into(),
+            icon: Default::default(),
+            version_number: version.version_number.get(),
+            dependencies: version.dependencies.iter().map(|s| (**s).into()).collect(),
+            download_url: Default::default(),
+            downloads: version.downloads.into(),
+            date_created: version.date_created.into(),
+            website_url: version.website_url.as_ref().map(|s| (**s).into()),
+            is_active: version.is_active,
+            uuid4: Default::default(),
+            file_size: version.file_size.into(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct ModAndVersion<'a> {
@@ -1,10 +1,56 @@
-use std::path::PathBuf;
+//! The directories the app keeps its files in.
+//!
+//! # Portable mode
+//!
+//! If a file named `portable.txt` is next to the executable, or the `MANDERROW_PORTABLE`
+//! environment variable is set to anything but an empty string or `0`, the app is portable: the
+//! config, local data, and cache directories are the `config`, `data`, and `cache` directories
+//! beside the executable. For an AppImage, that is beside the AppImage itself. The environment
+//! variable only reaches processes started by the app, and not the wrapper when Steam starts it,
+//! so games launched through Steam need the marker file.
+//!
+//! Portable mode takes precedence over the platform's directories, which aren't read at all when
+//! portable, so a portable copy never sees or changes the files of an installed copy on the same
+//! machine. A cache location moved in the settings is still respected, since it is recorded in the
+//! portable config directory. The runtime directory, which only holds transient files, and the home
+//! directory aren't affected.
+//!
+//! # Overrides
+//!
+//! `MANDERROW_CONFIG_DIR`, `MANDERROW_DATA_DIR`, and `MANDERROW_CACHE_DIR` replace the config,
+//! local data, and cache directories, taking precedence over both portable mode and a cache
+//! location moved in the settings. Each must be an absolute path, and is created if it doesn't
+//! exist.
+//!
+//! Like `MANDERROW_PORTABLE`, the overrides only reach processes started by the app. The wrapper
+//! resolves its directories before it reads its arguments, so when Steam starts it, it uses the
+//! directories it would without the overrides, and can't find profiles kept elsewhere. Games that
+//! are launched with overridden directories must therefore be launched directly rather than
+//! through Steam, or have the same variables set in their launch options.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
-use anyhow::{anyhow, Context as _, Result};
+use anyhow::{anyhow, ensure, Context as _, Result};
 
+use crate::util::IoErrorKindExt as _;
 use crate::{identifier, product_name};
 
+/// The name of the file, in the config directory, that holds the path of the cache directory if
+/// the user moved it from the default location.
+const CACHE_LOCATION_FILE_NAME: &str = "cache_location";
+
+const PORTABLE_MARKER_FILE_NAME: &str = "portable.txt";
+const PORTABLE_ENV_VAR: &str = "MANDERROW_PORTABLE";
+
+const CONFIG_DIR_ENV_VAR: &str = "MANDERROW_CONFIG_DIR";
+const DATA_DIR_ENV_VAR: &str = "MANDERROW_DATA_DIR";
+const CACHE_DIR_ENV_VAR: &str = "MANDERROW_CACHE_DIR";
+
+/// The directory of the executable, if the app is portable.
+static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
 static HOME_DIR: OnceLock<PathBuf> = OnceLock::new();
 static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
 static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
@@ -12,33 +58,49 @@ static LOCAL_DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
 static RUNTIME_DIR: OnceLock<PathBuf> = OnceLock::new();
 
 pub fn init() -> Result<()> {
+    let portable_dir = find_portable_dir()?;
+    PORTABLE_DIR
+        .set(portable_dir.clone())
+        .map_err(|_| anyhow!("Already set"))?;
     HOME_DIR
         .set(dirs::home_dir().context("Unable to determine home directory")?)
         .map_err(|_| anyhow!("Already set"))?;
-    CACHE_DIR
-        .set({
-            let mut p = dirs::cache_dir().context("Unable to determine cache directory")?;
-            p.push(identifier());
-            if cfg!(windows) {
-                p.push("cache");
-            }
-            p
+    CONFIG_DIR
+        .set(match override_dir(CONFIG_DIR_ENV_VAR)? {
+            Some(p) => p,
+            None => match &portable_dir {
+                Some(p) => p.join("config"),
+                None => {
+                    let mut p =
+                        dirs::config_dir().context("Unable to determine config directory")?;
+                    p.push(product_name());
+                    p
+                }
+            },
         })
         .map_err(|_| anyhow!("Already set"))?;
-    CONFIG_DIR
-        .set({
-            let mut p = dirs::config_dir().context("Unable to determine config directory")?;
-            p.push(product_name());
-            p
+    CACHE_DIR
+        .set(match override_dir(CACHE_DIR_ENV_VAR)? {
+            Some(p) => p,
+            None => match read_cache_location()? {
+                Some(p) => p,
+                None => default_cache_dir()?,
+            },
         })
         .map_err(|_| anyhow!("Already set"))?;
     LOCAL_DATA_DIR
-        .set({
-            let mut p =
-                dirs::data_local_dir().context("Unable to determine local data directory")?;
-            // TODO: replace with product_name()
-            p.push(identifier());
-            p
+        .set(match override_dir(DATA_DIR_ENV_VAR)? {
+            Some(p) => p,
+            None => match &portable_dir {
+                Some(p) => p.join("data"),
+                None => {
+                    let mut p = dirs::data_local_dir()
+                        .context("Unable to determine local data directory")?;
+                    // TODO: replace with product_name()
+                    p.push(identifier());
+                    p
+                }
+            },
         })
         .map_err(|_| anyhow!("Already set"))?;
     RUNTIME_DIR
@@ -60,6 +122,64 @@ pub fn init() -> Result<()> {
     Ok(())
 }
 
+/// Returns the directory `var` is set to, creating it if it doesn't exist. The directory must be
+/// an absolute path.
+fn override_dir(var: &str) -> Result<Option<PathBuf>> {
+    match std::env::var_os(var) {
+        Some(p) if !p.is_empty() => {
+            let p = PathBuf::from(p);
+            // relative to whichever directory the process happened to be started in
+            ensure!(
+                p.is_absolute(),
+                "{var} must be an absolute path, found {p:?}"
+            );
+            ensure_dir(&p).with_context(|| format!("Invalid {var} {p:?}"))?;
+            Ok(Some(p))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn ensure_dir(path: &Path) -> Result<()> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            ensure!(metadata.is_dir(), "Not a directory");
+            Ok(())
+        }
+        Err(e) if e.is_not_found() => Ok(std::fs::create_dir_all(path)?),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn executable_dir() -> Result<PathBuf> {
+    // an AppImage runs from a temporary mount, so the directory that matters is the image's
+    let exe = match std::env::var_os("APPIMAGE") {
+        Some(p) if cfg!(target_os = "linux") => PathBuf::from(p),
+        _ => std::env::current_exe().context("Unable to determine executable path")?,
+    };
+    exe.parent()
+        .map(Path::to_owned)
+        .context("Executable has no parent directory")
+}
+
+fn is_portable_env_value(value: &OsStr) -> bool {
+    !value.is_empty() && value != "0"
+}
+
+fn find_portable_dir() -> Result<Option<PathBuf>> {
+    if std::env::var_os(PORTABLE_ENV_VAR).is_some_and(|v| is_portable_env_value(&v)) {
+        return executable_dir().map(Some);
+    }
+    Ok(executable_dir()
+        .ok()
+        .filter(|dir| dir.join(PORTABLE_MARKER_FILE_NAME).is_file()))
+}
+
+/// Returns the directory of the executable if the app is [portable](self#portable-mode).
+pub fn portable_dir() -> Option<&'static Path> {
+    PORTABLE_DIR.get().unwrap().as_deref()
+}
+
 pub fn home_dir() -> &'static PathBuf {
     HOME_DIR.get().unwrap()
 }
@@ -79,3 +199,83 @@ pub fn local_data_dir() -> &'static PathBuf {
 pub fn runtime_dir() -> &'static PathBuf {
     RUNTIME_DIR.get().unwrap()
 }
+
+/// Where the reports of crashes that happened away from the app, like in the wrapper, are written.
+pub fn crash_reports_dir() -> PathBuf {
+    local_data_dir().join("crash_reports")
+}
+
+pub fn default_cache_dir() -> Result<PathBuf> {
+    if let Some(p) = portable_dir() {
+        return Ok(p.join("cache"));
+    }
+    let mut p = dirs::cache_dir().context("Unable to determine cache directory")?;
+    p.push(identifier());
+    if cfg!(windows) {
+        p.push("cache");
+    }
+    Ok(p)
+}
+
+fn read_cache_location() -> Result<Option<PathBuf>> {
+    let path = config_dir().join(CACHE_LOCATION_FILE_NAME);
+    let s = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {path:?}")),
+    };
+    let s = s.trim();
+    Ok((!s.is_empty()).then(|| PathBuf::from(s)))
+}
+
+/// Changes the cache directory used from the next start onwards. The current process keeps using
+/// [`cache_dir`], as paths derived from it are already in use.
+pub fn set_cache_location(dir: &Path) -> Result<()> {
+    ensure!(
+        dir.is_absolute(),
+        "Cache directory must be an absolute path"
+    );
+    let path = config_dir().join(CACHE_LOCATION_FILE_NAME);
+    if dir == default_cache_dir()? {
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+        return Ok(());
+    }
+    std::fs::create_dir_all(config_dir())?;
+    let dir = dir
+        .to_str()
+        .context("Cache directory must be valid UTF-8")?;
+    std::fs::write(&path, dir).with_context(|| format!("Failed to write {path:?}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::{ensure_dir, is_portable_env_value};
+
+    #[test]
+    fn test_portable_env_value() {
+        assert!(is_portable_env_value(OsStr::new("1")));
+        assert!(is_portable_env_value(OsStr::new("true")));
+        assert!(!is_portable_env_value(OsStr::new("0")));
+        assert!(!is_portable_env_value(OsStr::new("")));
+    }
+
+    #[test]
+    fn test_ensure_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("a").join("b");
+        ensure_dir(&missing).unwrap();
+        assert!(missing.is_dir());
+        ensure_dir(&missing).unwrap();
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        assert!(ensure_dir(&file).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! Backing up a profile's exact on-disk state to an archive and restoring it.
+//!
+//! Unlike exporting a profile, a backup includes everything in the profile's directory, including
+//! package content indexes and changes made by the user, so that restoring it reproduces the
+//! profile exactly.
+
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use anyhow::{ensure, Context as _, Result};
+use slog::{info, warn};
+use tauri::AppHandle;
+use uuid::Uuid;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::installing::archive::{extract_archive, ArchiveFormat};
+use crate::tasks::{self, TaskBuilder};
+
+use super::{
+    is_record_dir, profile_path, read_profile_file, remove_profile_dir, watcher, Profile,
+    PROFILES_DIR,
+};
+
+const PROFILE_FILE_NAME: &str = "profile.json";
+
+/// The most a backup may extract to. Profiles can hold many packages, so this is more than is
+/// allowed for a single package.
+const MAX_RESTORED_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Walks the entries of the profile's directory that belong in a backup.
+fn walk_profile(src: &Path) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> {
+    WalkDir::new(src)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !is_record_dir(e))
+}
+
+/// Writes the entire contents of the profile's directory to an archive at `dest`. The archive is
+/// a tarball if `dest` ends with `.tar.zst` or `.tar.gz`, and otherwise a zip archive. Symlinks
+/// are stored as symlinks rather than followed.
+pub async fn backup_profile(
+    app: &AppHandle,
+    id: Uuid,
+    dest: &Path,
+    task_id: Option<tasks::Id>,
+) -> Result<()> {
+    let log = slog_scope::logger();
+
+    let src = profile_path(id);
+    ensure!(
+        !dest.starts_with(&src),
+        "Destination must not be inside of the profile"
+    );
+    let dest_dir = dest.parent().context("Destination must have a parent")?;
+    let format = ArchiveFormat::detect(b"", &dest.to_string_lossy()).unwrap_or(ArchiveFormat::Zip);
+
+    TaskBuilder::with_id(
+        task_id.unwrap_or_else(tasks::allocate_task),
+        format!("Back up profile to {dest:?}"),
+    )
+    .progress_unit(tasks::ProgressUnit::Bytes)
+    .run_with_handle(Some(app), |handle| async move {
+        let _permit = crate::governor::cpu().await;
+        tokio::task::block_in_place(|| {
+            let mut total = 0u64;
+            for e in walk_profile(&src) {
+                let e = e?;
+                if e.file_type().is_file() {
+                    total += e.metadata()?.len();
+                }
+            }
+
+            std::fs::create_dir_all(dest_dir)?;
+            // written next to the destination so that a failed backup never replaces a good one
+            let mut file = tempfile::NamedTempFile::new_in(dest_dir)?;
+            let wtr = std::io::BufWriter::new(file.as_file_mut());
+
+            let mut completed = 0u64;
+            handle.send_progress_manually(app, completed, total)?;
+            let mut progress = |len| {
+                completed += len;
+                handle.send_progress_manually(app, completed, total)
+            };
+            match format {
+                ArchiveFormat::Zip => write_zip(&src, wtr, &mut progress)?,
+                ArchiveFormat::TarGz => {
+                    let encoder = flate2::write::GzEncoder::new(wtr, Default::default());
+                    write_tar(&src, encoder, &mut progress)?.finish()?.flush()?;
+                }
+                ArchiveFormat::TarZst => {
+                    let mut encoder = zstd::stream::write::Encoder::new(wtr, 0)?;
+                    // tar has no checksums of its own, so that restoring can verify the content
+                    encoder.include_checksum(true)?;
+                    write_tar(&src, encoder, &mut progress)?.finish()?.flush()?;
+                }
+            }
+
+            file.as_file().sync_all()?;
+            file.persist(dest)?;
+
+            info!(log, "Backed up profile {id} to {dest:?}");
+
+            Ok::<_, anyhow::Error>(())
+        })
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Writes the profile's directory at `src` to a zip archive, calling `progress` with the size of
+/// each file written.
+fn write_zip(
+    src: &Path,
+    wtr: impl Write + Seek,
+    progress: &mut dyn FnMut(u64) -> Result<()>,
+) -> Result<()> {
+    let mut writer = ZipWriter::new(wtr);
+    for e in walk_profile(src) {
+        let e = e?;
+        let rel_path = e.path().strip_prefix(src)?;
+        let mut name = String::new();
+        for component in rel_path.components() {
+            if !name.is_empty() {
+                name.push('/');
+            }
+            name.push_str(
+                component
+                    .as_os_str()
+                    .to_str()
+                    .with_context(|| format!("Non-UTF-8 path {rel_path:?}"))?,
+            );
+        }
+        let metadata = e.path().symlink_metadata()?;
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        #[cfg(unix)]
+        let options = {
+            use std::os::unix::fs::PermissionsExt as _;
+            options.unix_permissions(metadata.permissions().mode())
+        };
+        if e.file_type().is_dir() {
+            writer.add_directory(name, options)?;
+        } else if e.file_type().is_symlink() {
+            let target = std::fs::read_link(e.path())?;
+            let target = target
+                .to_str()
+                .with_context(|| format!("Non-UTF-8 symlink target {target:?}"))?;
+            writer.add_symlink(name, target, options)?;
+        } else {
+            let options = options.large_file(metadata.len() >= u32::MAX.into());
+            writer.start_file(name, options)?;
+            let mut rdr = std::fs::File::open(e.path())?;
+            let len = std::io::copy(&mut rdr, &mut writer)
+                .with_context(|| format!("Failed to back up {:?}", e.path()))?;
+            progress(len)?;
+        }
+    }
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+/// Writes the profile's directory at `src` to a tarball, calling `progress` with the size of each
+/// file written. Returns `wtr` so that the compression can be finished.
+fn write_tar<W: Write>(
+    src: &Path,
+    wtr: W,
+    progress: &mut dyn FnMut(u64) -> Result<()>,
+) -> Result<W> {
+    let mut builder = tar::Builder::new(wtr);
+    builder.follow_symlinks(false);
+    for e in walk_profile(src) {
+        let e = e?;
+        let rel_path = e.path().strip_prefix(src)?;
+        builder
+            .append_path_with_name(e.path(), rel_path)
+            .with_context(|| format!("Failed to back up {:?}", e.path()))?;
+        if e.file_type().is_file() {
+            progress(e.metadata()?.len())?;
+        }
+    }
+    Ok(builder.into_inner()?)
+}
+
+/// Restores a profile from a backup made by [`backup_profile`]. The profile is restored with a new
+/// id unless `replace` is given, in which case that profile is replaced once the backup has been
+/// extracted successfully. The profile's hooks, environment variables, launch arguments and
+/// compatibility tool, if any, are not restored. Returns the id of the restored profile.
+pub async fn restore_profile(
+    app: &AppHandle,
+    src: &Path,
+    replace: Option<Uuid>,
+    task_id: Option<tasks::Id>,
+) -> Result<Uuid> {
+    let log = slog_scope::logger();
+
+    tokio::fs::create_dir_all(&*PROFILES_DIR)
+        .await
+        .context("Failed to create profiles directory")?;
+
+    TaskBuilder::with_id(
+        task_id.unwrap_or_else(tasks::allocate_task),
+        format!("Restore profile from {src:?}"),
+    )
+    .run(Some(app), async move {
+        let temp_dir = tempfile::tempdir_in(&*PROFILES_DIR)?;
+        {
+            let _permit = crate::governor::cpu().await;
+            tokio::task::block_in_place(|| {
+                let mut rdr = std::io::BufReader::new(std::fs::File::open(src)?);
+                let format = ArchiveFormat::detect_from(&mut rdr, &src.to_string_lossy())
+                    .context("Invalid backup")?;
+                // the checksums of a zip archive's entries, or of a zstd stream, are verified as
+                // it is extracted
+                extract_archive(
+                    format,
+                    rdr,
+                    temp_dir.path(),
+                    &[],
+                    MAX_RESTORED_SIZE,
+                    &mut |_, _| {},
+                )
+                .context("Invalid backup")?;
+                Ok::<_, anyhow::Error>(())
+            })?;
+        }
+        let profile_file = temp_dir.path().join(PROFILE_FILE_NAME);
+        ensure!(
+            tokio::fs::symlink_metadata(&profile_file)
+                .await
+                .is_ok_and(|m| m.is_file()),
+            "Invalid backup: missing {PROFILE_FILE_NAME}"
+        );
+        let mut profile = read_profile_file(&profile_file)
+            .await
+            .context("Invalid backup")?;
+        if sanitize(&mut profile) {
+            warn!(
+                log,
+                "Removing the hooks, environment, arguments and compatibility tool of the restored \
+                 profile {:?}",
+                profile.name
+            );
+            tokio::fs::write(&profile_file, serde_json::to_vec(&profile)?)
+                .await
+                .context("Failed to write profile metadata")?;
+        }
+
+        let id = match replace {
+            Some(id) => {
+                let _guard = watcher::suppress(id);
+                let path = profile_path(id);
+                // moved aside rather than removed, so that it is left as it was if the restored
+                // profile can't take its place
+                let replaced = PROFILES_DIR.join(format!(".replaced-{}", Uuid::new_v4()));
+                tokio::fs::rename(&path, &replaced)
+                    .await
+                    .context("Failed to move aside the profile being replaced")?;
+                if let Err(e) = tokio::fs::rename(temp_dir.path(), &path).await {
+                    tokio::fs::rename(&replaced, &path).await?;
+                    return Err(e.into());
+                }
+                if let Err(e) = remove_profile_dir(&replaced).await {
+                    warn!(
+                        log,
+                        "Failed to remove the replaced profile at {replaced:?}: {e:#}"
+                    );
+                }
+                id
+            }
+            None => {
+                let id = Uuid::new_v4();
+                tokio::fs::rename(temp_dir.path(), profile_path(id)).await?;
+                id
+            }
+        };
+
+        info!(
+            log,
+            "Restored profile {:?} as {id} from {src:?}", profile.name
+        );
+
+        Ok::<_, anyhow::Error>(id)
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Clears the settings of a restored profile that choose what is run when launching it, since a
+/// backup may come from anywhere. Returns whether any were set.
+fn sanitize(profile: &mut Profile) -> bool {
+    let hooks = profile.pre_launch.take().is_some() | profile.post_exit.take().is_some();
+    let env = !std::mem::take(&mut profile.env).is_empty();
+    let args = !std::mem::take(&mut profile.args).is_empty();
+    let compat_tool = profile.compat_tool.take().is_some();
+    hooks | env | args | compat_tool
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write as _};
+
+    use crate::installing::archive::{extract_archive, ArchiveFormat};
+
+    use super::{sanitize, write_tar, write_zip, MAX_RESTORED_SIZE};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_backup_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("profile");
+        std::fs::create_dir_all(src.join("BepInEx/plugins")).unwrap();
+        std::fs::write(src.join("profile.json"), b"{}").unwrap();
+        std::fs::write(src.join("BepInEx/plugins/Foo.dll"), b"dll").unwrap();
+        std::os::unix::fs::symlink("plugins/Foo.dll", src.join("BepInEx/Foo.dll")).unwrap();
+
+        for format in [ArchiveFormat::Zip, ArchiveFormat::TarZst] {
+            let mut written = 0;
+            let mut progress = |len| {
+                written += len;
+                Ok(())
+            };
+            let bytes = match format {
+                ArchiveFormat::Zip => {
+                    let mut wtr = Cursor::new(Vec::new());
+                    write_zip(&src, &mut wtr, &mut progress).unwrap();
+                    wtr.into_inner()
+                }
+                _ => {
+                    let encoder = zstd::stream::write::Encoder::new(Vec::new(), 0).unwrap();
+                    let mut encoder = write_tar(&src, encoder, &mut progress).unwrap();
+                    encoder.flush().unwrap();
+                    encoder.finish().unwrap()
+                }
+            };
+            assert_eq!(written, 5);
+
+            let mut rdr = Cursor::new(bytes);
+            assert_eq!(ArchiveFormat::detect_from(&mut rdr, "").unwrap(), format);
+            let dest = dir.path().join(format!("{format:?}"));
+            std::fs::create_dir(&dest).unwrap();
+            extract_archive(format, rdr, &dest, &[], MAX_RESTORED_SIZE, &mut |_, _| {}).unwrap();
+            assert_eq!(std::fs::read(dest.join("profile.json")).unwrap(), b"{}");
+            assert!(dest.join("BepInEx/Foo.dll").is_symlink());
+            assert_eq!(std::fs::read(dest.join("BepInEx/Foo.dll")).unwrap(), b"dll");
+        }
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let mut profile = serde_json::from_str(
+            r#"{
+                "name": "a",
+                "game": "riskofrain2",
+                "pre_launch": { "command": "rm -rf ~" },
+                "post_exit": { "command": "true" },
+                "env": { "LD_PRELOAD": "/tmp/evil.so" },
+                "args": ["--evil"],
+                "compat_tool": "/tmp/evil/proton"
+            }"#,
+        )
+        .unwrap();
+        assert!(sanitize(&mut profile));
+        assert!(profile.pre_launch.is_none());
+        assert!(profile.post_exit.is_none());
+        assert!(profile.compat_tool.is_none());
+        assert!(profile.env.is_empty());
+        assert!(profile.args.is_empty());
+        assert!(!sanitize(&mut profile));
+    }
+}
@@ -1,12 +1,26 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use anyhow::Result;
 use smol_str::SmolStr;
 use tauri::{AppHandle, State};
 use uuid::Uuid;
 
-use crate::mods::{ModMetadata, ModVersion};
+use crate::installing::test_extract::ExtractionReport;
+use crate::installing::{FileConflict, IndexRepair, Status};
+use crate::launching::hooks::Hook;
+use crate::mods::{ModMetadata, ModVersion, Version};
+use crate::settings::SettingsState;
 use crate::{tasks, CommandError, Reqwest};
 
-use super::ProfileWithId;
+use super::dependency_graph::DependencyGraph;
+use super::load_order::LoadOrderReport;
+use super::loader_update::LoaderUpdate;
+use super::lockfile::Lockfile;
+use super::orphans::OrphanedCacheEntry;
+use super::provenance::FileProvenance;
+use super::watcher::ProfileWatcher;
+use super::{GameUpdateStatus, InstalledMod, ProfileFingerprint, ProfileWithId};
 
 #[tauri::command]
 pub async fn get_profiles() -> Result<Vec<ProfileWithId>, CommandError> {
@@ -18,16 +32,162 @@ pub async fn create_profile(game: SmolStr, name: SmolStr) -> Result<Uuid, Comman
     super::create_profile(game, name).await.map_err(Into::into)
 }
 
+#[tauri::command]
+pub async fn set_profile_executable(id: Uuid, exe: Option<String>) -> Result<(), CommandError> {
+    super::set_profile_executable(id, exe)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn set_profile_mods_enabled(id: Uuid, enabled: bool) -> Result<(), CommandError> {
+    super::set_profile_mods_enabled(id, enabled)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn set_profile_compat_tool(
+    id: Uuid,
+    compat_tool: Option<PathBuf>,
+) -> Result<(), CommandError> {
+    super::set_profile_compat_tool(id, compat_tool)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn set_profile_launch_options(
+    id: Uuid,
+    env: BTreeMap<String, String>,
+    args: Vec<String>,
+) -> Result<(), CommandError> {
+    super::set_profile_launch_options(id, env, args)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn set_profile_hooks(
+    id: Uuid,
+    pre_launch: Option<Hook>,
+    post_exit: Option<Hook>,
+) -> Result<(), CommandError> {
+    super::set_profile_hooks(id, pre_launch, post_exit)
+        .await
+        .map_err(Into::into)
+}
+
 #[tauri::command]
 pub async fn delete_profile(id: Uuid) -> Result<(), CommandError> {
     super::delete_profile(id).await.map_err(Into::into)
 }
 
+#[tauri::command]
+pub async fn export_profile_as_folder(
+    app: AppHandle,
+    id: Uuid,
+    dest: PathBuf,
+    skip_indexes: bool,
+    task_id: tasks::Id,
+) -> Result<(), CommandError> {
+    super::export_profile_as_folder(&app, id, &dest, skip_indexes, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn backup_profile(
+    app: AppHandle,
+    id: Uuid,
+    dest: PathBuf,
+    task_id: tasks::Id,
+) -> Result<(), CommandError> {
+    super::backup::backup_profile(&app, id, &dest, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+/// Restores a profile from a backup, replacing the profile `replace` if given, or otherwise
+/// creating a new one. Returns the id of the restored profile.
+#[tauri::command]
+pub async fn restore_profile(
+    app: AppHandle,
+    src: PathBuf,
+    replace: Option<Uuid>,
+    task_id: tasks::Id,
+) -> Result<Uuid, CommandError> {
+    super::backup::restore_profile(&app, &src, replace, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn move_profile(
+    app: AppHandle,
+    id: Uuid,
+    new_root: PathBuf,
+    task_id: tasks::Id,
+) -> Result<(), CommandError> {
+    super::relocate::move_profile(&app, id, &new_root, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
 #[tauri::command]
 pub async fn get_profile_mods(id: Uuid) -> Result<tauri::ipc::Response, CommandError> {
     super::get_profile_mods(id).await.map_err(Into::into)
 }
 
+#[tauri::command]
+pub async fn list_installed_mods(id: Uuid) -> Result<Vec<InstalledMod>, CommandError> {
+    super::list_installed_mods(id).await.map_err(Into::into)
+}
+
+/// Maps every file in the profile to the package that provided it. See
+/// [`super::provenance::file_provenance`].
+#[tauri::command]
+pub async fn get_file_provenance(
+    id: Uuid,
+    rescan: bool,
+) -> Result<Vec<FileProvenance>, CommandError> {
+    super::provenance::file_provenance(id, rescan)
+        .await
+        .map(std::sync::Arc::unwrap_or_clone)
+        .map_err(Into::into)
+}
+
+/// Lists the cached package archives that aren't installed in any profile. See
+/// [`super::orphans`].
+#[tauri::command]
+pub async fn find_orphaned_cache_entries() -> Result<Vec<OrphanedCacheEntry>, CommandError> {
+    super::orphans::find_orphaned_cache_entries()
+        .await
+        .map_err(Into::into)
+}
+
+/// Returns the dependency graph of the profile's mods. See [`super::dependency_graph`].
+#[tauri::command]
+pub async fn get_dependency_graph(id: Uuid) -> Result<DependencyGraph, CommandError> {
+    super::dependency_graph::get_dependency_graph(id)
+        .await
+        .map_err(Into::into)
+}
+
+/// Reports contradictory load order constraints between the profile's mods. See
+/// [`super::load_order`].
+#[tauri::command]
+pub async fn check_load_order(id: Uuid) -> Result<LoadOrderReport, CommandError> {
+    super::load_order::check_load_order(id)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn check_game_updated(id: Uuid) -> Result<GameUpdateStatus, CommandError> {
+    super::check_game_updated(id).await.map_err(Into::into)
+}
+
 #[tauri::command]
 pub async fn install_profile_mod(
     app: AppHandle,
@@ -35,16 +195,214 @@ pub async fn install_profile_mod(
     id: Uuid,
     r#mod: ModMetadata<'_>,
     version: ModVersion<'_>,
+    exclude: Option<Vec<String>>,
+    ignore_conflicts: Option<bool>,
+    task_id: tasks::Id,
+) -> Result<Vec<FileConflict>, CommandError> {
+    super::install_profile_mod(
+        &app,
+        &*reqwest,
+        id,
+        r#mod,
+        version,
+        exclude.as_deref().unwrap_or_default(),
+        ignore_conflicts.unwrap_or(false),
+        Some(task_id),
+    )
+    .await
+    .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn get_mod_optional_components(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    owner: &str,
+    name: &str,
+    version: Version,
     task_id: tasks::Id,
+) -> Result<Vec<String>, CommandError> {
+    super::get_mod_optional_components(&app, &*reqwest, owner, name, version, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn downgrade_profile_loader(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    id: Uuid,
+    version: Version,
+    task_id: tasks::Id,
+) -> Result<(), CommandError> {
+    super::downgrade_profile_loader(&app, &*reqwest, id, version, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn check_profile_loader_update(id: Uuid) -> Result<Option<LoaderUpdate>, CommandError> {
+    super::loader_update::check_loader_update(id)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn update_profile_loader(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    id: Uuid,
+    task_id: tasks::Id,
+) -> Result<Option<LoaderUpdate>, CommandError> {
+    super::loader_update::update_profile_loader(&app, &*reqwest, id, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn decline_profile_loader_update(id: Uuid, version: Version) -> Result<(), CommandError> {
+    super::loader_update::decline_loader_update(id, version)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn set_profile_loader_pin(
+    id: Uuid,
+    version: Option<Version>,
 ) -> Result<(), CommandError> {
-    super::install_profile_mod(&app, &*reqwest, id, r#mod, version, Some(task_id))
+    super::loader_update::set_loader_pin(id, version)
         .await
         .map_err(Into::into)
 }
 
 #[tauri::command]
-pub async fn uninstall_profile_mod(id: Uuid, owner: &str, name: &str) -> Result<(), CommandError> {
+pub async fn get_profile_lockfile(id: Uuid) -> Result<Lockfile, CommandError> {
+    super::lockfile::read_lockfile(id).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn apply_profile_lockfile(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    id: Uuid,
+    lockfile: Lockfile,
+) -> Result<(), CommandError> {
+    super::lockfile::apply_lockfile(&app, &*reqwest, id, &lockfile)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn test_extract_mod(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    owner: &str,
+    name: &str,
+    version: Version,
+    task_id: tasks::Id,
+) -> Result<ExtractionReport, CommandError> {
+    super::test_extract_mod(&app, &*reqwest, owner, name, version, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn uninstall_profile_mod(
+    id: Uuid,
+    owner: &str,
+    name: &str,
+) -> Result<Vec<PathBuf>, CommandError> {
     super::uninstall_profile_mod(id, owner, name)
         .await
         .map_err(Into::into)
 }
+
+#[tauri::command]
+pub async fn reset_profile_mod_config(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    id: Uuid,
+    owner: &str,
+    name: &str,
+    task_id: tasks::Id,
+) -> Result<Vec<PathBuf>, CommandError> {
+    super::reset_profile_mod_config(&app, &*reqwest, id, owner, name, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn repair_profile_mod_index(
+    app: AppHandle,
+    reqwest: State<'_, Reqwest>,
+    id: Uuid,
+    owner: &str,
+    name: &str,
+    task_id: tasks::Id,
+) -> Result<IndexRepair, CommandError> {
+    super::repair_profile_mod_index(&app, &*reqwest, id, owner, name, Some(task_id))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn accept_profile_mod_changes(
+    id: Uuid,
+    owner: &str,
+    name: &str,
+    paths: Vec<PathBuf>,
+) -> Result<(), CommandError> {
+    super::accept_profile_mod_changes(id, owner, name, &paths)
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn get_profile_fingerprint(id: Uuid) -> Result<ProfileFingerprint, CommandError> {
+    super::profile_fingerprint(id).await.map_err(Into::into)
+}
+
+/// Returns the paths, relative to the profile, that were created, modified, or deleted since the
+/// profile was launched `launches_ago` launches before its last one (defaulting to its last), or
+/// `None` if it has not been launched that many times.
+#[tauri::command]
+pub async fn get_profile_changes_since_launch(
+    id: Uuid,
+    launches_ago: Option<usize>,
+) -> Result<Option<BTreeMap<String, Status>>, CommandError> {
+    super::snapshot::changes_since_launch(&slog_scope::logger(), id, launches_ago.unwrap_or(0))
+        .await
+        .map_err(Into::into)
+}
+
+/// Starts watching the profile for external modifications, if enabled in the settings. Returns
+/// whether the profile is being watched.
+#[tauri::command]
+pub async fn watch_profile(
+    app: AppHandle,
+    settings: SettingsState<'_>,
+    watcher: State<'_, ProfileWatcher>,
+    id: Uuid,
+) -> Result<bool, CommandError> {
+    let enabled = {
+        let settings = settings.read().await;
+        settings
+            .as_ref()
+            .map_err(Clone::clone)?
+            .watch_profiles()
+            .value
+    };
+    if enabled {
+        watcher.watch(&app, id)?;
+    }
+    Ok(enabled)
+}
+
+#[tauri::command]
+pub async fn unwatch_profile(
+    watcher: State<'_, ProfileWatcher>,
+    id: Uuid,
+) -> Result<(), CommandError> {
+    watcher.unwatch(id).map_err(Into::into)
+}
@@ -0,0 +1,194 @@
+//! The dependency graph of the mods installed in a profile.
+//!
+//! Edges come from the dependency strings in each installed mod's manifest, which are those of
+//! the mod index. Mods that no installed mod depends on are the roots of the graph, and are
+//! presumably the ones the user chose to install. Their dependencies are direct, and anything
+//! only reachable through other dependencies is transitive. Roots that don't depend on anything
+//! installed either aren't part of the graph at all, which usually means they were added by hand.
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Result;
+use smol_str::SmolStr;
+use uuid::Uuid;
+
+use crate::mods::Version;
+
+use super::{list_installed_mods, InstalledMod};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Relation {
+    /// Not depended on by any installed mod.
+    Root,
+    /// Depended on by a root.
+    Direct,
+    /// Only depended on through other dependencies.
+    Transitive,
+    /// Neither depended on by nor depending on any installed mod.
+    Unreferenced,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    /// The mod, as `Owner-Name`.
+    pub id: String,
+    pub owner: SmolStr,
+    pub name: SmolStr,
+    pub version: Version,
+    pub relation: Relation,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Edge {
+    /// The dependent mod, as `Owner-Name`.
+    pub from: String,
+    /// The dependency, as `Owner-Name`, which isn't among the nodes if it isn't installed.
+    pub to: String,
+    /// The version of the dependency asked for, if the dependency string had a valid one.
+    pub version: Option<Version>,
+    pub installed: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Splits a dependency string, like `Owner-Name-1.0.0`, into the mod and the version.
+fn parse_dependency(dependency: &str) -> (&str, Option<Version>) {
+    match dependency.rsplit_once('-') {
+        Some((id, version)) => match Version::from_str(version) {
+            Ok(version) => (id, Some(version)),
+            Err(_) => (dependency, None),
+        },
+        None => (dependency, None),
+    }
+}
+
+impl DependencyGraph {
+    fn new(mods: &[InstalledMod]) -> Self {
+        let ids = mods
+            .iter()
+            .map(|m| format!("{}-{}", m.owner, m.name))
+            .collect::<Vec<_>>();
+        let installed = ids.iter().map(String::as_str).collect::<HashSet<_>>();
+
+        let mut edges = Vec::new();
+        let mut dependencies = BTreeMap::<&str, Vec<&str>>::new();
+        let mut dependents = HashSet::new();
+        for (m, id) in mods.iter().zip(&ids) {
+            let deps = dependencies.entry(id.as_str()).or_default();
+            for dependency in &m.dependencies {
+                let (to, version) = parse_dependency(dependency);
+                let target = installed.get(to).copied();
+                if let Some(to) = target {
+                    deps.push(to);
+                    dependents.insert(to);
+                }
+                edges.push(Edge {
+                    from: id.clone(),
+                    to: to.to_owned(),
+                    version,
+                    installed: target.is_some(),
+                });
+            }
+        }
+
+        let roots = ids
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !dependents.contains(id))
+            .collect::<HashSet<_>>();
+        let direct = roots
+            .iter()
+            .flat_map(|id| &dependencies[id])
+            .copied()
+            .collect::<HashSet<_>>();
+
+        let nodes = mods
+            .iter()
+            .zip(ids)
+            .map(|(m, id)| {
+                let relation = if !roots.contains(&*id) {
+                    if direct.contains(&*id) {
+                        Relation::Direct
+                    } else {
+                        Relation::Transitive
+                    }
+                } else if dependencies[&*id].is_empty() {
+                    Relation::Unreferenced
+                } else {
+                    Relation::Root
+                };
+                Node {
+                    id,
+                    owner: m.owner.clone(),
+                    name: m.name.clone(),
+                    version: m.version,
+                    relation,
+                }
+            })
+            .collect();
+
+        Self { nodes, edges }
+    }
+}
+
+/// Builds the dependency graph of the mods installed in the profile.
+pub async fn get_dependency_graph(id: Uuid) -> Result<DependencyGraph> {
+    let mods = list_installed_mods(id).await?;
+    Ok(DependencyGraph::new(&mods))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mods::Version;
+    use crate::profiles::InstalledMod;
+
+    use super::{DependencyGraph, Relation};
+
+    fn installed_mod(id: &str, dependencies: &[&str]) -> InstalledMod {
+        let (owner, name) = id.split_once('-').unwrap();
+        InstalledMod {
+            owner: owner.into(),
+            name: name.into(),
+            version: Version::new(1, 0, 0).unwrap(),
+            description: Default::default(),
+            dependencies: dependencies.iter().map(|&dep| dep.into()).collect(),
+            source: String::new(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let graph = DependencyGraph::new(&[
+            installed_mod("Loader-Pack", &[]),
+            installed_mod("Lib-Core", &["Loader-Pack-5.4.0"]),
+            installed_mod("Some-Mod", &["Lib-Core-1.0.0", "Gone-Lib-2.0.0"]),
+            installed_mod("Hand-Added", &[]),
+        ]);
+        let relation = |id| {
+            graph
+                .nodes
+                .iter()
+                .find(|node| node.id == id)
+                .unwrap()
+                .relation
+        };
+        assert_eq!(relation("Some-Mod"), Relation::Root);
+        assert_eq!(relation("Lib-Core"), Relation::Direct);
+        assert_eq!(relation("Loader-Pack"), Relation::Transitive);
+        assert_eq!(relation("Hand-Added"), Relation::Unreferenced);
+
+        let missing = graph.edges.iter().find(|e| e.to == "Gone-Lib").unwrap();
+        assert!(!missing.installed);
+        assert_eq!(missing.version, Some(Version::new(2, 0, 0).unwrap()));
+        assert_eq!(graph.edges.len(), 3);
+    }
+}
@@ -0,0 +1,208 @@
+//! Checking that the mods of a profile can be loaded in an order that satisfies all of them.
+//!
+//! Thunderstore packages don't declare any load order explicitly, so the only constraints known
+//! are that every mod must be loaded after each of its dependencies. These constraints contradict
+//! each other when the dependencies of some mods form a cycle, in which case some of those mods
+//! will be loaded before a mod they depend on, whatever the loader does.
+//!
+//! Most loaders resolve the order themselves, but some load mods in order of their file names,
+//! for which a concrete order satisfying every constraint outside of cycles is suggested.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{Context as _, Result};
+use uuid::Uuid;
+
+use crate::games::games_by_id;
+
+use super::{list_installed_mods, read_profile};
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadOrderReport {
+    /// Groups of installed mods, as `Owner-Name`, whose dependencies on one another form a cycle.
+    pub cycles: Vec<Vec<String>>,
+    /// The mods in an order that loads every mod after its dependencies, except within cycles.
+    /// Only given for loaders that load mods in order of their file names.
+    pub suggested_order: Option<Vec<String>>,
+}
+
+/// Strips the version from a dependency string, like `Owner-Name-1.0.0`.
+fn dependency_id(dependency: &str) -> &str {
+    dependency.rsplit_once('-').map_or(dependency, |(id, _)| id)
+}
+
+struct Graph<'a> {
+    /// The dependencies of each mod, limited to installed mods. Sorted so that the results don't
+    /// depend on the order the mods were listed in.
+    edges: BTreeMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(mods: &'a [(String, Vec<String>)]) -> Self {
+        let mut edges = mods
+            .iter()
+            .map(|(id, _)| (id.as_str(), Vec::new()))
+            .collect::<BTreeMap<_, _>>();
+        for (id, dependencies) in mods {
+            let mut deps = dependencies
+                .iter()
+                .map(|dep| dependency_id(dep))
+                .filter(|dep| edges.contains_key(dep))
+                .collect::<Vec<_>>();
+            deps.sort_unstable();
+            deps.dedup();
+            edges.insert(id.as_str(), deps);
+        }
+        Self { edges }
+    }
+
+    /// Finds the strongly connected components with more than one mod, or a mod depending on
+    /// itself, using Tarjan's algorithm.
+    fn cycles(&self) -> Vec<Vec<String>> {
+        struct State<'a> {
+            next_index: usize,
+            indices: HashMap<&'a str, (usize, usize)>,
+            stack: Vec<&'a str>,
+            on_stack: HashSet<&'a str>,
+            cycles: Vec<Vec<String>>,
+        }
+
+        fn visit<'a>(graph: &Graph<'a>, state: &mut State<'a>, node: &'a str) {
+            let index = state.next_index;
+            state.next_index += 1;
+            state.indices.insert(node, (index, index));
+            state.stack.push(node);
+            state.on_stack.insert(node);
+
+            for &dep in &graph.edges[node] {
+                if !state.indices.contains_key(dep) {
+                    visit(graph, state, dep);
+                    let low = state.indices[dep].1.min(state.indices[node].1);
+                    state.indices.get_mut(node).unwrap().1 = low;
+                } else if state.on_stack.contains(dep) {
+                    let low = state.indices[dep].0.min(state.indices[node].1);
+                    state.indices.get_mut(node).unwrap().1 = low;
+                }
+            }
+
+            let (index, low) = state.indices[node];
+            if index == low {
+                let mut component = Vec::new();
+                loop {
+                    let member = state.stack.pop().unwrap();
+                    state.on_stack.remove(member);
+                    component.push(member.to_owned());
+                    if member == node {
+                        break;
+                    }
+                }
+                if component.len() > 1 || graph.edges[node].contains(&node) {
+                    component.sort_unstable();
+                    state.cycles.push(component);
+                }
+            }
+        }
+
+        let mut state = State {
+            next_index: 0,
+            indices: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            cycles: Vec::new(),
+        };
+        for &node in self.edges.keys() {
+            if !state.indices.contains_key(node) {
+                visit(self, &mut state, node);
+            }
+        }
+        state.cycles.sort_unstable();
+        state.cycles
+    }
+
+    /// Orders the mods so that each comes after its dependencies, breaking cycles arbitrarily.
+    fn order(&self) -> Vec<String> {
+        fn visit<'a>(
+            graph: &Graph<'a>,
+            visited: &mut HashSet<&'a str>,
+            order: &mut Vec<String>,
+            node: &'a str,
+        ) {
+            if !visited.insert(node) {
+                return;
+            }
+            for &dep in &graph.edges[node] {
+                visit(graph, visited, order, dep);
+            }
+            order.push(node.to_owned());
+        }
+
+        let mut visited = HashSet::new();
+        let mut order = Vec::with_capacity(self.edges.len());
+        for &node in self.edges.keys() {
+            visit(self, &mut visited, &mut order, node);
+        }
+        order
+    }
+}
+
+/// Checks the ordering constraints between the mods installed in the profile.
+pub async fn check_load_order(id: Uuid) -> Result<LoadOrderReport> {
+    let profile = read_profile(id).await?;
+    let game = *games_by_id()?
+        .get(&*profile.game)
+        .context("Unrecognized game")?;
+    let mods = list_installed_mods(id)
+        .await?
+        .into_iter()
+        .map(|m| {
+            (
+                format!("{}-{}", m.owner, m.name),
+                m.dependencies.into_iter().map(String::from).collect(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let graph = Graph::new(&mods);
+    Ok(LoadOrderReport {
+        cycles: graph.cycles(),
+        suggested_order: game
+            .package_loader
+            .loads_in_file_name_order()
+            .then(|| graph.order()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Graph;
+
+    fn mods(mods: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+        mods.iter()
+            .map(|(id, deps)| {
+                (
+                    (*id).to_owned(),
+                    deps.iter().map(|dep| format!("{dep}-1.0.0")).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_load_order() {
+        let mods = mods(&[
+            ("A-Core", &[]),
+            ("B-Mod", &["A-Core", "Missing-Dep"]),
+            ("C-Mod", &["B-Mod", "D-Mod"]),
+            ("D-Mod", &["C-Mod"]),
+            ("E-Mod", &["E-Mod"]),
+        ]);
+        let graph = Graph::new(&mods);
+        assert_eq!(graph.cycles(), [vec!["C-Mod", "D-Mod"], vec!["E-Mod"]]);
+        let order = graph.order();
+        let position = |id| order.iter().position(|m| m == id).unwrap();
+        assert_eq!(order.len(), 5);
+        assert!(position("A-Core") < position("B-Mod"));
+        assert!(position("B-Mod") < position("C-Mod"));
+        assert!(position("B-Mod") < position("D-Mod"));
+    }
+}
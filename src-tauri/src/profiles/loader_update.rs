@@ -0,0 +1,201 @@
+//! Keeping a profile's mod loader up to date.
+//!
+//! Updates are looked up in the mod index that is already in memory, so checking never touches
+//! the network, and finds nothing when the index hasn't been fetched, like when offline. A
+//! profile whose loader was downgraded with
+//! [`downgrade_profile_loader`](super::downgrade_profile_loader) is pinned to that version until
+//! the pin is cleared, and an update the user declined isn't offered again, although newer ones
+//! are.
+//!
+//! With the `update_loader_on_launch` setting, available updates are installed right before the
+//! profile is launched. Any failure to do so is logged and the profile is launched with the
+//! loader it has.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use slog::{info, warn};
+use smol_str::SmolStr;
+use tauri::{AppHandle, Manager as _};
+use uuid::Uuid;
+
+use crate::games::games_by_id;
+use crate::mod_index::{get_from_mod_index, read_mod_index};
+use crate::mods::{ModId, Version};
+use crate::tasks;
+use crate::Reqwest;
+
+use super::{find_loader_package, install_profile_mod, read_profile, update_profile};
+
+/// How long to wait for the mod index before launching without updating. The index is only
+/// locked for long while it is being fetched.
+const LAUNCH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LoaderUpdate {
+    pub owner: SmolStr,
+    pub name: SmolStr,
+    pub from: Version,
+    pub to: Version,
+}
+
+/// Returns the update available for the profile's mod loader, if any, unless the loader is
+/// pinned or the update was declined.
+pub async fn check_loader_update(id: Uuid) -> Result<Option<LoaderUpdate>> {
+    let profile = read_profile(id).await?;
+    if profile.loader_pin.is_some() {
+        return Ok(None);
+    }
+    let game = *games_by_id()?.get(&*profile.game).context("No such game")?;
+    let Some(installed) = find_loader_package(id, game.package_loader).await? else {
+        return Ok(None);
+    };
+
+    let mod_index = read_mod_index(&profile.game).await?;
+    let mod_ids = HashSet::from([ModId {
+        owner: installed.owner.as_str().into(),
+        name: installed.name.as_str().into(),
+    }]);
+    let Some(m) = get_from_mod_index(&mod_index, &mod_ids)
+        .await?
+        .into_iter()
+        .next()
+    else {
+        return Ok(None);
+    };
+    let Some(latest) = m
+        .versions
+        .iter()
+        .map(|v| v.version_number.get())
+        .max_by_key(|v| v.components())
+    else {
+        return Ok(None);
+    };
+
+    let from = installed.version.version_number;
+    if latest.components() <= from.components() || profile.declined_loader_update == Some(latest) {
+        return Ok(None);
+    }
+    Ok(Some(LoaderUpdate {
+        owner: installed.owner,
+        name: installed.name,
+        from,
+        to: latest,
+    }))
+}
+
+/// Installs the update available for the profile's mod loader, if any. Returns the update that
+/// was installed.
+pub async fn update_profile_loader(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    id: Uuid,
+    task_id: Option<tasks::Id>,
+) -> Result<Option<LoaderUpdate>> {
+    let log = slog_scope::logger();
+
+    let Some(update) = check_loader_update(id).await? else {
+        return Ok(None);
+    };
+
+    let profile = read_profile(id).await?;
+    let mod_index = read_mod_index(&profile.game).await?;
+    let mod_ids = HashSet::from([ModId {
+        owner: update.owner.as_str().into(),
+        name: update.name.as_str().into(),
+    }]);
+    let m = get_from_mod_index(&mod_index, &mod_ids)
+        .await?
+        .into_iter()
+        .next()
+        .with_context(|| format!("Missing mod {}-{}", update.owner, update.name))?;
+    let m_version = m
+        .versions
+        .iter()
+        .find(|v| v.version_number.get() == update.to)
+        .with_context(|| {
+            format!(
+                "Missing version {} of mod {}-{}",
+                update.to, update.owner, update.name
+            )
+        })?;
+
+    install_profile_mod(
+        app,
+        reqwest,
+        id,
+        (&m.metadata).into(),
+        m_version.into(),
+        &[],
+        true,
+        task_id,
+    )
+    .await?;
+
+    info!(
+        log,
+        "Updated {}-{} in profile {id} from {} to {}",
+        update.owner,
+        update.name,
+        update.from,
+        update.to
+    );
+
+    Ok(Some(update))
+}
+
+/// Records that the user doesn't want `version` of the profile's mod loader, so that it isn't
+/// offered again.
+pub async fn decline_loader_update(id: Uuid, version: Version) -> Result<()> {
+    update_profile(id, |profile| {
+        profile.declined_loader_update = Some(version);
+        Ok(())
+    })
+    .await
+}
+
+/// Pins the profile's mod loader to `version`, or clears the pin if `None`.
+pub async fn set_loader_pin(id: Uuid, version: Option<Version>) -> Result<()> {
+    update_profile(id, |profile| {
+        profile.loader_pin = version;
+        Ok(())
+    })
+    .await
+}
+
+/// Installs the update available for the profile's mod loader before launching it. Never fails,
+/// so that the profile can be launched with the loader it has.
+pub async fn update_before_launch(app: &AppHandle, log: &slog::Logger, id: Uuid) {
+    match tokio::time::timeout(LAUNCH_CHECK_TIMEOUT, check_loader_update(id)).await {
+        Ok(Ok(Some(_))) => {}
+        Ok(Ok(None)) => return,
+        Ok(Err(e)) => {
+            warn!(
+                log,
+                "Failed to check for mod loader updates before launch: {e:#}"
+            );
+            return;
+        }
+        Err(_) => {
+            warn!(
+                log,
+                "Timed out checking for mod loader updates before launch"
+            );
+            return;
+        }
+    }
+    // not timed out, since cancelling an install part way would leave the loader broken
+    match update_profile_loader(app, &app.state::<Reqwest>(), id, None).await {
+        Ok(Some(update)) => info!(
+            log,
+            "Updated {}-{} from {} to {} before launch",
+            update.owner,
+            update.name,
+            update.from,
+            update.to
+        ),
+        Ok(None) => {}
+        Err(e) => warn!(log, "Failed to update the mod loader before launch: {e:#}"),
+    }
+}
@@ -0,0 +1,267 @@
+//! A record of exactly what is installed in a profile, so that the same mods can be installed
+//! elsewhere.
+//!
+//! Every install and uninstall of a mod updates the profile's lockfile with the mod's version and
+//! the hash of the archive it was installed from. Applying a lockfile installs exactly those
+//! versions, downloading archives cached by their hash so that any archive that doesn't match is
+//! rejected, and uninstalls the mods recorded in the profile's lockfile that aren't listed. Mods
+//! installed before lockfiles were introduced aren't recorded, so they are left alone.
+
+use std::collections::HashSet;
+use std::io::Write as _;
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result};
+use slog::{debug, warn};
+use tauri::AppHandle;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::installing::CacheOptions;
+use crate::mod_index::{get_from_mod_index, read_mod_index};
+use crate::mods::{ModId, Version};
+use crate::util::IoErrorKindExt as _;
+use crate::Reqwest;
+
+use super::{
+    install_profile_mod_with_cache, list_installed_mods, profile_path, read_profile,
+    uninstall_profile_mod,
+};
+
+pub const LOCKFILE_NAME: &str = "lock.json";
+
+/// Serializes updates of lockfiles, since mods may be installed concurrently.
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockfile {
+    /// Sorted by full name.
+    pub mods: Vec<LockedMod>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedMod {
+    /// The mod, as `Owner-Name`.
+    pub full_name: String,
+    pub version: Version,
+    /// The BLAKE3 hash of the archive the mod was installed from, in hex.
+    pub hash: String,
+    /// The optional components that were left out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl Lockfile {
+    fn insert(&mut self, locked: LockedMod) {
+        match self
+            .mods
+            .binary_search_by(|m| m.full_name.cmp(&locked.full_name))
+        {
+            Ok(i) => self.mods[i] = locked,
+            Err(i) => self.mods.insert(i, locked),
+        }
+    }
+
+    fn remove(&mut self, full_name: &str) {
+        self.mods.retain(|m| m.full_name != full_name);
+    }
+
+    fn get(&self, full_name: &str) -> Option<&LockedMod> {
+        self.mods.iter().find(|m| m.full_name == full_name)
+    }
+}
+
+fn lockfile_path(id: Uuid) -> PathBuf {
+    profile_path(id).join(LOCKFILE_NAME)
+}
+
+/// Reads the profile's lockfile, which is empty if no mods have been installed since lockfiles
+/// were introduced.
+pub async fn read_lockfile(id: Uuid) -> Result<Lockfile> {
+    let path = lockfile_path(id);
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => {
+            serde_json::from_slice(&bytes).with_context(|| format!("Invalid lockfile {path:?}"))
+        }
+        Err(e) if e.is_not_found() => Ok(Lockfile::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read lockfile {path:?}")),
+    }
+}
+
+async fn update_lockfile(id: Uuid, f: impl FnOnce(&mut Lockfile)) -> Result<()> {
+    let _lock = LOCK.lock().await;
+    let mut lockfile = read_lockfile(id).await?;
+    f(&mut lockfile);
+    let bytes = serde_json::to_vec_pretty(&lockfile)?;
+    tokio::task::block_in_place(|| {
+        let mut temp_file = tempfile::NamedTempFile::new_in(profile_path(id))?;
+        temp_file.write_all(&bytes)?;
+        temp_file.as_file().sync_all()?;
+        temp_file.persist(lockfile_path(id))?;
+        Ok::<_, anyhow::Error>(())
+    })
+    .context("Failed to write lockfile")
+}
+
+/// Records a mod that was just installed in the profile.
+pub(super) async fn record_install(
+    id: Uuid,
+    owner: &str,
+    name: &str,
+    version: Version,
+    archive_hash: blake3::Hash,
+    exclude: &[String],
+) -> Result<()> {
+    let locked = LockedMod {
+        full_name: format!("{owner}-{name}"),
+        version,
+        hash: archive_hash.to_hex().to_string(),
+        exclude: exclude.to_owned(),
+    };
+    update_lockfile(id, |lockfile| lockfile.insert(locked)).await
+}
+
+/// Returns the optional components that were left out when the mod was installed in the profile.
+pub(super) async fn excluded_components(id: Uuid, owner: &str, name: &str) -> Result<Vec<String>> {
+    let full_name = format!("{owner}-{name}");
+    Ok(read_lockfile(id)
+        .await?
+        .get(&full_name)
+        .map(|locked| locked.exclude.clone())
+        .unwrap_or_default())
+}
+
+/// Records a mod that was just uninstalled from the profile.
+pub(super) async fn record_uninstall(id: Uuid, owner: &str, name: &str) -> Result<()> {
+    let full_name = format!("{owner}-{name}");
+    update_lockfile(id, |lockfile| lockfile.remove(&full_name)).await
+}
+
+/// Makes the mods installed in the profile match `lockfile`. Mods already installed from the
+/// same archive, and mods the profile's lockfile doesn't record, are left alone.
+pub async fn apply_lockfile(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    id: Uuid,
+    lockfile: &Lockfile,
+) -> Result<()> {
+    let log = slog_scope::logger();
+
+    let profile = read_profile(id).await?;
+    let current = read_lockfile(id).await?;
+    let installed = list_installed_mods(id).await?;
+
+    if let Err(e) = super::snapshot::take(&log, id).await {
+        warn!(
+            log,
+            "Failed to snapshot profile before applying lockfile: {e:?}"
+        );
+    }
+
+    for m in &installed {
+        let full_name = format!("{}-{}", m.owner, m.name);
+        if lockfile.get(&full_name).is_some() {
+            continue;
+        }
+        if current.get(&full_name).is_none() {
+            warn!(
+                log,
+                "Keeping {full_name}, which the profile's lockfile doesn't record"
+            );
+            continue;
+        }
+        debug!(log, "Uninstalling {full_name}, which isn't in the lockfile");
+        uninstall_profile_mod(id, &m.owner, &m.name).await?;
+    }
+
+    let installed = installed
+        .iter()
+        .map(|m| (format!("{}-{}", m.owner, m.name), m.version))
+        .collect::<HashSet<_>>();
+    let pending = lockfile
+        .mods
+        .iter()
+        .filter(|locked| {
+            let up_to_date = current.get(&locked.full_name) == Some(*locked)
+                && installed.contains(&(locked.full_name.clone(), locked.version));
+            if up_to_date {
+                debug!(
+                    log,
+                    "{} is already installed from the locked archive", locked.full_name
+                );
+            }
+            !up_to_date
+        })
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let mod_ids = pending
+        .iter()
+        .map(|locked| ModId::from_str(&locked.full_name))
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid lockfile: {e}"))?;
+    let mod_index = read_mod_index(&profile.game).await?;
+    let mods = get_from_mod_index(&mod_index, &mod_ids).await?;
+    for locked in pending {
+        let m = mods
+            .iter()
+            .find(|m| format!("{}-{}", &*m.owner, &*m.name) == locked.full_name)
+            .with_context(|| format!("Missing mod {}", locked.full_name))?;
+        let m_version = m
+            .versions
+            .iter()
+            .find(|v| v.version_number.get() == locked.version)
+            .with_context(|| {
+                format!(
+                    "Missing version {} of mod {}",
+                    locked.version, locked.full_name
+                )
+            })?;
+        install_profile_mod_with_cache(
+            app,
+            reqwest,
+            id,
+            (&m.metadata).into(),
+            m_version.into(),
+            &locked.exclude,
+            true,
+            CacheOptions::by_hash(&locked.hash),
+            None,
+        )
+        .await
+        .with_context(|| format!("Failed to install {}", locked.full_name))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mods::Version;
+
+    use super::{LockedMod, Lockfile};
+
+    fn locked(full_name: &str, patch: u64) -> LockedMod {
+        LockedMod {
+            full_name: full_name.to_owned(),
+            version: Version::new(1, 0, patch).unwrap(),
+            hash: String::new(),
+            exclude: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_lockfile_updates() {
+        let mut lockfile = Lockfile::default();
+        lockfile.insert(locked("B-Mod", 0));
+        lockfile.insert(locked("A-Mod", 0));
+        lockfile.insert(locked("C-Mod", 0));
+        lockfile.insert(locked("B-Mod", 1));
+        lockfile.remove("C-Mod");
+        assert_eq!(lockfile.mods, [locked("A-Mod", 0), locked("B-Mod", 1)]);
+    }
+}
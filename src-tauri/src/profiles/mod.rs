@@ -1,28 +1,92 @@
+pub mod backup;
 pub mod commands;
+pub mod dependency_graph;
+pub mod load_order;
+pub mod loader_update;
+pub mod lockfile;
+pub mod orphans;
+pub mod post_install;
+pub mod provenance;
+pub mod relocate;
+pub mod snapshot;
+pub mod watcher;
 
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
 
-use anyhow::{Context as _, Result};
+use anyhow::{bail, ensure, Context as _, Result};
 use futures::stream::FuturesOrdered;
 use futures::StreamExt as _;
-use slog::error;
+use slog::{error, info, warn};
 use smol_str::SmolStr;
 use tauri::AppHandle;
 use uuid::Uuid;
+use walkdir::WalkDir;
 
-use crate::installing::{install_zip, uninstall_package};
-use crate::mods::{ModAndVersion, ModMetadata, ModVersion};
+use crate::games::{games_by_id, Game, PackageLoader};
+use crate::installing::test_extract::{test_extract_zip, ExtractionReport};
+use crate::installing::{
+    accept_package_changes, install_zip, list_optional_components, repair_package_index,
+    restore_package_files, uninstall_package, CacheOptions, FileConflict, IndexRepair,
+    StagedPackage, INDEX_FILE_NAME,
+};
+use crate::launching::hooks::Hook;
+use crate::launching::logs::LOGS_DIR_NAME;
+use crate::launching::{game_install_fingerprint, GameInstallFingerprint};
+use crate::mod_index::{get_from_mod_index, read_mod_index};
+use crate::mods::{ModAndVersion, ModId, ModMetadata, ModVersion, Version};
 use crate::paths::local_data_dir;
+use crate::tasks::{self, TaskBuilder};
 use crate::util::{hyphenated_uuid, IoErrorKindExt as _};
-use crate::{tasks, Reqwest};
+use crate::Reqwest;
+
+use snapshot::SNAPSHOTS_DIR_NAME;
 
 pub static PROFILES_DIR: LazyLock<PathBuf> = LazyLock::new(|| local_data_dir().join("profiles"));
 
+/// Serializes updates of profile metadata, since they may be made concurrently.
+static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Profile {
     pub name: SmolStr,
     pub game: SmolStr,
+    /// Environment variables to set when launching the profile.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Arguments to pass to the game when launching the profile, after those the store passes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// The executable to launch, which must be one of the game's `exe_names`. If unset, the first
+    /// of [`find_game_executables`](crate::launching::find_game_executables) is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+    /// Whether all mods are switched off, so that launching the profile runs the game vanilla.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mods_disabled: bool,
+    /// The state of the game's installation when the mod loader was last installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_install: Option<GameInstallFingerprint>,
+    /// A command to run before launching the game. See [`crate::launching::hooks`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_launch: Option<Hook>,
+    /// A command to run after the game exits. See [`crate::launching::hooks`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_exit: Option<Hook>,
+    /// The Proton build or Wine executable to run the game with, as found by
+    /// [`find_compat_tools`](crate::stores::steam::proton::find_compat_tools). If unset, the
+    /// store decides.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compat_tool: Option<PathBuf>,
+    /// The version the mod loader is kept at. See [`loader_update`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader_pin: Option<Version>,
+    /// The last update of the mod loader the user declined. See [`loader_update`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub declined_loader_update: Option<Version>,
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -50,6 +114,28 @@ pub async fn read_profile(id: Uuid) -> Result<Profile, ReadProfileError> {
     read_profile_file(&path).await
 }
 
+pub async fn write_profile(id: Uuid, profile: &Profile) -> Result<()> {
+    let dir = profile_path(id);
+    let bytes = serde_json::to_vec(profile)?;
+    tokio::task::block_in_place(|| {
+        let mut temp_file = tempfile::NamedTempFile::new_in(&dir)?;
+        temp_file.write_all(&bytes)?;
+        temp_file.as_file().sync_all()?;
+        temp_file.persist(dir.join("profile.json"))?;
+        Ok::<_, anyhow::Error>(())
+    })
+    .context("Failed to write profile metadata")
+}
+
+/// Reads the profile's metadata, modifies it with `f`, and writes it back, without racing with
+/// other updates.
+pub async fn update_profile(id: Uuid, f: impl FnOnce(&mut Profile) -> Result<()>) -> Result<()> {
+    let _lock = LOCK.lock().await;
+    let mut profile = read_profile(id).await?;
+    f(&mut profile)?;
+    write_profile(id, &profile).await
+}
+
 pub fn profile_path(id: Uuid) -> PathBuf {
     PROFILES_DIR.join(hyphenated_uuid!(id))
 }
@@ -97,29 +183,320 @@ pub async fn create_profile(game: SmolStr, name: SmolStr) -> Result<Uuid> {
         .await
         .context("Failed to create profiles directory")?;
     let id = Uuid::new_v4();
-    let mut path = profile_path(id);
+    let path = profile_path(id);
     tokio::fs::create_dir(&path)
         .await
         .context("Failed to create profile directory")?;
-    path.push("profile.json");
-    tokio::fs::write(path, &serde_json::to_vec(&Profile { name, game }).unwrap())
-        .await
-        .context("Failed to write profile metadata")?;
+    write_profile(
+        id,
+        &Profile {
+            name,
+            game,
+            env: BTreeMap::new(),
+            args: Vec::new(),
+            exe: None,
+            mods_disabled: false,
+            game_install: None,
+            pre_launch: None,
+            post_exit: None,
+            compat_tool: None,
+            loader_pin: None,
+            declined_loader_update: None,
+        },
+    )
+    .await?;
     Ok(id)
 }
 
+/// Sets the executable launched for the profile. `None` restores the default.
+pub async fn set_profile_executable(id: Uuid, exe: Option<String>) -> Result<()> {
+    update_profile(id, |profile| {
+        if let Some(exe) = &exe {
+            let game = *games_by_id()?
+                .get(&*profile.game)
+                .context("Unrecognized game")?;
+            ensure!(
+                game.exe_names.iter().any(|name| name == exe),
+                "{exe:?} is not an executable of {}",
+                game.name
+            );
+        }
+        profile.exe = exe;
+        Ok(())
+    })
+    .await
+}
+
+/// Sets the compatibility tool the profile is launched with. `None` restores the default.
+pub async fn set_profile_compat_tool(id: Uuid, compat_tool: Option<PathBuf>) -> Result<()> {
+    if let Some(path) = &compat_tool {
+        ensure!(
+            tokio::fs::try_exists(path).await?,
+            "Compatibility tool {path:?} does not exist"
+        );
+    }
+    update_profile(id, |profile| {
+        profile.compat_tool = compat_tool;
+        Ok(())
+    })
+    .await
+}
+
+/// Enables or disables every mod in the profile at once by flipping the loader's master switch,
+/// which leaves the installed files untouched.
+pub async fn set_profile_mods_enabled(id: Uuid, enabled: bool) -> Result<()> {
+    update_profile(id, |profile| {
+        let game = *games_by_id()?
+            .get(&*profile.game)
+            .context("Unrecognized game")?;
+        // there is no per-mod toggling to fall back to
+        ensure!(
+            game.package_loader.has_master_switch(),
+            "{} cannot be switched off as a whole",
+            game.package_loader.as_str()
+        );
+        profile.mods_disabled = !enabled;
+        Ok(())
+    })
+    .await
+}
+
+/// Sets the environment variables and arguments the profile is launched with, replacing the
+/// previous ones.
+pub async fn set_profile_launch_options(
+    id: Uuid,
+    env: BTreeMap<String, String>,
+    args: Vec<String>,
+) -> Result<()> {
+    for key in env.keys() {
+        ensure!(
+            !key.is_empty() && !key.contains(['=', '\0']),
+            "Invalid environment variable name {key:?}"
+        );
+    }
+    update_profile(id, |profile| {
+        profile.env = env;
+        profile.args = args;
+        Ok(())
+    })
+    .await
+}
+
+/// Sets the commands to run before launching the profile and after the game exits. These run
+/// arbitrary commands, so they must only ever be set at the user's explicit request.
+pub async fn set_profile_hooks(
+    id: Uuid,
+    pre_launch: Option<Hook>,
+    post_exit: Option<Hook>,
+) -> Result<()> {
+    for hook in pre_launch.iter().chain(&post_exit) {
+        ensure!(
+            !hook.command.trim().is_empty(),
+            "Hook command must not be empty"
+        );
+    }
+    update_profile(id, |profile| {
+        profile.pre_launch = pre_launch;
+        profile.post_exit = post_exit;
+        Ok(())
+    })
+    .await
+}
+
 pub async fn delete_profile(id: Uuid) -> Result<()> {
-    let path = profile_path(id);
-    tokio::fs::remove_dir_all(&path)
+    remove_profile_dir(&profile_path(id)).await
+}
+
+/// Removes the profile directory at `path`, or, if the profile was moved elsewhere, the
+/// directory it was moved to and the link to it at `path`.
+async fn remove_profile_dir(path: &Path) -> Result<()> {
+    if tokio::fs::symlink_metadata(path).await?.is_symlink() {
+        // the profile was moved elsewhere by relocate::move_profile
+        let target = tokio::fs::canonicalize(path).await?;
+        crate::util::fs::remove_dir_all(&target)
+            .await
+            .context("Failed to delete profile directory")?;
+        tokio::task::block_in_place(|| relocate::remove_symlink_dir(path))?;
+        return Ok(());
+    }
+    crate::util::fs::remove_dir_all(path)
         .await
         .context("Failed to delete profile directory")?;
     Ok(())
 }
 
+/// Copies the profile's directory to `dest`, which must not already exist. Symlinks are copied
+/// as symlinks rather than followed. Package content indexes are skipped if `skip_indexes` is
+/// set.
+pub async fn export_profile_as_folder(
+    app: &AppHandle,
+    id: Uuid,
+    dest: &Path,
+    skip_indexes: bool,
+    task_id: Option<tasks::Id>,
+) -> Result<()> {
+    let log = slog_scope::logger();
+
+    let src = profile_path(id);
+    ensure!(
+        !dest.starts_with(&src),
+        "Destination must not be inside of the profile"
+    );
+
+    TaskBuilder::with_id(
+        task_id.unwrap_or_else(tasks::allocate_task),
+        format!("Export profile to {dest:?}"),
+    )
+    .progress_unit(tasks::ProgressUnit::Bytes)
+    .run_with_handle(Some(app), |handle| async move {
+        tokio::task::block_in_place(|| {
+            let is_skipped = |e: &walkdir::DirEntry| {
+                is_record_dir(e)
+                    || skip_indexes && e.file_name() == INDEX_FILE_NAME && e.file_type().is_file()
+            };
+
+            let mut total = 0u64;
+            for e in WalkDir::new(&src)
+                .into_iter()
+                .filter_entry(|e| !is_record_dir(e))
+            {
+                let e = e?;
+                if e.file_type().is_file() && !is_skipped(&e) {
+                    total += e.metadata()?.len();
+                }
+            }
+
+            std::fs::create_dir_all(dest.parent().context("Destination must have a parent")?)?;
+            std::fs::create_dir(dest).context("Failed to create destination directory")?;
+
+            let mut completed = 0u64;
+            handle.send_progress_manually(app, completed, total)?;
+            copy_tree(&src, dest, is_skipped, |copied| {
+                completed += copied;
+                handle.send_progress_manually(app, completed, total)
+            })?;
+
+            info!(log, "Exported profile {id} to {dest:?}");
+
+            Ok::<_, anyhow::Error>(())
+        })
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Returns whether `name` is that of one of the top-level directories in which the profile's
+/// launch logs and snapshots are kept, which are records of the profile rather than part of it.
+fn is_record_dir_name(name: &OsStr) -> bool {
+    name == LOGS_DIR_NAME || name == SNAPSHOTS_DIR_NAME
+}
+
+/// Returns whether `e`, found by walking a profile's directory, is one of the directories in which
+/// the profile's launch logs and snapshots are kept. See [`is_record_dir_name`].
+pub(crate) fn is_record_dir(e: &walkdir::DirEntry) -> bool {
+    e.depth() == 1 && is_record_dir_name(e.file_name())
+}
+
+/// Copies the contents of `src` into the existing directory `dest`, except for entries for which
+/// `is_skipped` returns true, along with their contents. Symlinks are copied as symlinks rather
+/// than followed. `on_copied` is called with the size of each file after it is copied.
+fn copy_tree(
+    src: &Path,
+    dest: &Path,
+    is_skipped: impl Fn(&walkdir::DirEntry) -> bool,
+    mut on_copied: impl FnMut(u64) -> Result<()>,
+) -> Result<()> {
+    for e in WalkDir::new(src)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !is_skipped(e))
+    {
+        let e = e?;
+        let target = dest.join(e.path().strip_prefix(src)?);
+        if e.file_type().is_dir() {
+            std::fs::create_dir(&target)?;
+        } else if e.file_type().is_symlink() {
+            let link = std::fs::read_link(e.path())?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&link, &target)?;
+            #[cfg(windows)]
+            if std::fs::metadata(e.path()).is_ok_and(|m| m.is_dir()) {
+                std::os::windows::fs::symlink_dir(&link, &target)?;
+            } else {
+                std::os::windows::fs::symlink_file(&link, &target)?;
+            }
+        } else {
+            on_copied(
+                std::fs::copy(e.path(), &target)
+                    .with_context(|| format!("Failed to copy {:?}", e.path()))?,
+            )?;
+        }
+    }
+    Ok(())
+}
+
 pub const MODS_FOLDER: &str = "mods";
 
 const MANIFEST_FILE_NAME: &str = "manderrow_mod.json";
 
+/// The parts of an installed mod's manifest needed to identify its package.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct InstalledModManifest {
+    pub owner: SmolStr,
+    pub name: SmolStr,
+    pub version: InstalledModVersion,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct InstalledModVersion {
+    pub version_number: Version,
+    #[serde(default)]
+    pub description: SmolStr,
+    #[serde(default)]
+    pub dependencies: Vec<SmolStr>,
+}
+
+/// An installed mod, as listed by [`list_installed_mods`].
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledMod {
+    pub owner: SmolStr,
+    pub name: SmolStr,
+    pub version: Version,
+    pub description: SmolStr,
+    pub dependencies: Vec<SmolStr>,
+    /// The URL the package was installed from.
+    pub source: String,
+    pub enabled: bool,
+}
+
+pub fn profile_mod_path(id: Uuid, owner: &str, name: &str) -> PathBuf {
+    let mut path = profile_path(id);
+    path.push(MODS_FOLDER);
+    path.push(owner);
+    path.as_mut_os_string().push("-");
+    path.as_mut_os_string().push(name);
+    path
+}
+
+pub async fn read_installed_mod_manifest(mod_path: &Path) -> Result<InstalledModManifest> {
+    let path = mod_path.join(MANIFEST_FILE_NAME);
+    let bytes = tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("Failed to read mod manifest {path:?}"))?;
+    serde_json::from_slice(&bytes).with_context(|| format!("Invalid mod manifest {path:?}"))
+}
+
+pub fn thunderstore_package_url(owner: &str, name: &str, version: Version) -> String {
+    format!("https://gcdn.thunderstore.io/live/repository/packages/{owner}-{name}-{version}.zip")
+}
+
+/// Returns whether the entry of a profile's mods directory is hidden, like the staging directories
+/// and backups left by installing, and so is not an installed mod.
+pub fn is_hidden_mod_entry(e: &tokio::fs::DirEntry) -> bool {
+    e.file_name().as_encoded_bytes().starts_with(b".")
+}
+
 pub async fn get_profile_mods(id: Uuid) -> Result<tauri::ipc::Response> {
     let mut path = profile_path(id);
 
@@ -132,7 +509,7 @@ pub async fn get_profile_mods(id: Uuid) -> Result<tauri::ipc::Response> {
     };
     let mut tasks = FuturesOrdered::new();
     while let Some(e) = iter.next_entry().await.map_err(anyhow::Error::from)? {
-        if e.file_type().await.map_err(anyhow::Error::from)?.is_dir() {
+        if !is_hidden_mod_entry(&e) && e.file_type().await.map_err(anyhow::Error::from)?.is_dir() {
             let mut path = path.clone();
             tasks.push_back(tokio::task::spawn(async move {
                 path.push(e.file_name());
@@ -164,16 +541,116 @@ pub async fn get_profile_mods(id: Uuid) -> Result<tauri::ipc::Response> {
     Ok(tauri::ipc::Response::new(buf))
 }
 
+/// Lists the mods installed in a profile, sorted by name. Only the manifests written when
+/// installing are read, so this stays fast regardless of how many files the mods contain.
+pub async fn list_installed_mods(id: Uuid) -> Result<Vec<InstalledMod>> {
+    let enabled = !read_profile(id).await?.mods_disabled;
+    let path = profile_path(id).join(MODS_FOLDER);
+    let mut iter = match tokio::fs::read_dir(&path).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {path:?}")),
+    };
+    let mut tasks = FuturesOrdered::new();
+    while let Some(e) = iter.next_entry().await? {
+        if !is_hidden_mod_entry(&e) && e.file_type().await?.is_dir() {
+            tasks.push_back(tokio::task::spawn(async move {
+                let path = e.path().join(MANIFEST_FILE_NAME);
+                let bytes = match tokio::fs::read(&path).await {
+                    Ok(t) => t,
+                    Err(e) if e.is_not_found() => return Ok(None),
+                    Err(e) => {
+                        return Err(anyhow::Error::from(e)
+                            .context(format!("Failed to read mod manifest {path:?}")))
+                    }
+                };
+                let manifest = serde_json::from_slice::<InstalledModManifest>(&bytes)
+                    .with_context(|| format!("Invalid mod manifest {path:?}"))?;
+                Ok(Some(manifest))
+            }));
+        }
+    }
+    let mut mods = Vec::new();
+    while let Some(r) = tasks.next().await {
+        if let Some(m) = r?? {
+            mods.push(InstalledMod {
+                source: thunderstore_package_url(&m.owner, &m.name, m.version.version_number),
+                owner: m.owner,
+                name: m.name,
+                version: m.version.version_number,
+                description: m.version.description,
+                dependencies: m.version.dependencies,
+                enabled,
+            });
+        }
+    }
+    mods.sort_by(|a, b| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.owner.cmp(&b.owner))
+    });
+    Ok(mods)
+}
+
+/// Installs the mod in the profile, unless it shares files with mods already installed and
+/// `ignore_conflicts` is false, in which case nothing is installed and the conflicts are returned.
+/// Otherwise, any conflicts that were ignored are returned.
 pub async fn install_profile_mod(
     app: &AppHandle,
     reqwest: &Reqwest,
     id: Uuid,
     r#mod: ModMetadata<'_>,
     version: ModVersion<'_>,
+    exclude: &[String],
+    ignore_conflicts: bool,
     task_id: Option<tasks::Id>,
-) -> Result<()> {
+) -> Result<Vec<FileConflict>> {
+    install_profile_mod_with_cache(
+        app,
+        reqwest,
+        id,
+        r#mod,
+        version,
+        exclude,
+        ignore_conflicts,
+        CacheOptions::by_url(),
+        task_id,
+    )
+    .await
+}
+
+/// Like [`install_profile_mod`], but caches the downloaded archive with `cache`, which is how a
+/// [`lockfile`] has the archive checked against its hash.
+async fn install_profile_mod_with_cache(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    id: Uuid,
+    r#mod: ModMetadata<'_>,
+    version: ModVersion<'_>,
+    exclude: &[String],
+    ignore_conflicts: bool,
+    cache: CacheOptions<'_>,
+    task_id: Option<tasks::Id>,
+) -> Result<Vec<FileConflict>> {
     let log = slog_scope::logger();
 
+    let game = games_by_id()?
+        .get(&*read_profile(id).await?.game)
+        .context("No such game")?;
+    if let Some(component) = exclude.iter().find(|component| {
+        game.package_loader
+            .required_package_entries()
+            .contains(&component.as_str())
+    }) {
+        bail!(
+            "{component:?} is required by {} and cannot be excluded",
+            game.package_loader.as_str()
+        );
+    }
+
+    let _guard = watcher::suppress(id);
+
     let mut path = profile_path(id);
 
     path.push(MODS_FOLDER);
@@ -185,16 +662,16 @@ pub async fn install_profile_mod(
     path.push(&r#mod.owner);
     path.as_mut_os_string().push("-");
     path.as_mut_os_string().push(&r#mod.name);
+    let (owner, name, version_number) = (r#mod.owner, r#mod.name, version.version_number);
     let staged = install_zip(
         Some(app),
         &log,
         reqwest,
-        &format!(
-            "https://gcdn.thunderstore.io/live/repository/packages/{}-{}-{}.zip",
-            r#mod.owner, r#mod.name, version.version_number
-        ),
-        Some(crate::installing::CacheOptions::by_url()),
+        &thunderstore_package_url(owner, name, version_number),
+        Some(cache),
         &path,
+        exclude,
+        None,
         task_id,
     )
     .await?;
@@ -212,14 +689,150 @@ pub async fn install_profile_mod(
         Ok::<_, anyhow::Error>(())
     })?;
 
+    let conflicts = match find_conflicts(&staged).await {
+        Ok(conflicts) => conflicts,
+        Err(e) => {
+            warn!(log, "Failed to check for conflicts with other mods: {e:?}");
+            Vec::new()
+        }
+    };
+    for conflict in &conflicts {
+        warn!(
+            log,
+            "{:?} is also provided by {:?}", conflict.path, conflict.other
+        );
+    }
+    if !conflicts.is_empty() && !ignore_conflicts {
+        // the staged package is discarded
+        info!(
+            log,
+            "Not installing {owner}-{name} because of conflicts with other mods"
+        );
+        return Ok(conflicts);
+    }
+
+    let archive_hash = staged.archive_hash();
     staged.finish(&log).await?;
 
-    Ok(())
+    post_install::run(&log, game.package_loader, id, &path).await?;
+
+    if let Some(archive_hash) = archive_hash {
+        if let Err(e) =
+            lockfile::record_install(id, owner, name, version_number, archive_hash, exclude).await
+        {
+            warn!(log, "Failed to update the profile's lockfile: {e:?}");
+        }
+    }
+
+    let is_loader = match game.package_loader.package_marker() {
+        Some(marker) => tokio::fs::try_exists(path.join(marker))
+            .await
+            .unwrap_or(false),
+        None => false,
+    };
+    if let Err(e) = record_game_install(id, game, is_loader).await {
+        warn!(log, "Failed to record the game's installation: {e:?}");
+    }
+
+    Ok(conflicts)
+}
+
+/// The files at the root of a package that describe it, which every package has its own of.
+const PACKAGE_METADATA_FILES: &[&str] = &[
+    "manifest.json",
+    "icon.png",
+    "README.md",
+    "CHANGELOG.md",
+    "LICENSE",
+    "LICENSE.md",
+    "LICENSE.txt",
+    MANIFEST_FILE_NAME,
+];
+
+fn is_package_metadata(rel_path: &Path) -> bool {
+    rel_path.parent() == Some(Path::new(""))
+        && rel_path.to_str().is_some_and(|name| {
+            PACKAGE_METADATA_FILES
+                .iter()
+                .any(|metadata| metadata.eq_ignore_ascii_case(name))
+        })
+}
+
+/// Finds the files, other than its metadata, that the staged mod shares with the other mods
+/// installed next to it, like config templates or plugins that both ship.
+async fn find_conflicts(staged: &StagedPackage<'_>) -> Result<Vec<FileConflict>> {
+    let mods_dir = staged
+        .target()
+        .parent()
+        .context("Mod must be in a directory")?;
+    let mut others = Vec::new();
+    let mut iter = tokio::fs::read_dir(mods_dir).await?;
+    while let Some(e) = iter.next_entry().await? {
+        if is_hidden_mod_entry(&e) || !e.file_type().await?.is_dir() {
+            continue;
+        }
+        others.push(e.path());
+    }
+    staged.conflicts_with(&others, is_package_metadata).await
+}
+
+/// Records the current state of the game's installation in the profile if none has been recorded
+/// yet, or if `force` is true because the mod loader was just (re)installed.
+async fn record_game_install(id: Uuid, game: &Game<'_>, force: bool) -> Result<()> {
+    if !force && read_profile(id).await?.game_install.is_some() {
+        return Ok(());
+    }
+    // hashing the executable is slow, so it's done before taking the lock
+    let fingerprint = game_install_fingerprint(game).await?;
+    update_profile(id, |profile| {
+        if force || profile.game_install.is_none() {
+            profile.game_install = Some(fingerprint);
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum GameUpdateStatus {
+    /// Nothing was recorded when mods were installed, so there is nothing to compare against.
+    Unknown,
+    Unchanged,
+    /// The game has been updated since the mod loader was installed, which may break mods.
+    Updated {
+        old: GameInstallFingerprint,
+        new: GameInstallFingerprint,
+    },
+}
+
+/// Compares the game's installation to the one recorded when the profile's mod loader was
+/// installed.
+pub async fn check_game_updated(id: Uuid) -> Result<GameUpdateStatus> {
+    let profile = read_profile(id).await?;
+    let Some(old) = profile.game_install else {
+        return Ok(GameUpdateStatus::Unknown);
+    };
+    let game = games_by_id()?
+        .get(&*profile.game)
+        .context("Unrecognized game")?;
+    let new = game_install_fingerprint(game).await?;
+    if new.exe == old.exe && new.size == old.size && new.hash == old.hash {
+        Ok(GameUpdateStatus::Unchanged)
+    } else {
+        Ok(GameUpdateStatus::Updated { old, new })
+    }
 }
 
-pub async fn uninstall_profile_mod(id: Uuid, owner: &str, name: &str) -> Result<()> {
+/// Uninstalls a mod from the profile, keeping any files that were created or modified since it
+/// was installed.
+///
+/// Returns the preserved paths, relative to the mod's directory.
+pub async fn uninstall_profile_mod(id: Uuid, owner: &str, name: &str) -> Result<Vec<PathBuf>> {
     let log = slog_scope::logger();
 
+    let _guard = watcher::suppress(id);
+
     let mut path = profile_path(id);
 
     path.push(MODS_FOLDER);
@@ -229,13 +842,353 @@ pub async fn uninstall_profile_mod(id: Uuid, owner: &str, name: &str) -> Result<
 
     // remove the manifest so it isn't left over after uninstalling the package
     path.push(MANIFEST_FILE_NAME);
-    tokio::fs::remove_file(&path)
+    crate::util::fs::remove_file(&path)
         .await
         .context("Failed to remove manifest file")?;
     path.pop();
 
     // keep_changes is true so that configs and any other changes are
     // preserved. Zero-risk uninstallation!
-    uninstall_package(&log, &path, true).await?;
+    let preserved = uninstall_package(&log, &path, true).await?;
+
+    if let Err(e) = lockfile::record_uninstall(id, owner, name).await {
+        warn!(log, "Failed to update the profile's lockfile: {e:?}");
+    }
+    Ok(preserved)
+}
+
+/// Restores the config files that came with a mod to their original contents, leaving any other
+/// files and mods untouched.
+///
+/// Returns the restored paths, relative to the mod's directory.
+pub async fn reset_profile_mod_config(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    id: Uuid,
+    owner: &str,
+    name: &str,
+    task_id: Option<tasks::Id>,
+) -> Result<Vec<PathBuf>> {
+    let log = slog_scope::logger();
+
+    let profile = read_profile(id).await.context("Failed to read profile")?;
+    let config_dirs = games_by_id()?
+        .get(&*profile.game)
+        .context("No such game")?
+        .package_loader
+        .config_dirs();
+
+    let path = profile_mod_path(id, owner, name);
+    let manifest = read_installed_mod_manifest(&path).await?;
+
+    let _guard = watcher::suppress(id);
+
+    let restored = restore_package_files(
+        Some(app),
+        &log,
+        reqwest,
+        &thunderstore_package_url(
+            &manifest.owner,
+            &manifest.name,
+            manifest.version.version_number,
+        ),
+        Some(CacheOptions::by_url()),
+        &path,
+        |rel_path| config_dirs.iter().any(|dir| rel_path.starts_with(dir)),
+        task_id,
+    )
+    .await?;
+
+    info!(
+        log,
+        "Reset {} config files of {owner}-{name}",
+        restored.len()
+    );
+
+    Ok(restored)
+}
+
+/// Regenerates the content index of an installed mod if it is missing or corrupt.
+pub async fn repair_profile_mod_index(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    id: Uuid,
+    owner: &str,
+    name: &str,
+    task_id: Option<tasks::Id>,
+) -> Result<IndexRepair> {
+    let log = slog_scope::logger();
+
+    let path = profile_mod_path(id, owner, name);
+    let manifest = read_installed_mod_manifest(&path).await?;
+    let exclude = lockfile::excluded_components(id, owner, name).await?;
+
+    let _guard = watcher::suppress(id);
+
+    repair_package_index(
+        Some(app),
+        &log,
+        reqwest,
+        &thunderstore_package_url(
+            &manifest.owner,
+            &manifest.name,
+            manifest.version.version_number,
+        ),
+        Some(CacheOptions::by_url()),
+        &path,
+        &exclude,
+        task_id,
+    )
+    .await
+}
+
+/// Makes the current state of the given files of an installed mod its new baseline, so that the
+/// user's changes to them are no longer reported. `paths` are relative to the mod's directory.
+pub async fn accept_profile_mod_changes(
+    id: Uuid,
+    owner: &str,
+    name: &str,
+    paths: &[PathBuf],
+) -> Result<()> {
+    let log = slog_scope::logger();
+
+    let path = profile_mod_path(id, owner, name);
+    let _guard = watcher::suppress(id);
+
+    accept_package_changes(&log, &path, paths).await?;
+
+    info!(
+        log,
+        "Accepted changes to {} paths of {owner}-{name}",
+        paths.len()
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ModFingerprint {
+    pub owner: SmolStr,
+    pub name: SmolStr,
+    pub version: String,
+    /// Hex-encoded hash of the mod's contents.
+    pub content_hash: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ProfileFingerprint {
+    /// Hex-encoded.
+    pub fingerprint: String,
+    /// A short, human-comparable form of `fingerprint`.
+    pub display: String,
+    /// The fingerprints of the individual mods, sorted by full name, so that users can find
+    /// which mods differ.
+    pub mods: Vec<ModFingerprint>,
+}
+
+impl ProfileFingerprint {
+    fn new(mut mods: Vec<ModFingerprint>) -> Self {
+        mods.sort_unstable_by(|a, b| (&a.owner, &a.name).cmp(&(&b.owner, &b.name)));
+
+        let mut hasher = blake3::Hasher::new();
+        for m in &mods {
+            hasher.update(
+                format!("{}-{}-{} {}\n", m.owner, m.name, m.version, m.content_hash).as_bytes(),
+            );
+        }
+        let fingerprint = hasher.finalize().to_hex().to_string();
+
+        let display = fingerprint[..16]
+            .as_bytes()
+            .chunks(4)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap())
+            .collect::<Vec<_>>()
+            .join("-");
+
+        Self {
+            fingerprint,
+            display,
+            mods,
+        }
+    }
+}
+
+/// Computes a fingerprint of the profile's installed mods from each mod's full name, version, and
+/// current content. Two profiles have the same fingerprint only if their mods are byte-identical.
+pub async fn profile_fingerprint(id: Uuid) -> Result<ProfileFingerprint> {
+    let mut path = profile_path(id);
+    path.push(MODS_FOLDER);
+
+    let mut mods = Vec::new();
+    let mut iter = match tokio::fs::read_dir(&path).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(ProfileFingerprint::new(mods)),
+        Err(e) => return Err(e).context("Failed to read mods directory"),
+    };
+    while let Some(e) = iter.next_entry().await? {
+        if is_hidden_mod_entry(&e) || !e.file_type().await?.is_dir() {
+            continue;
+        }
+        let mod_path = e.path();
+        if !tokio::fs::try_exists(mod_path.join(MANIFEST_FILE_NAME)).await? {
+            continue;
+        }
+        let manifest = read_installed_mod_manifest(&mod_path).await?;
+        let content_hash = tokio::task::block_in_place(|| {
+            // the manifest includes metadata, such as download counts, that differs between
+            // otherwise identical installs
+            crate::installing::package_content_hash(&mod_path, |rel_path| {
+                rel_path == Path::new(MANIFEST_FILE_NAME)
+            })
+        })
+        .with_context(|| format!("Failed to hash {mod_path:?}"))?;
+        mods.push(ModFingerprint {
+            owner: manifest.owner,
+            name: manifest.name,
+            version: manifest.version.version_number.to_string(),
+            content_hash: content_hash.to_hex().to_string(),
+        });
+    }
+    Ok(ProfileFingerprint::new(mods))
+}
+
+/// Finds the package of the mod loader `loader` among the mods installed in the profile.
+async fn find_loader_package(
+    id: Uuid,
+    loader: PackageLoader,
+) -> Result<Option<InstalledModManifest>> {
+    let Some(marker) = loader.package_marker() else {
+        return Ok(None);
+    };
+    let mut iter = match tokio::fs::read_dir(profile_path(id).join(MODS_FOLDER)).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(e) = iter.next_entry().await? {
+        if !is_hidden_mod_entry(&e) && tokio::fs::try_exists(e.path().join(marker)).await? {
+            return Ok(Some(read_installed_mod_manifest(&e.path()).await?));
+        }
+    }
+    Ok(None)
+}
+
+/// Replaces the profile's mod loader with an older `version` of its package, such as when an
+/// update of the loader broke the profile's mods. Mods and configs are left untouched.
+pub async fn downgrade_profile_loader(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    id: Uuid,
+    version: Version,
+    task_id: Option<tasks::Id>,
+) -> Result<()> {
+    let log = slog_scope::logger();
+
+    let profile = read_profile(id).await?;
+    let game = *games_by_id()?.get(&*profile.game).context("No such game")?;
+    let installed = find_loader_package(id, game.package_loader)
+        .await?
+        .context("The profile does not have a mod loader installed")?;
+    ensure!(
+        version.components() < installed.version.version_number.components(),
+        "{version} is not older than the installed version {}",
+        installed.version.version_number
+    );
+
+    let mod_index = read_mod_index(&profile.game).await?;
+    let mod_ids = HashSet::from([ModId {
+        owner: installed.owner.as_str().into(),
+        name: installed.name.as_str().into(),
+    }]);
+    let m = get_from_mod_index(&mod_index, &mod_ids)
+        .await?
+        .into_iter()
+        .next()
+        .with_context(|| format!("Missing mod {}-{}", installed.owner, installed.name))?;
+    let m_version = m
+        .versions
+        .iter()
+        .find(|v| v.version_number.get() == version)
+        .with_context(|| {
+            format!(
+                "Missing version {version} of mod {}-{}",
+                installed.owner, installed.name
+            )
+        })?;
+
+    install_profile_mod(
+        app,
+        reqwest,
+        id,
+        (&m.metadata).into(),
+        m_version.into(),
+        &[],
+        true,
+        task_id,
+    )
+    .await?;
+
+    let downgraded = find_loader_package(id, game.package_loader)
+        .await?
+        .context("The mod loader is missing after downgrading it")?;
+    ensure!(
+        downgraded.version.version_number == version,
+        "Expected version {version} of the mod loader to be installed, but found {}",
+        downgraded.version.version_number
+    );
+    // don't update it right back
+    loader_update::set_loader_pin(id, Some(version)).await?;
+    info!(
+        log,
+        "Downgraded {}-{} in profile {id} from {} to {version}",
+        installed.owner,
+        installed.name,
+        installed.version.version_number
+    );
+
     Ok(())
 }
+
+/// Downloads a mod and extracts it into a temporary directory to diagnose installation failures.
+pub async fn test_extract_mod(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    owner: &str,
+    name: &str,
+    version: Version,
+    task_id: Option<tasks::Id>,
+) -> Result<ExtractionReport> {
+    let log = slog_scope::logger();
+
+    test_extract_zip(
+        Some(app),
+        &log,
+        reqwest,
+        &thunderstore_package_url(owner, name, version),
+        Some(CacheOptions::by_url()),
+        task_id,
+    )
+    .await
+}
+
+/// Lists the components of a mod version that may be excluded when installing it.
+pub async fn get_mod_optional_components(
+    app: &AppHandle,
+    reqwest: &Reqwest,
+    owner: &str,
+    name: &str,
+    version: Version,
+    task_id: Option<tasks::Id>,
+) -> Result<Vec<String>> {
+    let log = slog_scope::logger();
+
+    list_optional_components(
+        Some(app),
+        &log,
+        reqwest,
+        &thunderstore_package_url(owner, name, version),
+        Some(CacheOptions::by_url()),
+        task_id,
+    )
+    .await
+}
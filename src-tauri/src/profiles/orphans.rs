@@ -0,0 +1,108 @@
+//! Finding package archives in the cache that no profile uses anymore.
+//!
+//! Packages are cached by the URL they were downloaded from, which is derived from the owner,
+//! name, and version recorded in each installed mod's manifest, so the cache entry of every
+//! installed package can be located without recording anything extra. Other entries, like the
+//! mod loaders cached by hash, are shared by all profiles and never considered orphaned.
+
+use std::collections::HashSet;
+
+use anyhow::{Context as _, Result};
+use smol_str::SmolStr;
+
+use crate::installing::{url_cache_path, url_from_cache_file_name};
+use crate::paths::cache_dir;
+use crate::util::IoErrorKindExt as _;
+
+use super::{get_profiles, list_installed_mods};
+
+const PACKAGE_URL_PREFIX: &str = "https://gcdn.thunderstore.io/live/repository/packages/";
+const ARCHIVE_SUFFIX: &str = ".zip";
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedCacheEntry {
+    /// The name of the file in the cache directory.
+    pub file_name: String,
+    pub size: u64,
+    pub owner: SmolStr,
+    pub name: SmolStr,
+    pub version: SmolStr,
+}
+
+/// Splits the file name at the end of a package URL into the package's owner, name, and version.
+fn parse_package_url(url: &str) -> Option<(&str, &str, &str)> {
+    let file_name = url
+        .strip_prefix(PACKAGE_URL_PREFIX)?
+        .strip_suffix(ARCHIVE_SUFFIX)?;
+    // names and versions can't contain hyphens, but owners can
+    let (rest, version) = file_name.rsplit_once('-')?;
+    let (owner, name) = rest.rsplit_once('-')?;
+    Some((owner, name, version))
+}
+
+/// Lists the cached package archives that aren't installed in any profile, sorted by size, the
+/// largest first.
+pub async fn find_orphaned_cache_entries() -> Result<Vec<OrphanedCacheEntry>> {
+    let mut referenced = HashSet::new();
+    for profile in get_profiles().await? {
+        for m in list_installed_mods(profile.id).await? {
+            if let Some(file_name) = url_cache_path(&m.source, ARCHIVE_SUFFIX).file_name() {
+                referenced.insert(file_name.to_owned());
+            }
+        }
+    }
+
+    let mut orphans = Vec::new();
+    let mut iter = match tokio::fs::read_dir(cache_dir()).await {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(orphans),
+        Err(e) => return Err(e).context("Failed to read cache directory"),
+    };
+    while let Some(e) = iter.next_entry().await? {
+        if referenced.contains(&e.file_name()) {
+            continue;
+        }
+        let Some(file_name) = e.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        let Some(url) = url_from_cache_file_name(&file_name, ARCHIVE_SUFFIX) else {
+            continue;
+        };
+        let Some((owner, name, version)) = parse_package_url(&url) else {
+            continue;
+        };
+        let metadata = e.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        orphans.push(OrphanedCacheEntry {
+            size: metadata.len(),
+            owner: owner.into(),
+            name: name.into(),
+            version: version.into(),
+            file_name,
+        });
+    }
+    orphans.sort_unstable_by(|a, b| b.size.cmp(&a.size));
+    Ok(orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_package_url;
+
+    #[test]
+    fn test_parse_package_url() {
+        assert_eq!(
+            parse_package_url(
+                "https://gcdn.thunderstore.io/live/repository/packages/Some-Team-Mod_Name-1.2.3.zip"
+            ),
+            Some(("Some-Team", "Mod_Name", "1.2.3"))
+        );
+        assert_eq!(
+            parse_package_url("https://example.com/Owner-Name-1.0.0.zip"),
+            None
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Declarative steps performed after a package is installed into a profile.
+//!
+//! Some loaders expect files that come with a package to end up somewhere other than the
+//! package's own directory. Rather than running arbitrary code, each loader declares a fixed list
+//! of [`Action`]s in [`actions`], which keeps what happens to a profile auditable. Actions never
+//! run external programs, and all of their paths are relative and confined to the package or the
+//! profile.
+
+use std::path::{Component, Path};
+
+use anyhow::{ensure, Context as _, Result};
+use slog::debug;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use crate::games::PackageLoader;
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum Action {
+    /// Copies a file or directory tree from the package into the profile. Missing sources are
+    /// ignored. Existing files are only replaced if `overwrite` is set, so that users' edits
+    /// survive reinstalls.
+    Copy {
+        from: &'static str,
+        to: &'static str,
+        overwrite: bool,
+    },
+}
+
+/// Returns the actions to perform after installing a package for `loader`.
+pub const fn actions(loader: PackageLoader) -> &'static [Action] {
+    match loader {
+        // BepInEx reads configs from the profile, not from each package, so seed it with the
+        // defaults that came with the package.
+        PackageLoader::BepInEx => &[
+            Action::Copy {
+                from: "config",
+                to: "config",
+                overwrite: false,
+            },
+            Action::Copy {
+                from: "BepInEx/config",
+                to: "config",
+                overwrite: false,
+            },
+        ],
+        PackageLoader::MelonLoader
+        | PackageLoader::NorthStar
+        | PackageLoader::GodotML
+        | PackageLoader::AncientDungeonVR
+        | PackageLoader::ShimLoader
+        | PackageLoader::Lovely
+        | PackageLoader::ReturnOfModding
+        | PackageLoader::GDWeave => &[],
+    }
+}
+
+fn confined_path(base: &Path, rel_path: &str) -> Result<std::path::PathBuf> {
+    let rel_path = Path::new(rel_path);
+    ensure!(
+        rel_path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_))),
+        "Path must be relative and may not escape its base: {rel_path:?}"
+    );
+    Ok(base.join(rel_path))
+}
+
+/// Performs the post-install actions for `loader` on the package installed at `package_path`
+/// in the profile `id`.
+pub async fn run(
+    log: &slog::Logger,
+    loader: PackageLoader,
+    id: Uuid,
+    package_path: &Path,
+) -> Result<()> {
+    let profile_path = super::profile_path(id);
+    for action in actions(loader) {
+        debug!(
+            log,
+            "Running post-install action {action:?} for {package_path:?}"
+        );
+        match *action {
+            Action::Copy {
+                from,
+                to,
+                overwrite,
+            } => {
+                let from = confined_path(package_path, from)?;
+                let to = confined_path(&profile_path, to)?;
+                tokio::task::block_in_place(|| copy(&from, &to, overwrite))
+                    .with_context(|| format!("Failed to copy {from:?} to {to:?}"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn copy(from: &Path, to: &Path, overwrite: bool) -> Result<()> {
+    if !from.exists() {
+        return Ok(());
+    }
+    for e in WalkDir::new(from) {
+        let e = e?;
+        let target = to.join(e.path().strip_prefix(from)?);
+        if e.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if e.file_type().is_file() {
+            if !overwrite && target.exists() {
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(e.path(), &target)?;
+        }
+        // symlinks are deliberately not copied, as they could point outside of the package
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::confined_path;
+
+    #[test]
+    fn test_confined_path() {
+        let base = Path::new("/profiles/a");
+        assert_eq!(
+            confined_path(base, "BepInEx/config").unwrap(),
+            Path::new("/profiles/a/BepInEx/config")
+        );
+        assert!(confined_path(base, "../b").is_err());
+        assert!(confined_path(base, "config/../../b").is_err());
+        assert!(confined_path(base, "/etc").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! A reverse index from each file in a profile to the package that provided it.
+//!
+//! Scanning requires walking the whole profile, so the result is cached per profile until the
+//! profile is next modified, either by us or, if it is being watched, externally.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use slog::debug;
+use smol_str::SmolStr;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use crate::installing::classify_package_files;
+
+use super::{profile_path, read_installed_mod_manifest, MANIFEST_FILE_NAME, MODS_FOLDER};
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum Origin {
+    /// The file was provided by the package.
+    Package { owner: SmolStr, name: SmolStr },
+    /// The file is inside of the package's directory, but the package has no content index to
+    /// tell whether the package provided it.
+    Unindexed { owner: SmolStr, name: SmolStr },
+    /// The file was created by the user or by the game, not installed by a package.
+    User,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct FileProvenance {
+    /// The path of the file, relative to the profile's directory.
+    pub path: PathBuf,
+    pub origin: Origin,
+}
+
+static CACHE: LazyLock<Mutex<HashMap<Uuid, Arc<Vec<FileProvenance>>>>> =
+    LazyLock::new(Default::default);
+
+/// Drops the cached provenance of the profile's files.
+pub fn invalidate(id: Uuid) {
+    CACHE.lock().remove(&id);
+}
+
+async fn scan_package(
+    profile_dir: &Path,
+    package_dir: &Path,
+    files: &mut Vec<FileProvenance>,
+) -> Result<()> {
+    if !tokio::fs::try_exists(package_dir.join(MANIFEST_FILE_NAME)).await? {
+        // not a package we installed
+        return tokio::task::block_in_place(|| scan_user_files(profile_dir, package_dir, files));
+    }
+    let manifest = read_installed_mod_manifest(package_dir).await?;
+    let rel_dir = package_dir.strip_prefix(profile_dir)?;
+    match classify_package_files(package_dir).await? {
+        Some(classified) => {
+            for (path, provided) in classified {
+                if path == Path::new(MANIFEST_FILE_NAME) {
+                    continue;
+                }
+                files.push(FileProvenance {
+                    path: rel_dir.join(path),
+                    origin: if provided {
+                        Origin::Package {
+                            owner: manifest.owner.clone(),
+                            name: manifest.name.clone(),
+                        }
+                    } else {
+                        Origin::User
+                    },
+                });
+            }
+        }
+        None => tokio::task::block_in_place(|| {
+            for e in WalkDir::new(package_dir).min_depth(1) {
+                let e = e?;
+                if e.file_type().is_dir() || e.file_name() == MANIFEST_FILE_NAME {
+                    continue;
+                }
+                files.push(FileProvenance {
+                    path: e.path().strip_prefix(profile_dir)?.to_owned(),
+                    origin: Origin::Unindexed {
+                        owner: manifest.owner.clone(),
+                        name: manifest.name.clone(),
+                    },
+                });
+            }
+            Ok::<_, anyhow::Error>(())
+        })?,
+    }
+    Ok(())
+}
+
+fn scan_user_files(profile_dir: &Path, dir: &Path, files: &mut Vec<FileProvenance>) -> Result<()> {
+    for e in WalkDir::new(dir).min_depth(1) {
+        let e = e?;
+        if !e.file_type().is_dir() {
+            files.push(FileProvenance {
+                path: e.path().strip_prefix(profile_dir)?.to_owned(),
+                origin: Origin::User,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the provenance of every file in the profile, sorted by path. Unless `rescan` is true,
+/// the result of the last scan is reused if the profile hasn't been modified since.
+pub async fn file_provenance(id: Uuid, rescan: bool) -> Result<Arc<Vec<FileProvenance>>> {
+    if !rescan {
+        if let Some(files) = CACHE.lock().get(&id) {
+            return Ok(files.clone());
+        }
+    }
+
+    let profile_dir = profile_path(id);
+    let mut files = Vec::new();
+    let mut iter = tokio::fs::read_dir(&profile_dir).await?;
+    while let Some(e) = iter.next_entry().await? {
+        let path = e.path();
+        if e.file_name() == MODS_FOLDER && e.file_type().await?.is_dir() {
+            let mut packages = tokio::fs::read_dir(&path).await?;
+            while let Some(e) = packages.next_entry().await? {
+                if e.file_type().await?.is_dir() {
+                    scan_package(&profile_dir, &e.path(), &mut files).await?;
+                } else {
+                    files.push(FileProvenance {
+                        path: e.path().strip_prefix(&profile_dir)?.to_owned(),
+                        origin: Origin::User,
+                    });
+                }
+            }
+        } else if e.file_name() == "profile.json" {
+            continue;
+        } else if e.file_type().await?.is_dir() {
+            tokio::task::block_in_place(|| scan_user_files(&profile_dir, &path, &mut files))?;
+        } else {
+            files.push(FileProvenance {
+                path: e.file_name().into(),
+                origin: Origin::User,
+            });
+        }
+    }
+    files.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+    debug!(
+        slog_scope::logger(),
+        "Scanned provenance of {} files in profile {id}",
+        files.len()
+    );
+    let files = Arc::new(files);
+    CACHE.lock().insert(id, files.clone());
+    Ok(files)
+}
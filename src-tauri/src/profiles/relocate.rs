@@ -0,0 +1,291 @@
+//! Moving a profile's files to another directory, such as on a bigger drive.
+//!
+//! A relocated profile keeps a symlink at its usual location in [`PROFILES_DIR`] that points to
+//! its new directory, so the rest of the app can keep using [`profile_path`] unchanged. On
+//! Windows, the link is a junction, since creating a symlink needs elevated privileges there.
+//!
+//! Within a filesystem, the directory is simply renamed. Across filesystems, it is copied and
+//! the copy is compared with the original before anything is removed, so interrupting a move at
+//! any point before the link is replaced leaves the original intact. If the move is interrupted
+//! after that, the original is left behind in the source directory and can be deleted by hand.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context as _, Result};
+use slog::{info, warn};
+use tauri::AppHandle;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use crate::installing::hash_file;
+use crate::tasks::{self, TaskBuilder};
+use crate::util::hyphenated_uuid;
+
+use super::{copy_tree, profile_path, watcher, PROFILES_DIR};
+
+/// Returns the directory that actually holds the profile's files, following the link left by a
+/// previous move.
+async fn resolve_profile_dir(id: Uuid) -> Result<PathBuf> {
+    let path = profile_path(id);
+    tokio::fs::canonicalize(&path)
+        .await
+        .with_context(|| format!("Failed to resolve profile directory {path:?}"))
+}
+
+fn symlink_dir(original: &Path, link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(original, link);
+    #[cfg(windows)]
+    return junction::create(original, link);
+}
+
+/// Removes a symlink or junction to a directory, which Windows treats as a directory rather than
+/// a file.
+pub(super) fn remove_symlink_dir(link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::fs::remove_file(link);
+    #[cfg(windows)]
+    return std::fs::remove_dir(link);
+}
+
+/// Compares every entry below `src` with the corresponding entry below `dest`, by type, symlink
+/// target, size, and content. `on_verified` is called with the size of each file after it is
+/// compared.
+fn verify_copy(
+    src: &Path,
+    dest: &Path,
+    mut on_verified: impl FnMut(u64) -> Result<()>,
+) -> Result<()> {
+    let mut count = 0usize;
+    for e in WalkDir::new(src).min_depth(1) {
+        let e = e?;
+        count += 1;
+        let target = dest.join(e.path().strip_prefix(src)?);
+        let metadata = std::fs::symlink_metadata(&target)
+            .with_context(|| format!("{target:?} is missing from the copy"))?;
+        if metadata.file_type() != e.file_type() {
+            bail!("{target:?} has a different type than {:?}", e.path());
+        }
+        if e.file_type().is_symlink() {
+            ensure!(
+                std::fs::read_link(&target)? == std::fs::read_link(e.path())?,
+                "{target:?} links to a different path than {:?}",
+                e.path()
+            );
+        } else if e.file_type().is_file() {
+            let len = e.metadata()?.len();
+            ensure!(
+                metadata.len() == len && hash_file(&target)? == hash_file(e.path())?,
+                "{target:?} differs from {:?}",
+                e.path()
+            );
+            on_verified(len)?;
+        }
+    }
+    ensure!(
+        WalkDir::new(dest).min_depth(1).into_iter().count() == count,
+        "The copy contains unexpected entries"
+    );
+    Ok(())
+}
+
+/// Moves the profile's files into a directory named after its id in `new_root`. If `new_root`
+/// is [`PROFILES_DIR`], a previously moved profile is moved back to its usual location.
+pub async fn move_profile(
+    app: &AppHandle,
+    id: Uuid,
+    new_root: &Path,
+    task_id: Option<tasks::Id>,
+) -> Result<()> {
+    let log = slog_scope::logger();
+
+    let link = profile_path(id);
+    let src = resolve_profile_dir(id).await?;
+
+    tokio::fs::create_dir_all(new_root)
+        .await
+        .context("Failed to create destination directory")?;
+    let new_root = tokio::fs::canonicalize(new_root).await?;
+    let returning_home = tokio::fs::canonicalize(&*PROFILES_DIR).await? == new_root;
+    let dest = new_root.join(hyphenated_uuid!(id));
+    if dest == src {
+        return Ok(());
+    }
+    ensure!(
+        !dest.starts_with(&src),
+        "Destination must not be inside of the profile"
+    );
+    // when returning home, the link occupies the destination until the copy is verified
+    let staging = if returning_home {
+        dest.with_extension("moving")
+    } else {
+        dest.clone()
+    };
+    ensure!(
+        !tokio::fs::try_exists(&staging).await?,
+        "{staging:?} already exists"
+    );
+
+    let _guard = watcher::suppress(id);
+
+    TaskBuilder::with_id(
+        task_id.unwrap_or_else(tasks::allocate_task),
+        format!("Move profile to {new_root:?}"),
+    )
+    .progress_unit(tasks::ProgressUnit::Bytes)
+    .run_with_handle(Some(app), |handle| async move {
+        tokio::task::block_in_place(|| {
+            move_dir(
+                &log,
+                id,
+                &src,
+                &staging,
+                &dest,
+                &link,
+                |completed, total| handle.send_progress_manually(app, completed, total),
+            )
+        })
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Moves the profile `id` from `src` to `dest`, by way of `staging` if it differs from `dest`,
+/// and points `link` at `dest` unless `dest` is where `link` is. `on_progress` is called with the
+/// number of bytes processed so far and in total while copying across filesystems.
+fn move_dir(
+    log: &slog::Logger,
+    id: Uuid,
+    src: &Path,
+    staging: &Path,
+    dest: &Path,
+    link: &Path,
+    mut on_progress: impl FnMut(u64, u64) -> Result<()>,
+) -> Result<()> {
+    let returning_home = staging != dest;
+    match std::fs::rename(src, staging) {
+        Ok(()) => {
+            info!(log, "Renamed profile {id} to {staging:?}");
+        }
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mut total = 0u64;
+            for e in WalkDir::new(src) {
+                let e = e?;
+                if e.file_type().is_file() {
+                    total += e.metadata()?.len();
+                }
+            }
+            // every byte is read twice, once to copy it and once to verify it
+            total *= 2;
+
+            std::fs::create_dir(staging).context("Failed to create destination directory")?;
+            let mut completed = 0u64;
+            on_progress(completed, total)?;
+            let result = copy_tree(
+                src,
+                staging,
+                |_| false,
+                |copied| {
+                    completed += copied;
+                    on_progress(completed, total)
+                },
+            )
+            .and_then(|()| {
+                verify_copy(src, staging, |verified| {
+                    completed += verified;
+                    on_progress(completed, total)
+                })
+            });
+            if let Err(e) = result {
+                if let Err(e) = crate::util::fs::remove_dir_all_blocking(staging) {
+                    warn!(
+                        log,
+                        "Failed to clean up incomplete copy at {staging:?}: {e}"
+                    );
+                }
+                return Err(e.context("Failed to copy profile"));
+            }
+            info!(log, "Copied profile {id} to {staging:?}");
+        }
+        Err(e) => return Err(e).context("Failed to move profile"),
+    }
+    // checked before linking, since `src` may be where the link is created
+    let src_remains = std::fs::symlink_metadata(src).is_ok();
+
+    // the original directory is gone after a rename, so only the link remains to be replaced
+    let relinked = link.is_symlink();
+    if relinked {
+        remove_symlink_dir(link)?;
+    } else if link.exists() {
+        // copied out of its usual location, which must make way for the link
+        let old = link.with_extension("old");
+        std::fs::rename(link, &old)?;
+        if let Err(e) = symlink_dir(dest, link) {
+            let undone = std::fs::rename(&old, link)
+                .and_then(|()| crate::util::fs::remove_dir_all_blocking(staging));
+            if let Err(e) = undone {
+                warn!(log, "Failed to undo the move of profile {id}: {e}");
+            }
+            return Err(e).context("Failed to link the profile to its new directory");
+        }
+        crate::util::fs::remove_dir_all_blocking(&old)
+            .context("Failed to remove the original profile directory")?;
+        return Ok(());
+    }
+    if returning_home {
+        std::fs::rename(staging, dest)?;
+    } else if let Err(e) = symlink_dir(dest, link) {
+        // put the profile back where its link, if any, pointed
+        let undone = if src_remains {
+            crate::util::fs::remove_dir_all_blocking(staging)
+        } else {
+            std::fs::rename(staging, src)
+        }
+        .and_then(|()| {
+            if relinked {
+                symlink_dir(src, link)
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = undone {
+            warn!(log, "Failed to undo the move of profile {id}: {e}");
+        }
+        return Err(e).context("Failed to link the profile to its new directory");
+    }
+    // `src` may be the link by now, which `is_symlink` catches even if the paths are spelled
+    // differently
+    if src_remains && src != link && !src.is_symlink() {
+        crate::util::fs::remove_dir_all_blocking(src)
+            .context("Failed to remove the original profile directory")?;
+    }
+
+    info!(log, "Moved profile {id} to {dest:?}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::move_dir;
+
+    #[test]
+    fn test_move_dir_out_of_home() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("profiles").join("a");
+        let dest = dir.path().join("elsewhere").join("a");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        std::fs::write(home.join("profile.json"), b"{}").unwrap();
+
+        move_dir(&log, Uuid::nil(), &home, &dest, &dest, &home, |_, _| Ok(())).unwrap();
+
+        assert!(home.is_symlink());
+        assert_eq!(std::fs::read(home.join("profile.json")).unwrap(), b"{}");
+        assert!(dest.join("profile.json").is_file());
+    }
+}
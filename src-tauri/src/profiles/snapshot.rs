@@ -0,0 +1,280 @@
+//! Snapshots of a profile's files taken at launch, so that users can find out what a game or its
+//! mods changed since then.
+//!
+//! Snapshots only record hashes and file metadata, never file contents. Files whose size and
+//! modification time are unchanged from the previous snapshot are not rehashed.
+
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context as _, Result};
+use slog::debug;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use crate::installing::{hash_file, Status};
+use crate::util::IoErrorKindExt as _;
+
+use super::{is_record_dir, profile_path};
+
+/// The name of the directory, inside of the profile, in which snapshots are stored.
+pub const SNAPSHOTS_DIR_NAME: &str = ".manderrow_launch_snapshots";
+
+/// The number of snapshots kept per profile, and so the number of launches that changes can be
+/// reported since.
+const MAX_SNAPSHOTS: usize = 3;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Decode, bincode::Encode)]
+enum Entry {
+    File {
+        len: u64,
+        /// Seconds and nanoseconds since the Unix epoch.
+        modified: (u64, u32),
+        hash: [u8; 32],
+    },
+    Directory,
+    Symlink {
+        target: String,
+    },
+}
+
+#[derive(Debug, Default, bincode::Decode, bincode::Encode)]
+struct Snapshot {
+    /// Keyed by `/`-separated paths relative to the profile.
+    entries: BTreeMap<String, Entry>,
+}
+
+fn snapshots_dir(id: Uuid) -> PathBuf {
+    profile_path(id).join(SNAPSHOTS_DIR_NAME)
+}
+
+/// Returns the paths of the profile's snapshots, oldest first.
+fn list_snapshots(dir: &Path) -> Result<Vec<PathBuf>> {
+    let iter = match std::fs::read_dir(dir) {
+        Ok(t) => t,
+        Err(e) if e.is_not_found() => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut snapshots = Vec::new();
+    for e in iter {
+        let e = e?;
+        if let Some(timestamp) = e.file_name().to_str().and_then(|s| s.parse::<u128>().ok()) {
+            snapshots.push((timestamp, e.path()));
+        }
+    }
+    snapshots.sort_unstable_by_key(|(timestamp, _)| *timestamp);
+    Ok(snapshots.into_iter().map(|(_, path)| path).collect())
+}
+
+fn read_snapshot(path: &Path) -> Result<Snapshot> {
+    let file = std::fs::File::open(path)?;
+    bincode::decode_from_std_read(&mut std::io::BufReader::new(file), BINCODE_CONFIG)
+        .with_context(|| format!("Invalid snapshot at {path:?}"))
+}
+
+fn read_latest(dir: &Path) -> Result<Option<Snapshot>> {
+    list_snapshots(dir)?
+        .pop()
+        .map(|path| read_snapshot(&path))
+        .transpose()
+}
+
+fn scan(log: &slog::Logger, root: &Path, previous: Option<&Snapshot>) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::default();
+    let iter = WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !is_record_dir(e));
+    for e in iter {
+        let e = e?;
+        let key = e
+            .path()
+            .strip_prefix(root)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let entry = if e.file_type().is_dir() {
+            Entry::Directory
+        } else if e.file_type().is_symlink() {
+            Entry::Symlink {
+                target: std::fs::read_link(e.path())?.to_string_lossy().into_owned(),
+            }
+        } else {
+            let metadata = e.metadata()?;
+            let len = metadata.len();
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let modified = (modified.as_secs(), modified.subsec_nanos());
+            let hash = match previous.and_then(|s| s.entries.get(&key)) {
+                Some(&Entry::File {
+                    len: prev_len,
+                    modified: prev_modified,
+                    hash,
+                }) if prev_len == len && prev_modified == modified => hash,
+                _ => *hash_file(e.path())?.as_bytes(),
+            };
+            Entry::File {
+                len,
+                modified,
+                hash,
+            }
+        };
+        snapshot.entries.insert(key, entry);
+    }
+    debug!(
+        log,
+        "Scanned {} entries in {root:?}",
+        snapshot.entries.len()
+    );
+    Ok(snapshot)
+}
+
+/// Returns whether `path`'s parent directory was recorded in `changes` with `status`, in which
+/// case there's no need to report `path` itself.
+fn parent_has_status(changes: &BTreeMap<String, Status>, path: &str, status: Status) -> bool {
+    path.rsplit_once('/')
+        .is_some_and(|(parent, _)| changes.get(parent) == Some(&status))
+}
+
+fn diff(old: &Snapshot, new: &Snapshot) -> BTreeMap<String, Status> {
+    let mut changes = BTreeMap::new();
+    // parents sort before their children, so they are always recorded first
+    for (path, new_entry) in &new.entries {
+        let status = match (old.entries.get(path), new_entry) {
+            (None, _) => Status::Created,
+            (Some(Entry::File { hash: a, .. }), Entry::File { hash: b, .. }) if a != b => {
+                Status::ContentModified
+            }
+            (Some(Entry::Symlink { target: a }), Entry::Symlink { target: b }) if a != b => {
+                Status::LinkTargetChanged
+            }
+            (Some(old_entry), new_entry)
+                if std::mem::discriminant(old_entry) != std::mem::discriminant(new_entry) =>
+            {
+                Status::TypeChanged
+            }
+            _ => continue,
+        };
+        if status == Status::Created && parent_has_status(&changes, path, Status::Created) {
+            continue;
+        }
+        changes.insert(path.clone(), status);
+    }
+    for path in old.entries.keys() {
+        if !new.entries.contains_key(path) && !parent_has_status(&changes, path, Status::Deleted) {
+            changes.insert(path.clone(), Status::Deleted);
+        }
+    }
+    changes
+}
+
+/// Records the current state of the profile, and removes all but the latest [`MAX_SNAPSHOTS`]
+/// snapshots.
+pub async fn take(log: &slog::Logger, id: Uuid) -> Result<()> {
+    let _guard = super::watcher::suppress(id);
+    let root = profile_path(id);
+    let dir = snapshots_dir(id);
+    tokio::task::block_in_place(|| {
+        let previous = read_latest(&dir)?;
+        let snapshot = scan(log, &root, previous.as_ref())?;
+
+        std::fs::create_dir_all(&dir)?;
+        let mut file = tempfile::NamedTempFile::new_in(&dir)?;
+        let mut wtr = std::io::BufWriter::new(file.as_file_mut());
+        bincode::encode_into_std_write(&snapshot, &mut wtr, BINCODE_CONFIG)?;
+        wtr.flush()?;
+        drop(wtr);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        file.persist(dir.join(timestamp.to_string()))?;
+
+        let snapshots = list_snapshots(&dir)?;
+        for path in &snapshots[..snapshots.len().saturating_sub(MAX_SNAPSHOTS)] {
+            debug!(log, "Pruning snapshot {path:?}");
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Compares the profile against the snapshot taken `launches_ago` launches before the last one,
+/// so `0` reports the changes since the last launch. Returns `None` if there is no such snapshot.
+///
+/// Every snapshot is complete, so the profile is compared against the base snapshot itself rather
+/// than against the snapshots taken after it, which would miss changes made before them.
+pub async fn changes_since_launch(
+    log: &slog::Logger,
+    id: Uuid,
+    launches_ago: usize,
+) -> Result<Option<BTreeMap<String, Status>>> {
+    let root = profile_path(id);
+    let dir = snapshots_dir(id);
+    tokio::task::block_in_place(|| {
+        let snapshots = list_snapshots(&dir)?;
+        let Some(i) = snapshots.len().checked_sub(launches_ago + 1) else {
+            return Ok(None);
+        };
+        let base = read_snapshot(&snapshots[i])?;
+        // the latest snapshot is the best source of cached hashes
+        let latest = match &snapshots[i + 1..] {
+            [.., path] => Some(read_snapshot(path)?),
+            [] => None,
+        };
+        let current = scan(log, &root, Some(latest.as_ref().unwrap_or(&base)))?;
+        Ok(Some(diff(&base, &current)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::installing::Status;
+
+    use super::{diff, Entry, Snapshot};
+
+    fn file(hash: u8) -> Entry {
+        Entry::File {
+            len: 1,
+            modified: (0, 0),
+            hash: [hash; 32],
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Snapshot {
+            entries: [
+                ("a".to_owned(), file(0)),
+                ("b".to_owned(), file(0)),
+                ("c".to_owned(), file(0)),
+                ("d".to_owned(), file(0)),
+            ]
+            .into(),
+        };
+        let new = Snapshot {
+            entries: [
+                ("a".to_owned(), file(0)),
+                ("b".to_owned(), file(1)),
+                ("c".to_owned(), Entry::Directory),
+                ("e".to_owned(), Entry::Directory),
+                ("e/f".to_owned(), file(0)),
+            ]
+            .into(),
+        };
+        assert_eq!(
+            diff(&old, &new).into_iter().collect::<Vec<_>>(),
+            [
+                ("b".to_owned(), Status::ContentModified),
+                ("c".to_owned(), Status::TypeChanged),
+                ("d".to_owned(), Status::Deleted),
+                ("e".to_owned(), Status::Created),
+            ]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Opt-in watching of profile directories for modifications made outside of Manderrow, so that
+//! the frontend can refresh anything it derived from the profile's contents.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use notify::{RecursiveMode, Watcher as _};
+use parking_lot::Mutex;
+use slog::{debug, error};
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+use super::{is_record_dir_name, profile_path, PROFILES_DIR};
+
+/// The name of the event emitted with the id of a profile that was modified externally.
+pub const EVENT: &str = "profile_modified";
+
+/// How long the profile must go without modification before the event is emitted.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Profiles whose modifications should not be reported because we are the ones making them.
+static SUPPRESSED: LazyLock<Mutex<HashMap<Uuid, usize>>> = LazyLock::new(Default::default);
+
+#[must_use]
+pub struct SuppressGuard(Uuid);
+
+impl Drop for SuppressGuard {
+    fn drop(&mut self) {
+        super::provenance::invalidate(self.0);
+        let mut suppressed = SUPPRESSED.lock();
+        if let Some(count) = suppressed.get_mut(&self.0) {
+            *count -= 1;
+            if *count == 0 {
+                suppressed.remove(&self.0);
+            }
+        }
+    }
+}
+
+/// Suppresses modification events for the profile until the guard is dropped.
+pub fn suppress(id: Uuid) -> SuppressGuard {
+    *SUPPRESSED.lock().entry(id).or_default() += 1;
+    SuppressGuard(id)
+}
+
+fn is_suppressed(id: Uuid) -> bool {
+    SUPPRESSED.lock().contains_key(&id)
+}
+
+/// Returns the profile that `path` belongs to, unless it is in one of the directories the
+/// profile's launch logs and snapshots are kept in, which change with every launch.
+fn profile_id_of_path(path: &Path) -> Option<Uuid> {
+    let rel_path = path.strip_prefix(&*PROFILES_DIR).ok()?;
+    let mut components = rel_path.components();
+    let id = components.next()?.as_os_str().to_str()?;
+    if components
+        .next()
+        .is_some_and(|c| is_record_dir_name(c.as_os_str()))
+    {
+        return None;
+    }
+    Uuid::try_parse(id).ok()
+}
+
+struct Inner {
+    watcher: notify::RecommendedWatcher,
+    watched: HashSet<Uuid>,
+}
+
+impl Inner {
+    fn new(app: AppHandle) -> Result<Self> {
+        let (tx, rx) = flume::unbounded();
+        let watcher =
+            notify::recommended_watcher(move |r: notify::Result<notify::Event>| match r {
+                Ok(event) if event.kind.is_access() => {}
+                Ok(event) => {
+                    for path in event.paths {
+                        if let Some(id) = profile_id_of_path(&path) {
+                            super::provenance::invalidate(id);
+                            if !is_suppressed(id) {
+                                _ = tx.send(id);
+                            }
+                        }
+                    }
+                }
+                Err(e) => error!(slog_scope::logger(), "Profile watcher failed: {e}"),
+            })?;
+        tauri::async_runtime::spawn(emit_debounced(app, rx));
+        Ok(Self {
+            watcher,
+            watched: HashSet::new(),
+        })
+    }
+}
+
+async fn emit_debounced(app: AppHandle, rx: flume::Receiver<Uuid>) {
+    let mut pending = HashSet::new();
+    loop {
+        let r = if pending.is_empty() {
+            Ok(rx.recv_async().await)
+        } else {
+            tokio::time::timeout(DEBOUNCE, rx.recv_async()).await
+        };
+        match r {
+            Ok(Ok(id)) => {
+                pending.insert(id);
+            }
+            // the watcher was dropped
+            Ok(Err(flume::RecvError::Disconnected)) => return,
+            Err(_) => {
+                for id in pending.drain() {
+                    debug!(slog_scope::logger(), "Profile {id} was modified externally");
+                    if let Err(e) = app.emit(EVENT, id) {
+                        error!(slog_scope::logger(), "Failed to emit {EVENT} event: {e}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Managed state holding the watcher, which is only created once a profile is first watched.
+#[derive(Default)]
+pub struct ProfileWatcher {
+    inner: Mutex<Option<Inner>>,
+}
+
+impl ProfileWatcher {
+    pub fn watch(&self, app: &AppHandle, id: Uuid) -> Result<()> {
+        let mut inner = self.inner.lock();
+        let inner = match &mut *inner {
+            Some(t) => t,
+            None => inner.insert(Inner::new(app.clone())?),
+        };
+        if !inner.watched.contains(&id) {
+            inner
+                .watcher
+                .watch(&profile_path(id), RecursiveMode::Recursive)?;
+            inner.watched.insert(id);
+        }
+        Ok(())
+    }
+
+    pub fn unwatch(&self, id: Uuid) -> Result<()> {
+        let mut guard = self.inner.lock();
+        if let Some(inner) = &mut *guard {
+            if inner.watched.remove(&id) {
+                inner.watcher.unwatch(&profile_path(id))?;
+            }
+            if inner.watched.is_empty() {
+                // release the watcher's resources
+                *guard = None;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use tauri::{ipc::Response, AppHandle, Emitter};
 
 use crate::CommandError;
@@ -28,6 +30,37 @@ pub async fn update_settings(
     settings.as_mut().map_err(|e| e.clone())?.update(patch);
     let settings = settings.downgrade();
     let settings = settings.as_ref().unwrap();
+    super::apply(settings);
+    app.emit(EVENT, settings.defaulted())
+        .map_err(anyhow::Error::from)?;
+    super::write(settings).await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn export_settings(
+    settings: SettingsState<'_>,
+    path: PathBuf,
+) -> Result<(), CommandError> {
+    let settings = settings.read().await;
+    let settings = settings.as_ref().map_err(Clone::clone)?;
+    super::export(settings, &path).await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn import_settings(
+    app: AppHandle,
+    settings: SettingsState<'_>,
+    path: PathBuf,
+) -> Result<(), CommandError> {
+    let imported = super::import(&path).await?;
+    let mut settings = settings.write().await;
+    // replaces the settings even if they previously failed to load
+    *settings = Ok(imported);
+    let settings = settings.downgrade();
+    let settings = settings.as_ref().unwrap();
+    super::apply(settings);
     app.emit(EVENT, settings.defaulted())
         .map_err(anyhow::Error::from)?;
     super::write(settings).await?;
@@ -3,9 +3,11 @@
 //! The backend performs final validation, makes the modified settings active, and finally writes
 //! them to disk.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
 
+use anyhow::{ensure, Context as _};
+
 use tauri::State;
 use tokio::sync::RwLock;
 use triomphe::Arc;
@@ -26,26 +28,69 @@ fn read() -> anyhow::Result<Option<Settings>> {
         Err(e) if e.is_not_found() => return Ok(None),
         Err(e) => return Err(e.into()),
     };
-    let SettingsOnDisk {
-        default_game,
-        open_console_on_launch,
-    } = simd_json::from_slice::<SettingsOnDisk>(&mut bytes)?;
-    Ok(Some(Settings {
-        default_game,
-        open_console_on_launch,
-    }))
+    Ok(Some(
+        simd_json::from_slice::<SettingsOnDisk>(&mut bytes)?.into(),
+    ))
 }
 
-async fn write(
-    &Settings {
-        ref default_game,
-        open_console_on_launch,
-    }: &Settings,
-) -> anyhow::Result<()> {
-    let settings = SettingsOnDisk {
-        default_game: default_game.clone(),
-        open_console_on_launch,
-    };
+impl From<SettingsOnDisk> for Settings {
+    fn from(
+        SettingsOnDisk {
+            default_game,
+            open_console_on_launch,
+            watch_profiles,
+            launch_via_steam_url,
+            update_loader_on_launch,
+            max_concurrent_downloads,
+            max_concurrent_cpu_tasks,
+            download_attempts,
+            dedupe_installed_files,
+        }: SettingsOnDisk,
+    ) -> Self {
+        Settings {
+            default_game,
+            open_console_on_launch,
+            watch_profiles,
+            launch_via_steam_url,
+            update_loader_on_launch,
+            max_concurrent_downloads,
+            max_concurrent_cpu_tasks,
+            download_attempts,
+            dedupe_installed_files,
+        }
+    }
+}
+
+impl From<&Settings> for SettingsOnDisk {
+    fn from(
+        &Settings {
+            ref default_game,
+            open_console_on_launch,
+            watch_profiles,
+            launch_via_steam_url,
+            update_loader_on_launch,
+            max_concurrent_downloads,
+            max_concurrent_cpu_tasks,
+            download_attempts,
+            dedupe_installed_files,
+        }: &Settings,
+    ) -> Self {
+        SettingsOnDisk {
+            default_game: default_game.clone(),
+            open_console_on_launch,
+            watch_profiles,
+            launch_via_steam_url,
+            update_loader_on_launch,
+            max_concurrent_downloads,
+            max_concurrent_cpu_tasks,
+            download_attempts,
+            dedupe_installed_files,
+        }
+    }
+}
+
+async fn write(settings: &Settings) -> anyhow::Result<()> {
+    let settings = SettingsOnDisk::from(settings);
     tokio::task::spawn_blocking(move || {
         let path = get_path();
         std::fs::create_dir_all(path.parent().unwrap())?;
@@ -57,6 +102,59 @@ async fn write(
     Ok(())
 }
 
+/// The current version of the settings export format. Bump this whenever a change is made that
+/// older versions of the app would misinterpret.
+const EXPORT_VERSION: u32 = 1;
+
+/// A portable copy of the settings. Machine-specific settings, such as absolute paths, must not
+/// be included unless the user is prompted to remap them on import.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct SettingsExport {
+    version: u32,
+    settings: SettingsOnDisk,
+}
+
+async fn export(settings: &Settings, path: &Path) -> anyhow::Result<()> {
+    let export = SettingsExport {
+        version: EXPORT_VERSION,
+        settings: settings.into(),
+    };
+    tokio::fs::write(path, serde_json::to_vec_pretty(&export)?)
+        .await
+        .with_context(|| format!("Failed to write settings to {path:?}"))?;
+    Ok(())
+}
+
+async fn import(path: &Path) -> anyhow::Result<Settings> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read settings from {path:?}"))?;
+
+    #[derive(serde::Deserialize)]
+    struct Header {
+        version: u32,
+    }
+    let Header { version } =
+        serde_json::from_slice(&bytes).context("Not a settings export file")?;
+    ensure!(
+        version <= EXPORT_VERSION,
+        "The settings were exported by a newer version of Manderrow (format version {version}, but only up to {EXPORT_VERSION} is supported)"
+    );
+
+    let SettingsExport { settings, .. } =
+        serde_json::from_slice(&bytes).context("Invalid settings export file")?;
+    let settings = Settings::from(settings);
+
+    if let Some(game) = settings.default_game().value {
+        ensure!(
+            crate::games::games_by_id()?.contains_key(game.as_str()),
+            "Unrecognized default game {game:?}"
+        );
+    }
+
+    Ok(settings)
+}
+
 static PATH: LazyLock<PathBuf> =
     LazyLock::new(|| config_dir().join(format!("{}.json", product_name())));
 
@@ -65,18 +163,32 @@ fn get_path() -> &'static PathBuf {
 }
 
 pub fn try_read() -> SettingsStateInner {
-    Arc::new(RwLock::new(match read() {
+    let settings = match read() {
         Ok(Some(t)) => Ok(t),
         Ok(None) => Ok(Default::default()),
         Err(e) => Err(CommandError::from(e)),
-    }))
+    };
+    if let Ok(settings) = &settings {
+        apply(settings);
+    }
+    Arc::new(RwLock::new(settings))
+}
+
+/// Makes the settings that are read by other subsystems, rather than on demand, take effect.
+fn apply(settings: &Settings) {
+    crate::governor::configure(
+        settings.max_concurrent_downloads().value,
+        settings.max_concurrent_cpu_tasks().value,
+    );
+    crate::util::http::configure_download_attempts(settings.download_attempts().value);
+    crate::installing::dedupe::configure(settings.dedupe_installed_files().value);
 }
 
 #[derive(Debug, Clone, Copy, serde::Serialize)]
 pub struct Setting<T> {
-    value: T,
+    pub value: T,
     #[serde(rename = "isDefault")]
-    is_default: bool,
+    pub is_default: bool,
 }
 
 impl<T: ToOwned> Setting<T> {
@@ -96,7 +208,7 @@ enum Change<T> {
     Override(T),
 }
 
-#[manderrow_macros::settings(sections = [general, launching])]
+#[manderrow_macros::settings(sections = [general, launching, performance])]
 struct Settings {
     #[section(general)]
     #[default(None)]
@@ -104,11 +216,58 @@ struct Settings {
     #[ref_by(Option<&'a String>, Option::as_ref)]
     default_game: Option<String>,
 
+    // Off by default because of the watcher's resource cost on large profiles.
+    #[section(general)]
+    #[default(false)]
+    #[input(toggle)]
+    #[ref_by(bool, bool::clone)]
+    watch_profiles: bool,
+
+    // Off by default since a linked file modified in one profile is modified in all of them.
+    #[section(general)]
+    #[default(false)]
+    #[input(toggle)]
+    #[ref_by(bool, bool::clone)]
+    dedupe_installed_files: bool,
+
     #[section(launching)]
     #[default(false)]
     #[input(toggle)]
     #[ref_by(bool, bool::clone)]
     open_console_on_launch: bool,
+
+    // Starts Steam games as if from the Steam library instead of with `-applaunch`.
+    #[section(launching)]
+    #[default(false)]
+    #[input(toggle)]
+    #[ref_by(bool, bool::clone)]
+    launch_via_steam_url: bool,
+
+    // Off by default since an update can break mods that haven't caught up with it.
+    #[section(launching)]
+    #[default(false)]
+    #[input(toggle)]
+    #[ref_by(bool, bool::clone)]
+    update_loader_on_launch: bool,
+
+    // `None` uses the default derived from the machine. See `crate::governor`.
+    #[section(performance)]
+    #[default(None)]
+    #[input(number)]
+    #[ref_by(Option<u32>, Option::clone)]
+    max_concurrent_downloads: Option<u32>,
+
+    #[section(performance)]
+    #[default(None)]
+    #[input(number)]
+    #[ref_by(Option<u32>, Option::clone)]
+    max_concurrent_cpu_tasks: Option<u32>,
+
+    #[section(performance)]
+    #[default(None)]
+    #[input(number)]
+    #[ref_by(Option<u32>, Option::clone)]
+    download_attempts: Option<u32>,
 }
 
 /// A representation of settings that must retain complete backwards compatibility. Any necessary
@@ -120,4 +279,25 @@ pub struct SettingsOnDisk {
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     open_console_on_launch: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    watch_profiles: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    launch_via_steam_url: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    update_loader_on_launch: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_concurrent_downloads: Option<Option<u32>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_concurrent_cpu_tasks: Option<Option<u32>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    download_attempts: Option<Option<u32>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dedupe_installed_files: Option<bool>,
 }
@@ -0,0 +1,110 @@
+//! Detection of games installed from GOG.
+//!
+//! On Windows, GOG Galaxy and GOG's offline installers both register the games they install. On
+//! Linux, GOG games are installed through Heroic, or by running the offline installer in a Wine
+//! prefix, in which case the only trace of them is the `goggame-<id>.info` file every GOG game
+//! ships in its directory.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use slog::debug;
+
+use crate::paths::home_dir;
+use crate::util::IoErrorKindExt as _;
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GogInstall {
+    pub install_path: PathBuf,
+    /// The Wine prefix the game is installed in, if it isn't native.
+    pub wine_prefix: Option<PathBuf>,
+}
+
+/// The directories, relative to a Wine prefix, that GOG games are installed in by default.
+const PREFIX_GAME_DIRS: &[&str] = &[
+    "drive_c/GOG Games",
+    "drive_c/Program Files (x86)/GOG Galaxy/Games",
+    "drive_c/Program Files/GOG Galaxy/Games",
+];
+
+/// Looks for the GOG game with the product id `id`.
+pub async fn find_gog_game(log: &slog::Logger, id: &str) -> Result<Option<GogInstall>> {
+    #[cfg(windows)]
+    if let Some(install_path) = find_in_registry(log, id)? {
+        return Ok(Some(GogInstall {
+            install_path,
+            wine_prefix: None,
+        }));
+    }
+
+    if cfg!(target_os = "linux") {
+        if let Some(install) = super::heroic::find_gog_game(log, id).await? {
+            return Ok(Some(GogInstall {
+                install_path: install.install_path,
+                wine_prefix: install.wine_prefix,
+            }));
+        }
+        for prefix in wine_prefixes().await? {
+            if let Some(install_path) = find_in_prefix(&prefix, id).await? {
+                debug!(log, "Found GOG game {id:?} at {install_path:?}");
+                return Ok(Some(GogInstall {
+                    install_path,
+                    wine_prefix: Some(prefix),
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(windows)]
+fn find_in_registry(log: &slog::Logger, id: &str) -> Result<Option<PathBuf>> {
+    use registry::{Data, Hive, Security};
+    let key = format!("SOFTWARE\\WOW6432Node\\GOG.com\\Games\\{id}");
+    let regkey = match Hive::LocalMachine.open(key, Security::Read) {
+        Ok(t) => t,
+        Err(e) => {
+            debug!(log, "GOG game {id:?} is not registered: {e}");
+            return Ok(None);
+        }
+    };
+    match regkey.value("path")? {
+        Data::String(s) | Data::ExpandString(s) => Ok(Some(PathBuf::from(s.to_string()?))),
+        _ => Err(anyhow::anyhow!("Unexpected data type in registry")),
+    }
+}
+
+/// Returns the default Wine prefix and those Heroic creates for the games it installs.
+async fn wine_prefixes() -> Result<Vec<PathBuf>> {
+    let mut prefixes = vec![home_dir().join(".wine")];
+    let heroic_prefixes = home_dir().join("Games/Heroic/Prefixes");
+    let mut iter = match tokio::fs::read_dir(&heroic_prefixes).await {
+        Ok(iter) => iter,
+        Err(e) if e.is_not_found() => return Ok(prefixes),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(e) = iter.next_entry().await? {
+        prefixes.push(e.path());
+    }
+    Ok(prefixes)
+}
+
+async fn find_in_prefix(prefix: &Path, id: &str) -> Result<Option<PathBuf>> {
+    let info_name = format!("goggame-{id}.info");
+    for dir in PREFIX_GAME_DIRS {
+        let mut iter = match tokio::fs::read_dir(prefix.join(dir)).await {
+            Ok(iter) => iter,
+            Err(e) if e.is_not_found() => continue,
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(e) = iter.next_entry().await? {
+            let path = e.path();
+            if tokio::fs::try_exists(path.join(&info_name)).await? {
+                return Ok(Some(path));
+            }
+        }
+    }
+    Ok(None)
+}
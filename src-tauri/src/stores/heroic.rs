@@ -0,0 +1,203 @@
+//! Detection of Epic Games Store games installed through Heroic Games Launcher or Legendary, and
+//! of GOG games installed through Heroic, which is how those games are usually run on Linux.
+//!
+//! Heroic bundles its own copy of Legendary, so both keep the same `installed.json`, just in
+//! different places. Heroic additionally keeps a config per game, which holds its Wine prefix.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use slog::{debug, warn};
+
+use crate::paths::home_dir;
+use crate::util::IoErrorKindExt as _;
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeroicInstall {
+    /// Legendary's name for the game.
+    pub app_name: String,
+    pub install_path: PathBuf,
+    /// The Wine prefix the game runs in, if Heroic manages it and the game isn't native.
+    pub wine_prefix: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize)]
+struct InstalledGame {
+    app_name: String,
+    install_path: PathBuf,
+    #[serde(default)]
+    platform: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct GogInstalledFile {
+    installed: Vec<GogInstalledGame>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GogInstalledGame {
+    app_name: String,
+    #[serde(rename = "install_path")]
+    install_path: PathBuf,
+    #[serde(default)]
+    platform: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct GameMetadataFile {
+    metadata: GameMetadata,
+}
+
+#[derive(serde::Deserialize)]
+struct GameMetadata {
+    #[serde(default)]
+    namespace: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HeroicGameConfig {
+    #[serde(default)]
+    wine_prefix: Option<PathBuf>,
+}
+
+/// Returns the directories Heroic keeps its configuration in, paired with the Legendary
+/// configuration directory of each, followed by that of a standalone Legendary install.
+fn config_dirs() -> Vec<(Option<PathBuf>, PathBuf)> {
+    let config_dir = dirs::config_dir().unwrap_or_else(|| home_dir().join(".config"));
+    let mut dirs = Vec::with_capacity(3);
+    for heroic in [
+        config_dir.join("heroic"),
+        home_dir().join(".var/app/com.heroicgameslauncher.hgl/config/heroic"),
+    ] {
+        let legendary = heroic.join("legendaryConfig/legendary");
+        dirs.push((Some(heroic), legendary));
+    }
+    dirs.push((None, config_dir.join("legendary")));
+    dirs
+}
+
+/// Reads the Wine prefix of `app_name` from Heroic's config of the game.
+async fn read_wine_prefix(heroic_dir: &Path, app_name: &str) -> Result<Option<PathBuf>> {
+    let path = heroic_dir
+        .join("GamesConfig")
+        .join(format!("{app_name}.json"));
+    // the file also holds keys other than the game's config, like `version`
+    Ok(read_json::<serde_json::Value>(&path)
+        .await?
+        .and_then(|mut value| value.get_mut(app_name).map(|v| v.take()))
+        .and_then(|value| serde_json::from_value::<HeroicGameConfig>(value).ok())
+        .and_then(|config| config.wine_prefix))
+}
+
+fn is_windows_build(platform: Option<&str>) -> bool {
+    platform.is_none_or(|p| p.eq_ignore_ascii_case("windows"))
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .with_context(|| format!("Invalid JSON in {path:?}")),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {path:?}")),
+    }
+}
+
+/// Returns whether the Epic `store_identifier`, which may be an app name, a catalog namespace, or
+/// a catalog item id, refers to the installed game `app_name`.
+async fn matches_identifier(legendary_dir: &Path, app_name: &str, store_identifier: &str) -> bool {
+    if app_name == store_identifier {
+        return true;
+    }
+    let path = legendary_dir
+        .join("metadata")
+        .join(format!("{app_name}.json"));
+    match read_json::<GameMetadataFile>(&path).await {
+        Ok(Some(GameMetadataFile { metadata })) => {
+            metadata.namespace.as_deref() == Some(store_identifier)
+                || metadata.id.as_deref() == Some(store_identifier)
+        }
+        _ => false,
+    }
+}
+
+/// Looks for the Epic Games Store game identified by `store_identifier` among the games installed
+/// through Heroic or Legendary.
+pub async fn find_epic_game(
+    log: &slog::Logger,
+    store_identifier: &str,
+) -> Result<Option<HeroicInstall>> {
+    for (heroic_dir, legendary_dir) in config_dirs() {
+        let path = legendary_dir.join("installed.json");
+        let installed = match read_json::<HashMap<String, InstalledGame>>(&path).await {
+            Ok(Some(t)) => t,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(log, "{e:#}");
+                continue;
+            }
+        };
+        for game in installed.into_values() {
+            if !matches_identifier(&legendary_dir, &game.app_name, store_identifier).await {
+                continue;
+            }
+            let wine_prefix = match &heroic_dir {
+                Some(heroic_dir) if is_windows_build(game.platform.as_deref()) => {
+                    read_wine_prefix(heroic_dir, &game.app_name).await?
+                }
+                _ => None,
+            };
+            debug!(
+                log,
+                "Found {store_identifier:?} installed as {:?} at {:?}",
+                game.app_name,
+                game.install_path
+            );
+            return Ok(Some(HeroicInstall {
+                app_name: game.app_name,
+                install_path: game.install_path,
+                wine_prefix,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Looks for the GOG game with the product id `id` among the games installed through Heroic.
+pub async fn find_gog_game(log: &slog::Logger, id: &str) -> Result<Option<HeroicInstall>> {
+    for (heroic_dir, _) in config_dirs() {
+        let Some(heroic_dir) = heroic_dir else {
+            continue;
+        };
+        let path = heroic_dir.join("gog_store").join("installed.json");
+        let installed = match read_json::<GogInstalledFile>(&path).await {
+            Ok(Some(t)) => t.installed,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(log, "{e:#}");
+                continue;
+            }
+        };
+        let Some(game) = installed.into_iter().find(|game| game.app_name == id) else {
+            continue;
+        };
+        let wine_prefix = if is_windows_build(game.platform.as_deref()) {
+            read_wine_prefix(&heroic_dir, &game.app_name).await?
+        } else {
+            None
+        };
+        debug!(log, "Found GOG game {id:?} at {:?}", game.install_path);
+        return Ok(Some(HeroicInstall {
+            app_name: game.app_name,
+            install_path: game.install_path,
+            wine_prefix,
+        }));
+    }
+    Ok(None)
+}
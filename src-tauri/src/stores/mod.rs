@@ -1 +1,3 @@
+pub mod gog;
+pub mod heroic;
 pub mod steam;
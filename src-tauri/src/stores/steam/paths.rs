@@ -4,6 +4,7 @@ use std::path::{Path, PathBuf};
 use anyhow::{anyhow, bail, ensure, Result};
 
 use crate::paths::home_dir;
+use crate::util::IoErrorKindExt as _;
 
 #[cfg(windows)]
 pub fn get_steam_install_path_from_registry() -> Result<PathBuf> {
@@ -109,76 +110,109 @@ pub async fn resolve_steamapps_directory() -> Result<PathBuf> {
     Err(anyhow::Error::msg(ERROR_MSG))
 }
 
+/// Returns the `steamapps` directories of every Steam library, starting with the one in Steam's
+/// own directory. Libraries on drives that aren't currently attached are included.
 pub async fn resolve_steam_library_folders() -> Result<Vec<PathBuf>> {
     let steamapps_dir = resolve_steamapps_directory().await?;
     let mut locations = vec![steamapps_dir.clone()];
+    let mut canonical_locations = vec![canonicalize_or_keep(&steamapps_dir).await];
 
     let mut iter = tokio::fs::read_dir(&steamapps_dir).await?;
-    let mut path_buf = steamapps_dir;
     while let Some(e) = iter.next_entry().await? {
         let name = e.file_name();
         if name.eq_ignore_ascii_case("libraryfolders.vdf") {
-            path_buf.push(name);
-            tokio::task::block_in_place(|| {
-                let mut rdr = vdf::Reader::new(std::fs::File::open(&path_buf)?);
-                let Some(vdf::Event::GroupStart { key, .. }) = rdr.next()? else {
-                    bail!("Invalid libraryfolders.vdf file: Invalid VDF file")
-                };
-                if !key.s.eq_ignore_ascii_case(b"libraryfolders") {
-                    bail!("Invalid libraryfolders.vdf file: Unexpected root key")
+            let roots = tokio::task::block_in_place(|| {
+                parse_library_folders(std::fs::File::open(e.path())?)
+            })?;
+            for root in roots {
+                let location = root.join("steamapps");
+                // the library in Steam's own directory is listed too, maybe by another path
+                let canonical = canonicalize_or_keep(&location).await;
+                if !canonical_locations.contains(&canonical) {
+                    canonical_locations.push(canonical);
+                    locations.push(location);
                 }
+            }
+        }
+    }
+    Ok(locations)
+}
+
+async fn canonicalize_or_keep(path: &Path) -> PathBuf {
+    tokio::fs::canonicalize(path)
+        .await
+        .unwrap_or_else(|_| path.to_owned())
+}
+
+/// Parses the roots of the libraries listed in a `libraryfolders.vdf` file, in both the current
+/// format, where each library is a group with a `path`, and the legacy one, where each library is
+/// just its path.
+fn parse_library_folders(rdr: impl std::io::Read) -> Result<Vec<PathBuf>> {
+    let mut roots = Vec::new();
+    let mut rdr = vdf::Reader::new(rdr);
+    let Some(vdf::Event::GroupStart { key, .. }) = rdr.next()? else {
+        bail!("Invalid libraryfolders.vdf file: Invalid VDF file")
+    };
+    if !key.s.eq_ignore_ascii_case(b"libraryfolders") {
+        bail!("Invalid libraryfolders.vdf file: Unexpected root key")
+    }
+    while let Some(event) = rdr.next()? {
+        match event {
+            vdf::Event::GroupEnd { .. } => break,
+            vdf::Event::GroupStart { .. } => {
+                let mut depth = 0;
                 while let Some(event) = rdr.next()? {
                     match event {
-                        vdf::Event::GroupEnd { .. } => break,
-                        vdf::Event::GroupStart { .. } => {
-                            let mut depth = 0;
-                            while let Some(event) = rdr.next()? {
-                                match event {
-                                    vdf::Event::GroupStart { .. } => depth += 1,
-                                    vdf::Event::GroupEnd { .. } if depth == 0 => break,
-                                    vdf::Event::GroupEnd { .. } => depth -= 1,
-                                    vdf::Event::Item { key, value, .. } if key.s == b"path" => {
-                                        locations.push(value.validate_utf8()?.s.into());
-                                    }
-                                    vdf::Event::Item { .. } => {}
-                                    vdf::Event::Comment { .. } => {}
-                                    vdf::Event::FileEnd { .. } => bail!("Unexpected EOF"),
-                                }
-                            }
-                        }
-                        vdf::Event::Item { value, .. } => {
-                            locations.push(value.validate_utf8()?.s.into());
+                        vdf::Event::GroupStart { .. } => depth += 1,
+                        vdf::Event::GroupEnd { .. } if depth == 0 => break,
+                        vdf::Event::GroupEnd { .. } => depth -= 1,
+                        vdf::Event::Item { key, value, .. } if depth == 0 && key.s == b"path" => {
+                            roots.push(value.validate_utf8()?.s.into());
                         }
+                        vdf::Event::Item { .. } => {}
                         vdf::Event::Comment { .. } => {}
-                        vdf::Event::FileEnd { .. } => {}
+                        vdf::Event::FileEnd { .. } => bail!("Unexpected EOF"),
                     }
                 }
-                Result::Ok(())
-            })?;
-            path_buf.pop();
+            }
+            // legacy entries, like `"1" "D:\\SteamLibrary"`, among keys like `ContentStatsID`
+            vdf::Event::Item { key, value, .. } if key.s.iter().all(u8::is_ascii_digit) => {
+                roots.push(value.validate_utf8()?.s.into());
+            }
+            vdf::Event::Item { .. } => {}
+            vdf::Event::Comment { .. } => {}
+            vdf::Event::FileEnd { .. } => {}
         }
     }
-    Ok(locations)
+    Ok(roots)
 }
 
-pub async fn resolve_steam_app_manifest(game_id: &str) -> Result<PathBuf> {
+/// Returns the path to the game's app manifest in whichever library it is installed in, or
+/// `None` if it isn't installed.
+pub async fn find_steam_app_manifest(game_id: &str) -> Result<Option<PathBuf>> {
     let target_name = format!("appmanifest_{game_id}.acf");
 
-    let library_folders = resolve_steam_library_folders().await?;
-    for (i, path) in library_folders.iter().enumerate() {
-        let mut iter = tokio::fs::read_dir(&path).await?;
+    for library in resolve_steam_library_folders().await? {
+        let mut iter = match tokio::fs::read_dir(&library).await {
+            Ok(iter) => iter,
+            // the library's drive isn't attached
+            Err(e) if e.is_not_found() => continue,
+            Err(e) => return Err(e.into()),
+        };
         while let Some(e) = iter.next_entry().await? {
             let name = e.file_name();
             if name.eq_ignore_ascii_case(&target_name) {
-                let mut path = library_folders.into_iter().nth(i).unwrap();
-                path.push(name);
-                return Ok(path);
+                return Ok(Some(library.join(name)));
             }
         }
     }
-    Err(anyhow!(
-        "Unable to locate game app manifest in {library_folders:?}"
-    ))
+    Ok(None)
+}
+
+pub async fn resolve_steam_app_manifest(game_id: &str) -> Result<PathBuf> {
+    find_steam_app_manifest(game_id)
+        .await?
+        .ok_or_else(|| anyhow!("Unable to locate app manifest of game {game_id:?}"))
 }
 
 pub async fn resolve_steam_app_compat_data_directory(game_id: &str) -> Result<PathBuf> {
@@ -189,8 +223,28 @@ pub async fn resolve_steam_app_compat_data_directory(game_id: &str) -> Result<Pa
     Ok(path)
 }
 
+/// Returns the `steamapps/common/<installdir>` directory the game is installed in, in whichever
+/// library that is, or `None` if it isn't installed.
+pub async fn find_steam_app_install_directory(game_id: &str) -> Result<Option<PathBuf>> {
+    let Some(manifest) = find_steam_app_manifest(game_id).await? else {
+        return Ok(None);
+    };
+    let path = read_app_install_directory(game_id, manifest)?;
+    // the manifest may be left over from an install that was deleted by hand
+    if tokio::fs::try_exists(&path).await? {
+        Ok(Some(path))
+    } else {
+        Ok(None)
+    }
+}
+
 pub async fn resolve_steam_app_install_directory(game_id: &str) -> Result<PathBuf> {
-    let manifest = resolve_steam_app_manifest(game_id).await?;
+    find_steam_app_install_directory(game_id)
+        .await?
+        .ok_or_else(|| anyhow!("Game {game_id:?} is not installed"))
+}
+
+fn read_app_install_directory(game_id: &str, manifest: PathBuf) -> Result<PathBuf> {
     tokio::task::block_in_place(|| {
         let mut rdr = vdf::Reader::new(std::fs::File::open(&manifest)?);
         let Some(vdf::Event::GroupStart { key, .. }) = rdr.next()? else {
@@ -232,3 +286,51 @@ pub async fn resolve_steam_app_install_directory(game_id: &str) -> Result<PathBu
         ))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::parse_library_folders;
+
+    #[test]
+    fn test_parse_library_folders() {
+        let vdf = br#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"apps"
+		{
+			"632360"		"1234"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games/SteamLibrary"
+	}
+}
+"#;
+        assert_eq!(
+            parse_library_folders(&vdf[..]).unwrap(),
+            [
+                PathBuf::from("/home/user/.local/share/Steam"),
+                PathBuf::from("/mnt/games/SteamLibrary"),
+            ]
+        );
+
+        let legacy = br#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1700000000"
+	"ContentStatsID"		"-123"
+	"1"		"/mnt/games/SteamLibrary"
+}
+"#;
+        assert_eq!(
+            parse_library_folders(&legacy[..]).unwrap(),
+            [PathBuf::from("/mnt/games/SteamLibrary")]
+        );
+    }
+}
@@ -1,9 +1,16 @@
+use std::ffi::OsString;
 use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Result};
 use slog::{debug, trace};
 
-use super::paths::{resolve_steam_app_compat_data_directory, resolve_steam_app_install_directory};
+use crate::util::IoErrorKindExt as _;
+
+use super::paths::{
+    resolve_steam_app_compat_data_directory, resolve_steam_app_install_directory,
+    resolve_steam_directory, resolve_steam_library_folders,
+};
 
 pub async fn uses_proton(log: &slog::Logger, game_id: &str) -> Result<bool> {
     if cfg!(target_os = "linux") {
@@ -25,6 +32,235 @@ pub async fn uses_proton(log: &slog::Logger, game_id: &str) -> Result<bool> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum CompatToolKind {
+    /// A Proton build, either shipped by Valve or custom.
+    Proton,
+    /// A Wine installation from the system.
+    Wine,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CompatTool {
+    pub name: String,
+    /// The directory of a Proton build, or the `wine` executable.
+    pub path: PathBuf,
+    pub kind: CompatToolKind,
+}
+
+/// Custom compatibility tools installed for all users, in addition to the ones in Steam's own
+/// `compatibilitytools.d`.
+const SYSTEM_COMPAT_TOOLS_DIRS: &[&str] = &[
+    "/usr/share/steam/compatibilitytools.d",
+    "/usr/local/share/steam/compatibilitytools.d",
+];
+
+/// Lists the Proton builds in the subdirectories of `dir` whose names pass `filter`. A Proton
+/// build is recognized by the `proton` script at its root.
+async fn find_proton_builds(
+    dir: &Path,
+    filter: impl Fn(&str) -> bool,
+    buf: &mut Vec<CompatTool>,
+) -> Result<()> {
+    let mut iter = match tokio::fs::read_dir(dir).await {
+        Ok(iter) => iter,
+        Err(e) if e.is_not_found() => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(e) = iter.next_entry().await? {
+        let Ok(name) = e.file_name().into_string() else {
+            continue;
+        };
+        let path = e.path();
+        if !filter(&name) || !tokio::fs::try_exists(path.join("proton")).await? {
+            continue;
+        }
+        if buf.iter().all(|tool| tool.path != path) {
+            buf.push(CompatTool {
+                name,
+                path,
+                kind: CompatToolKind::Proton,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Finds the `wine` executable on the `PATH`, if any.
+async fn find_system_wine() -> Result<Option<PathBuf>> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return Ok(None);
+    };
+    for dir in std::env::split_paths(&path) {
+        let exe = dir.join("wine");
+        if tokio::fs::metadata(&exe).await.is_ok_and(|m| m.is_file()) {
+            return Ok(Some(exe));
+        }
+    }
+    Ok(None)
+}
+
+/// Lists the compatibility tools available to run Windows games with, from Steam's
+/// `compatibilitytools.d` directories, the Proton builds installed in every Steam library, and the
+/// system's Wine. Returns an empty list on platforms other than Linux, and only the system's Wine
+/// if Steam isn't installed.
+pub async fn find_compat_tools(log: &slog::Logger) -> Result<Vec<CompatTool>> {
+    let mut tools = Vec::new();
+    if !cfg!(target_os = "linux") {
+        return Ok(tools);
+    }
+
+    match resolve_steam_directory().await {
+        Ok(steam_dir) => {
+            find_proton_builds(
+                &steam_dir.join("compatibilitytools.d"),
+                |_| true,
+                &mut tools,
+            )
+            .await?;
+            for dir in SYSTEM_COMPAT_TOOLS_DIRS {
+                find_proton_builds(Path::new(dir), |_| true, &mut tools).await?;
+            }
+            let libraries = resolve_steam_library_folders().await.unwrap_or_else(|e| {
+                debug!(log, "Not looking for Proton in Steam libraries: {e}");
+                Vec::new()
+            });
+            for library in libraries {
+                let common = library.join("common");
+                find_proton_builds(&common, |name| name.starts_with("Proton"), &mut tools).await?;
+            }
+        }
+        Err(e) => debug!(log, "Not looking for Proton: {e}"),
+    }
+
+    if let Some(wine) = find_system_wine().await? {
+        tools.push(CompatTool {
+            name: "Wine".to_owned(),
+            path: wine,
+            kind: CompatToolKind::Wine,
+        });
+    }
+
+    debug!(log, "Found compatibility tools: {tools:?}");
+
+    Ok(tools)
+}
+
+/// What Proton recorded in a game's `compatdata` directory about the build that last ran it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatDataConfig {
+    /// The version of the prefix, like `9.0-203`.
+    pub version: String,
+    /// The directory of the Proton build, if the config names one.
+    pub proton_dir: Option<PathBuf>,
+}
+
+/// Parses the `config_info` file Proton writes to a game's `compatdata` directory. Its first line
+/// is the version of the prefix, and the following ones are paths into the `files` directory of
+/// the Proton build, or `dist` before Proton 5.13.
+fn parse_compat_data_config(s: &str) -> Option<CompatDataConfig> {
+    let mut lines = s.lines();
+    let version = lines.next()?.trim();
+    if version.is_empty() {
+        return None;
+    }
+    let proton_dir = lines.find_map(|line| {
+        Path::new(line.trim())
+            .ancestors()
+            .find(|path| {
+                path.file_name()
+                    .is_some_and(|name| name == "files" || name == "dist")
+            })
+            .and_then(Path::parent)
+            .map(Path::to_owned)
+    });
+    Some(CompatDataConfig {
+        version: version.to_owned(),
+        proton_dir,
+    })
+}
+
+/// Reads the config from the `compatdata` directory of a game, which Proton only writes once it
+/// has run the game.
+pub async fn read_compat_data_config(compat_data_dir: &Path) -> Result<Option<CompatDataConfig>> {
+    match tokio::fs::read_to_string(compat_data_dir.join("config_info")).await {
+        Ok(s) => Ok(parse_compat_data_config(&s)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A command that runs a Windows executable with a compatibility tool, without going through
+/// Steam.
+#[derive(Debug)]
+pub struct CompatCommand {
+    /// The compatibility tool and its arguments, ending with the executable.
+    pub args: Vec<OsString>,
+    pub env: Vec<(&'static str, OsString)>,
+}
+
+/// Builds the command that runs `exe` of the Steam app `game_id` with `compat_tool`, a Proton
+/// build or Wine executable, or otherwise with the Proton build recorded in the game's
+/// `compatdata`. Either way, the game's own prefix is used. Returns `None` if no compatibility
+/// tool was given and none was recorded.
+pub async fn compat_tool_command(
+    log: &slog::Logger,
+    game_id: &str,
+    compat_tool: Option<&Path>,
+    exe: &Path,
+) -> Result<Option<CompatCommand>> {
+    let compat_data_dir = resolve_steam_app_compat_data_directory(game_id).await?;
+    let tool = match compat_tool {
+        Some(path) => path.to_owned(),
+        None => match read_compat_data_config(&compat_data_dir).await? {
+            Some(CompatDataConfig {
+                version,
+                proton_dir: Some(dir),
+            }) => {
+                debug!(
+                    log,
+                    "Detected Proton {version} at {dir:?} from the game's compatdata"
+                );
+                dir
+            }
+            Some(CompatDataConfig { version, .. }) => {
+                debug!(
+                    log,
+                    "The game's compatdata doesn't say where Proton {version} is"
+                );
+                return Ok(None);
+            }
+            None => {
+                debug!(log, "The game has not been run with Proton yet");
+                return Ok(None);
+            }
+        },
+    };
+
+    if tokio::fs::metadata(&tool).await?.is_dir() {
+        let proton = tool.join("proton");
+        if !tokio::fs::try_exists(&proton).await? {
+            bail!("{tool:?} is not a Proton build");
+        }
+        let steam_dir = resolve_steam_directory().await?;
+        Ok(Some(CompatCommand {
+            args: vec![proton.into(), "run".into(), exe.into()],
+            env: vec![
+                ("STEAM_COMPAT_DATA_PATH", compat_data_dir.into()),
+                ("STEAM_COMPAT_CLIENT_INSTALL_PATH", steam_dir.into()),
+                ("SteamAppId", game_id.into()),
+                ("SteamGameId", game_id.into()),
+            ],
+        }))
+    } else {
+        // the same prefix as under Proton, so that the DLL override applies to both
+        Ok(Some(CompatCommand {
+            args: vec![tool.into(), exe.into()],
+            env: vec![("WINEPREFIX", compat_data_dir.join("pfx").into())],
+        }))
+    }
+}
+
 pub async fn ensure_wine_will_load_dll_override(
     log: &slog::Logger,
     game_id: &str,
@@ -144,7 +380,33 @@ fn reg_add_in_section(
 
 #[cfg(test)]
 mod tests {
-    use super::reg_add_in_section;
+    use std::path::PathBuf;
+
+    use super::{parse_compat_data_config, reg_add_in_section, CompatDataConfig};
+
+    #[test]
+    fn test_parse_compat_data_config() {
+        let config = "9.0-203\n\
+            /home/user/.local/share/Steam/steamapps/common/Proton 9.0 (Beta)/files/share/fonts/\n\
+            /home/user/.local/share/Steam/steamapps/common/Proton 9.0 (Beta)/files/lib/\n";
+        assert_eq!(
+            parse_compat_data_config(config),
+            Some(CompatDataConfig {
+                version: "9.0-203".to_owned(),
+                proton_dir: Some(PathBuf::from(
+                    "/home/user/.local/share/Steam/steamapps/common/Proton 9.0 (Beta)"
+                )),
+            })
+        );
+        assert_eq!(
+            parse_compat_data_config("5.0-10\n"),
+            Some(CompatDataConfig {
+                version: "5.0-10".to_owned(),
+                proton_dir: None,
+            })
+        );
+        assert_eq!(parse_compat_data_config(""), None);
+    }
 
     #[test]
     fn test_reg_add_in_section() {
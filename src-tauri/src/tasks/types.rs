@@ -15,6 +15,8 @@ pub struct Metadata {
 pub enum Kind {
     Aggregate,
     Download,
+    /// Extracting an archive, with progress counted in entries.
+    Extract,
     Other,
 }
 
@@ -0,0 +1,91 @@
+//! Removal of files and directories that copes with the read-only attribute.
+//!
+//! Windows refuses to delete read-only files, and some mods ship them or have them marked so by
+//! antivirus software. These functions clear the attribute and retry once before giving up. On
+//! other platforms, deleting a file depends only on the permissions of its parent directory, so
+//! they behave exactly like their [`std::fs`] and [`tokio::fs`] counterparts.
+
+use std::io;
+use std::path::Path;
+
+/// Clears the read-only attribute of `path`, returning whether it was set.
+#[cfg(windows)]
+fn clear_readonly(path: &Path) -> io::Result<bool> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    let mut permissions = metadata.permissions();
+    if metadata.is_symlink() || !permissions.readonly() {
+        return Ok(false);
+    }
+    // on Windows this only clears the attribute rather than making the file world-writable
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)?;
+    Ok(true)
+}
+
+#[cfg(not(windows))]
+fn clear_readonly(_path: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Clears the read-only attribute of `path` and everything below it, returning whether it was
+/// set on anything.
+fn clear_readonly_recursive(path: &Path) -> io::Result<bool> {
+    let mut cleared = false;
+    for e in walkdir::WalkDir::new(path).follow_links(false) {
+        cleared |= clear_readonly(e?.path())?;
+    }
+    Ok(cleared)
+}
+
+pub fn remove_file_blocking(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            if clear_readonly(path)? {
+                std::fs::remove_file(path)
+            } else {
+                Err(e)
+            }
+        }
+        r => r,
+    }
+}
+
+pub fn remove_dir_all_blocking(path: &Path) -> io::Result<()> {
+    match std::fs::remove_dir_all(path) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            if clear_readonly_recursive(path)? {
+                std::fs::remove_dir_all(path)
+            } else {
+                Err(e)
+            }
+        }
+        r => r,
+    }
+}
+
+pub async fn remove_file(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            if tokio::task::block_in_place(|| clear_readonly(path))? {
+                tokio::fs::remove_file(path).await
+            } else {
+                Err(e)
+            }
+        }
+        r => r,
+    }
+}
+
+pub async fn remove_dir_all(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_dir_all(path).await {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            if tokio::task::block_in_place(|| clear_readonly_recursive(path))? {
+                tokio::fs::remove_dir_all(path).await
+            } else {
+                Err(e)
+            }
+        }
+        r => r,
+    }
+}
@@ -1,6 +1,11 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
 use bytes::Bytes;
 use pin_project_lite::pin_project;
 use reqwest::Response;
+use slog::debug;
 use tokio::io::{AsyncBufRead, AsyncRead};
 use tokio_util::io::StreamReader;
 
@@ -23,6 +28,85 @@ mod private {
 
 pub use private::ReqwestBytesStream;
 
+/// Returns the status code of the first HTTP error found in the chain of `error`, if any.
+pub fn error_status(error: &anyhow::Error) -> Option<reqwest::StatusCode> {
+    error
+        .chain()
+        .find_map(|e| e.downcast_ref::<reqwest::Error>())
+        .and_then(reqwest::Error::status)
+}
+
+/// How many times a download is attempted before giving up, unless configured otherwise.
+pub const DEFAULT_DOWNLOAD_ATTEMPTS: u32 = 4;
+/// The most times a download can be configured to be attempted.
+pub const MAX_DOWNLOAD_ATTEMPTS: u32 = 16;
+/// The delay before the first retry of a failed download. It doubles with every attempt, up to
+/// [`RETRY_MAX_DELAY`].
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+static DOWNLOAD_ATTEMPTS: AtomicU32 = AtomicU32::new(DEFAULT_DOWNLOAD_ATTEMPTS);
+
+/// Applies the number of download attempts from the settings. `None` or zero restores the
+/// default, and more than [`MAX_DOWNLOAD_ATTEMPTS`] is limited to that.
+pub fn configure_download_attempts(attempts: Option<u32>) {
+    let attempts = attempts
+        .filter(|&n| n != 0)
+        .unwrap_or(DEFAULT_DOWNLOAD_ATTEMPTS)
+        .min(MAX_DOWNLOAD_ATTEMPTS);
+    DOWNLOAD_ATTEMPTS.store(attempts, Ordering::Relaxed);
+}
+
+/// Returns whether `error` might not happen again if the request is retried, like failing to
+/// connect, timing out, the response being cut off, or a server error. Client errors, like
+/// `404 Not Found`, are not.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .find_map(|e| e.downcast_ref::<reqwest::Error>())
+        .is_some_and(|e| {
+            e.is_connect()
+                || e.is_timeout()
+                || e.is_request()
+                || e.is_body()
+                || e.status().is_some_and(|status| status.is_server_error())
+        })
+}
+
+/// Returns how long to wait before retrying after the failed `attempt`, counting from 1.
+fn retry_delay(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt - 1)
+        .and_then(|factor| RETRY_BASE_DELAY.checked_mul(factor))
+        .map_or(RETRY_MAX_DELAY, |delay| delay.min(RETRY_MAX_DELAY))
+}
+
+/// Runs the download `f`, retrying it with exponential backoff while it fails with a
+/// [transient](is_transient) error, up to the configured number of attempts. Every call of `f`
+/// must start the download over from scratch.
+pub async fn retry_download<T, F, Fut>(log: &slog::Logger, url: &str, mut f: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let max_attempts = DOWNLOAD_ATTEMPTS.load(Ordering::Relaxed);
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(e) if attempt < max_attempts && is_transient(&e) => {
+                let delay = retry_delay(attempt);
+                debug!(
+                    log,
+                    "Attempt {attempt} of {max_attempts} to download {url:?} failed, retrying in \
+                     {delay:?}: {e}"
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 use super::{Progress, UsizeExt};
 
 pub trait ResponseExt {
@@ -93,3 +177,18 @@ impl<'a, R: AsyncBufRead> AsyncBufRead for ProgressReader<'a, R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{retry_delay, RETRY_MAX_DELAY};
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_millis(500));
+        assert_eq!(retry_delay(3), Duration::from_secs(2));
+        assert_eq!(retry_delay(8), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX_DELAY);
+    }
+}
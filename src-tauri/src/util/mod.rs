@@ -1,3 +1,4 @@
+pub mod fs;
 pub mod http;
 pub mod process;
 mod progress;
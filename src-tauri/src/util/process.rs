@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use anyhow::Result;
 use slog::Logger;
 
@@ -10,6 +12,59 @@ pub struct Pid {
 }
 
 impl Pid {
+    pub fn from_raw(pid: u32) -> Option<Self> {
+        #[cfg(windows)]
+        {
+            Some(Self { value: pid })
+        }
+        #[cfg(unix)]
+        {
+            let value = rustix::process::Pid::from_raw(pid.try_into().ok()?)?;
+            Some(Self { value })
+        }
+    }
+
+    /// Asks the process to exit, and kills it if it hasn't after `timeout`. On Unix, this applies
+    /// to the process group the process leads, so that the processes it started exit with it.
+    /// Windows has no such request, so the process is terminated right away.
+    #[cfg_attr(windows, allow(unused_variables))]
+    pub async fn terminate(self, log: &Logger, timeout: Duration) -> Result<()> {
+        let pid = self.value;
+        #[cfg(windows)]
+        {
+            use winsafe::prelude::*;
+
+            tokio::task::block_in_place(|| {
+                let proc =
+                    winsafe::HPROCESS::OpenProcess(winsafe::co::PROCESS::TERMINATE, false, pid)?;
+                proc.TerminateProcess(1)?;
+                Ok(())
+            })
+        }
+        #[cfg(unix)]
+        {
+            use rustix::io::Errno;
+            use rustix::process::{kill_process_group, Signal};
+
+            match kill_process_group(pid, Signal::TERM) {
+                Ok(()) => {}
+                Err(Errno::SRCH) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+            if tokio::time::timeout(timeout, self.wait_for_exit(log))
+                .await
+                .is_err()
+            {
+                slog::warn!(log, "Process {pid:?} did not exit in time, killing it");
+                match kill_process_group(pid, Signal::KILL) {
+                    Ok(()) | Err(Errno::SRCH) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Ok(())
+        }
+    }
+
     pub async fn wait_for_exit(self, log: &Logger) -> Result<()> {
         let pid = self.value;
         #[cfg(windows)]
@@ -84,3 +139,114 @@ impl Pid {
         }
     }
 }
+
+/// Returns whether the file name of `path`, which may be a Windows path, is one of `names`.
+fn has_file_name(names: &[&str], path: &str) -> bool {
+    let file_name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    names
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(file_name))
+}
+
+/// Returns the running processes whose executable has one of `names` as its file name, compared
+/// case-insensitively. Under Wine, this is the name of the Windows executable.
+pub async fn find_processes_by_name(names: &[&str]) -> Result<Vec<Pid>> {
+    #[cfg(windows)]
+    {
+        use std::ptr::NonNull;
+
+        use winsafe::prelude::*;
+
+        tokio::task::block_in_place(|| {
+            let mut found = Vec::new();
+            for proc in winsafe::HPROCESSLIST::CreateToolhelp32Snapshot(
+                winsafe::co::TH32CS::SNAPPROCESS,
+                None,
+            )?
+            .iter_processes()
+            {
+                let proc = proc?;
+                // see crate::stores::steam::launching::kill_steam
+                let proc = unsafe {
+                    NonNull::from(proc)
+                        .cast::<windows::Win32::System::Diagnostics::ToolHelp::PROCESSENTRY32>()
+                        .as_ref()
+                };
+                let name = unsafe { NonNull::from(&proc.szExeFile).cast::<[u8; 260]>().as_ref() };
+                let name = std::ffi::CStr::from_bytes_until_nul(name)?;
+                if name.to_str().is_ok_and(|name| has_file_name(names, name)) {
+                    found.push(Pid {
+                        value: proc.th32ProcessID,
+                    });
+                }
+            }
+            Ok(found)
+        })
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let output = tokio::process::Command::new("ps")
+            .args(["-axo", "pid=,comm="])
+            .output()
+            .await?;
+        anyhow::ensure!(output.status.success(), "ps exited with {}", output.status);
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let (pid, comm) = line.trim_start().split_once(' ')?;
+                if !has_file_name(names, comm.trim()) {
+                    return None;
+                }
+                let value = rustix::process::Pid::from_raw(pid.parse().ok()?)?;
+                Some(Pid { value })
+            })
+            .collect())
+    }
+    #[cfg(target_os = "linux")]
+    {
+        tokio::task::block_in_place(|| {
+            let mut found = Vec::new();
+            for e in std::fs::read_dir("/proc")? {
+                let e = e?;
+                let Some(value) = e
+                    .file_name()
+                    .to_str()
+                    .and_then(|s| s.parse().ok())
+                    .and_then(rustix::process::Pid::from_raw)
+                else {
+                    continue;
+                };
+                // the process may have exited since, or belong to another user
+                let Ok(cmdline) = std::fs::read(e.path().join("cmdline")) else {
+                    continue;
+                };
+                // Wine sets this to the path of the Windows executable
+                let argv0 = cmdline.split(|&b| b == 0).next().unwrap_or_default();
+                if std::str::from_utf8(argv0).is_ok_and(|argv0| has_file_name(names, argv0)) {
+                    found.push(Pid { value });
+                }
+            }
+            Ok(found)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::has_file_name;
+
+    #[test]
+    fn test_has_file_name() {
+        let names = ["Lethal Company.exe", "Lethal Company.x86_64"];
+        assert!(has_file_name(&names, "Lethal Company.exe"));
+        assert!(has_file_name(
+            &names,
+            "Z:\\home\\user\\Games\\Lethal Company\\lethal company.exe"
+        ));
+        assert!(has_file_name(
+            &names,
+            "/home/user/Games/Lethal Company/Lethal Company.x86_64"
+        ));
+        assert!(!has_file_name(&names, "/usr/bin/Lethal Company.exe.sh"));
+    }
+}
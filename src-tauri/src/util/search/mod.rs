@@ -5,6 +5,31 @@ use backend_sublime_fuzzy as backend;
 
 pub use backend::*;
 
+/// How a query is matched against text. Defaults to [`Fuzzy`](Self::Fuzzy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum SearchMode {
+    /// Matches the characters of the query in order, allowing gaps between them. See [`score`].
+    #[default]
+    Fuzzy,
+    /// Matches the query as a whole. See [`substring_score`].
+    Substring,
+}
+
+impl SearchMode {
+    pub fn score(self, needle: &str, haystack: &str) -> Option<Score> {
+        match self {
+            Self::Fuzzy => score(needle, haystack),
+            Self::Substring => substring_score(needle, haystack),
+        }
+    }
+}
+
+impl serde::Serialize for Score {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
 #[derive(Clone, Copy, serde::Deserialize)]
 pub struct SortOption<C> {
     pub column: C,
@@ -19,6 +44,20 @@ pub fn add_scores(a: Option<Score>, b: Option<Score>) -> Option<Score> {
     }
 }
 
+/// Scores `haystack` if it contains `needle`, ignoring ASCII case, by the length of `needle`.
+/// Matches at the start of `haystack` score twice as high.
+pub fn substring_score(needle: &str, haystack: &str) -> Option<Score> {
+    let (needle, haystack) = (needle.as_bytes(), haystack.as_bytes());
+    if needle.is_empty() {
+        return Some(Score::ZERO);
+    }
+    let i = haystack
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle))?;
+    let len = ScoreValue::try_from(needle.len()).unwrap_or(ScoreValue::MAX);
+    Some(Score(if i == 0 { len.saturating_mul(2) } else { len }))
+}
+
 #[allow(unused)]
 pub fn add_bonus(score: Option<Score>, bonus: Score) -> Option<Score> {
     match (score, bonus) {
@@ -27,3 +66,16 @@ pub fn add_bonus(score: Option<Score>, bonus: Score) -> Option<Score> {
         (None, bonus) => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{substring_score, Score};
+
+    #[test]
+    fn test_substring_score() {
+        assert_eq!(substring_score("lethal", "LethalLib"), Some(Score(12)));
+        assert_eq!(substring_score("lib", "LethalLib"), Some(Score(3)));
+        assert_eq!(substring_score("lbi", "LethalLib"), None);
+        assert_eq!(substring_score("LethalLibrary", "LethalLib"), None);
+    }
+}
@@ -3,21 +3,25 @@ use std::ffi::OsString;
 use std::future::join;
 use std::ops::ControlFlow;
 use std::panic::AssertUnwindSafe;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail, ensure, Context as _, Result};
 use ipc_channel::ipc::IpcSender;
 use lexopt::ValueExt;
 use parking_lot::Mutex;
 use slog::o;
-use slog::{debug, info};
+use slog::{debug, info, warn};
 use tokio::select;
 use tokio::{io::AsyncBufReadExt as _, process::Command};
 use triomphe::Arc;
 use uuid::Uuid;
 
-use crate::games::PackageLoader;
-use crate::ipc::{C2SMessage, Ipc, LogLevel, OutputLine, S2CMessage};
+use crate::games::{games_by_id, Game, PackageLoader};
+use crate::ipc::{C2SMessage, Ipc, LogLevel, OutputLine, S2CMessage, WrapMessage};
+use crate::launching::hooks;
+use crate::launching::loaders::LoaderOptions;
+use crate::launching::logs::LaunchLog;
+use crate::util::hyphenated_uuid;
 
 async fn send_ipc(
     log: &slog::Logger,
@@ -47,6 +51,29 @@ impl std::fmt::Display for DisplayArgList {
     }
 }
 
+/// Replaces the game's executable in the command line with its sibling `exe`. Returns `false` if
+/// none of the game's executables appear in the command line.
+fn select_executable(
+    game: &Game<'_>,
+    exe: &str,
+    command_name: &mut OsString,
+    command_args: &mut [OsString],
+) -> bool {
+    // under Proton, the executable is passed as an argument to the compatibility tool
+    for arg in std::iter::once(command_name).chain(command_args.iter_mut()) {
+        let path = Path::new(arg.as_os_str());
+        let is_game_exe = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|name| game.exe_names.iter().any(|e| e.eq_ignore_ascii_case(name)));
+        if is_game_exe {
+            *arg = path.with_file_name(exe).into_os_string();
+            return true;
+        }
+    }
+    false
+}
+
 pub async fn run(args: lexopt::Parser) -> Result<()> {
     async fn inner1(mut args: lexopt::Parser) -> Result<()> {
         use lexopt::Arg::*;
@@ -69,17 +96,32 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
             }
         }
 
+        // launches started through a steam:// URL can't pass us arguments directly
+        if !args.iter().any(|s| s == "--c2s-tx") {
+            let game = args
+                .iter()
+                .position(|s| s == "--game")
+                .and_then(|i| args.get(i + 1))
+                .and_then(|s| s.to_str())
+                .map(str::to_owned);
+            if let Some(game) = game {
+                if let Some(pending) = crate::launching::pending::take(&game).await? {
+                    args.extend(pending);
+                }
+            }
+        }
+
         // TODO: use lexopt to parse `args`
         let ipc = if let Some(i) = args.iter().position(|s| s == "--c2s-tx") {
             args.remove(i);
-            let c2s_tx = IpcSender::<C2SMessage>::connect(
+            let c2s_tx = IpcSender::<WrapMessage>::connect(
                 args.remove(i)
                     .into_string()
                     .map_err(|e| anyhow!("Invalid value for option --c2s-tx: {e:?}"))?,
             )?;
 
             let (s2c_rx, s2c_tx) = ipc_channel::ipc::IpcOneShotServer::<S2CMessage>::new()?;
-            c2s_tx.send(C2SMessage::Connect { s2c_tx })?;
+            c2s_tx.send(C2SMessage::Connect { s2c_tx }.into())?;
             let (s2c_rx, msg) = s2c_rx.accept()?;
             ensure!(
                 matches!(msg, S2CMessage::Connect),
@@ -96,7 +138,7 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
 
         let _guard = if let Some(ipc) = &ipc {
             struct Logger {
-                c2s_tx: AssertUnwindSafe<Mutex<IpcSender<C2SMessage>>>,
+                c2s_tx: AssertUnwindSafe<Mutex<IpcSender<WrapMessage>>>,
             }
 
             impl slog::Drain for Logger {
@@ -110,11 +152,14 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
                     _values: &slog::OwnedKVList,
                 ) -> Result<Self::Ok, Self::Err> {
                     _ = tokio::task::block_in_place(|| {
-                        self.c2s_tx.lock().send(C2SMessage::Log {
-                            level: record.level().into(),
-                            scope: "manderrow_wrap".into(),
-                            message: record.msg().to_string(),
-                        })
+                        self.c2s_tx.lock().send(
+                            C2SMessage::Log {
+                                level: record.level().into(),
+                                scope: "manderrow_wrap".into(),
+                                message: record.msg().to_string(),
+                            }
+                            .into(),
+                        )
                     });
                     Ok(())
                 }
@@ -150,7 +195,7 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
 
     async fn inner(
         args: Vec<OsString>,
-        command_name: OsString,
+        mut command_name: OsString,
         mut command_args: Vec<OsString>,
         log: &slog::Logger,
         ipc: Option<&Ipc>,
@@ -165,6 +210,9 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
         let mut wrapper_stage2_path = None::<PathBuf>;
         let mut doorstop_path = None::<PathBuf>;
         let mut legacy_doorstop = false;
+        let mut exe = None::<String>;
+        let mut override_env = Vec::<(String, OsString)>::new();
+        let mut override_args = Vec::<OsString>::new();
 
         while let Some(arg) = parsed_args.next()? {
             match arg {
@@ -216,6 +264,30 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
                     }
                     doorstop_path = Some(parsed_args.value()?.into());
                 }
+                Long("exe") => {
+                    if exe.is_some() {
+                        bail!("--exe specified twice");
+                    }
+                    exe = Some(
+                        parsed_args
+                            .value()?
+                            .into_string()
+                            .map_err(|s| anyhow!("Invalid executable name: {s:?}"))?,
+                    );
+                }
+                Long("env") => {
+                    let value = parsed_args
+                        .value()?
+                        .into_string()
+                        .map_err(|s| anyhow!("Invalid environment variable: {s:?}"))?;
+                    let (key, value) = value
+                        .split_once('=')
+                        .with_context(|| format!("Expected KEY=VALUE, found {value:?}"))?;
+                    override_env.push((key.to_owned(), value.into()));
+                }
+                Long("arg") => {
+                    override_args.push(parsed_args.value()?);
+                }
                 Long("legacy-doorstop") => {
                     if legacy_doorstop {
                         bail!("--legacy-doorstop specified twice");
@@ -231,6 +303,12 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
 
         let game = game.context("Missing required option --game")?;
 
+        let mut env = HashMap::default();
+        // applied over the loader's environment, so that the user can override it
+        let mut profile_env = HashMap::<String, OsString>::default();
+        let mut mods_disabled = false;
+        let mut hooks = None;
+
         if let Some(id) = profile {
             let profile = crate::profiles::read_profile(id).await?;
             if profile.game != game {
@@ -240,53 +318,103 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
                     game
                 );
             }
+            mods_disabled = profile.mods_disabled;
+            profile_env.extend(
+                profile
+                    .env
+                    .into_iter()
+                    .map(|(key, value)| (key, OsString::from(value))),
+            );
+            command_args.extend(profile.args.into_iter().map(OsString::from));
+
+            if profile.pre_launch.is_some() || profile.post_exit.is_some() {
+                let mut hook_env = profile_env.clone();
+                hook_env.insert("MANDERROW_GAME".to_owned(), game.clone().into());
+                hook_env.insert(
+                    "MANDERROW_PROFILE_ID".to_owned(),
+                    hyphenated_uuid!(id).to_owned().into(),
+                );
+                hook_env.insert(
+                    "MANDERROW_PROFILE_DIR".to_owned(),
+                    crate::profiles::profile_path(id).into_os_string(),
+                );
+                if let Some(hook) = &profile.pre_launch {
+                    hooks::run(log, "pre_launch", hook, &hook_env).await?;
+                }
+                hooks = profile.post_exit.map(|hook| (hook, hook_env));
+            }
         }
 
-        let mut env = HashMap::default();
-        match (profile, loader) {
-            (None, Some(_)) => bail!("Cannot launch modded without a profile"),
-            (Some(profile), Some(PackageLoader::BepInEx)) => {
-                struct CommandBuilder<'a> {
-                    env: &'a mut HashMap<String, OsString>,
-                    args: &'a mut Vec<OsString>,
-                }
-                impl<'a> crate::launching::bep_in_ex::CommandBuilder for CommandBuilder<'a> {
-                    fn env(&mut self, key: impl AsRef<str>, value: impl AsRef<std::ffi::OsStr>) {
-                        self.env
-                            .insert(key.as_ref().to_owned(), value.as_ref().to_owned());
-                    }
+        struct CommandBuilder<'a> {
+            env: &'a mut HashMap<String, OsString>,
+            args: &'a mut Vec<OsString>,
+        }
+        impl<'a> crate::launching::loaders::CommandBuilder for CommandBuilder<'a> {
+            fn env(&mut self, key: impl AsRef<str>, value: impl AsRef<std::ffi::OsStr>) {
+                self.env
+                    .insert(key.as_ref().to_owned(), value.as_ref().to_owned());
+            }
 
-                    fn args(
-                        &mut self,
-                        args: impl IntoIterator<Item = impl AsRef<std::ffi::OsStr>>,
-                    ) {
-                        self.args
-                            .extend(args.into_iter().map(|s| s.as_ref().to_owned()))
-                    }
+            fn args(&mut self, args: impl IntoIterator<Item = impl AsRef<std::ffi::OsStr>>) {
+                self.args
+                    .extend(args.into_iter().map(|s| s.as_ref().to_owned()))
+            }
 
-                    fn arg(&mut self, arg: impl AsRef<std::ffi::OsStr>) {
-                        self.args.push(arg.as_ref().to_owned())
-                    }
-                }
-                crate::launching::bep_in_ex::configure_command(
+            fn arg(&mut self, arg: impl AsRef<std::ffi::OsStr>) {
+                self.args.push(arg.as_ref().to_owned())
+            }
+        }
+
+        match (profile, loader) {
+            (None, Some(_)) => bail!("Cannot launch modded without a profile"),
+            (Some(profile), Some(loader)) => {
+                let launch = crate::launching::loaders::configure_loader(
                     &log,
                     &mut CommandBuilder {
                         env: &mut env,
                         args: &mut command_args,
                     },
+                    loader,
                     &game,
                     profile,
-                    doorstop_path,
-                    legacy_doorstop,
+                    LoaderOptions {
+                        doorstop_path,
+                        legacy_doorstop,
+                        mods_disabled,
+                        exe: exe.clone(),
+                    },
                 )
                 .await?;
-            }
-            (Some(_), Some(loader)) => {
-                bail!("The mod loader {loader:?} is not yet supported by the wrap command")
+                if let Some(loader_exe) = launch.exe {
+                    exe = Some(loader_exe.to_owned());
+                }
+                send_ipc(log, ipc, || Ok(C2SMessage::LoaderInjected { loader })).await?;
             }
             (_, None) => {}
         }
 
+        env.extend(profile_env);
+        env.extend(override_env);
+        command_args.extend(override_args);
+
+        if let Some(exe) = exe {
+            let game_info = *games_by_id()?
+                .get(&*game)
+                .with_context(|| format!("Unrecognized game {game:?}"))?;
+            ensure!(
+                game_info.exe_names.iter().any(|name| *name == exe),
+                "{exe:?} is not an executable of {game:?}"
+            );
+            if select_executable(game_info, &exe, &mut command_name, &mut command_args) {
+                debug!(log, "Launching selected executable {exe:?}");
+            } else {
+                warn!(
+                    log,
+                    "Unable to find the game's executable in the command, launching the default"
+                );
+            }
+        }
+
         let mut command = Command::new(&command_name);
         command.kill_on_drop(true);
 
@@ -318,6 +446,39 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
             command.envs(&env);
         }
 
+        command.args(&command_args);
+        // so that the game can be stopped along with the processes it starts, see
+        // crate::launching::running
+        #[cfg(unix)]
+        command.process_group(0);
+
+        // written even without IPC, so that relaunches by the store, which can't be passed our
+        // arguments, are logged too
+        let launch_log = match profile.map(|id| LaunchLog::create(log, id)) {
+            Some(Ok((path, launch_log))) => {
+                info!(log, "Logging the game's output to {path:?}");
+                Some(Arc::new(launch_log))
+            }
+            Some(Err(e)) => {
+                warn!(log, "Unable to create launch log: {e:#}");
+                None
+            }
+            None => None,
+        };
+
+        if ipc.is_some() || launch_log.is_some() {
+            command.stdout(std::process::Stdio::piped());
+            command.stderr(std::process::Stdio::piped());
+        }
+        let mut child = match command.spawn() {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Could not locate command {command_name:?}")))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
         send_ipc(log, ipc, || {
             Ok(C2SMessage::Start {
                 command: command_name.clone().into(),
@@ -330,32 +491,21 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
                     .iter()
                     .map(|(k, v)| (k.clone(), v.clone().into()))
                     .collect::<HashMap<_, _>>(),
+                pid: child.id(),
             })
         })
         .await?;
 
-        command.args(command_args);
-
-        if ipc.is_some() {
-            command.stdout(std::process::Stdio::piped());
-            command.stderr(std::process::Stdio::piped());
-        }
-        let mut child = match command.spawn() {
-            Ok(t) => t,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                return Err(anyhow::Error::new(e)
-                    .context(format!("Could not locate command {command_name:?}")))
-            }
-            Err(e) => return Err(e.into()),
-        };
-
-        let tasks = if let Some(ipc) = ipc {
+        // the pipes are read until every process holding them exits, so that the output of a game
+        // that detaches from the process we started is captured too
+        let tasks = if ipc.is_some() || launch_log.is_some() {
             fn spawn_output_pipe_task<const TRY_PARSE_LOGS: bool>(
-                c2s_tx: &IpcSender<C2SMessage>,
+                c2s_tx: Option<&IpcSender<WrapMessage>>,
+                launch_log: Option<Arc<LaunchLog>>,
                 rdr: impl tokio::io::AsyncRead + Unpin + Send + 'static,
                 channel: crate::ipc::StandardOutputChannel,
             ) -> tokio::task::JoinHandle<Result<(), anyhow::Error>> {
-                let c2s_tx = c2s_tx.clone();
+                let c2s_tx = c2s_tx.cloned();
                 tokio::task::spawn(async move {
                     let mut rdr = tokio::io::BufReader::new(rdr);
                     let mut buf = Vec::new();
@@ -370,28 +520,36 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
                                 buf.pop();
                             }
                         }
+                        if let Some(launch_log) = &launch_log {
+                            _ = launch_log.write_line(channel, &buf);
+                        }
+                        let Some(c2s_tx) = &c2s_tx else {
+                            buf.clear();
+                            continue;
+                        };
                         if TRY_PARSE_LOGS {
-                            if let ControlFlow::Break(()) = try_handle_log_record(&c2s_tx, &buf) {
+                            if let ControlFlow::Break(()) = try_handle_log_record(c2s_tx, &buf) {
                                 buf.clear();
                                 continue;
                             }
                         }
                         let line = OutputLine::new(std::mem::take(&mut buf));
-                        let c2s_tx = &c2s_tx;
                         _ = tokio::task::block_in_place(move || {
-                            c2s_tx.send(C2SMessage::Output { channel, line })
+                            c2s_tx.send(C2SMessage::Output { channel, line }.into())
                         });
                     }
                 })
             }
             Some((
                 spawn_output_pipe_task::<false>(
-                    &ipc.c2s_tx,
+                    ipc.map(|ipc| &ipc.c2s_tx),
+                    launch_log.clone(),
                     child.stdout.take().unwrap(),
                     crate::ipc::StandardOutputChannel::Out,
                 ),
                 spawn_output_pipe_task::<true>(
-                    &ipc.c2s_tx,
+                    ipc.map(|ipc| &ipc.c2s_tx),
+                    launch_log,
                     child.stderr.take().unwrap(),
                     crate::ipc::StandardOutputChannel::Err,
                 ),
@@ -431,6 +589,15 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
                     None
                 };
 
+                if let Some((hook, mut hook_env)) = hooks {
+                    if let Some(code) = status.code() {
+                        hook_env.insert("MANDERROW_EXIT_CODE".to_owned(), code.to_string().into());
+                    }
+                    if let Err(e) = hooks::run(log, "post_exit", &hook, &hook_env).await {
+                        warn!(log, "{e:#}");
+                    }
+                }
+
                 send_ipc(log, ipc, || {
                     Ok(C2SMessage::Exit {
                         code: status.code(),
@@ -451,18 +618,74 @@ pub async fn run(args: lexopt::Parser) -> Result<()> {
     match inner1(args).await {
         Ok(()) => Ok(()),
         Err(e) => {
-            tokio::fs::write(
-                "manderrow-wrap-crash.txt",
-                format!("{e}\nargs: {}", DisplayArgList),
-            )
-            .await
-            .unwrap();
+            match write_crash_report(&e).await {
+                Ok(path) => eprintln!("Wrote crash report to {path:?}"),
+                Err(e) => eprintln!("Unable to write crash report: {e:?}"),
+            }
             Err(e)
         }
     }
 }
 
-fn try_handle_log_record(c2s_tx: &IpcSender<C2SMessage>, buf: &[u8]) -> ControlFlow<()> {
+/// Returns whether the environment variable is one that affects how the game is launched, and so
+/// belongs in a crash report.
+fn is_relevant_env(key: &str) -> bool {
+    const PREFIXES: &[&str] = &[
+        "MANDERROW_",
+        "DOORSTOP_",
+        "BEPINEX_",
+        "STEAM_COMPAT_",
+        "PROTON_",
+        "WINE",
+        "LD_",
+        "DYLD_",
+    ];
+    const KEYS: &[&str] = &[
+        "SteamAppId",
+        "SteamGameId",
+        "SteamOverlayGameId",
+        "RUST_LOG",
+    ];
+    PREFIXES.iter().any(|prefix| key.starts_with(prefix)) || KEYS.contains(&key)
+}
+
+/// Writes a report of the wrapper's failure to the [crash reports
+/// directory](crate::paths::crash_reports_dir), and returns its path.
+async fn write_crash_report(error: &anyhow::Error) -> Result<PathBuf> {
+    use std::fmt::Write as _;
+
+    let mut report = String::new();
+    writeln!(report, "Manderrow {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(
+        report,
+        "platform: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )?;
+    writeln!(report, "args: {}", DisplayArgList)?;
+    writeln!(report, "cwd: {:?}", std::env::current_dir())?;
+    writeln!(report, "env:")?;
+    let mut env = std::env::vars_os()
+        .filter(|(key, _)| key.to_str().is_some_and(is_relevant_env))
+        .collect::<Vec<_>>();
+    env.sort();
+    for (key, value) in env {
+        writeln!(report, "  {}={value:?}", key.to_string_lossy())?;
+    }
+    writeln!(report)?;
+    writeln!(report, "{error:?}")?;
+
+    let dir = crate::paths::crash_reports_dir();
+    tokio::fs::create_dir_all(&dir).await?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis();
+    let path = dir.join(format!("wrap-{timestamp}.txt"));
+    tokio::fs::write(&path, report).await?;
+    Ok(path)
+}
+
+fn try_handle_log_record(c2s_tx: &IpcSender<WrapMessage>, buf: &[u8]) -> ControlFlow<()> {
     if let Some((level, rem)) = buf.split_once(|b| *b == b' ') {
         if let Some((scope, msg)) = rem.split_once(|b| *b == b' ') {
             let level = match level {
@@ -479,11 +702,14 @@ fn try_handle_log_record(c2s_tx: &IpcSender<C2SMessage>, buf: &[u8]) -> ControlF
                         if let Ok(msg) = std::str::from_utf8(msg) {
                             let c2s_tx = c2s_tx;
                             _ = tokio::task::block_in_place(move || {
-                                c2s_tx.send(C2SMessage::Log {
-                                    level,
-                                    scope: scope.into(),
-                                    message: msg.to_owned(),
-                                })
+                                c2s_tx.send(
+                                    C2SMessage::Log {
+                                        level,
+                                        scope: scope.into(),
+                                        message: msg.to_owned(),
+                                    }
+                                    .into(),
+                                )
                             });
                             return ControlFlow::Break(());
                         }
@@ -1,5 +1,5 @@
 import { Channel, invoke } from "@tauri-apps/api/core";
-import { Game, ModListing, ModMetadata, ModPackage, ModVersion } from "./types";
+import { Game, ModListing, ModMetadata, ModPackage, ModVersion, PackageLoader } from "./types";
 import { invokeWithListener, Listener, TaskEvent, Id as TaskId } from "./api/tasks";
 import { C2SMessage } from "./api/ipc";
 
@@ -31,6 +31,15 @@ export class AbortedError extends Error {
   }
 }
 
+export class GameAlreadyRunningError extends Error {
+  readonly game: string;
+
+  constructor(game: string) {
+    super(`${game} is already running`);
+    this.game = game;
+  }
+}
+
 export async function wrapInvoke<T>(f: () => Promise<T>): Promise<T> {
   try {
     return await f();
@@ -38,6 +47,8 @@ export async function wrapInvoke<T>(f: () => Promise<T>): Promise<T> {
     console.error("Error in invoke", e);
     if (e === "Aborted") {
       throw new AbortedError();
+    } else if (e instanceof Object && "GameAlreadyRunning" in e) {
+      throw new GameAlreadyRunningError(e.GameAlreadyRunning.game);
     } else if (e instanceof Object && "Error" in e) {
       throw new NativeError(e.Error.messages, e.Error.backtrace);
     } else {
@@ -50,6 +61,10 @@ export async function getGames(): Promise<Game[]> {
   return await wrapInvoke(() => invoke("get_games", {}));
 }
 
+export async function getGamesByLoader(loader: PackageLoader): Promise<Game[]> {
+  return await wrapInvoke(() => invoke("get_games_by_loader", { loader }));
+}
+
 export enum GameSortColumn {
   Relevance = "Relevance",
   Name = "Name",
@@ -57,8 +72,12 @@ export enum GameSortColumn {
   ModDownloads = "ModDownloads",
 }
 
-export async function searchGames(query: string, sort: readonly SortOption<GameSortColumn>[]): Promise<number[]> {
-  return await wrapInvoke(() => invoke("search_games", { query, sort }));
+export async function searchGames(
+  query: string,
+  sort: readonly SortOption<GameSortColumn>[],
+  loader?: PackageLoader,
+): Promise<number[]> {
+  return await wrapInvoke(() => invoke("search_games", { query, loader, sort }));
 }
 
 export async function getGamesPopularity(): Promise<{ [key: string]: number }> {
@@ -85,17 +104,30 @@ export interface SortOption<C> {
   descending: boolean;
 }
 
-export async function countModIndex(game: string, query: string): Promise<number> {
-  return await wrapInvoke(() => invoke("count_mod_index", { game, query }));
+/**
+ * How a query is matched against the name and owner of mods. Descriptions only ever match the
+ * query as a whole. Defaults to {@link SearchMode.Fuzzy}.
+ */
+export enum SearchMode {
+  /** Matches the characters of the query in order, allowing gaps between them. */
+  Fuzzy = "Fuzzy",
+  /** Matches the query as a whole. */
+  Substring = "Substring",
+}
+
+export async function countModIndex(game: string, query: string, mode?: SearchMode): Promise<number> {
+  return await wrapInvoke(() => invoke("count_mod_index", { game, query, mode }));
 }
 
 export async function queryModIndex(
   game: string,
   query: string,
   sort: readonly SortOption<ModSortColumn>[],
-  options: { skip?: number; limit?: Exclude<number, 0> },
+  options: { mode?: SearchMode; skip?: number; limit?: Exclude<number, 0> },
 ): Promise<{
   mods: ModListing[];
+  /** The match score of each mod in {@link mods}, or `null` for an empty query. */
+  scores: (number | null)[];
   count: number;
 }> {
   return await wrapInvoke(() => invoke("query_mod_index", { game, query, sort, ...options }));
@@ -143,28 +175,73 @@ export async function deleteProfile(id: string): Promise<void> {
   return await wrapInvoke(() => invoke("delete_profile", { id }));
 }
 
+/**
+ * Environment variables and arguments for a single launch, which take precedence over those of the profile.
+ */
+export interface LaunchOverrides {
+  env?: Record<string, string>;
+  args?: string[];
+}
+
 export async function launchProfile(
   target: { profile: string } | { vanilla: string },
   channel: Channel<C2SMessage>,
-  options: { modded: boolean },
+  options: { modded: boolean; overrides?: LaunchOverrides; force?: boolean },
 ): Promise<void> {
   return await wrapInvoke(() => invoke("launch_profile", { target, channel, ...options }));
 }
 
+export async function stopProfile(id: string): Promise<void> {
+  return await wrapInvoke(() => invoke("stop_profile", { id }));
+}
+
+/**
+ * Lists the paths of the logs of the output of the profile's launches, newest first.
+ */
+export async function getLaunchLogs(id: string): Promise<string[]> {
+  return await wrapInvoke(() => invoke("get_launch_logs", { id }));
+}
+
+export async function setProfileLaunchOptions(id: string, env: Record<string, string>, args: string[]): Promise<void> {
+  return await wrapInvoke(() => invoke("set_profile_launch_options", { id, env, args }));
+}
+
 export async function getProfileMods(id: string): Promise<ModPackage[]> {
   return await wrapInvoke(() => invoke("get_profile_mods", { id }));
 }
 
+/**
+ * Installs the mod in the profile. If it shares files with installed mods, it is not installed
+ * unless `ignoreConflicts` is set, and the conflicts are returned either way.
+ */
 export async function installProfileMod(
   id: string,
   mod: ModMetadata,
   version: ModVersion,
   listener: Listener,
-): Promise<void> {
-  await invokeWithListener(listener, (taskId) => invoke("install_profile_mod", { id, mod, version, taskId }));
+  options: { ignoreConflicts?: boolean } = {},
+): Promise<FileConflict[]> {
+  return await invokeWithListener(listener, (taskId) =>
+    invoke("install_profile_mod", { id, mod, version, taskId, ...options }),
+  );
+}
+
+/**
+ * A file that a mod being installed shares with another installed mod.
+ */
+export interface FileConflict {
+  /** Relative to either mod's directory. */
+  path: string;
+  /** The directory of the mod being installed. */
+  package: string;
+  /** The directory of the other mod. */
+  other: string;
 }
 
-export async function uninstallProfileMod(id: string, owner: string, name: string): Promise<void> {
+/**
+ * @returns the paths of files created or modified since the mod was installed, which were left in place.
+ */
+export async function uninstallProfileMod(id: string, owner: string, name: string): Promise<string[]> {
   return await wrapInvoke(() => invoke("uninstall_profile_mod", { id, owner, name }));
 }
 
@@ -184,10 +261,19 @@ export enum Diff {
   Modified = "Modified",
 }
 
+export type ResolutionProblem = "MissingMod" | "MissingVersion" | "InactiveVersion" | "InvalidVersion";
+
+export interface UnresolvedMod {
+  fullName: string;
+  version: string;
+  problem: ResolutionProblem;
+}
+
 export interface Modpack {
   name: string;
   mods: ModSpec[];
   diff: PathDiff[];
+  unresolved: UnresolvedMod[];
 }
 
 export async function previewImportModpackFromThunderstoreCode(
@@ -201,19 +287,34 @@ export async function previewImportModpackFromThunderstoreCode(
   );
 }
 
-export interface ModProgressRegistration {
-  url: string;
-  task: TaskId;
-}
+export type ProfileInstallProgress =
+  | { type: "Resolving" }
+  | { type: "InstallingMods"; total: number }
+  | { type: "ModStarted"; url: string; task: TaskId }
+  | { type: "ModInstalled"; url: string; completed: number }
+  | { type: "ExtractingFiles"; total: number }
+  | { type: "Finished"; profile: string };
 
 export async function importModpackFromThunderstoreCode(
   thunderstoreId: string,
   game: string,
   profileId: string | undefined,
-  modProgressChannel: Channel<ModProgressRegistration>,
+  progressChannel: Channel<ProfileInstallProgress>,
+  listener: Listener,
+): Promise<string> {
+  return await invokeWithListener(listener, (taskId) =>
+    invoke("import_modpack_from_thunderstore_code", { thunderstoreId, game, profileId, progressChannel, taskId }),
+  );
+}
+
+export async function importModpackFromFile(
+  path: string,
+  game: string,
+  profileId: string | undefined,
+  progressChannel: Channel<ProfileInstallProgress>,
   listener: Listener,
 ): Promise<string> {
   return await invokeWithListener(listener, (taskId) =>
-    invoke("import_modpack_from_thunderstore_code", { thunderstoreId, game, profileId, modProgressChannel, taskId }),
+    invoke("import_modpack_from_file", { path, game, profileId, progressChannel, taskId }),
   );
 }
@@ -3,4 +3,34 @@ import { wrapInvoke } from "../api";
 
 export async function clearCache() {
   return await wrapInvoke(() => invoke<void>("clear_cache"))
-}
\ No newline at end of file
+}
+
+export interface PrunedCache {
+  removedFiles: number;
+  freedBytes: number;
+  /** The size of the archives left in the cache. */
+  remainingBytes: number;
+}
+
+/**
+ * Deletes the least recently used archives from the cache until the rest take up at most `maxBytes`.
+ */
+export async function pruneCache(maxBytes: number): Promise<PrunedCache> {
+  return await wrapInvoke(() => invoke<PrunedCache>("prune_cache", { maxBytes }));
+}
+
+export type PackageChange =
+  | "ContentModified"
+  | "Created"
+  | "TypeChanged"
+  | "LinkTargetChanged"
+  | "Deleted"
+  | "EscapingSymlink"
+  | "PermissionsChanged";
+
+/**
+ * Lists the changes made to the installed package at `path` since it was installed, as paths relative to the package.
+ */
+export async function scanPackageChanges(path: string): Promise<[string, PackageChange][]> {
+  return await wrapInvoke(() => invoke<[string, PackageChange][]>("scan_package_changes", { path }));
+}
@@ -1,5 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
 import { wrapInvoke } from "../api";
+import { PackageLoader } from "../types";
 
 export type SafeOsString = { Unicode: string } | { NonUnicodeBytes: number[] } | { NonUnicodeWide: number[] };
 
@@ -30,6 +31,12 @@ export type C2SMessage =
         command: SafeOsString;
         args: SafeOsString[];
         env: { [key: string]: SafeOsString };
+        pid?: number;
+      };
+    }
+  | {
+      LoaderInjected: {
+        loader: PackageLoader;
       };
     }
   | {
@@ -13,7 +13,14 @@ export interface Setting<T> {
 
 export interface Settings {
   defaultGame: Setting<string | null>;
+  watchProfiles: Setting<boolean>;
+  dedupeInstalledFiles: Setting<boolean>;
   openConsoleOnLaunch: Setting<boolean>;
+  launchViaSteamUrl: Setting<boolean>;
+  updateLoaderOnLaunch: Setting<boolean>;
+  maxConcurrentDownloads: Setting<number | null>;
+  maxConcurrentCpuTasks: Setting<number | null>;
+  downloadAttempts: Setting<number | null>;
 }
 
 export type SettingsT<T> = keyof {
@@ -25,4 +25,10 @@ export interface GameSelectSetting {
   input: { type: "game_select" };
 }
 
-export type Setting = ToggleSetting | TextSetting | GameSelectSetting;
+/** A positive integer, or `null` to use a default chosen by the backend. */
+export interface NumberSetting {
+  key: SettingsT<number | null>;
+  input: { type: "number" };
+}
+
+export type Setting = ToggleSetting | TextSetting | GameSelectSetting | NumberSetting;
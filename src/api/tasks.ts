@@ -92,6 +92,7 @@ export class Task {
 export enum Kind {
   Aggregate = "Aggregate",
   Download = "Download",
+  Extract = "Extract",
   Other = "Other",
 }
 
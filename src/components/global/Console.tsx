@@ -126,6 +126,12 @@ export default function Console(props: { conn: ConsoleConnection | undefined })
                   </For>
                 </p>
               );
+            } else if ("LoaderInjected" in event) {
+              return (
+                <p>
+                  <span class={styles.event__type}>[LOADER]</span> {event.LoaderInjected.loader}
+                </p>
+              );
             } else if ("Exit" in event) {
               return (
                 <p>
@@ -6,7 +6,7 @@ import { Settings, SettingsPatch, updateSettings, settings, settingsUI } from ".
 import Fa from "solid-fa";
 import { faClockRotateLeft } from "@fortawesome/free-solid-svg-icons";
 import { t } from "../../i18n/i18n";
-import { GameSelectSetting, Setting, TextSetting, ToggleSetting } from "../../api/settings/ui";
+import { GameSelectSetting, NumberSetting, Setting, TextSetting, ToggleSetting } from "../../api/settings/ui";
 import SelectDropdown from "./SelectDropdown";
 import { games } from "../../globals";
 import ErrorBoundary, { ErrorContext, ReportErrFn } from "./ErrorBoundary";
@@ -41,6 +41,9 @@ export default function SettingsDialog(props: { onDismiss: DismissCallback }) {
                           <Match when={setting.input.type === "text"}>
                             <TextInput idPrefix={idPrefix} setting={setting as TextSetting} />
                           </Match>
+                          <Match when={setting.input.type === "number"}>
+                            <NumberInput idPrefix={idPrefix} setting={setting as NumberSetting} />
+                          </Match>
                           <Match when={setting.input.type === "game_select"}>
                             <GameSelectInput idPrefix={idPrefix} setting={setting as GameSelectSetting} />
                           </Match>
@@ -119,6 +122,21 @@ function TextInput(props: { idPrefix: string; setting: TextSetting }) {
   );
 }
 
+function NumberInput(props: { idPrefix: string; setting: NumberSetting }) {
+  const reportErr = useContext(ErrorContext);
+  return (
+    <input
+      type="number"
+      min="1"
+      step="1"
+      placeholder="Automatic"
+      id={`${props.idPrefix}_${props.setting.key}`}
+      value={get(props.setting) ?? ""}
+      on:change={onChange(reportErr, props.setting, (e) => (e.value === "" ? null : e.valueAsNumber))}
+    />
+  );
+}
+
 function GameSelectInput(props: { idPrefix: string; setting: GameSelectSetting }) {
   const reportErr = useContext(ErrorContext);
   function onChanged(value: string, selected: boolean) {
@@ -93,7 +93,8 @@ function TaskList(props: { where: (task: Task) => boolean }) {
                 <div>
                   <div>
                     <h4>
-                      <Show when={task.metadata.kind === "Download"}>Download</Show> {task.metadata.title}
+                      <Show when={task.metadata.kind === "Download"}>Download</Show>
+                      <Show when={task.metadata.kind === "Extract"}>Extract</Show> {task.metadata.title}
                     </h4>
                     <p>
                       status=<span>{task.status.status}</span>
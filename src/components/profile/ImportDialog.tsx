@@ -3,7 +3,7 @@ import { createMemo, createSignal, createUniqueId, For, JSX, Show } from "solid-
 import {
   importModpackFromThunderstoreCode,
   Modpack,
-  ModProgressRegistration,
+  ProfileInstallProgress,
   ModSpec,
   previewImportModpackFromThunderstoreCode,
 } from "../../api";
@@ -107,15 +107,17 @@ function PreviewPageInner(props: {
   let [modProgress, setModProgress] = createStore<Record<string, TaskId>>({});
 
   async function onImport(listener: Listener) {
-    const modProgressChannel = new Channel<ModProgressRegistration>();
-    modProgressChannel.onmessage = (info) => {
-      setModProgress(info.url, info.task);
+    const progressChannel = new Channel<ProfileInstallProgress>();
+    progressChannel.onmessage = (event) => {
+      if (event.type === "ModStarted") {
+        setModProgress(event.url, event.task);
+      }
     };
     const id = await importModpackFromThunderstoreCode(
       props.thunderstoreCode,
       props.gameId,
       props.profile,
-      modProgressChannel,
+      progressChannel,
       listener,
     );
     console.log(`Imported to profile ${id}`);
@@ -353,12 +353,25 @@ function InstallButton(props: { mod: ModListing; installContext: NonNullable<typ
       progress
       class={styles.downloadBtn}
       onClick={async (listener) => {
-        await installProfileMod(
-          props.installContext.profile,
-          removeProperty(props.mod, "versions"),
-          props.mod.versions[0],
-          listener,
-        );
+        const install = (ignoreConflicts: boolean) =>
+          installProfileMod(
+            props.installContext.profile,
+            removeProperty(props.mod, "versions"),
+            props.mod.versions[0],
+            listener,
+            { ignoreConflicts },
+          );
+        const conflicts = await install(false);
+        if (
+          conflicts.length !== 0 &&
+          confirm(
+            `${props.mod.name} shares these files with other installed mods:\n\n` +
+              conflicts.map((conflict) => conflict.path).join("\n") +
+              "\n\nInstall it anyway?",
+          )
+        ) {
+          await install(true);
+        }
         await props.installContext.refetchInstalled();
       }}
     >
@@ -38,7 +38,15 @@ import TabRenderer from "../../components/global/TabRenderer";
 import ModList, { ModInstallContext } from "../../components/profile/ModList";
 import ModSearch from "../../components/profile/ModSearch";
 
-import { createProfile, deleteProfile, getProfileMods, launchProfile, ProfileWithId } from "../../api";
+import {
+  createProfile,
+  deleteProfile,
+  GameAlreadyRunningError,
+  getProfileMods,
+  launchProfile,
+  ProfileWithId,
+  stopProfile,
+} from "../../api";
 import * as globals from "../../globals";
 import { refetchProfiles } from "../../globals";
 import { Refetcher } from "../../types";
@@ -100,7 +108,9 @@ export default function Profile() {
 
   const [connection, setConnection] = createSignal<ConsoleConnection>();
 
-  async function launch(modded: boolean) {
+  const [alreadyRunning, setAlreadyRunning] = createSignal<{ game: string; modded: boolean }>();
+
+  async function launch(modded: boolean, force = false) {
     try {
       const conn = new ConsoleConnection();
       setConnection(conn);
@@ -110,16 +120,24 @@ export default function Profile() {
       await launchProfile(
         params.profileId !== undefined ? { profile: params.profileId } : { vanilla: params.gameId },
         conn.channel,
-        { modded },
+        { modded, force },
       );
     } catch (e) {
-      reportErr(e);
+      if (e instanceof GameAlreadyRunningError) {
+        setAlreadyRunning({ game: e.game, modded });
+      } else {
+        reportErr(e);
+      }
     }
   }
 
   async function killGame() {
     try {
-      await sendS2CMessage("Kill");
+      if (params.profileId !== undefined) {
+        await stopProfile(params.profileId);
+      } else {
+        await sendS2CMessage("Kill");
+      }
     } catch (e) {
       reportErr(e);
     }
@@ -318,6 +336,33 @@ export default function Profile() {
       <Show when={tasksDialogOpen()}>
         <TasksDialog onDismiss={() => setTasksDialogOpen(false)} />
       </Show>
+
+      <Show when={alreadyRunning()}>
+        {(state) => (
+          <PromptDialog
+            options={{
+              title: "Game already running",
+              question: `${state().game} is already running. Running two instances at once may break its mods.`,
+              btns: {
+                ok: {
+                  type: "danger",
+                  text: "Launch anyway",
+                  callback() {
+                    const { modded } = state();
+                    setAlreadyRunning();
+                    launch(modded, true);
+                  },
+                },
+                cancel: {
+                  callback() {
+                    setAlreadyRunning();
+                  },
+                },
+              },
+            }}
+          />
+        )}
+      </Show>
     </main>
   );
 }
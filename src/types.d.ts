@@ -12,12 +12,14 @@ export type Refetcher<T, R = unknown> = (info?: R) => T | Promise<T> | undefined
 export interface Game {
   id: string;
   name: string;
+  aliases?: string[];
   exeNames: string[];
   instanceType: "Game" | "Server";
   packageLoader: PackageLoader;
   storePlatformMetadata: StorePlatformMetadata[];
   thunderstoreId: string;
   thunderstoreUrl: string;
+  userDefined: boolean;
 }
 
 export enum PackageLoader {
@@ -38,6 +40,7 @@ export type StorePlatformMetadata =
       | { storePlatform: "SteamDirect" }
       | { storePlatform: "Epic" }
       | { storePlatform: "Xbox" }
+      | { storePlatform: "Gog" }
     ) & { storeIdentifier: string })
   | { storePlatform: "Oculus" }
   | { storePlatform: "Origin" }